@@ -0,0 +1,97 @@
+//! Decoding benchmarks, run with `cargo bench --bench decode`. Run them with
+//! and without `--features simd` to compare the SSE2 kernels to the scalar
+//! loops.
+extern crate tiff;
+
+use std::io::Cursor;
+use std::time::{Duration, Instant};
+
+use tiff::tag::*;
+use tiff::{Directory, Endian, Image, TIFFReader, TIFFValue, TIFFWriter};
+
+/// Reports the fastest of a few runs of `f`, after a first one warming up.
+fn bench<F: FnMut()>(name: &str, mut f: F) {
+    f();
+    let best = (0..5)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or_else(Duration::default);
+    println!("{:<32} {:>10.3} ms", name, best.as_secs_f64() * 1000.0);
+}
+
+/// Writes a file of `count` strips or tiles of `segment` each, described by
+/// `directory`.
+fn write(directory: &Directory, count: usize, segment: &[u8]) -> Vec<u8> {
+    let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+    writer
+        .write_image(directory, count, |_| Ok(segment.to_vec()))
+        .unwrap();
+    writer.into_inner().into_inner()
+}
+
+/// A 4096 x 4096 RGBA image whose strips of 16 rows are horizontally
+/// differenced.
+fn predictor() -> Vec<u8> {
+    let mut directory = Directory::new();
+    directory.set_field(&ImageWidth(4096));
+    directory.set_field(&ImageLength(4096));
+    directory.set_field(&SamplesPerPixel(4));
+    directory.set_field(&BitsPerSample(vec![8; 4]));
+    directory.set_field(&RowsPerStrip(16));
+    directory.set_field(&PhotometricInterpretation::RGB);
+    directory.set(Tag::ExtraSamples, TIFFValue::Short(vec![2]));
+    directory.set(Tag::Predictor, TIFFValue::Short(vec![2]));
+    write(&directory, 256, &vec![1; 4096 * 4 * 16])
+}
+
+/// A 4096 x 4096 YCbCr image without chroma subsampling, in strips of 16
+/// rows.
+fn ycbcr() -> Vec<u8> {
+    let mut directory = Directory::new();
+    directory.set_field(&ImageWidth(4096));
+    directory.set_field(&ImageLength(4096));
+    directory.set_field(&SamplesPerPixel(3));
+    directory.set_field(&BitsPerSample(vec![8; 3]));
+    directory.set_field(&RowsPerStrip(16));
+    directory.set_field(&PhotometricInterpretation::YCbCr);
+    directory.set(Tag::YCbCrSubSampling, TIFFValue::Short(vec![1, 1]));
+    write(&directory, 256, &vec![100; 4096 * 3 * 16])
+}
+
+/// A 2048 x 2048 RGB image in 16384 differenced tiles of 16 x 16 pixels.
+fn small_tiles() -> Vec<u8> {
+    let mut directory = Directory::new();
+    directory.set_field(&ImageWidth(2048));
+    directory.set_field(&ImageLength(2048));
+    directory.set_field(&SamplesPerPixel(3));
+    directory.set_field(&BitsPerSample(vec![8; 3]));
+    directory.set_field(&TileWidth(16));
+    directory.set_field(&TileLength(16));
+    directory.set_field(&PhotometricInterpretation::RGB);
+    directory.set(Tag::Predictor, TIFFValue::Short(vec![2]));
+    write(&directory, 128 * 128, &[1; 16 * 16 * 3])
+}
+
+fn main() {
+    let file = predictor();
+    bench("horizontal differencing", || {
+        let mut read = TIFFReader::from_bytes(&file).unwrap();
+        Image::new(&mut read).unwrap().read_data().unwrap();
+    });
+
+    let file = ycbcr();
+    bench("YCbCr to RGB", || {
+        let mut read = TIFFReader::from_bytes(&file).unwrap();
+        Image::new(&mut read).unwrap().decode().unwrap();
+    });
+
+    let file = small_tiles();
+    bench("small tiles", || {
+        let mut read = TIFFReader::from_bytes(&file).unwrap();
+        Image::new(&mut read).unwrap().read_region(0, 0, 2048, 2048).unwrap();
+    });
+}
@@ -0,0 +1,48 @@
+//! Copies the images of a TIFF file to a new file, rewriting its structure:
+//! directories are written in order and unused space is dropped.
+//!
+//! Usage: `tiffcp [-p PAGES] INPUT OUTPUT`, where `PAGES` lists the indices of
+//! the pages to copy, separated by commas, such as `0,2`.
+extern crate tiff;
+
+use std::env;
+use std::process;
+use tiff::{Page, TiffDocument};
+
+fn copy(input: &str, output: &str, pages: Option<&[usize]>) -> tiff::Result<()> {
+    let mut document = TiffDocument::open(input)?;
+    if let Some(pages) = pages {
+        let count = document.pages().len();
+        if let Some(page) = pages.iter().find(|page| **page >= count) {
+            eprintln!("{}: no page {}, the file has {}", input, page, count);
+            process::exit(2);
+        }
+        // Pages listed twice are copied once
+        let mut all: Vec<Option<Page>> = document.pages_mut().drain(..).map(Some).collect();
+        *document.pages_mut() = pages.iter().filter_map(|page| all[*page].take()).collect();
+    }
+    document.save_as(output)
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: tiffcp [-p PAGES] INPUT OUTPUT");
+    process::exit(2);
+}
+
+fn main() {
+    let mut arguments: Vec<String> = env::args().skip(1).collect();
+    let mut pages = None;
+    if arguments.first().map(String::as_str) == Some("-p") && arguments.len() > 1 {
+        let list: Result<Vec<usize>, _> = arguments[1].split(',').map(|e| e.parse()).collect();
+        pages = Some(list.unwrap_or_else(|_| usage()));
+        arguments.drain(..2);
+    }
+    if arguments.len() != 2 {
+        usage();
+    }
+
+    if let Err(error) = copy(&arguments[0], &arguments[1], pages.as_ref().map(|e| &e[..])) {
+        eprintln!("{}: {}", arguments[0], error);
+        process::exit(1);
+    }
+}
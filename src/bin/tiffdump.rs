@@ -0,0 +1,77 @@
+//! Prints the raw structure of TIFF files: the header, every entry of every
+//! directory with its value, and the location of the strips or tiles.
+//!
+//! Usage: `tiffdump [-n COUNT] FILE...`, where `COUNT` is the number of
+//! characters of each value printed, 80 by default.
+extern crate tiff;
+
+use std::env;
+use std::fs::File;
+use std::process;
+use tiff::{Event, EventReader};
+
+fn dump(path: &str, max_length: usize) -> tiff::Result<()> {
+    let mut file = File::open(path)?;
+    let mut events = EventReader::new(&mut file)?;
+    println!("{}:", path);
+    while let Some(event) = events.next() {
+        match event? {
+            Event::HeaderParsed(info) => println!(
+                "Magic: {:?} endian, {}, first directory at {:#x}",
+                info.endian,
+                if info.big_tiff { "BigTIFF" } else { "classic TIFF" },
+                info.first_ifd_offset
+            ),
+            Event::DirectoryStart { index, offset, entries } => {
+                println!("Directory {} at {:#x}, {} entries", index, offset, entries)
+            }
+            Event::Entry(entry) => {
+                let name = entry.tag.name().unwrap_or("Unknown");
+                let mut value = events.read_value(&entry)?.to_string();
+                if value.chars().count() > max_length {
+                    value = value.chars().take(max_length).collect::<String>() + "...";
+                }
+                println!(
+                    "  {} ({}) type {} count {} <{}>",
+                    name,
+                    u16::from(entry.tag),
+                    entry.value_type,
+                    entry.count,
+                    value
+                );
+            }
+            Event::ImageDataRef { index, offset, length } => {
+                println!("  Segment {} at {:#x}, {} bytes", index, offset, length)
+            }
+            Event::DirectoryEnd { next } => println!("Next directory at {:#x}", next),
+        }
+    }
+    for warning in events.warnings() {
+        println!("Warning: {:?}", warning);
+    }
+    Ok(())
+}
+
+fn main() {
+    let mut arguments: Vec<String> = env::args().skip(1).collect();
+    let mut max_length = 80;
+    if arguments.first().map(String::as_str) == Some("-n") && arguments.len() > 1 {
+        max_length = arguments[1].parse().unwrap_or(max_length);
+        arguments.drain(..2);
+    }
+    if arguments.is_empty() {
+        eprintln!("Usage: tiffdump [-n COUNT] FILE...");
+        process::exit(2);
+    }
+
+    let mut failed = false;
+    for path in &arguments {
+        if let Err(error) = dump(path, max_length) {
+            eprintln!("{}: {}", path, error);
+            failed = true;
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
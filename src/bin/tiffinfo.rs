@@ -0,0 +1,87 @@
+//! Prints a summary of each image of TIFF files, followed by their fields.
+//!
+//! Usage: `tiffinfo FILE...`
+extern crate tiff;
+
+use std::env;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::process;
+use tiff::tag::Tag;
+use tiff::{Directory, DirectoryHandle, Image, TIFFReader};
+
+/// Prints the fields of `directory` and of its child directories, such as the
+/// EXIF one, indented by `indent` spaces.
+fn print_fields(directory: &Directory, indent: usize) {
+    for (tag, value) in directory.entries() {
+        let name = tag.name().map_or_else(|| format!("Tag {}", u16::from(tag)), String::from);
+        let mut value = value.to_string();
+        if value.chars().count() > 60 {
+            value = value.chars().take(60).collect::<String>() + "...";
+        }
+        println!("{:indent$}{}: {}", "", name, value, indent = indent);
+    }
+    for tag in &[Tag::ExifIFD, Tag::GPSInfo, Tag::InteroperabilityIFD] {
+        if let Some(child) = directory.sub_directory(*tag) {
+            println!("{:indent$}{}:", "", tag.name().unwrap_or(""), indent = indent);
+            print_fields(child, indent + 2);
+        }
+    }
+}
+
+fn print_image<R: Read + Seek>(read: &mut TIFFReader<R>, directory: &DirectoryHandle) {
+    match Image::from_directory(read, directory) {
+        Ok(image) => println!(
+            "  Image: {} x {} pixels, {} bands, {} bits per pixel, {} bytes decoded",
+            image.width(),
+            image.length(),
+            image.bands(),
+            image.bits_per_pixel(),
+            image.estimated_decoded_size()
+        ),
+        Err(error) => println!("  Image: {}", error),
+    }
+}
+
+fn info(path: &str) -> tiff::Result<()> {
+    let mut read = TIFFReader::new(File::open(path)?)?;
+    let count = read.ifds().len();
+    println!("{}: {:?} endian, {} directories", path, read.endianness(), count);
+
+    let directories = read.directories().collect::<tiff::Result<Vec<_>>>()?;
+    for directory in &directories {
+        let index = directory.index();
+        println!("Directory {}:", index);
+        print_image(&mut read, directory);
+        let statistics = read.directory_statistics(index)?;
+        if statistics.tile_count > 0 {
+            println!("  Tiles: {}", statistics.tile_count);
+        } else {
+            println!("  Strips: {}", statistics.strip_count);
+        }
+        print_fields(&read.with_directory(directory, |read| read.read_directory())?, 2);
+    }
+    for warning in read.warnings() {
+        println!("Warning: {:?}", warning);
+    }
+    Ok(())
+}
+
+fn main() {
+    let paths: Vec<String> = env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("Usage: tiffinfo FILE...");
+        process::exit(2);
+    }
+
+    let mut failed = false;
+    for path in &paths {
+        if let Err(error) = info(path) {
+            eprintln!("{}: {}", path, error);
+            failed = true;
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
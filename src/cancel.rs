@@ -0,0 +1,153 @@
+//! This module handles stopping long-running operations from another thread.
+use error::{Result, TiffError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag shared between long-running operations and the code that may stop
+/// them. Clones share the same flag.
+///
+/// Decoding, encoding and copying check it between strips or tiles, and fail
+/// with `TiffError::Cancelled` once it is set.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Creates a token which is not cancelled.
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Asks the operations checking this token to stop.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether `cancel` was called.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Fails with `TiffError::Cancelled` when `token` is cancelled.
+pub(crate) fn check(token: &Option<CancellationToken>) -> Result<()> {
+    match token {
+        Some(token) if token.is_cancelled() => Err(TiffError::Cancelled),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use document::TiffDocument;
+    use endian::Endian;
+    use image::Image;
+    use options::ReaderOptions;
+    use reader::TIFFReader;
+    use std::io::{self, Cursor, Read, Seek, SeekFrom};
+    use tag::*;
+    use writer::{Directory, TIFFWriter};
+
+    fn is_cancelled<T>(result: Result<T>) -> bool {
+        matches!(result, Err(TiffError::Cancelled))
+    }
+
+    fn directory() -> Directory {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(1));
+        directory.set_field(&ImageLength(4));
+        directory.set_field(&BitsPerSample(vec![8]));
+        directory.set_field(&RowsPerStrip(1));
+        directory
+    }
+
+    #[test]
+    fn test_cancel_write() {
+        let token = CancellationToken::new();
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        writer.set_cancellation_token(Some(token.clone()));
+        let directory = directory();
+
+        let mut requested = Vec::new();
+        let source = |index: usize| {
+            requested.push(index);
+            if index == 1 {
+                token.cancel();
+            }
+            Ok(vec![0])
+        };
+        assert!(is_cancelled(writer.write_image(&directory, 4, source)));
+        assert_eq!(requested, [0, 1]);
+    }
+
+    #[test]
+    fn test_cancel_read() {
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        writer.write_image(&directory(), 4, |_| Ok(vec![0])).unwrap();
+        let bytes = writer.into_inner().into_inner();
+
+        let token = CancellationToken::new();
+        let options = ReaderOptions {
+            cancellation: Some(token.clone()),
+            ..ReaderOptions::default()
+        };
+        let mut read = TIFFReader::with_options(Cursor::new(bytes), options).unwrap();
+        assert!(Image::new(&mut read).unwrap().read_data().is_ok());
+
+        token.cancel();
+        assert!(is_cancelled(Image::new(&mut read).unwrap().read_data()));
+        let mut document = TiffDocument::from_reader(read).unwrap();
+        assert!(is_cancelled(document.write_to(Cursor::new(Vec::new()))));
+    }
+
+    /// Cancels `token` once the byte at `offset` is read.
+    struct CancellingSource {
+        inner: Cursor<Vec<u8>>,
+        token: CancellationToken,
+        offset: u64,
+    }
+
+    impl Read for CancellingSource {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let start = self.inner.position();
+            if (start..start + buf.len() as u64).contains(&self.offset) {
+                self.token.cancel();
+            }
+            self.inner.read(buf)
+        }
+    }
+
+    impl Seek for CancellingSource {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_cancel_during_read() {
+        let mut directory = directory();
+        directory.set_field(&ImageWidth(2));
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        writer.write_image(&directory, 4, |_| Ok(vec![0, 0])).unwrap();
+        let bytes = writer.into_inner().into_inner();
+        let mut read = TIFFReader::new(Cursor::new(&bytes[..])).unwrap();
+        let offset = read.read_unsigned(Tag::StripOffsets).unwrap()[0];
+
+        // The strips are read at once, and not decompressed
+        let token = CancellationToken::new();
+        let options = ReaderOptions {
+            cancellation: Some(token.clone()),
+            ..ReaderOptions::default()
+        };
+        let source = CancellingSource {
+            inner: Cursor::new(bytes),
+            token: token.clone(),
+            offset,
+        };
+        let mut read = TIFFReader::with_options(source, options).unwrap();
+        assert!(!token.is_cancelled());
+        assert!(is_cancelled(Image::new(&mut read).unwrap().read_data()));
+    }
+}
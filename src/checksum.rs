@@ -0,0 +1,250 @@
+//! This module handles checksums of the image data, to detect corruption of
+//! archived files.
+use crc32fast;
+use error::{CorruptError, Result};
+use reader::TIFFReader;
+use std::io::{self, Read, Seek, Write};
+use tag::Tag;
+use value::TIFFValue;
+use xxhash_rust::xxh64::Xxh64;
+
+/// The private tag under which `TIFFWriter` embeds checksums.
+pub const CHECKSUM_TAG: u16 = 0xfe5a;
+
+/// A checksum algorithm.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Crc32,
+    XxHash64,
+}
+
+impl ChecksumAlgorithm {
+    fn id(self) -> u32 {
+        match self {
+            ChecksumAlgorithm::Crc32 => 1,
+            ChecksumAlgorithm::XxHash64 => 2,
+        }
+    }
+
+    fn from_id(id: u32) -> Option<ChecksumAlgorithm> {
+        match id {
+            1 => Some(ChecksumAlgorithm::Crc32),
+            2 => Some(ChecksumAlgorithm::XxHash64),
+            _ => None,
+        }
+    }
+}
+
+/// Checksums of the strips or tiles of a directory, as stored in the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksums {
+    /// The algorithm the checksums were computed with.
+    pub algorithm: ChecksumAlgorithm,
+    /// The checksum of each strip or tile.
+    pub segments: Vec<u64>,
+    /// The checksum of all strips or tiles, concatenated in order.
+    pub image: u64,
+}
+
+impl Checksums {
+    /// Encodes the checksums as the value of `CHECKSUM_TAG`: the algorithm,
+    /// then the image and segment checksums, each as two longs, low first.
+    pub fn to_value(&self) -> TIFFValue {
+        let mut longs = vec![self.algorithm.id()];
+        for checksum in Some(self.image).iter().chain(&self.segments) {
+            longs.push(*checksum as u32);
+            longs.push((*checksum >> 32) as u32);
+        }
+        TIFFValue::Long(longs)
+    }
+
+    /// Decodes a value of `CHECKSUM_TAG`.
+    pub fn from_value(value: &TIFFValue) -> Option<Checksums> {
+        let longs = match value {
+            TIFFValue::Long(longs) if longs.len() % 2 == 1 && longs.len() >= 3 => longs,
+            _ => return None,
+        };
+        let algorithm = ChecksumAlgorithm::from_id(longs[0])?;
+        let mut checksums = longs[1..]
+            .chunks(2)
+            .map(|pair| u64::from(pair[0]) | u64::from(pair[1]) << 32);
+        let image = checksums.next()?;
+        Some(Checksums {
+            algorithm,
+            segments: checksums.collect(),
+            image,
+        })
+    }
+}
+
+enum State {
+    Crc32(crc32fast::Hasher),
+    XxHash64(Xxh64),
+}
+
+/// Computes checksums of segments written to it one after the other.
+pub(crate) struct ChecksumBuilder {
+    algorithm: ChecksumAlgorithm,
+    segment: State,
+    image: State,
+    segments: Vec<u64>,
+}
+
+impl ChecksumBuilder {
+    pub(crate) fn new(algorithm: ChecksumAlgorithm) -> ChecksumBuilder {
+        ChecksumBuilder {
+            algorithm,
+            segment: ChecksumBuilder::state(algorithm),
+            image: ChecksumBuilder::state(algorithm),
+            segments: Vec::new(),
+        }
+    }
+
+    fn state(algorithm: ChecksumAlgorithm) -> State {
+        match algorithm {
+            ChecksumAlgorithm::Crc32 => State::Crc32(crc32fast::Hasher::new()),
+            ChecksumAlgorithm::XxHash64 => State::XxHash64(Xxh64::new(0)),
+        }
+    }
+
+    fn digest(state: State) -> u64 {
+        match state {
+            State::Crc32(hasher) => u64::from(hasher.finalize()),
+            State::XxHash64(hasher) => hasher.digest(),
+        }
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        for state in [&mut self.segment, &mut self.image] {
+            match state {
+                State::Crc32(hasher) => hasher.update(bytes),
+                State::XxHash64(hasher) => hasher.update(bytes),
+            }
+        }
+    }
+
+    /// Ends the current segment.
+    pub(crate) fn end_segment(&mut self) {
+        let state = ::std::mem::replace(&mut self.segment, ChecksumBuilder::state(self.algorithm));
+        self.segments.push(ChecksumBuilder::digest(state));
+    }
+
+    pub(crate) fn finish(self) -> Checksums {
+        Checksums {
+            algorithm: self.algorithm,
+            segments: self.segments,
+            image: ChecksumBuilder::digest(self.image),
+        }
+    }
+}
+
+impl Write for ChecksumBuilder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Forwards writes to `inner` while feeding them to a `ChecksumBuilder`.
+pub(crate) struct ChecksumWriter<'a> {
+    pub(crate) inner: &'a mut dyn Write,
+    pub(crate) builder: &'a mut ChecksumBuilder,
+}
+
+impl<'a> Write for ChecksumWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.builder.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R: Read + Seek> TIFFReader<R> {
+    /// Computes the checksums of the strips or tiles of the current directory,
+    /// streaming their bytes from the file.
+    pub fn checksums(&mut self, algorithm: ChecksumAlgorithm) -> Result<Checksums> {
+        let tiled = self.ifds()[self.directory_index()]
+            .get_entry_from_tag(Tag::TileOffsets)
+            .is_some();
+        let (offsets, counts) = if tiled {
+            (Tag::TileOffsets, Tag::TileByteCounts)
+        } else {
+            (Tag::StripOffsets, Tag::StripByteCounts)
+        };
+        let offsets = self.read_unsigned(offsets)?;
+        let counts = self.read_unsigned(counts)?;
+
+        let mut builder = ChecksumBuilder::new(algorithm);
+        for (offset, count) in offsets.into_iter().zip(counts) {
+            self.copy_bytes(offset, count, &mut builder)?;
+            builder.end_segment();
+        }
+        Ok(builder.finish())
+    }
+
+    /// The checksums embedded in the current directory by `TIFFWriter`, if any.
+    pub fn embedded_checksums(&mut self) -> Result<Option<Checksums>> {
+        match self.read_tag(Tag::Unknown(CHECKSUM_TAG))? {
+            Some(value) => Checksums::from_value(&value)
+                .map(Some)
+                .ok_or_else(|| CorruptError::Invalid("Invalid checksum value").into()),
+            None => Ok(None),
+        }
+    }
+
+    /// Checks the image data of the current directory against its embedded
+    /// checksums, returning `None` when there are none.
+    pub fn verify_checksums(&mut self) -> Result<Option<bool>> {
+        match self.embedded_checksums()? {
+            Some(embedded) => Ok(Some(self.checksums(embedded.algorithm)? == embedded)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::tests::write_then_read;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_checksums() {
+        let bytes: &[u8] = include_bytes!("../samples/picoawards_le.tiff");
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+
+        let checksums = read.checksums(ChecksumAlgorithm::Crc32).unwrap();
+        assert_eq!(checksums.segments.len(), 61);
+        let strip = read.read_strip(10).unwrap();
+        assert_eq!(checksums.segments[10], u64::from(crc32fast::hash(&strip)));
+
+        let checksums = read.checksums(ChecksumAlgorithm::XxHash64).unwrap();
+        let mut image = Vec::new();
+        for index in 0..61 {
+            image.extend(read.read_strip(index).unwrap());
+        }
+        assert_eq!(checksums.image, xxhash_rust::xxh64::xxh64(&image, 0));
+
+        assert_eq!(Checksums::from_value(&checksums.to_value()), Some(checksums));
+        assert_eq!(read.verify_checksums().unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid_checksums() {
+        let mut directory = ::writer::Directory::new();
+        directory.set_field(&::tag::ImageWidth(1));
+        directory.set_field(&::tag::ImageLength(1));
+        directory.set(Tag::Unknown(CHECKSUM_TAG), TIFFValue::Undefined(vec![9]));
+        let mut read = write_then_read(&directory, &[vec![0]]);
+        let error = read.embedded_checksums().unwrap_err();
+        assert_eq!(error.kind(), ::error::ErrorKind::CorruptData);
+    }
+}
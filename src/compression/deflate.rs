@@ -0,0 +1,67 @@
+//! Decompression of Deflate coded segments (`Compression` 8 and 32946).
+use flate2::{Decompress, FlushDecompress, Status};
+
+use error::{CorruptError, Result};
+
+/// Decompresses a zlib stream into at most `size` bytes, where the rest of
+/// the stream is left out. Data cut short before the end of the stream is
+/// returned as far as it goes.
+pub fn decompress(input: &[u8], size: usize) -> Result<Vec<u8>> {
+    let mut decompress = Decompress::new(true);
+    let mut output = Vec::with_capacity(input.len().saturating_mul(4).min(size));
+    loop {
+        if output.len() >= size {
+            output.truncate(size);
+            break;
+        }
+        if output.len() == output.capacity() {
+            output.reserve_exact(output.len().max(4096).min(size - output.len()));
+        }
+        let (read, written) = (decompress.total_in(), decompress.total_out());
+        let status = decompress
+            .decompress_vec(&input[read as usize..], &mut output, FlushDecompress::None)
+            .map_err(|_| CorruptError::Invalid("Invalid Deflate data"))?;
+        let stalled = decompress.total_in() == read && decompress.total_out() == written;
+        match status {
+            Status::StreamEnd => break,
+            // No progress with room left in the output: the input is cut short
+            _ if stalled && output.len() < output.capacity() => break,
+            _ => {}
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    #[test]
+    fn test_decompress() {
+        let data: Vec<u8> = (0..20_000u32).map(|value| (value % 251) as u8).collect();
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+        assert_eq!(decompress(&compressed, 1000).unwrap(), &data[..1000]);
+
+        let truncated = decompress(&compressed[..compressed.len() / 2], data.len()).unwrap();
+        assert!(!truncated.is_empty() && data.starts_with(&truncated));
+        assert!(decompress(&[0x78, 0x9c, 0xff, 0xff], 16).is_err());
+    }
+
+    #[test]
+    fn test_decompression_bomb() {
+        // 16 MiB of zeros compress to about 16 KiB
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&vec![0; 16 << 20]).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let decompressed = decompress(&compressed, 4096).unwrap();
+        assert_eq!(decompressed, [0; 4096]);
+        assert!(decompressed.capacity() <= 4096);
+    }
+}
@@ -0,0 +1,675 @@
+//! Decompression of CCITT bilevel segments: Modified Huffman (`Compression`
+//! 2), T.4 (`Compression` 3) coding, one or two-dimensional, and T.6
+//! (`Compression` 4) coding.
+//!
+//! Rows are returned packed eight pixels per byte, black pixels as 1 bits.
+use std::sync::OnceLock;
+
+use error::{CorruptError, Result, TiffError};
+
+/// Codes of white runs: terminating codes of 0 to 63 pixels, then make-up
+/// codes of multiples of 64.
+const WHITE_CODES: [(&str, u16); 91] = [
+    ("00110101", 0),
+    ("000111", 1),
+    ("0111", 2),
+    ("1000", 3),
+    ("1011", 4),
+    ("1100", 5),
+    ("1110", 6),
+    ("1111", 7),
+    ("10011", 8),
+    ("10100", 9),
+    ("00111", 10),
+    ("01000", 11),
+    ("001000", 12),
+    ("000011", 13),
+    ("110100", 14),
+    ("110101", 15),
+    ("101010", 16),
+    ("101011", 17),
+    ("0100111", 18),
+    ("0001100", 19),
+    ("0001000", 20),
+    ("0010111", 21),
+    ("0000011", 22),
+    ("0000100", 23),
+    ("0101000", 24),
+    ("0101011", 25),
+    ("0010011", 26),
+    ("0100100", 27),
+    ("0011000", 28),
+    ("00000010", 29),
+    ("00000011", 30),
+    ("00011010", 31),
+    ("00011011", 32),
+    ("00010010", 33),
+    ("00010011", 34),
+    ("00010100", 35),
+    ("00010101", 36),
+    ("00010110", 37),
+    ("00010111", 38),
+    ("00101000", 39),
+    ("00101001", 40),
+    ("00101010", 41),
+    ("00101011", 42),
+    ("00101100", 43),
+    ("00101101", 44),
+    ("00000100", 45),
+    ("00000101", 46),
+    ("00001010", 47),
+    ("00001011", 48),
+    ("01010010", 49),
+    ("01010011", 50),
+    ("01010100", 51),
+    ("01010101", 52),
+    ("00100100", 53),
+    ("00100101", 54),
+    ("01011000", 55),
+    ("01011001", 56),
+    ("01011010", 57),
+    ("01011011", 58),
+    ("01001010", 59),
+    ("01001011", 60),
+    ("00110010", 61),
+    ("00110011", 62),
+    ("00110100", 63),
+    ("11011", 64),
+    ("10010", 128),
+    ("010111", 192),
+    ("0110111", 256),
+    ("00110110", 320),
+    ("00110111", 384),
+    ("01100100", 448),
+    ("01100101", 512),
+    ("01101000", 576),
+    ("01100111", 640),
+    ("011001100", 704),
+    ("011001101", 768),
+    ("011010010", 832),
+    ("011010011", 896),
+    ("011010100", 960),
+    ("011010101", 1024),
+    ("011010110", 1088),
+    ("011010111", 1152),
+    ("011011000", 1216),
+    ("011011001", 1280),
+    ("011011010", 1344),
+    ("011011011", 1408),
+    ("010011000", 1472),
+    ("010011001", 1536),
+    ("010011010", 1600),
+    ("011000", 1664),
+    ("010011011", 1728),
+];
+
+/// Codes of black runs, laid out as `WHITE_CODES`.
+const BLACK_CODES: [(&str, u16); 91] = [
+    ("0000110111", 0),
+    ("010", 1),
+    ("11", 2),
+    ("10", 3),
+    ("011", 4),
+    ("0011", 5),
+    ("0010", 6),
+    ("00011", 7),
+    ("000101", 8),
+    ("000100", 9),
+    ("0000100", 10),
+    ("0000101", 11),
+    ("0000111", 12),
+    ("00000100", 13),
+    ("00000111", 14),
+    ("000011000", 15),
+    ("0000010111", 16),
+    ("0000011000", 17),
+    ("0000001000", 18),
+    ("00001100111", 19),
+    ("00001101000", 20),
+    ("00001101100", 21),
+    ("00000110111", 22),
+    ("00000101000", 23),
+    ("00000010111", 24),
+    ("00000011000", 25),
+    ("000011001010", 26),
+    ("000011001011", 27),
+    ("000011001100", 28),
+    ("000011001101", 29),
+    ("000001101000", 30),
+    ("000001101001", 31),
+    ("000001101010", 32),
+    ("000001101011", 33),
+    ("000011010010", 34),
+    ("000011010011", 35),
+    ("000011010100", 36),
+    ("000011010101", 37),
+    ("000011010110", 38),
+    ("000011010111", 39),
+    ("000001101100", 40),
+    ("000001101101", 41),
+    ("000011011010", 42),
+    ("000011011011", 43),
+    ("000001010100", 44),
+    ("000001010101", 45),
+    ("000001010110", 46),
+    ("000001010111", 47),
+    ("000001100100", 48),
+    ("000001100101", 49),
+    ("000001010010", 50),
+    ("000001010011", 51),
+    ("000000100100", 52),
+    ("000000110111", 53),
+    ("000000111000", 54),
+    ("000000100111", 55),
+    ("000000101000", 56),
+    ("000001011000", 57),
+    ("000001011001", 58),
+    ("000000101011", 59),
+    ("000000101100", 60),
+    ("000001011010", 61),
+    ("000001100110", 62),
+    ("000001100111", 63),
+    ("0000001111", 64),
+    ("000011001000", 128),
+    ("000011001001", 192),
+    ("000001011011", 256),
+    ("000000110011", 320),
+    ("000000110100", 384),
+    ("000000110101", 448),
+    ("0000001101100", 512),
+    ("0000001101101", 576),
+    ("0000001001010", 640),
+    ("0000001001011", 704),
+    ("0000001001100", 768),
+    ("0000001001101", 832),
+    ("0000001110010", 896),
+    ("0000001110011", 960),
+    ("0000001110100", 1024),
+    ("0000001110101", 1088),
+    ("0000001110110", 1152),
+    ("0000001110111", 1216),
+    ("0000001010010", 1280),
+    ("0000001010011", 1344),
+    ("0000001010100", 1408),
+    ("0000001010101", 1472),
+    ("0000001011010", 1536),
+    ("0000001011011", 1600),
+    ("0000001100100", 1664),
+    ("0000001100101", 1728),
+];
+
+/// Make-up codes shared by both colors, for runs of 1792 to 2560 pixels.
+const EXTENDED_CODES: [(&str, u16); 13] = [
+    ("00000001000", 1792),
+    ("00000001100", 1856),
+    ("00000001101", 1920),
+    ("000000010010", 1984),
+    ("000000010011", 2048),
+    ("000000010100", 2112),
+    ("000000010101", 2176),
+    ("000000010110", 2240),
+    ("000000010111", 2304),
+    ("000000011100", 2368),
+    ("000000011101", 2432),
+    ("000000011110", 2496),
+    ("000000011111", 2560),
+];
+
+/// The length of the longest run code.
+const LOOKUP_BITS: u32 = 13;
+
+/// The run and code length of every value of the next `LOOKUP_BITS` bits,
+/// with a zero length where no code matches.
+struct RunTable(Vec<(u8, u16)>);
+
+impl RunTable {
+    fn new(codes: &[(&str, u16)]) -> RunTable {
+        let mut table = vec![(0, 0); 1 << LOOKUP_BITS];
+        for (code, run) in codes.iter().chain(EXTENDED_CODES.iter()) {
+            let length = code.len() as u32;
+            let value = u32::from_str_radix(code, 2).unwrap();
+            let first = (value << (LOOKUP_BITS - length)) as usize;
+            for entry in &mut table[first..first + (1 << (LOOKUP_BITS - length))] {
+                debug_assert_eq!(entry.0, 0, "{} is the prefix of another code", code);
+                *entry = (length as u8, *run);
+            }
+        }
+        RunTable(table)
+    }
+}
+
+/// The white and black run tables, built on first use.
+fn run_tables() -> &'static (RunTable, RunTable) {
+    static TABLES: OnceLock<(RunTable, RunTable)> = OnceLock::new();
+    TABLES.get_or_init(|| (RunTable::new(&WHITE_CODES), RunTable::new(&BLACK_CODES)))
+}
+
+/// Reads the input bit by bit, most significant bit first.
+struct Bits<'a> {
+    input: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Bits<'a> {
+    fn is_empty(&self) -> bool {
+        self.position >= self.input.len() * 8
+    }
+
+    /// The next `count` bits, up to 24, padded with zeros past the end.
+    fn peek(&self, count: u32) -> u32 {
+        let byte = self.position / 8;
+        let window = (0..4).fold(0u32, |window, index| {
+            (window << 8) | u32::from(self.input.get(byte + index).cloned().unwrap_or(0))
+        });
+        (window << (self.position % 8)) >> (32 - count)
+    }
+
+    /// Consumes `count` bits, or returns false if fewer remain.
+    fn consume(&mut self, count: u32) -> bool {
+        self.position += count as usize;
+        self.position <= self.input.len() * 8
+    }
+
+    fn align(&mut self) {
+        self.position = self.position.div_ceil(8) * 8;
+    }
+
+    /// The number of zero bits at the current position, and whether a one
+    /// follows them.
+    fn zeros(&self) -> (usize, bool) {
+        let zeros = (self.position..self.input.len() * 8)
+            .take_while(|bit| self.input[bit / 8] & (0x80 >> (bit % 8)) == 0)
+            .count();
+        (zeros, self.position + zeros < self.input.len() * 8)
+    }
+
+    /// Whether only zeros, such as the padding of the last byte, remain.
+    fn at_fill(&self) -> bool {
+        !self.zeros().1
+    }
+
+    /// The length of the end-of-line code at the current position, made of
+    /// at least 11 zeros, fill bits included, and a one.
+    fn end_of_line(&self) -> Option<u32> {
+        match self.zeros() {
+            (zeros, true) if zeros >= 11 => Some(zeros as u32 + 1),
+            _ => None,
+        }
+    }
+
+    /// Consumes an end-of-line code if there is one.
+    fn skip_end_of_line(&mut self) -> bool {
+        match self.end_of_line() {
+            Some(length) => self.consume(length),
+            None => false,
+        }
+    }
+}
+
+/// How decoding a row stopped.
+#[derive(Debug, PartialEq)]
+enum Row {
+    Complete,
+    /// An end-of-line code, left unread, cut the row short.
+    EndOfLine,
+    /// The data ended within the row.
+    Truncated,
+}
+
+/// The ways the pixels of a row are coded relative to the reference row.
+enum Mode {
+    Pass,
+    Horizontal,
+    Vertical(i64),
+}
+
+/// An error for runs going past the end of their row.
+fn run_too_long() -> TiffError {
+    CorruptError::Invalid("Fax run longer than its row").into()
+}
+
+/// Reads a run of one color, made of make-up codes and a terminating code,
+/// of at most `width` pixels. Returns `None` at the end of the data or at an
+/// end-of-line code.
+fn read_run(bits: &mut Bits, table: &RunTable, width: u32) -> Result<Option<u32>> {
+    let mut total: u32 = 0;
+    loop {
+        if bits.is_empty() {
+            return Ok(None);
+        }
+        let (length, run) = table.0[bits.peek(LOOKUP_BITS) as usize];
+        if length == 0 {
+            return if bits.end_of_line().is_some() || bits.at_fill() {
+                Ok(None)
+            } else {
+                Err(CorruptError::Invalid("Invalid fax run code").into())
+            };
+        }
+        if !bits.consume(u32::from(length)) {
+            return Ok(None);
+        }
+        total = total
+            .checked_add(u32::from(run))
+            .filter(|total| *total <= width)
+            .ok_or_else(run_too_long)?;
+        if run < 64 {
+            return Ok(Some(total));
+        }
+    }
+}
+
+/// Reads the mode of the next changing element of a two-dimensional row.
+/// Returns `None` at the end of the data or at an end-of-line code.
+fn read_mode(bits: &mut Bits) -> Result<Option<Mode>> {
+    if bits.is_empty() {
+        return Ok(None);
+    }
+    let next = bits.peek(7);
+    let (length, mode) = match next {
+        _ if next >> 6 == 1 => (1, Mode::Vertical(0)),
+        _ if next >> 4 == 0b011 => (3, Mode::Vertical(1)),
+        _ if next >> 4 == 0b010 => (3, Mode::Vertical(-1)),
+        _ if next >> 4 == 0b001 => (3, Mode::Horizontal),
+        _ if next >> 3 == 0b0001 => (4, Mode::Pass),
+        _ if next >> 1 == 0b000011 => (6, Mode::Vertical(2)),
+        _ if next >> 1 == 0b000010 => (6, Mode::Vertical(-2)),
+        0b0000011 => (7, Mode::Vertical(3)),
+        0b0000010 => (7, Mode::Vertical(-3)),
+        0b0000001 => return Err(TiffError::Unsupported("Uncompressed fax data")),
+        _ => {
+            return if bits.end_of_line().is_some() || bits.at_fill() {
+                Ok(None)
+            } else {
+                Err(CorruptError::Invalid("Invalid fax mode code").into())
+            };
+        }
+    };
+    if !bits.consume(length) {
+        return Ok(None);
+    }
+    Ok(Some(mode))
+}
+
+/// How a row stopped when a run or mode could not be read.
+fn stopped(bits: &Bits) -> Row {
+    if bits.end_of_line().is_some() {
+        Row::EndOfLine
+    } else {
+        Row::Truncated
+    }
+}
+
+/// Decodes a one-dimensional row into the positions where its color changes,
+/// starting from white.
+fn read_1d_row(bits: &mut Bits, width: u32, changes: &mut Vec<u32>) -> Result<Row> {
+    let (white_runs, black_runs) = run_tables();
+    changes.clear();
+    let (mut position, mut white) = (0, true);
+    while position < width {
+        let table = if white { white_runs } else { black_runs };
+        match read_run(bits, table, width)? {
+            Some(run) => position = position.checked_add(run).ok_or_else(run_too_long)?.min(width),
+            None => return Ok(stopped(bits)),
+        }
+        changes.push(position);
+        white = !white;
+    }
+    Ok(Row::Complete)
+}
+
+/// Decodes a two-dimensional row into the positions where its color changes,
+/// from those of the row above.
+fn read_2d_row(
+    bits: &mut Bits,
+    width: u32,
+    reference: &[u32],
+    changes: &mut Vec<u32>,
+) -> Result<Row> {
+    let (white_runs, black_runs) = run_tables();
+    changes.clear();
+    // a0 starts on an imaginary white pixel left of the row
+    let (mut a0, mut white) = (-1, true);
+    let mut index = 0;
+    while a0 < i64::from(width) {
+        // b1 is the first change of the reference row right of a0 to the
+        // opposite color, changes to black being at even indices
+        index = usize::saturating_sub(index, 2);
+        while index < reference.len()
+            && (i64::from(reference[index]) <= a0 || (index % 2 == 0) != white)
+        {
+            index += 1;
+        }
+        let b1 = reference.get(index).map_or(width, |b1| *b1);
+        let b2 = reference.get(index + 1).map_or(width, |b2| *b2);
+
+        match read_mode(bits)? {
+            Some(Mode::Pass) => a0 = i64::from(b2),
+            Some(Mode::Horizontal) => {
+                let (first, second) = if white {
+                    (white_runs, black_runs)
+                } else {
+                    (black_runs, white_runs)
+                };
+                let runs = match read_run(bits, first, width)? {
+                    Some(run) => read_run(bits, second, width)?.map(|second| (run, second)),
+                    None => None,
+                };
+                let (run, second) = match runs {
+                    Some(runs) => runs,
+                    None => return Ok(stopped(bits)),
+                };
+                let a1 = (a0.max(0) as u32).checked_add(run).ok_or_else(run_too_long)?;
+                let a1 = a1.min(width);
+                let a2 = a1.checked_add(second).ok_or_else(run_too_long)?.min(width);
+                changes.extend_from_slice(&[a1, a2]);
+                a0 = i64::from(a2);
+            }
+            Some(Mode::Vertical(delta)) => {
+                let a1 = i64::from(b1) + delta;
+                if a1 < a0.max(0) || a1 > i64::from(width) {
+                    return Err(CorruptError::Invalid("Invalid fax vertical mode").into());
+                }
+                changes.push(a1 as u32);
+                a0 = a1;
+                white = !white;
+            }
+            None => return Ok(stopped(bits)),
+        }
+    }
+    Ok(Row::Complete)
+}
+
+/// Appends a row of `width` pixels, black between each pair of changes.
+fn pack_row(changes: &[u32], width: u32, output: &mut Vec<u8>) {
+    let start = output.len();
+    output.resize(start + (width as usize).div_ceil(8), 0);
+    let row = &mut output[start..];
+    for pair in changes.chunks(2) {
+        let end = pair.get(1).map_or(width, |end| *end);
+        for x in pair[0] as usize..end.min(width) as usize {
+            row[x / 8] |= 0x80 >> (x % 8);
+        }
+    }
+}
+
+/// Decompresses Modified Huffman rows of `width` pixels, each starting on a
+/// byte boundary.
+pub fn decompress_modified_huffman(input: &[u8], width: u32) -> Result<Vec<u8>> {
+    let mut bits = Bits { input, position: 0 };
+    let mut output = Vec::new();
+    let mut changes = Vec::new();
+    while !bits.at_fill() {
+        if read_1d_row(&mut bits, width, &mut changes)? != Row::Complete {
+            break;
+        }
+        pack_row(&changes, width, &mut output);
+        bits.align();
+    }
+    Ok(output)
+}
+
+/// Decompresses T.4 rows of `width` pixels, coded according to the
+/// `T4Options` flags: two-dimensionally when bit 0 is set, uncompressed
+/// mode, bit 1, being unsupported. Decoding stops at the return to control
+/// of six end-of-line codes, and data cut short is returned as far as it
+/// goes.
+pub fn decompress_t4(input: &[u8], width: u32, options: u32) -> Result<Vec<u8>> {
+    if options & 2 != 0 {
+        return Err(TiffError::Unsupported("Uncompressed fax data"));
+    }
+    let two_dimensional = options & 1 != 0;
+    let mut bits = Bits { input, position: 0 };
+    let mut output = Vec::new();
+    let (mut reference, mut changes) = (Vec::new(), Vec::new());
+    while !bits.at_fill() {
+        // Rows start with an end-of-line code, which may be left out before
+        // one-dimensional rows, and is followed by the coding of the row
+        let end_of_line = bits.skip_end_of_line();
+        let one_dimensional = !two_dimensional || bits.peek(1) == 1;
+        if two_dimensional && !bits.consume(1) {
+            break;
+        }
+        if bits.at_fill() || (end_of_line && bits.end_of_line().is_some()) {
+            break;
+        }
+
+        let row = if one_dimensional {
+            read_1d_row(&mut bits, width, &mut changes)?
+        } else {
+            read_2d_row(&mut bits, width, &reference, &mut changes)?
+        };
+        if row == Row::Truncated {
+            break;
+        }
+        // Rows cut short by an end-of-line code keep their last color
+        pack_row(&changes, width, &mut output);
+        ::std::mem::swap(&mut reference, &mut changes);
+    }
+    Ok(output)
+}
+
+/// Decompresses T.6 rows of `width` pixels, all coded two-dimensionally
+/// from an imaginary white row above the first one. Uncompressed mode, bit 1
+/// of the `T6Options` flags, is unsupported. Decoding stops at the
+/// end-of-facsimile-block code, and data cut short is returned as far as it
+/// goes.
+pub fn decompress_t6(input: &[u8], width: u32, options: u32) -> Result<Vec<u8>> {
+    if options & 2 != 0 {
+        return Err(TiffError::Unsupported("Uncompressed fax data"));
+    }
+    let mut bits = Bits { input, position: 0 };
+    let mut output = Vec::new();
+    let (mut reference, mut changes) = (Vec::new(), Vec::new());
+    while !bits.at_fill() {
+        // Rows hold no end-of-line codes, so one ends the data
+        if read_2d_row(&mut bits, width, &reference, &mut changes)? != Row::Complete {
+            break;
+        }
+        pack_row(&changes, width, &mut output);
+        ::std::mem::swap(&mut reference, &mut changes);
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Concatenates codes given as strings of bits, padded with zeros.
+    fn pack(codes: &[&str]) -> Vec<u8> {
+        let bits: String = codes.concat();
+        bits.as_bytes()
+            .chunks(8)
+            .map(|chunk| {
+                let byte = String::from_utf8(chunk.to_vec()).unwrap();
+                u8::from_str_radix(&format!("{:0<8}", byte), 2).unwrap()
+            })
+            .collect()
+    }
+
+    const EOL: &str = "000000000001";
+
+    #[test]
+    fn test_run_tables() {
+        let (white, black) = run_tables();
+        let index = |code: &str| u32::from_str_radix(code, 2).unwrap() << (13 - code.len());
+        assert_eq!(white.0[index("00110101") as usize], (8, 0));
+        assert_eq!(white.0[index("010011011") as usize], (9, 1728));
+        assert_eq!(black.0[index("0000001100101") as usize], (13, 1728));
+        assert_eq!(black.0[index("000000011111") as usize], (12, 2560));
+        assert_eq!(white.0[index(EOL) as usize].0, 0);
+    }
+
+    #[test]
+    fn test_modified_huffman() {
+        // 3 white, 2 black, 5 white, then a byte aligned row of 10 black
+        let first = pack(&["1000", "11", "1100"]);
+        let second = pack(&["00110101", "0000100"]);
+        let input = [first, second].concat();
+        let decoded = decompress_modified_huffman(&input, 10).unwrap();
+        assert_eq!(decoded, [0b0001_1000, 0b0000_0000, 0xff, 0b1100_0000]);
+
+        // 1792 + 8 white
+        let input = pack(&["00000001000", "10011"]);
+        let decoded = decompress_modified_huffman(&input, 1800).unwrap();
+        assert_eq!(decoded, vec![0; 225]);
+        assert!(decompress_modified_huffman(&[0x00, 0xff], 8).is_err());
+    }
+
+    #[test]
+    fn test_run_overflow() {
+        // White make-up codes of 2560 pixels, two every three bytes, adding
+        // up to more than u32::MAX
+        let input: Vec<u8> = [0x01, 0xf0, 0x1f].repeat(900_000);
+        for width in [8, u32::MAX] {
+            let error = decompress_modified_huffman(&input, width).unwrap_err();
+            assert!(matches!(error, TiffError::Corrupt(_)), "{:?}", error);
+        }
+
+        // A horizontal mode run past the row
+        let codes = ["001", "000000011111", "0000000"];
+        assert!(decompress_t6(&pack(&codes), 8, 0).is_err());
+    }
+
+    #[test]
+    fn test_t4() {
+        // One-dimensional rows, then the return to control
+        let mut codes = vec![EOL, "0111", "11", "1011", EOL, "1011", "011"];
+        codes.extend_from_slice(&[EOL; 6]);
+        codes.push("1111");
+        let decoded = decompress_t4(&pack(&codes), 8, 0).unwrap();
+        assert_eq!(decoded, [0b0011_0000, 0b0000_1111]);
+
+        // A one-dimensional row, then rows coded against the row above with
+        // pass and horizontal modes, and with vertical modes
+        let codes = [
+            EOL, "1", "0111", "11", "1011", EOL, "0", "0001", "001", "0111", "11", EOL, "0", "1",
+            "010", "1",
+        ];
+        let decoded = decompress_t4(&pack(&codes), 8, 1).unwrap();
+        assert_eq!(decoded, [0b0011_0000, 0b0000_0011, 0b0000_0010]);
+
+        // A truncated second row is left out
+        let codes = [EOL, "0111", "11", "1011", EOL, "0111"];
+        assert_eq!(decompress_t4(&pack(&codes), 8, 0).unwrap(), [0b0011_0000]);
+        assert!(decompress_t4(&[], 8, 2).is_err());
+    }
+
+    #[test]
+    fn test_t6() {
+        // Rows coded with horizontal and vertical modes against a white row,
+        // with pass and horizontal modes, and with vertical modes, then the
+        // end of the block
+        let codes = [
+            "001", "0111", "11", "1", "0001", "001", "0111", "11", "1", "010", "1", EOL, EOL,
+            "1111",
+        ];
+        let decoded = decompress_t6(&pack(&codes), 8, 0).unwrap();
+        assert_eq!(decoded, [0b0011_0000, 0b0000_0011, 0b0000_0010]);
+
+        // A truncated row is left out
+        let codes = ["001", "0111", "11", "1", "001", "0111"];
+        assert_eq!(decompress_t6(&pack(&codes), 8, 0).unwrap(), [0b0011_0000]);
+        assert!(decompress_t6(&[], 8, 2).is_err());
+    }
+}
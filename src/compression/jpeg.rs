@@ -0,0 +1,82 @@
+//! Decompression of JPEG coded segments (`Compression` 7).
+use jpeg_decoder::{ColorTransform, Decoder, Error};
+
+use error::{CorruptError, Result, TiffError};
+
+/// Decompresses a JPEG stream, completing abbreviated streams with the
+/// `JPEGTables` of the image.
+///
+/// Components are returned as stored, chroma upsampled, so that `YCbCr`
+/// images stay in their color space like uncompressed ones.
+pub fn decompress(input: &[u8], tables: Option<&[u8]>) -> Result<Vec<u8>> {
+    let stream;
+    let input = match tables {
+        // Both are streams of their own: drop the end of the tables and the
+        // start of the segment
+        Some(tables) if tables.ends_with(&[0xff, 0xd9]) && input.starts_with(&[0xff, 0xd8]) => {
+            stream = [&tables[..tables.len() - 2], &input[2..]].concat();
+            &stream[..]
+        }
+        _ => input,
+    };
+
+    let mut decoder = Decoder::new(input);
+    decoder.set_color_transform(ColorTransform::None);
+    decoder.decode().map_err(|error| match error {
+        Error::Unsupported(_) => TiffError::Unsupported("JPEG coding process"),
+        Error::Io(error) => TiffError::Io(error),
+        _ => CorruptError::Invalid("Invalid JPEG data").into(),
+    })
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use image::Image;
+    use reader::tests::write_then_read;
+    use tag::*;
+    use value::TIFFValue;
+    use writer::Directory;
+
+    /// Tables with a DC quantizer of 64 and AC ones of 1, and a single code,
+    /// 0, for DC differences of category 1 and for the end of block.
+    pub fn tables() -> Vec<u8> {
+        let mut tables = vec![0xff, 0xd8, 0xff, 0xdb, 0x00, 0x43, 0x00, 64];
+        tables.extend_from_slice(&[1; 63]);
+        for (class, symbol) in &[(0x00, 1), (0x10, 0x00)] {
+            tables.extend_from_slice(&[0xff, 0xc4, 0x00, 0x14, *class, 1]);
+            tables.extend_from_slice(&[0; 15]);
+            tables.push(*symbol);
+        }
+        tables.extend_from_slice(&[0xff, 0xd9]);
+        tables
+    }
+
+    /// An abbreviated stream of an 8x8 grayscale block of 136: a DC
+    /// difference of 1, dequantized to 64.
+    pub const SEGMENT: [u8; 28] = [
+        0xff, 0xd8, 0xff, 0xc0, 0x00, 0x0b, 8, 0x00, 0x08, 0x00, 0x08, 1, 1, 0x11, 0, 0xff, 0xda,
+        0x00, 0x08, 1, 1, 0x00, 0, 63, 0, 0x5f, 0xff, 0xd9,
+    ];
+
+    #[test]
+    fn test_decompress() {
+        assert_eq!(decompress(&SEGMENT, Some(&tables())).unwrap(), vec![136; 64]);
+        assert!(decompress(&SEGMENT, None).is_err());
+        assert!(decompress(&[0xff, 0xd8, 0xff, 0xd9], None).is_err());
+    }
+
+    #[test]
+    fn test_read_jpeg() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(8));
+        directory.set_field(&ImageLength(8));
+        directory.set_field(&BitsPerSample(vec![8]));
+        directory.set_field(&PhotometricInterpretation::BlackIsZero);
+        directory.set(Tag::Compression, TIFFValue::Short(vec![7]));
+        directory.set(Tag::JPEGTables, TIFFValue::Undefined(tables()));
+        let mut read = write_then_read(&directory, &[SEGMENT.to_vec()]);
+        let data = Image::new(&mut read).unwrap().read_data().unwrap();
+        assert_eq!((data.data, data.rows), (vec![136; 64], 8));
+    }
+}
@@ -0,0 +1,233 @@
+//! Decompression of LZW coded segments (`Compression` 5).
+use error::{CorruptError, Result};
+
+const CLEAR_CODE: u16 = 256;
+const END_OF_INFORMATION: u16 = 257;
+const MAX_WIDTH: u32 = 12;
+
+/// A string of the code table: the code of its prefix, its last byte, its
+/// first byte and its length.
+#[derive(Clone, Copy)]
+struct Entry {
+    prefix: u16,
+    byte: u8,
+    first: u8,
+    length: u16,
+}
+
+/// Reads codes of increasing width from the input, most significant bit
+/// first, or least significant bit first for the old-style encoding.
+struct Codes<'a> {
+    input: &'a [u8],
+    position: usize,
+    buffer: u32,
+    available: u32,
+    lsb_first: bool,
+}
+
+impl<'a> Codes<'a> {
+    fn next(&mut self, width: u32) -> Option<u16> {
+        while self.available < width {
+            let byte = u32::from(*self.input.get(self.position)?);
+            self.position += 1;
+            if self.lsb_first {
+                self.buffer |= byte << self.available;
+            } else {
+                self.buffer = (self.buffer << 8) | byte;
+            }
+            self.available += 8;
+        }
+        let mask = (1 << width) - 1;
+        let code = if self.lsb_first {
+            let code = self.buffer & mask;
+            self.buffer >>= width;
+            code
+        } else {
+            (self.buffer >> (self.available - width)) & mask
+        };
+        self.available -= width;
+        Some(code as u16)
+    }
+}
+
+/// Decompresses an LZW coded segment into at most `size` bytes, where the
+/// rest of the segment is left out. Data cut short before the
+/// `EndOfInformation` code is returned as far as it goes.
+///
+/// Codes grow one code early, as written by every encoder since TIFF 6.0.
+/// Segments written by libtiff before that, whose codes are packed least
+/// significant bit first and grow on time, are detected and decoded too.
+pub fn decompress(input: &[u8], size: usize) -> Result<Vec<u8>> {
+    // The leading ClearCode of old-style segments starts with 0x00 0x01
+    let old_style = input.len() >= 2 && input[0] == 0 && input[1] & 1 == 1;
+    let early_change = if old_style { 0 } else { 1 };
+    let mut codes = Codes {
+        input,
+        position: 0,
+        buffer: 0,
+        available: 0,
+        lsb_first: old_style,
+    };
+
+    let mut table: Vec<Entry> = (0..=END_OF_INFORMATION)
+        .map(|code| Entry {
+            prefix: 0,
+            byte: code as u8,
+            first: code as u8,
+            length: 1,
+        })
+        .collect();
+    let mut output = Vec::with_capacity(input.len().saturating_mul(2).min(size));
+    let mut width = 9;
+    let mut previous: Option<u16> = None;
+
+    while output.len() < size {
+        let code = match codes.next(width) {
+            Some(code) => code,
+            None => break,
+        };
+        if code == CLEAR_CODE {
+            table.truncate(usize::from(END_OF_INFORMATION) + 1);
+            width = 9;
+            previous = None;
+            continue;
+        }
+        if code == END_OF_INFORMATION {
+            break;
+        }
+
+        let next = table.len();
+        let first = match previous {
+            None if code < CLEAR_CODE => code as u8,
+            None => return Err(CorruptError::Invalid("Invalid LZW code").into()),
+            Some(previous) => {
+                let first = match usize::from(code) {
+                    code if code < next => table[code].first,
+                    code if code == next => table[usize::from(previous)].first,
+                    _ => return Err(CorruptError::Invalid("Invalid LZW code").into()),
+                };
+                // A full table is only reset by a ClearCode
+                if next < 1 << MAX_WIDTH {
+                    let prefix = table[usize::from(previous)];
+                    table.push(Entry {
+                        prefix: previous,
+                        byte: first,
+                        first: prefix.first,
+                        length: prefix.length + 1,
+                    });
+                }
+                first
+            }
+        };
+        debug_assert_eq!(table[usize::from(code)].first, first);
+
+        // Strings are stored back to front, from their last byte, of which
+        // those past `size` are skipped
+        let length = usize::from(table[usize::from(code)].length);
+        let start = output.len();
+        let kept = length.min(size - start);
+        if output.capacity() - start < kept {
+            // Grow as a Vec would, without going past `size`
+            output.reserve_exact(output.capacity().max(kept).min(size - start));
+        }
+        output.resize(start + kept, 0);
+        let mut entry = code;
+        for _ in kept..length {
+            entry = table[usize::from(entry)].prefix;
+        }
+        for byte in output[start..].iter_mut().rev() {
+            *byte = table[usize::from(entry)].byte;
+            entry = table[usize::from(entry)].prefix;
+        }
+
+        previous = Some(code);
+        if table.len() + early_change >= 1 << width && width < MAX_WIDTH {
+            width += 1;
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packs codes of the given widths most significant bit first.
+    fn pack(codes: &[(u16, u32)]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let (mut buffer, mut available) = (0u32, 0);
+        for (code, width) in codes {
+            buffer = (buffer << width) | u32::from(*code);
+            available += width;
+            while available >= 8 {
+                bytes.push((buffer >> (available - 8)) as u8);
+                available -= 8;
+            }
+        }
+        if available > 0 {
+            bytes.push((buffer << (8 - available)) as u8);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_decompress() {
+        // "ABABABA": A, B, AB, ABA
+        let input = pack(&[(256, 9), (65, 9), (66, 9), (258, 9), (260, 9), (257, 9)]);
+        assert_eq!(decompress(&input, 64).unwrap(), b"ABABABA");
+        assert_eq!(decompress(&input, 6).unwrap(), b"ABABAB");
+
+        // Truncated before EndOfInformation
+        let input = pack(&[(256, 9), (65, 9), (66, 9)]);
+        assert_eq!(decompress(&input, 64).unwrap(), b"AB");
+        let input = pack(&[(256, 9), (65, 9), (300, 9)]);
+        assert!(decompress(&input, 64).is_err());
+    }
+
+    #[test]
+    fn test_early_change() {
+        // Every new code adds an entry, the width grows once 511 is next
+        let mut codes = vec![(256, 9), (0, 9)];
+        codes.extend((1..253).map(|value| (value, 9)));
+        assert_eq!(codes.len() + 256, 510);
+        codes.push((1, 9));
+        codes.push((2, 10));
+        codes.push((257, 10));
+        let decoded = decompress(&pack(&codes), 1024).unwrap();
+        assert_eq!(decoded.len(), 255);
+        assert_eq!(&decoded[250..], [250, 251, 252, 1, 2]);
+    }
+
+    #[test]
+    fn test_old_style() {
+        // "AAA" packed least significant bit first: A, then AA
+        let mut bytes = Vec::new();
+        let (mut buffer, mut available) = (0u32, 0);
+        for code in &[256u32, 65, 258, 257] {
+            buffer |= code << available;
+            available += 9;
+            while available >= 8 {
+                bytes.push(buffer as u8);
+                buffer >>= 8;
+                available -= 8;
+            }
+        }
+        bytes.push(buffer as u8);
+        assert_eq!(&bytes[..2], [0x00, 0x83]);
+        assert_eq!(decompress(&bytes, 64).unwrap(), b"AAA");
+    }
+
+    #[test]
+    fn test_decompression_bomb() {
+        // Zeros, then each code one zero longer than the previous: about
+        // 30 KB from 280 bytes
+        let mut codes = vec![(256, 9), (0, 9)];
+        codes.extend((258..500).map(|code| (code, 9)));
+        let input = pack(&codes);
+        assert_eq!(decompress(&input, 1 << 16).unwrap().len(), 29_646);
+
+        let decompressed = decompress(&input, 1000).unwrap();
+        assert_eq!(decompressed, [0; 1000]);
+        assert!(decompressed.capacity() <= 1000);
+    }
+}
@@ -0,0 +1,120 @@
+//! Decompression of strips and tiles, by `Compression` scheme.
+use error::{Result, TiffError};
+
+#[cfg(feature = "flate2")]
+mod deflate;
+mod fax;
+#[cfg(feature = "jpeg")]
+mod jpeg;
+mod lzw;
+#[cfg(feature = "jpeg")]
+mod ojpeg;
+#[cfg(feature = "webp")]
+mod webp;
+#[cfg(feature = "zstd")]
+mod zstd;
+
+#[cfg(feature = "jpeg")]
+pub use self::ojpeg::OldJpeg;
+
+/// How the segments of an image are decompressed.
+#[derive(Debug, Clone)]
+pub struct Codec {
+    compression: u16,
+    /// Width in pixels of the segments.
+    width: u32,
+    /// The `T4Options` or `T6Options` of fax segments.
+    fax_options: u32,
+    /// Whether fax segments store white pixels as 1 bits.
+    black_is_zero: bool,
+    /// The `JPEGTables` shared by JPEG segments.
+    jpeg_tables: Option<Vec<u8>>,
+    /// The tables and headers shared by old-style JPEG segments.
+    #[cfg(feature = "jpeg")]
+    old_jpeg: Option<OldJpeg>,
+}
+
+impl Codec {
+    /// Creates the codec of segments `width` pixels wide.
+    pub fn new(compression: u16, width: u32) -> Codec {
+        Codec {
+            compression,
+            width,
+            fax_options: 0,
+            black_is_zero: false,
+            jpeg_tables: None,
+            #[cfg(feature = "jpeg")]
+            old_jpeg: None,
+        }
+    }
+
+    /// Sets the `T4Options` or `T6Options` of fax segments, and whether
+    /// their image is `BlackIsZero` rather than `WhiteIsZero`.
+    pub fn set_fax_options(&mut self, options: u32, black_is_zero: bool) {
+        self.fax_options = options;
+        self.black_is_zero = black_is_zero;
+    }
+
+    /// Sets the `JPEGTables` completing the abbreviated streams of JPEG
+    /// segments.
+    pub fn set_jpeg_tables(&mut self, tables: Vec<u8>) {
+        self.jpeg_tables = Some(tables);
+    }
+
+    /// Sets the tables and headers completing old-style JPEG segments.
+    #[cfg(feature = "jpeg")]
+    pub fn set_old_jpeg(&mut self, jpeg: OldJpeg) {
+        self.old_jpeg = Some(jpeg);
+    }
+
+    /// The `Compression` scheme.
+    pub fn compression(&self) -> u16 {
+        self.compression
+    }
+
+    /// Decompresses a segment of `rows` rows, fewer than `RowsPerStrip` for
+    /// the last strip of an image, taking up `size` bytes once decompressed.
+    /// Data past `size` is dropped.
+    pub fn decompress(&self, bytes: Vec<u8>, rows: u32, size: usize) -> Result<Vec<u8>> {
+        let mut decompressed = match self.compression {
+            1 => return Ok(bytes),
+            2 => fax::decompress_modified_huffman(&bytes, self.width)?,
+            3 => fax::decompress_t4(&bytes, self.width, self.fax_options)?,
+            4 => fax::decompress_t6(&bytes, self.width, self.fax_options)?,
+            5 => return lzw::decompress(&bytes, size),
+            #[cfg(feature = "jpeg")]
+            6 => {
+                let missing = ::error::CorruptError::Invalid("Missing old-style JPEG tables");
+                let jpeg = self.old_jpeg.as_ref().ok_or(missing)?;
+                return ojpeg::decompress(&bytes, jpeg, rows);
+            }
+            #[cfg(feature = "jpeg")]
+            7 => return jpeg::decompress(&bytes, self.jpeg_tables.as_deref()),
+            #[cfg(not(feature = "jpeg"))]
+            6 | 7 => {
+                let _ = rows;
+                return Err(TiffError::Unsupported("JPEG without the jpeg feature"));
+            }
+            #[cfg(feature = "flate2")]
+            8 | 32946 => return deflate::decompress(&bytes, size),
+            #[cfg(not(feature = "flate2"))]
+            8 | 32946 => return Err(TiffError::Unsupported("Deflate without the flate2 feature")),
+            #[cfg(feature = "zstd")]
+            50000 => return zstd::decompress(&bytes),
+            #[cfg(not(feature = "zstd"))]
+            50000 => return Err(TiffError::Unsupported("Zstd without the zstd feature")),
+            #[cfg(feature = "webp")]
+            50001 => return webp::decompress(&bytes),
+            #[cfg(not(feature = "webp"))]
+            50001 => return Err(TiffError::Unsupported("WebP without the webp feature")),
+            _ => return Err(TiffError::Unsupported("Compression scheme")),
+        };
+        // Fax decoders write black pixels as 1 bits
+        if self.black_is_zero {
+            for byte in &mut decompressed {
+                *byte = !*byte;
+            }
+        }
+        Ok(decompressed)
+    }
+}
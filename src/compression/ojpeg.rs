@@ -0,0 +1,284 @@
+//! Decompression of old-style JPEG segments (`Compression` 6), whose JPEG
+//! stream is rebuilt from the tables of the directory.
+use std::io::{Read, Seek};
+
+use error::{CorruptError, Result, TiffError};
+use reader::TIFFReader;
+use tag::*;
+use value::TIFFValue;
+
+use super::jpeg;
+
+/// The parts of the JPEG stream shared by the segments of an old-style JPEG
+/// image.
+#[derive(Debug, Clone)]
+pub struct OldJpeg {
+    /// The quantization, Huffman and restart interval marker segments.
+    tables: Vec<u8>,
+    /// The identifier, sampling factors and quantization table of each
+    /// component of the frame.
+    components: Vec<[u8; 3]>,
+    /// The identifier and Huffman tables of each component of the scan.
+    scan: Vec<[u8; 2]>,
+    /// Size in pixels of the segments.
+    width: u16,
+    length: u16,
+}
+
+impl OldJpeg {
+    /// Reads the tables of the current directory of `reader`, for segments of
+    /// `width` x `length` pixels.
+    ///
+    /// They come from the JPEG interchange format stream when there is one,
+    /// and from `JPEGQTables`, `JPEGDCTables` and `JPEGACTables` otherwise.
+    /// Segments holding whole JPEG streams need none.
+    pub fn read<R: Read + Seek>(
+        reader: &mut TIFFReader<R>,
+        width: u32,
+        length: u32,
+    ) -> Result<OldJpeg> {
+        if width > 0xffff || length > 0xffff {
+            return Err(TiffError::Unsupported("Old-style JPEG segments over 65535 pixels"));
+        }
+        let unsigned = |value: Option<TIFFValue>| value.and_then(|v| v.unsigned_at(0));
+        if unsigned(reader.read_tag(Tag::JPEGProc)?).unwrap_or(1) != 1 {
+            return Err(TiffError::Unsupported("Lossless old-style JPEG"));
+        }
+        let mut jpeg = OldJpeg {
+            tables: Vec::new(),
+            components: Vec::new(),
+            scan: Vec::new(),
+            width: width as u16,
+            length: length as u16,
+        };
+
+        let offset = unsigned(reader.read_tag(Tag::JPEGInterchangeFormat)?);
+        let size = unsigned(reader.read_tag(Tag::JPEGInterchangeFormatLength)?);
+        if let (Some(offset), Some(size)) = (offset, size) {
+            let mut stream = Vec::new();
+            reader.read_bytes_into(offset, size, &mut stream)?;
+            jpeg.parse(&stream)?;
+        }
+
+        let mut offsets = |tag| -> Result<Vec<u64>> {
+            Ok(reader
+                .read_tag(tag)?
+                .and_then(|value| value.as_unsigned())
+                .unwrap_or_default())
+        };
+        let quantization = offsets(Tag::JPEGQTables)?;
+        let huffman = [(0x00, offsets(Tag::JPEGDCTables)?), (0x10, offsets(Tag::JPEGACTables)?)];
+        if jpeg.tables.is_empty() {
+            let mut bytes = Vec::new();
+            for (index, offset) in quantization.iter().enumerate() {
+                reader.read_bytes_into(*offset, 64, &mut bytes)?;
+                jpeg.push_quantization(index as u8, &bytes);
+            }
+            for (class, offsets) in &huffman {
+                for (index, offset) in offsets.iter().enumerate() {
+                    // 16 code counts by length, then the symbols
+                    reader.read_bytes_into(*offset, 16, &mut bytes)?;
+                    let symbols = bytes.iter().map(|count| u64::from(*count)).sum::<u64>();
+                    reader.read_bytes_into(*offset, 16 + symbols, &mut bytes)?;
+                    jpeg.push_huffman(class | index as u8, &bytes);
+                }
+            }
+            let interval = unsigned(reader.read_tag(Tag::JPEGRestartInterval)?).unwrap_or(0);
+            if interval > 0 {
+                let interval = (interval as u16).to_be_bytes();
+                jpeg.tables.extend_from_slice(&[0xff, 0xdd, 0x00, 0x04, interval[0], interval[1]]);
+            }
+        }
+        if jpeg.components.is_empty() {
+            let tables = (quantization.len(), huffman[0].1.len(), huffman[1].1.len());
+            jpeg.set_components(reader, tables)?;
+        }
+        Ok(jpeg)
+    }
+
+    /// Describes one component per sample, each with the tables of the same
+    /// index when there are enough of them. The chroma of `YCbCr` images is
+    /// subsampled according to `YCbCrSubSampling`.
+    fn set_components<R: Read + Seek>(
+        &mut self,
+        reader: &mut TIFFReader<R>,
+        (quantization, dc, ac): (usize, usize, usize),
+    ) -> Result<()> {
+        let samples = reader.get_field::<SamplesPerPixel>().unwrap_or_default().0;
+        let ycbcr = reader.get_field::<PhotometricInterpretation>()
+            == Some(PhotometricInterpretation::YCbCr);
+        let subsampling = reader.read_tag(Tag::YCbCrSubSampling)?;
+        let factor = |index| {
+            subsampling
+                .as_ref()
+                .and_then(|value| value.unsigned_at(index))
+                .map_or(2, |factor| factor.min(4) as u8)
+        };
+        let table = |index: u16, count: usize| index.min(count.max(1) as u16 - 1) as u8;
+        for index in 0..samples {
+            let sampling = if ycbcr && index == 0 {
+                factor(0) << 4 | factor(1)
+            } else {
+                0x11
+            };
+            let id = index as u8 + 1;
+            self.components
+                .push([id, sampling, table(index, quantization)]);
+            self.scan
+                .push([id, table(index, dc) << 4 | table(index, ac)]);
+        }
+        Ok(())
+    }
+
+    /// Adds a quantization table of 64 values in zigzag order.
+    fn push_quantization(&mut self, index: u8, values: &[u8]) {
+        self.tables.extend_from_slice(&[0xff, 0xdb, 0x00, 0x43, index]);
+        self.tables.extend_from_slice(values);
+    }
+
+    /// Adds a Huffman table of `class` and index, given as 16 code counts
+    /// by length followed by the symbols.
+    fn push_huffman(&mut self, class_index: u8, table: &[u8]) {
+        let length = (3 + table.len() as u16).to_be_bytes();
+        self.tables
+            .extend_from_slice(&[0xff, 0xc4, length[0], length[1], class_index]);
+        self.tables.extend_from_slice(table);
+    }
+
+    /// Collects the tables, frame and scan components of an interchange
+    /// format stream, up to its first scan.
+    fn parse(&mut self, stream: &[u8]) -> Result<()> {
+        let invalid = || CorruptError::Invalid("Invalid JPEG interchange format stream");
+        let mut position = 0;
+        while position + 2 <= stream.len() {
+            if stream[position] != 0xff {
+                return Err(invalid().into());
+            }
+            let marker = stream[position + 1];
+            match marker {
+                // Fill bytes and markers without a length
+                0xff => {
+                    position += 1;
+                    continue;
+                }
+                0xd8 | 0x01 | 0xd0..=0xd7 => {
+                    position += 2;
+                    continue;
+                }
+                0xd9 => break,
+                _ => {}
+            }
+            let length = stream
+                .get(position + 2..position + 4)
+                .map(|length| usize::from(u16::from_be_bytes([length[0], length[1]])))
+                .ok_or_else(invalid)?;
+            let segment = stream
+                .get(position..position + 2 + length)
+                .ok_or_else(invalid)?;
+            match marker {
+                0xdb | 0xc4 | 0xdd => self.tables.extend_from_slice(segment),
+                0xc0 | 0xc1 => {
+                    let count = usize::from(*segment.get(9).ok_or_else(invalid)?);
+                    let components = segment.get(10..10 + 3 * count).ok_or_else(invalid)?;
+                    self.components = components
+                        .chunks_exact(3)
+                        .map(|c| [c[0], c[1], c[2]])
+                        .collect();
+                }
+                0xda => {
+                    let count = usize::from(*segment.get(4).ok_or_else(invalid)?);
+                    let components = segment.get(5..5 + 2 * count).ok_or_else(invalid)?;
+                    self.scan = components.chunks_exact(2).map(|c| [c[0], c[1]]).collect();
+                    break;
+                }
+                _ => {}
+            }
+            position += 2 + length;
+        }
+        Ok(())
+    }
+}
+
+/// Decompresses an old-style JPEG segment of `rows` rows. Segments holding
+/// entropy coded data only are completed with the tables, frame and scan
+/// headers of the image, while those holding a JPEG stream of their own are
+/// decoded with the tables as defaults.
+pub fn decompress(input: &[u8], jpeg: &OldJpeg, rows: u32) -> Result<Vec<u8>> {
+    let mut stream = vec![0xff, 0xd8];
+    stream.extend_from_slice(&jpeg.tables);
+    if input.starts_with(&[0xff, 0xd8]) {
+        stream.extend_from_slice(&[0xff, 0xd9]);
+        return jpeg::decompress(input, Some(&stream));
+    }
+
+    // The last strip of an image may be shorter than the others
+    let length = rows.min(u32::from(jpeg.length)) as u16;
+    let (width, length) = (jpeg.width.to_be_bytes(), length.to_be_bytes());
+    let frame = (8 + 3 * jpeg.components.len() as u16).to_be_bytes();
+    stream.extend_from_slice(&[0xff, 0xc0, frame[0], frame[1], 8]);
+    stream.extend_from_slice(&[length[0], length[1], width[0], width[1]]);
+    stream.push(jpeg.components.len() as u8);
+    for component in &jpeg.components {
+        stream.extend_from_slice(component);
+    }
+    let scan = (6 + 2 * jpeg.scan.len() as u16).to_be_bytes();
+    stream.extend_from_slice(&[0xff, 0xda, scan[0], scan[1], jpeg.scan.len() as u8]);
+    for component in &jpeg.scan {
+        stream.extend_from_slice(component);
+    }
+    // Baseline spectral selection and no successive approximation
+    stream.extend_from_slice(&[0, 63, 0]);
+    stream.extend_from_slice(input);
+    stream.extend_from_slice(&[0xff, 0xd9]);
+    jpeg::decompress(&stream, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::jpeg::tests::{tables, SEGMENT};
+    use super::*;
+
+    fn old_jpeg() -> OldJpeg {
+        OldJpeg {
+            tables: Vec::new(),
+            components: Vec::new(),
+            scan: Vec::new(),
+            width: 8,
+            length: 8,
+        }
+    }
+
+    #[test]
+    fn test_rebuild_from_tables() {
+        let mut jpeg = old_jpeg();
+        let mut quantization = vec![64];
+        quantization.extend_from_slice(&[1; 63]);
+        jpeg.push_quantization(0, &quantization);
+        let mut dc = vec![0; 17];
+        dc[0] = 1;
+        dc[16] = 1;
+        jpeg.push_huffman(0x00, &dc);
+        dc[16] = 0;
+        jpeg.push_huffman(0x10, &dc);
+        jpeg.components.push([1, 0x11, 0]);
+        jpeg.scan.push([1, 0x00]);
+        assert_eq!(decompress(&[0x5f], &jpeg, 8).unwrap(), vec![136; 64]);
+
+        // A short last strip of 3 rows
+        assert_eq!(decompress(&[0x5f], &jpeg, 3).unwrap(), vec![136; 24]);
+    }
+
+    #[test]
+    fn test_rebuild_from_interchange_format() {
+        let tables = tables();
+        let stream = [&tables[..tables.len() - 2], &SEGMENT[2..]].concat();
+        let mut jpeg = old_jpeg();
+        jpeg.parse(&stream).unwrap();
+        assert_eq!((jpeg.components.len(), jpeg.scan.len()), (1, 1));
+        assert_eq!(decompress(&[0x5f], &jpeg, 8).unwrap(), vec![136; 64]);
+
+        // Segments with their own headers only take the tables
+        assert_eq!(decompress(&SEGMENT, &jpeg, 8).unwrap(), vec![136; 64]);
+        assert!(old_jpeg().parse(&[0xff, 0xd8, 0x00, 0x00]).is_err());
+    }
+}
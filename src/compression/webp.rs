@@ -0,0 +1,40 @@
+//! Decompression of WebP coded segments (`Compression` 50001).
+use std::io::Cursor;
+
+use image_webp::{DecodingError, WebPDecoder};
+
+use error::{CorruptError, Result, TiffError};
+
+/// Decodes a WebP image into interleaved 8-bit RGB samples, or RGBA ones when
+/// it has an alpha channel.
+pub fn decompress(input: &[u8]) -> Result<Vec<u8>> {
+    let error = |error| match error {
+        DecodingError::IoError(error) => TiffError::Io(error),
+        DecodingError::UnsupportedFeature(_) => TiffError::Unsupported("WebP feature"),
+        _ => CorruptError::Invalid("Invalid WebP data").into(),
+    };
+    let mut decoder = WebPDecoder::new(Cursor::new(input)).map_err(error)?;
+    let size = decoder
+        .output_buffer_size()
+        .ok_or(TiffError::Unsupported("WebP image too large"))?;
+    let mut output = vec![0; size];
+    decoder.read_image(&mut output).map_err(error)?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image_webp::{ColorType, WebPEncoder};
+
+    #[test]
+    fn test_decompress() {
+        let data: Vec<u8> = (0..4 * 3 * 4).map(|value| value as u8 * 5).collect();
+        let mut encoded = Vec::new();
+        WebPEncoder::new(&mut encoded)
+            .encode(&data, 4, 3, ColorType::Rgba8)
+            .unwrap();
+        assert_eq!(decompress(&encoded).unwrap(), data);
+        assert!(decompress(&encoded[..12]).is_err());
+    }
+}
@@ -0,0 +1,33 @@
+//! Decompression of Zstandard coded segments (`Compression` 50000).
+use std::io::Read;
+
+use ruzstd::decoding::StreamingDecoder;
+
+use error::{CorruptError, Result};
+
+/// Decompresses a Zstandard frame. Data cut short before the end of the frame
+/// is returned as far as it goes.
+pub fn decompress(mut input: &[u8]) -> Result<Vec<u8>> {
+    let invalid = || CorruptError::Invalid("Invalid Zstd data");
+    let mut decoder = StreamingDecoder::new(&mut input).map_err(|_| invalid())?;
+    let mut output = Vec::new();
+    match decoder.read_to_end(&mut output) {
+        Err(_) if output.is_empty() => Err(invalid().into()),
+        _ => Ok(output),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ruzstd::encoding::{compress_to_vec, CompressionLevel};
+
+    #[test]
+    fn test_decompress() {
+        let data: Vec<u8> = (0..20_000u32).map(|value| (value % 251) as u8).collect();
+        let compressed = compress_to_vec(&data[..], CompressionLevel::Fastest);
+        assert_eq!(decompress(&compressed).unwrap(), data);
+        assert!(decompress(&[0x28, 0xb5, 0x2f]).is_err());
+        assert!(decompress(&[0xff; 16]).is_err());
+    }
+}
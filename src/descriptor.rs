@@ -0,0 +1,278 @@
+//! This module maps tags to runtime field descriptors, for tools driven by
+//! data rather than by the `Field` types known at compile time.
+use std::collections::BTreeMap;
+use tag::{Field, Tag};
+use value::{Rational, TIFFValue};
+
+/// An object-safe counterpart of `Field`, converting the value of a tag to
+/// and from text.
+pub trait DynField {
+    /// The tag the field is stored under.
+    fn tag(&self) -> Tag;
+
+    /// The name of the field.
+    fn name(&self) -> &str;
+
+    /// Formats `value` as text, or returns `None` when it is not a valid
+    /// value of the field.
+    fn decode(&self, value: &TIFFValue) -> Option<String>;
+
+    /// Parses `text` into a value of the field.
+    fn encode(&self, text: &str) -> Option<TIFFValue>;
+}
+
+type Decode = Box<dyn Fn(&TIFFValue) -> Option<String>>;
+type Encode = Box<dyn Fn(&str) -> Option<TIFFValue>>;
+
+/// A `DynField` made of a decoding and an encoding closure.
+pub struct FieldDescriptor {
+    tag: Tag,
+    name: String,
+    decode: Decode,
+    encode: Encode,
+}
+
+impl FieldDescriptor {
+    /// Creates a descriptor of `tag` from its conversion closures.
+    pub fn new<D, E>(tag: Tag, name: &str, decode: D, encode: E) -> FieldDescriptor
+    where
+        D: Fn(&TIFFValue) -> Option<String> + 'static,
+        E: Fn(&str) -> Option<TIFFValue> + 'static,
+    {
+        FieldDescriptor {
+            tag,
+            name: name.to_string(),
+            decode: Box::new(decode),
+            encode: Box::new(encode),
+        }
+    }
+
+    /// Describes the field `T`, converting its decoded form with `to_text`
+    /// and `from_text`.
+    pub fn for_field<T, D, E>(name: &str, to_text: D, from_text: E) -> FieldDescriptor
+    where
+        T: Field,
+        D: Fn(&T) -> String + 'static,
+        E: Fn(&str) -> Option<T> + 'static,
+    {
+        FieldDescriptor::new(
+            T::tag(),
+            name,
+            move |value| T::decode_from_value(value).map(|field| to_text(&field)),
+            move |text| from_text(text)?.encode_to_value(),
+        )
+    }
+
+    /// Describes an ASCII field.
+    pub fn ascii(tag: Tag) -> FieldDescriptor {
+        FieldDescriptor::new(
+            tag,
+            &format!("{:?}", tag),
+            |value| match value {
+                TIFFValue::Ascii(strings) => strings.first().cloned(),
+                _ => None,
+            },
+            |text| Some(TIFFValue::Ascii(vec![text.to_string()])),
+        )
+    }
+
+    /// Describes a field of unsigned integers, written as comma separated
+    /// numbers, and stored as shorts when they all fit.
+    pub fn unsigned(tag: Tag) -> FieldDescriptor {
+        FieldDescriptor::new(
+            tag,
+            &format!("{:?}", tag),
+            |value| Some(join(value.as_unsigned()?.iter())),
+            |text| {
+                let numbers = split::<u32>(text)?;
+                if numbers.iter().all(|number| *number <= u32::from(u16::MAX)) {
+                    Some(TIFFValue::Short(numbers.iter().map(|e| *e as u16).collect()))
+                } else {
+                    Some(TIFFValue::Long(numbers))
+                }
+            },
+        )
+    }
+
+    /// Describes a field of rationals, written as comma separated fractions
+    /// such as `300/1`.
+    pub fn rational(tag: Tag) -> FieldDescriptor {
+        FieldDescriptor::new(
+            tag,
+            &format!("{:?}", tag),
+            |value| match value {
+                TIFFValue::Rational(rationals) => Some(join(
+                    rationals.iter().map(|e| format!("{}/{}", e.num, e.denom)),
+                )),
+                _ => None,
+            },
+            |text| {
+                let rationals = text
+                    .split(',')
+                    .map(|fraction| {
+                        let mut parts = fraction.trim().splitn(2, '/');
+                        let num = parts.next()?.trim().parse().ok()?;
+                        let denom = match parts.next() {
+                            Some(denom) => denom.trim().parse().ok()?,
+                            None => 1,
+                        };
+                        Some(Rational { num, denom })
+                    })
+                    .collect::<Option<_>>()?;
+                Some(TIFFValue::Rational(rationals))
+            },
+        )
+    }
+}
+
+fn join<T: ToString, I: Iterator<Item = T>>(values: I) -> String {
+    values.map(|e| e.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn split<T: ::std::str::FromStr>(text: &str) -> Option<Vec<T>> {
+    text.split(',').map(|e| e.trim().parse().ok()).collect()
+}
+
+impl DynField for FieldDescriptor {
+    fn tag(&self) -> Tag {
+        self.tag
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn decode(&self, value: &TIFFValue) -> Option<String> {
+        (self.decode)(value)
+    }
+
+    fn encode(&self, text: &str) -> Option<TIFFValue> {
+        (self.encode)(text)
+    }
+}
+
+/// A table of runtime field descriptors, indexed by tag.
+#[derive(Default)]
+pub struct FieldTable {
+    fields: BTreeMap<u16, Box<dyn DynField>>,
+}
+
+impl FieldTable {
+    /// Creates an empty table.
+    pub fn new() -> FieldTable {
+        FieldTable::default()
+    }
+
+    /// A table describing the common baseline fields.
+    pub fn baseline() -> FieldTable {
+        let mut table = FieldTable::new();
+        let unsigned = [
+            Tag::NewSubfileType,
+            Tag::ImageWidth,
+            Tag::ImageLength,
+            Tag::BitsPerSample,
+            Tag::Compression,
+            Tag::PhotometricInterpretation,
+            Tag::Orientation,
+            Tag::SamplesPerPixel,
+            Tag::RowsPerStrip,
+            Tag::PlanarConfiguration,
+            Tag::ResolutionUnit,
+            Tag::Predictor,
+            Tag::ExtraSamples,
+            Tag::SampleFormat,
+        ];
+        for tag in &unsigned {
+            table.insert(FieldDescriptor::unsigned(*tag));
+        }
+        for tag in &[Tag::XResolution, Tag::YResolution] {
+            table.insert(FieldDescriptor::rational(*tag));
+        }
+        let ascii = [
+            Tag::ImageDescription,
+            Tag::Make,
+            Tag::Model,
+            Tag::Software,
+            Tag::DateTime,
+            Tag::Artist,
+            Tag::HostComputer,
+            Tag::Copyright,
+        ];
+        for tag in &ascii {
+            table.insert(FieldDescriptor::ascii(*tag));
+        }
+        table
+    }
+
+    /// Adds `field`, replacing any descriptor of the same tag.
+    pub fn insert<F: DynField + 'static>(&mut self, field: F) {
+        self.fields.insert(u16::from(field.tag()), Box::new(field));
+    }
+
+    /// The descriptor of `tag`, if any.
+    pub fn get(&self, tag: Tag) -> Option<&dyn DynField> {
+        self.fields.get(&u16::from(tag)).map(|field| field.as_ref())
+    }
+
+    /// The descriptor named `name`, if any.
+    pub fn by_name(&self, name: &str) -> Option<&dyn DynField> {
+        self.iter().find(|field| field.name() == name)
+    }
+
+    /// The descriptors of the table, in ascending tag order.
+    pub fn iter(&self) -> impl Iterator<Item = &dyn DynField> + '_ {
+        self.fields.values().map(|field| field.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::TIFFReader;
+    use std::io::Cursor;
+    use tag::{ImageWidth, Orientation};
+
+    #[test]
+    fn test_baseline_table() {
+        let bytes: &[u8] = include_bytes!("../samples/arbitro_be.tiff");
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        let table = FieldTable::baseline();
+
+        let width = table.by_name("ImageWidth").unwrap();
+        let value = read.read_tag(width.tag()).unwrap().unwrap();
+        let width = width.decode(&value).unwrap();
+        assert_eq!(width, read.get_field::<ImageWidth>().unwrap().0.to_string());
+
+        let software = table.get(Tag::Software).unwrap();
+        assert!(software.decode(&TIFFValue::Short(vec![1])).is_none());
+        let value = software.encode("tiff").unwrap();
+        assert_eq!(software.decode(&value).unwrap(), "tiff");
+
+        let bits = table.get(Tag::BitsPerSample).unwrap();
+        assert_eq!(bits.decode(&bits.encode("8, 8,8").unwrap()).unwrap(), "8,8,8");
+        assert!(bits.encode("8,a").is_none());
+        let resolution = table.get(Tag::XResolution).unwrap();
+        let value = resolution.encode("300, 72/2").unwrap();
+        assert_eq!(resolution.decode(&value).unwrap(), "300/1,72/2");
+    }
+
+    #[test]
+    fn test_custom_field() {
+        let mut table = FieldTable::new();
+        table.insert(FieldDescriptor::for_field(
+            "Orientation",
+            |orientation: &Orientation| format!("{:?}", orientation),
+            |text| match text {
+                "RTopCLeft" => Some(Orientation::RTopCLeft),
+                "RBottomCRight" => Some(Orientation::RBottomCRight),
+                _ => None,
+            },
+        ));
+
+        let orientation = table.get(Tag::Orientation).unwrap();
+        let value = orientation.encode("RBottomCRight").unwrap();
+        assert_eq!(value.as_unsigned(), Some(vec![3]));
+        assert_eq!(orientation.decode(&value).unwrap(), "RBottomCRight");
+        assert_eq!(table.iter().count(), 1);
+    }
+}
@@ -0,0 +1,271 @@
+//! This module handles editing TIFF files as a whole.
+use error::Result;
+use reader::TIFFReader;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use tag::{Field, Tag};
+use value::TIFFValue;
+use writer::{Directory, SegmentSource, TIFFWriter};
+
+/// Fields holding offsets in the source file which are not carried over when
+/// saving, as the data they point to is not copied: `FreeOffsets`,
+/// `FreeByteCounts`, `SubIFDs`, `JPEGInterchangeFormat`, the JPEG tables, and
+/// the EXIF, GPS and interoperability directory pointers.
+const DROPPED_TAGS: [u16; 11] = [
+    0x120, 0x121, 0x14a, 0x201, 0x202, 0x207, 0x208, 0x209, 0x8769, 0x8825, 0xa005,
+];
+
+/// A page of a `TiffDocument`: the fields of its directory and its image data.
+pub struct Page {
+    directory: Directory,
+    segments: Vec<(u64, u64)>,
+}
+
+impl Page {
+    /// The value of `tag`, if present.
+    pub fn get(&self, tag: Tag) -> Option<&TIFFValue> {
+        self.directory.get(tag)
+    }
+
+    /// Decodes the field `T`, if present and valid.
+    pub fn get_field<T: Field>(&self) -> Option<T> {
+        self.directory.get_field()
+    }
+
+    /// Sets the value of `tag`, replacing any previous one.
+    ///
+    /// The strip or tile offsets and byte counts are computed when saving, so
+    /// setting them has no effect.
+    pub fn set(&mut self, tag: Tag, value: TIFFValue) {
+        self.directory.set(tag, value);
+    }
+
+    /// Sets the field `T`, replacing any previous value.
+    pub fn set_field<T: Field>(&mut self, field: &T) {
+        self.directory.set_field(field);
+    }
+
+    /// Removes `tag`, returning its value.
+    pub fn remove(&mut self, tag: Tag) -> Option<TIFFValue> {
+        self.directory.remove(tag)
+    }
+
+    /// The tags of the page, in ascending order.
+    pub fn tags(&self) -> impl Iterator<Item = Tag> + '_ {
+        self.directory.tags()
+    }
+}
+
+/// Streams the segments of a page from the source file into the output.
+struct PageSegments<'a, R: 'a> {
+    reader: &'a mut TIFFReader<R>,
+    segments: &'a [(u64, u64)],
+}
+
+impl<'a, R: Read + Seek> SegmentSource for PageSegments<'a, R> {
+    fn segment(&mut self, index: usize) -> Result<Vec<u8>> {
+        let (offset, count) = self.segments[index];
+        let mut buffer = Vec::new();
+        self.reader.read_bytes_into(offset, count, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn write_segment(&mut self, index: usize, output: &mut dyn Write) -> Result<u64> {
+        let (offset, count) = self.segments[index];
+        self.reader.copy_bytes(offset, count, output)?;
+        Ok(count)
+    }
+}
+
+/// A TIFF file loaded for editing.
+///
+/// The fields of every page are read when the document is created and can be
+/// inspected and modified in memory, while the image data stays in the source
+/// until the document is saved.
+pub struct TiffDocument<R> {
+    reader: TIFFReader<R>,
+    pages: Vec<Page>,
+    path: Option<PathBuf>,
+}
+
+impl<R: Read + Seek> TiffDocument<R> {
+    /// Loads the document stored in `reader`.
+    pub fn new(reader: R) -> Result<TiffDocument<R>> {
+        TiffDocument::from_reader(TIFFReader::new(reader)?)
+    }
+
+    /// Loads the document read by `reader`, keeping its options and limits.
+    pub fn from_reader(mut reader: TIFFReader<R>) -> Result<TiffDocument<R>> {
+        let mut pages = Vec::with_capacity(reader.ifds().len());
+        for index in 0..reader.ifds().len() {
+            reader.select_directory(index)?;
+            let tags: Vec<Tag> = reader.ifds()[index].all_tags().collect();
+
+            let (offsets_tag, counts_tag) = if tags.contains(&Tag::TileOffsets) {
+                (Tag::TileOffsets, Tag::TileByteCounts)
+            } else {
+                (Tag::StripOffsets, Tag::StripByteCounts)
+            };
+            let offsets = reader.read_unsigned(offsets_tag)?;
+            let counts = reader.read_unsigned(counts_tag)?;
+
+            let mut directory = Directory::new();
+            for tag in tags {
+                if tag == offsets_tag || tag == counts_tag || DROPPED_TAGS.contains(&tag.into()) {
+                    continue;
+                }
+                if let Some(value) = reader.read_tag(tag)? {
+                    directory.set(tag, value);
+                }
+            }
+
+            pages.push(Page {
+                directory,
+                segments: offsets.into_iter().zip(counts).collect(),
+            });
+        }
+
+        Ok(TiffDocument {
+            reader,
+            pages,
+            path: None,
+        })
+    }
+
+    /// The pages of the document.
+    pub fn pages(&self) -> &[Page] {
+        &self.pages
+    }
+
+    /// The pages of the document, which can be modified, removed or reordered.
+    pub fn pages_mut(&mut self) -> &mut Vec<Page> {
+        &mut self.pages
+    }
+
+    /// Writes the document to `output`, in the byte order of the source file,
+    /// and returns it.
+    ///
+    /// The copy stops between strips or tiles when the cancellation token of
+    /// the reader's options is cancelled.
+    ///
+    /// Strips and tiles are copied in small chunks, so memory use does not
+    /// depend on their size.
+    pub fn write_to<W: Write + Seek>(&mut self, output: W) -> Result<W> {
+        let mut writer = TIFFWriter::new(output, self.reader.endianness())?;
+        writer.set_cancellation_token(self.reader.options().cancellation.clone());
+        for page in &self.pages {
+            let source = PageSegments {
+                reader: &mut self.reader,
+                segments: &page.segments,
+            };
+            writer.write_image(&page.directory, page.segments.len(), source)?;
+        }
+        Ok(writer.into_inner())
+    }
+
+    /// Saves the document to a new file at `path`.
+    pub fn save_as<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let mut file = self.write_to(File::create(path)?)?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+impl TiffDocument<File> {
+    /// Loads the document stored in the file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<TiffDocument<File>> {
+        let mut document = TiffDocument::new(File::open(&path)?)?;
+        document.path = Some(path.as_ref().to_path_buf());
+        Ok(document)
+    }
+
+    /// Saves the document over the file it was opened from.
+    ///
+    /// The document is written to a temporary file next to it first, which
+    /// then replaces the original, so a failure leaves the original intact.
+    pub fn save(&mut self) -> Result<()> {
+        let path = match self.path.clone() {
+            Some(path) => path,
+            None => {
+                let message = "The document was not opened from a path";
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message).into());
+            }
+        };
+
+        let mut temporary = OsString::from(&path);
+        temporary.push(".tmp");
+        self.save_as(&temporary)?;
+        fs::rename(&temporary, &path)?;
+
+        // The image data now lives in the new file
+        *self = TiffDocument::open(&path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use limits::Limits;
+    use std::io::Cursor;
+    use tag::*;
+
+    #[test]
+    fn test_edit_document() {
+        let bytes: &[u8] = include_bytes!("../samples/arbitro_be.tiff");
+        let mut document = TiffDocument::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(document.pages().len(), 1);
+        document.pages_mut()[0].set_field(&Software("tiff".to_string()));
+        document.pages_mut()[0].remove(Tag::DateTime);
+
+        let output = document.write_to(Cursor::new(Vec::new())).unwrap();
+        let mut read = TIFFReader::new(Cursor::new(output.into_inner())).unwrap();
+        let mut source = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.endianness(), source.endianness());
+        assert_eq!(read.get_field::<Software>().unwrap().0, "tiff");
+        assert!(read.get_field::<DateTime>().is_none());
+        assert_eq!(
+            read.get_field::<ImageWidth>().unwrap().0,
+            source.get_field::<ImageWidth>().unwrap().0
+        );
+        for index in 0..source.strip_count().unwrap() {
+            assert_eq!(read.read_strip(index).unwrap(), source.read_strip(index).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_copy_large_strips() {
+        // The single 6391 bytes strip is above the value size limit
+        let bytes: &[u8] = include_bytes!("../samples/arbitro_be.tiff");
+        let limits = Limits {
+            max_value_size: 1024,
+            ..Limits::default()
+        };
+        let reader = TIFFReader::with_limits(Cursor::new(bytes), limits).unwrap();
+        let mut document = TiffDocument::from_reader(reader).unwrap();
+
+        let output = document.write_to(Cursor::new(Vec::new())).unwrap();
+        let mut read = TIFFReader::new(Cursor::new(output.into_inner())).unwrap();
+        let mut source = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.read_strip(0).unwrap(), source.read_strip(0).unwrap());
+    }
+
+    #[test]
+    fn test_save() {
+        let path = env::temp_dir().join("tiff_document_test_save.tiff");
+        fs::write(&path, &include_bytes!("../samples/picoawards_le.tiff")[..]).unwrap();
+
+        let mut document = TiffDocument::open(&path).unwrap();
+        document.pages_mut()[0].set_field(&ImageDescription("Someone".to_string()));
+        document.save().unwrap();
+        assert_eq!(document.pages()[0].get_field::<ImageDescription>().unwrap().0, "Someone");
+
+        let mut read = TIFFReader::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(read.get_field::<ImageDescription>().unwrap().0, "Someone");
+        assert_eq!(read.strip_count().unwrap(), 61);
+        fs::remove_file(&path).unwrap();
+    }
+}
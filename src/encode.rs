@@ -0,0 +1,457 @@
+//! This module encodes pixel buffers into complete uncompressed images.
+use error::Result;
+use planar::deinterleave;
+use quantize::quantize;
+use std::io::{Seek, Write};
+use tag::*;
+use value::TIFFValue;
+use writer::{invalid_input, Directory, TIFFWriter};
+
+/// Approximate size in bytes of the strips written from pixel buffers.
+const STRIP_SIZE: usize = 8192;
+
+/// How `TIFFWriter::write_bilevel` converts gray levels to black and white.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Bilevel {
+    /// Pixels at least as bright as the threshold become white.
+    Threshold(u8),
+    /// Floyd–Steinberg error diffusion, which preserves the average gray
+    /// levels of photographs.
+    FloydSteinberg,
+}
+
+/// Converts 8-bit gray levels to rows of bits, most significant first, where
+/// 1 is white.
+fn to_bilevel(gray: &[u8], width: usize, method: Bilevel) -> Vec<u8> {
+    let row_bytes = width.div_ceil(8);
+    let mut bits = vec![0; row_bytes * (gray.len() / width)];
+
+    // Errors diffused to the current and next rows, with a column of margin
+    // on each side
+    let mut current = vec![0i32; width + 2];
+    let mut next = vec![0i32; width + 2];
+    for (pixels, output) in gray.chunks_exact(width).zip(bits.chunks_exact_mut(row_bytes)) {
+        for (x, pixel) in pixels.iter().enumerate() {
+            let white = match method {
+                Bilevel::Threshold(threshold) => *pixel >= threshold,
+                Bilevel::FloydSteinberg => {
+                    let value = i32::from(*pixel) + current[x + 1] / 16;
+                    let white = value >= 128;
+                    let error = value - if white { 255 } else { 0 };
+                    current[x + 2] += error * 7;
+                    next[x] += error * 3;
+                    next[x + 1] += error * 5;
+                    next[x + 2] += error;
+                    white
+                }
+            };
+            if white {
+                output[x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        ::std::mem::swap(&mut current, &mut next);
+        next.iter_mut().for_each(|error| *error = 0);
+    }
+    bits
+}
+
+/// Scales the color samples of chunky `R G B A` pixels by their alpha.
+fn premultiply(rgba: &[u8]) -> Vec<u8> {
+    let mut premultiplied = Vec::with_capacity(rgba.len());
+    for pixel in rgba.chunks_exact(4) {
+        let alpha = u32::from(pixel[3]);
+        for color in &pixel[..3] {
+            premultiplied.push(((u32::from(*color) * alpha + 127) / 255) as u8);
+        }
+        premultiplied.push(pixel[3]);
+    }
+    premultiplied
+}
+
+impl<W: Write + Seek> TIFFWriter<W> {
+    /// Writes `data`, chunky samples of `bits` bits with `samples` per pixel
+    /// and rows starting on a byte boundary, completing a copy of `directory`
+    /// with the layout fields.
+    fn write_samples(
+        &mut self,
+        directory: &Directory,
+        width: u32,
+        length: u32,
+        (samples, bits): (u16, u16),
+        data: &[u8],
+    ) -> Result<u32> {
+        let row_bits = u64::from(width) * u64::from(samples) * u64::from(bits);
+        let row_bytes = row_bits.div_ceil(8) as usize;
+        if row_bytes == 0 || length == 0 || data.len() != row_bytes * length as usize {
+            return Err(invalid_input("Buffer size does not match the image dimensions").into());
+        }
+        let rows_per_strip = (STRIP_SIZE / row_bytes).clamp(1, length as usize);
+
+        let mut directory = directory.clone();
+        directory.set_field(&ImageWidth(width));
+        directory.set_field(&ImageLength(length));
+        directory.set_field(&BitsPerSample(vec![bits; usize::from(samples)]));
+        directory.set_field(&Compression::NoCompression);
+        directory.set_field(&SamplesPerPixel(samples));
+        directory.set_field(&RowsPerStrip(rows_per_strip as u32));
+        directory.set_field(&PlanarConfiguration::Chunky);
+
+        let strips: Vec<&[u8]> = data.chunks(rows_per_strip * row_bytes).collect();
+        self.write_image(&directory, strips.len(), |index: usize| Ok(strips[index].to_vec()))
+    }
+
+    /// Writes an image from chunky pixels with samples of `bits_per_sample`
+    /// bits and rows starting on a byte boundary, stored band after band in
+    /// the planar configuration.
+    ///
+    /// The layout fields of `directory` are overwritten, its other fields,
+    /// such as the photometric interpretation, are kept. Returns the offset
+    /// of the directory written.
+    pub fn write_planar(
+        &mut self,
+        directory: &Directory,
+        width: u32,
+        length: u32,
+        bits_per_sample: &[u16],
+        chunky: &[u8],
+    ) -> Result<u32> {
+        let pixel_bits: u64 = bits_per_sample.iter().map(|bits| u64::from(*bits)).sum();
+        let row_bytes = (u64::from(width) * pixel_bits).div_ceil(8) as usize;
+        if row_bytes == 0 || length == 0 || chunky.len() != row_bytes * length as usize {
+            return Err(invalid_input("Buffer size does not match the image dimensions").into());
+        }
+        let planes = deinterleave(chunky, bits_per_sample, width, length)?;
+
+        // Every plane is split at the same rows
+        let widest = planes.iter().map(|plane| plane.len()).max().unwrap_or(0) / length as usize;
+        let rows_per_strip = (STRIP_SIZE / widest.max(1)).clamp(1, length as usize);
+        let strips: Vec<&[u8]> = planes
+            .iter()
+            .flat_map(|plane| plane.chunks(rows_per_strip * plane.len() / length as usize))
+            .collect();
+
+        let mut directory = directory.clone();
+        directory.set_field(&ImageWidth(width));
+        directory.set_field(&ImageLength(length));
+        directory.set_field(&BitsPerSample(bits_per_sample.to_vec()));
+        directory.set_field(&Compression::NoCompression);
+        directory.set_field(&SamplesPerPixel(bits_per_sample.len() as u16));
+        directory.set_field(&RowsPerStrip(rows_per_strip as u32));
+        directory.set_field(&PlanarConfiguration::Planar);
+        self.write_image(&directory, strips.len(), |index: usize| Ok(strips[index].to_vec()))
+    }
+
+    /// Writes an image from chunky 8-bit `R G B A` pixels, with the alpha
+    /// marked as associated, ready for compositing.
+    ///
+    /// When `premultiply` is set, the colors of `rgba` are straight and get
+    /// multiplied by the alpha, otherwise they must already be. The layout
+    /// and color fields of `directory` are overwritten, its other fields are
+    /// kept. Returns the offset of the directory written.
+    pub fn write_rgba(
+        &mut self,
+        directory: &Directory,
+        width: u32,
+        length: u32,
+        rgba: &[u8],
+        premultiply: bool,
+    ) -> Result<u32> {
+        let mut directory = directory.clone();
+        directory.set_field(&PhotometricInterpretation::RGB);
+        directory.set_field(&ExtraSamples(vec![ExtraSampleDataValue::AssociatedAlpha]));
+
+        if premultiply {
+            let premultiplied = self::premultiply(rgba);
+            self.write_samples(&directory, width, length, (4, 8), &premultiplied)
+        } else {
+            self.write_samples(&directory, width, length, (4, 8), rgba)
+        }
+    }
+
+    /// Writes a separated image from chunky 8-bit `C M Y K` pixels, with the
+    /// matching `InkSet`, `NumberOfInks` and `InkNames`.
+    ///
+    /// `dot_range` gives the sample values of a 0% and a 100% dot, when they
+    /// are not 0 and 255.
+    pub fn write_cmyk(
+        &mut self,
+        directory: &Directory,
+        width: u32,
+        length: u32,
+        cmyk: &[u8],
+        dot_range: Option<(u8, u8)>,
+    ) -> Result<u32> {
+        let inks = ["Cyan", "Magenta", "Yellow", "Black"];
+        self.write_separated(directory, width, length, &inks, cmyk, dot_range)
+    }
+
+    /// Writes a separated image from chunky 8-bit pixels with a sample for
+    /// each of `inks`, such as CMYK plus spot colors.
+    ///
+    /// `InkSet` is `CMYK` only for the four process inks, and `InkNames`
+    /// always lists the inks in sample order.
+    pub fn write_separated(
+        &mut self,
+        directory: &Directory,
+        width: u32,
+        length: u32,
+        inks: &[&str],
+        data: &[u8],
+        dot_range: Option<(u8, u8)>,
+    ) -> Result<u32> {
+        if inks.is_empty() || inks.len() > usize::from(u16::MAX) {
+            return Err(invalid_input("Invalid number of inks").into());
+        }
+        if inks.iter().any(|ink| ink.is_empty() || ink.contains('\0')) {
+            return Err(invalid_input("Invalid ink name").into());
+        }
+
+        let mut directory = directory.clone();
+        let ink_set = if inks == ["Cyan", "Magenta", "Yellow", "Black"] {
+            InkSet::CMYK
+        } else {
+            InkSet::NotCMYK
+        };
+        directory.set_field(&PhotometricInterpretation::CMYK);
+        directory.set_field(&ink_set);
+        directory.set_field(&NumberOfInks(inks.len() as u16));
+        let names = inks.iter().map(|ink| ink.to_string()).collect();
+        directory.set(Tag::InkNames, TIFFValue::Ascii(names));
+        match dot_range {
+            Some((zero, full)) => {
+                let dot_range = vec![u16::from(zero), u16::from(full)];
+                directory.set(Tag::DotRange, TIFFValue::Short(dot_range))
+            }
+            None => {
+                directory.remove(Tag::DotRange);
+            }
+        }
+        directory.remove(Tag::ExtraSamples);
+
+        let samples = inks.len() as u16;
+        self.write_samples(&directory, width, length, (samples, 8), data)
+    }
+
+    /// Writes 8-bit gray levels as a bilevel image, with one bit per pixel,
+    /// for fax and document export.
+    ///
+    /// `Threshholding` records whether the gray levels were thresholded or
+    /// dithered.
+    pub fn write_bilevel(
+        &mut self,
+        directory: &Directory,
+        width: u32,
+        length: u32,
+        gray: &[u8],
+        method: Bilevel,
+    ) -> Result<u32> {
+        if width == 0 || gray.len() as u64 != u64::from(width) * u64::from(length) {
+            return Err(invalid_input("Buffer size does not match the image dimensions").into());
+        }
+        let bits = to_bilevel(gray, width as usize, method);
+
+        let mut directory = directory.clone();
+        directory.set_field(&PhotometricInterpretation::BlackIsZero);
+        let threshholding = match method {
+            Bilevel::Threshold(_) => 1,
+            Bilevel::FloydSteinberg => 3,
+        };
+        directory.set_field(&Threshholding(threshholding));
+        directory.remove(Tag::ExtraSamples);
+        self.write_samples(&directory, width, length, (1, 1), &bits)
+    }
+
+    /// Writes chunky 8-bit `R G B` pixels as a palette-color image of at most
+    /// `max_colors` colors, between 2 and 256.
+    ///
+    /// Images with more distinct colors are quantized with the median cut
+    /// algorithm, which suits screenshots and maps made of flat areas.
+    pub fn write_palette(
+        &mut self,
+        directory: &Directory,
+        width: u32,
+        length: u32,
+        rgb: &[u8],
+        max_colors: usize,
+    ) -> Result<u32> {
+        if !(2..=256).contains(&max_colors) {
+            return Err(invalid_input("The palette must have 2 to 256 colors").into());
+        }
+        if rgb.len() as u64 != 3 * u64::from(width) * u64::from(length) {
+            return Err(invalid_input("Buffer size does not match the image dimensions").into());
+        }
+        let (palette, pixels) = quantize(rgb, max_colors);
+
+        // All red values come first, then green and blue, scaled to 16 bits
+        let mut color_map = vec![0; 3 * 256];
+        for (index, color) in palette.iter().enumerate() {
+            for (channel, value) in color.iter().enumerate() {
+                color_map[channel * 256 + index] = u16::from(*value) * 257;
+            }
+        }
+
+        let mut directory = directory.clone();
+        directory.set_field(&PhotometricInterpretation::PaletteColor);
+        directory.set_field(&ColorMap(color_map));
+        directory.remove(Tag::ExtraSamples);
+        self.write_samples(&directory, width, length, (1, 8), &pixels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use endian::Endian;
+    use image::Image;
+    use reader::TIFFReader;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_write_rgba() {
+        let rgba = [255, 128, 0, 255, 200, 100, 50, 128, 10, 20, 30, 0];
+        let mut directory = Directory::new();
+        directory.set_field(&Software("tiff".to_string()));
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        writer.write_rgba(&directory, 3, 1, &rgba, true).unwrap();
+        assert!(writer.write_rgba(&directory, 2, 1, &rgba, true).is_err());
+
+        let bytes = writer.into_inner().into_inner();
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.get_field::<Software>().unwrap().0, "tiff");
+        assert_eq!(read.get_field::<SamplesPerPixel>().unwrap().0, 4);
+        assert_eq!(
+            read.get_field::<ExtraSamples>().unwrap().0,
+            [ExtraSampleDataValue::AssociatedAlpha]
+        );
+        let decoded = Image::new(&mut read).unwrap().read_data().unwrap();
+        assert_eq!(decoded.data, [255, 128, 0, 255, 100, 50, 25, 128, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_strip_layout() {
+        let rgba = vec![7; 64 * 100 * 4];
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Big).unwrap();
+        writer
+            .write_rgba(&Directory::new(), 64, 100, &rgba, false)
+            .unwrap();
+
+        let bytes = writer.into_inner().into_inner();
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.get_field::<RowsPerStrip>().unwrap().0, 32);
+        assert_eq!(read.strip_count().unwrap(), 4);
+        let decoded = Image::new(&mut read).unwrap().read_data().unwrap();
+        assert_eq!(decoded.data, rgba);
+    }
+
+    #[test]
+    fn test_write_planar() {
+        let chunky: Vec<u8> = (0..5000 * 3 * 2).map(|i| (i % 251) as u8).collect();
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        writer.write_planar(&Directory::new(), 5000, 3, &[8, 8], &chunky).unwrap();
+        // Two 4-bit bands of three pixels on two rows
+        let packed = [0x12, 0x34, 0x50, 0x67, 0x89, 0xa0];
+        writer.write_planar(&Directory::new(), 3, 2, &[4, 4], &packed).unwrap();
+        assert!(writer.write_planar(&Directory::new(), 3, 3, &[4, 4], &packed).is_err());
+
+        let bytes = writer.into_inner().into_inner();
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.get_field::<PlanarConfiguration>(), Some(PlanarConfiguration::Planar));
+        assert_eq!(read.strip_count().unwrap(), 6);
+        assert_eq!(read.read_strip(3).unwrap()[..2], [chunky[1], chunky[3]]);
+        assert_eq!(Image::new(&mut read).unwrap().read_data().unwrap().data, chunky);
+
+        read.select_directory(1).unwrap();
+        assert_eq!(read.read_strip(0).unwrap(), [0x13, 0x50, 0x68, 0xa0]);
+        assert_eq!(Image::new(&mut read).unwrap().read_data().unwrap().data, packed);
+    }
+
+    #[test]
+    fn test_write_separated() {
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        let cmyk = [0, 64, 128, 255, 10, 20, 30, 40];
+        writer.write_cmyk(&Directory::new(), 2, 1, &cmyk, None).unwrap();
+        let inks = ["Cyan", "Magenta", "Yellow", "Black", "PANTONE 185 C"];
+        let data = [1, 2, 3, 4, 5];
+        writer
+            .write_separated(&Directory::new(), 1, 1, &inks, &data, Some((5, 250)))
+            .unwrap();
+        assert!(writer.write_separated(&Directory::new(), 1, 1, &[], &[], None).is_err());
+
+        let bytes = writer.into_inner().into_inner();
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(
+            read.get_field::<PhotometricInterpretation>(),
+            Some(PhotometricInterpretation::CMYK)
+        );
+        assert_eq!(read.get_field::<InkSet>(), Some(InkSet::CMYK));
+        assert_eq!(read.get_field::<NumberOfInks>().unwrap().0, 4);
+        assert!(read.read_tag(Tag::DotRange).unwrap().is_none());
+        let decoded = Image::new(&mut read).unwrap().read_data().unwrap();
+        assert_eq!(decoded.data, cmyk);
+
+        read.select_directory(1).unwrap();
+        assert_eq!(read.get_field::<InkSet>(), Some(InkSet::NotCMYK));
+        assert_eq!(read.get_field::<NumberOfInks>().unwrap().0, 5);
+        assert_eq!(read.get_field::<SamplesPerPixel>().unwrap().0, 5);
+        match read.read_tag(Tag::InkNames).unwrap().unwrap() {
+            TIFFValue::Ascii(names) => assert_eq!(names, inks),
+            value => panic!("Unexpected value {:?}", value),
+        }
+        let dot_range = read.read_tag(Tag::DotRange).unwrap().unwrap();
+        assert_eq!(dot_range.as_unsigned(), Some(vec![5, 250]));
+    }
+
+    #[test]
+    fn test_write_palette() {
+        let rgb = [255, 0, 0, 0, 0, 255, 255, 0, 0, 255, 255, 255];
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        writer.write_palette(&Directory::new(), 2, 2, &rgb, 256).unwrap();
+        assert!(writer.write_palette(&Directory::new(), 2, 2, &rgb, 1).is_err());
+
+        let bytes = writer.into_inner().into_inner();
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(
+            read.get_field::<PhotometricInterpretation>(),
+            Some(PhotometricInterpretation::PaletteColor)
+        );
+        assert_eq!(read.get_field::<SamplesPerPixel>().unwrap().0, 1);
+        let color_map = read.get_field::<ColorMap>().unwrap().0;
+        assert_eq!(color_map.len(), 768);
+
+        let indices = Image::new(&mut read).unwrap().read_data().unwrap().data;
+        assert_eq!(indices[0], indices[2]);
+        for (pixel, index) in rgb.chunks(3).zip(indices) {
+            let index = usize::from(index);
+            let color = [color_map[index], color_map[256 + index], color_map[512 + index]];
+            assert_eq!(color, [0, 1, 2].map(|channel| u16::from(pixel[channel]) * 257));
+        }
+    }
+
+    #[test]
+    fn test_to_bilevel() {
+        let gray = [0, 100, 200, 255, 127, 128, 0, 0, 0, 255];
+        assert_eq!(to_bilevel(&gray, 10, Bilevel::Threshold(128)), [0b0011_0100, 0b0100_0000]);
+
+        // A uniform mid gray dithers to as many white pixels as black ones
+        let gray = vec![128; 16 * 16];
+        let bits = to_bilevel(&gray, 16, Bilevel::FloydSteinberg);
+        let white: u32 = bits.iter().map(|byte| byte.count_ones()).sum();
+        assert!((120..=136).contains(&white));
+    }
+
+    #[test]
+    fn test_write_bilevel() {
+        let gray = [0, 255, 0, 255, 0, 255, 0, 255, 0, 255];
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Big).unwrap();
+        writer
+            .write_bilevel(&Directory::new(), 5, 2, &gray, Bilevel::FloydSteinberg)
+            .unwrap();
+
+        let bytes = writer.into_inner().into_inner();
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.get_field::<BitsPerSample>().unwrap().0, [1]);
+        assert_eq!(read.get_field::<Threshholding>().unwrap().0, 3);
+        let decoded = Image::new(&mut read).unwrap().read_data().unwrap();
+        assert_eq!(decoded.data, [0b0101_0000, 0b1010_1000]);
+    }
+}
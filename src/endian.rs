@@ -1,5 +1,5 @@
-//! This module handles endianness reading.
-use std::io::{Read, Result, Seek, SeekFrom};
+//! This module handles endianness reading and writing.
+use std::io::{Read, Result, Seek, SeekFrom, Write};
 
 /// A simple enum representing known endianness.
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -16,14 +16,22 @@ pub const LE: Endian = Endian::Little;
 pub trait Short: Copy + Sized {
     fn from_bytes_le(bytes: [u8; 2]) -> Self;
     fn from_bytes_be(bytes: [u8; 2]) -> Self;
+    fn to_bytes_le(self) -> [u8; 2];
+    fn to_bytes_be(self) -> [u8; 2];
 }
 
 impl Short for u16 {
     fn from_bytes_le(bytes: [u8; 2]) -> u16 {
-        u16::from_le(u16::from_bytes(bytes))
+        u16::from_le_bytes(bytes)
     }
     fn from_bytes_be(bytes: [u8; 2]) -> u16 {
-        u16::from_be(u16::from_bytes(bytes))
+        u16::from_be_bytes(bytes)
+    }
+    fn to_bytes_le(self) -> [u8; 2] {
+        self.to_le_bytes()
+    }
+    fn to_bytes_be(self) -> [u8; 2] {
+        self.to_be_bytes()
     }
 }
 
@@ -34,18 +42,32 @@ impl Short for i16 {
     fn from_bytes_be(bytes: [u8; 2]) -> i16 {
         i16::from_be_bytes(bytes)
     }
+    fn to_bytes_le(self) -> [u8; 2] {
+        self.to_le_bytes()
+    }
+    fn to_bytes_be(self) -> [u8; 2] {
+        self.to_be_bytes()
+    }
 }
 pub trait Long: Copy + Sized {
     fn from_bytes_le(bytes: [u8; 4]) -> Self;
     fn from_bytes_be(bytes: [u8; 4]) -> Self;
+    fn to_bytes_le(self) -> [u8; 4];
+    fn to_bytes_be(self) -> [u8; 4];
 }
 
 impl Long for u32 {
     fn from_bytes_le(bytes: [u8; 4]) -> u32 {
-        u32::from_le(u32::from_bytes(bytes))
+        u32::from_le_bytes(bytes)
     }
     fn from_bytes_be(bytes: [u8; 4]) -> u32 {
-        u32::from_be(u32::from_bytes(bytes))
+        u32::from_be_bytes(bytes)
+    }
+    fn to_bytes_le(self) -> [u8; 4] {
+        self.to_le_bytes()
+    }
+    fn to_bytes_be(self) -> [u8; 4] {
+        self.to_be_bytes()
     }
 }
 
@@ -56,19 +78,48 @@ impl Long for i32 {
     fn from_bytes_be(bytes: [u8; 4]) -> i32 {
         i32::from_be_bytes(bytes)
     }
+    fn to_bytes_le(self) -> [u8; 4] {
+        self.to_le_bytes()
+    }
+    fn to_bytes_be(self) -> [u8; 4] {
+        self.to_be_bytes()
+    }
+}
+
+impl Long for f32 {
+    fn from_bytes_le(bytes: [u8; 4]) -> f32 {
+        f32::from_le_bytes(bytes)
+    }
+    fn from_bytes_be(bytes: [u8; 4]) -> f32 {
+        f32::from_be_bytes(bytes)
+    }
+    fn to_bytes_le(self) -> [u8; 4] {
+        self.to_le_bytes()
+    }
+    fn to_bytes_be(self) -> [u8; 4] {
+        self.to_be_bytes()
+    }
 }
 
 pub trait LongLong: Copy + Sized {
     fn from_bytes_le(bytes: [u8; 8]) -> Self;
     fn from_bytes_be(bytes: [u8; 8]) -> Self;
+    fn to_bytes_le(self) -> [u8; 8];
+    fn to_bytes_be(self) -> [u8; 8];
 }
 
 impl LongLong for u64 {
     fn from_bytes_le(bytes: [u8; 8]) -> u64 {
-        u64::from_le(u64::from_bytes(bytes))
+        u64::from_le_bytes(bytes)
     }
     fn from_bytes_be(bytes: [u8; 8]) -> u64 {
-        u64::from_be(u64::from_bytes(bytes))
+        u64::from_be_bytes(bytes)
+    }
+    fn to_bytes_le(self) -> [u8; 8] {
+        self.to_le_bytes()
+    }
+    fn to_bytes_be(self) -> [u8; 8] {
+        self.to_be_bytes()
     }
 }
 
@@ -79,6 +130,27 @@ impl LongLong for i64 {
     fn from_bytes_be(bytes: [u8; 8]) -> i64 {
         i64::from_be_bytes(bytes)
     }
+    fn to_bytes_le(self) -> [u8; 8] {
+        self.to_le_bytes()
+    }
+    fn to_bytes_be(self) -> [u8; 8] {
+        self.to_be_bytes()
+    }
+}
+
+impl LongLong for f64 {
+    fn from_bytes_le(bytes: [u8; 8]) -> f64 {
+        f64::from_le_bytes(bytes)
+    }
+    fn from_bytes_be(bytes: [u8; 8]) -> f64 {
+        f64::from_be_bytes(bytes)
+    }
+    fn to_bytes_le(self) -> [u8; 8] {
+        self.to_le_bytes()
+    }
+    fn to_bytes_be(self) -> [u8; 8] {
+        self.to_be_bytes()
+    }
 }
 
 impl Endian {
@@ -102,6 +174,27 @@ impl Endian {
             Endian::Little => T::from_bytes_le(bytes),
         }
     }
+
+    pub fn short_to_bytes<T: Short>(self, value: T) -> [u8; 2] {
+        match self {
+            Endian::Big => value.to_bytes_be(),
+            Endian::Little => value.to_bytes_le(),
+        }
+    }
+
+    pub fn long_to_bytes<T: Long>(self, value: T) -> [u8; 4] {
+        match self {
+            Endian::Big => value.to_bytes_be(),
+            Endian::Little => value.to_bytes_le(),
+        }
+    }
+
+    pub fn longlong_to_bytes<T: LongLong>(self, value: T) -> [u8; 8] {
+        match self {
+            Endian::Big => value.to_bytes_be(),
+            Endian::Little => value.to_bytes_le(),
+        }
+    }
 }
 
 /// A reader aware of endianness
@@ -140,13 +233,86 @@ impl<'a, R: Read> EndianReader<'a, R> {
         Ok(self.endian.long_from_bytes(buf))
     }
 
-    /// Read long from the reader.
-    #[allow(dead_code)]
+    /// Read long long from the reader.
     pub fn read_longlong<T: LongLong>(&mut self) -> Result<T> {
         let mut buf: [u8; 8] = [0; 8];
         self.inner.read_exact(&mut buf)?;
         Ok(self.endian.longlong_from_bytes(buf))
     }
+
+    /// Read a 32-bit float from the reader.
+    pub fn read_float(&mut self) -> Result<f32> {
+        self.read_long()
+    }
+
+    /// Read a 64-bit float from the reader.
+    pub fn read_double(&mut self) -> Result<f64> {
+        self.read_longlong()
+    }
+}
+
+/// A writer aware of endianness
+pub struct EndianWriter<'a, W: 'a> {
+    inner: &'a mut W,
+    endian: Endian,
+}
+
+impl<'a, W: Seek> Seek for EndianWriter<'a, W> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl<'a, W: Write> EndianWriter<'a, W> {
+    /// Creates an `EndianWriter` from a specific writer
+    /// and `Endian` value.
+    pub fn new(writer: &'a mut W, endian: Endian) -> EndianWriter<'a, W> {
+        EndianWriter {
+            inner: writer,
+            endian,
+        }
+    }
+
+    /// Write a single byte to the writer.
+    pub fn write_byte(&mut self, value: u8) -> Result<()> {
+        self.inner.write_all(&[value])
+    }
+
+    /// Write raw bytes, unaffected by endianness, to the writer.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.inner.write_all(bytes)
+    }
+
+    /// Write short to the writer.
+    pub fn write_short<T: Short>(&mut self, value: T) -> Result<()> {
+        self.inner.write_all(&self.endian.short_to_bytes(value))
+    }
+
+    /// Write long to the writer.
+    pub fn write_long<T: Long>(&mut self, value: T) -> Result<()> {
+        self.inner.write_all(&self.endian.long_to_bytes(value))
+    }
+
+    /// Write long long to the writer.
+    pub fn write_longlong<T: LongLong>(&mut self, value: T) -> Result<()> {
+        self.inner.write_all(&self.endian.longlong_to_bytes(value))
+    }
+
+    /// Write a rational, numerator first, to the writer.
+    pub fn write_rational<T: Long>(&mut self, numerator: T, denominator: T) -> Result<()> {
+        self.write_long(numerator)?;
+        self.write_long(denominator)
+    }
+
+    /// Write a 32-bit float to the writer.
+    pub fn write_float(&mut self, value: f32) -> Result<()> {
+        self.write_long(value)
+    }
+
+    /// Write a 64-bit float to the writer.
+    pub fn write_double(&mut self, value: f64) -> Result<()> {
+        self.write_longlong(value)
+    }
 }
 
 #[cfg(test)]
@@ -170,4 +336,31 @@ mod tests {
             assert_eq!(0x66554433u32, le_reader.read_long().unwrap());
         }
     }
+
+    #[test]
+    fn test_writer() {
+        for &endian in &[Endian::Big, Endian::Little] {
+            let mut cursor = Cursor::new(Vec::new());
+            {
+                let mut writer = EndianWriter::new(&mut cursor, endian);
+                writer.write_short(0x1122u16).unwrap();
+                writer.write_long(-2i32).unwrap();
+                writer.write_longlong(0x1122334455667788u64).unwrap();
+                writer.write_rational(72u32, 1u32).unwrap();
+                writer.write_float(0.5).unwrap();
+                writer.write_double(-1.25).unwrap();
+            }
+            assert_eq!(cursor.get_ref().len(), 34);
+
+            cursor.set_position(0);
+            let mut reader = EndianReader::new(&mut cursor, endian);
+            assert_eq!(0x1122u16, reader.read_short().unwrap());
+            assert_eq!(-2i32, reader.read_long().unwrap());
+            assert_eq!(0x1122334455667788u64, reader.read_longlong().unwrap());
+            assert_eq!(72u32, reader.read_long().unwrap());
+            assert_eq!(1u32, reader.read_long().unwrap());
+            assert_eq!(0.5, reader.read_float().unwrap());
+            assert_eq!(-1.25, reader.read_double().unwrap());
+        }
+    }
 }
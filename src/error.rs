@@ -0,0 +1,175 @@
+//! Errors returned while reading TIFF files.
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::string::FromUtf8Error;
+
+/// The ways in which a file can violate the TIFF specification.
+#[derive(Debug)]
+pub enum CorruptError {
+    /// The structure of the file is invalid.
+    Invalid(&'static str),
+    /// An offset points past the end of the file.
+    OffsetBeyondEndOfFile(u64),
+    /// An ASCII value is not valid UTF-8.
+    InvalidAscii(FromUtf8Error),
+}
+
+impl Display for CorruptError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            CorruptError::Invalid(message) => write!(f, "{}", message),
+            CorruptError::OffsetBeyondEndOfFile(offset) => {
+                write!(f, "Offset {} is beyond the end of file", offset)
+            }
+            CorruptError::InvalidAscii(e) => write!(f, "Invalid ASCII value: {}", e),
+        }
+    }
+}
+
+impl Error for CorruptError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CorruptError::InvalidAscii(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// The broad category of a `TiffError`, to decide whether to retry, skip the
+/// file, or fall back to another library.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Reading or writing failed, retrying may succeed.
+    Io,
+    /// The file violates the specification or is truncated.
+    CorruptData,
+    /// The file relies on a feature this build does not implement, such as a
+    /// compression scheme.
+    UnsupportedFeature,
+    /// A resource limit was exceeded.
+    LimitExceeded,
+    /// The caller passed an invalid index or buffer.
+    InvalidArgument,
+    /// The operation was cancelled.
+    Cancelled,
+}
+
+/// The error type of the library.
+#[derive(Debug)]
+pub enum TiffError {
+    /// Reading the input failed.
+    Io(io::Error),
+    /// The file is malformed.
+    Corrupt(CorruptError),
+    /// The file relies on a feature this library does not implement.
+    Unsupported(&'static str),
+    /// A resource limit was exceeded.
+    Limit(&'static str),
+    /// The requested directory does not exist.
+    DirectoryIndexOutOfBounds,
+    /// The requested strip or tile does not exist.
+    SegmentIndexOutOfBounds,
+    /// The requested band does not exist.
+    BandIndexOutOfBounds,
+    /// The operation was stopped through its `CancellationToken`.
+    Cancelled,
+}
+
+impl Display for TiffError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TiffError::Io(e) => write!(f, "I/O error: {}", e),
+            TiffError::Corrupt(e) => write!(f, "Invalid TIFF file: {}", e),
+            TiffError::Unsupported(feature) => write!(f, "Unsupported feature: {}", feature),
+            TiffError::Limit(limit) => write!(f, "Resource limit exceeded: {}", limit),
+            TiffError::DirectoryIndexOutOfBounds => write!(f, "Directory index out of bounds"),
+            TiffError::SegmentIndexOutOfBounds => write!(f, "Segment index out of bounds"),
+            TiffError::BandIndexOutOfBounds => write!(f, "Band index out of bounds"),
+            TiffError::Cancelled => write!(f, "Operation cancelled"),
+        }
+    }
+}
+
+impl TiffError {
+    /// The category of the error.
+    ///
+    /// I/O errors caused by the end of the input being reached too early, or
+    /// by offsets a source cannot seek to, are reported as `CorruptData`, as
+    /// they come from defects of the file.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            TiffError::Io(e) => match e.kind() {
+                io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData => {
+                    ErrorKind::CorruptData
+                }
+                io::ErrorKind::InvalidInput => ErrorKind::InvalidArgument,
+                _ => ErrorKind::Io,
+            },
+            TiffError::Corrupt(_) => ErrorKind::CorruptData,
+            TiffError::Unsupported(_) => ErrorKind::UnsupportedFeature,
+            TiffError::Limit(_) => ErrorKind::LimitExceeded,
+            TiffError::DirectoryIndexOutOfBounds
+            | TiffError::SegmentIndexOutOfBounds
+            | TiffError::BandIndexOutOfBounds => ErrorKind::InvalidArgument,
+            TiffError::Cancelled => ErrorKind::Cancelled,
+        }
+    }
+}
+
+impl Error for TiffError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TiffError::Io(e) => Some(e),
+            TiffError::Corrupt(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TiffError {
+    fn from(e: io::Error) -> TiffError {
+        TiffError::Io(e)
+    }
+}
+
+impl From<CorruptError> for TiffError {
+    fn from(e: CorruptError) -> TiffError {
+        TiffError::Corrupt(e)
+    }
+}
+
+/// A specialized `Result` type for TIFF operations.
+pub type Result<T> = ::std::result::Result<T, TiffError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use limits::Limits;
+    use reader::TIFFReader;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_error_kind() {
+        let truncated = &include_bytes!("../samples/arbitro_be.tiff")[..100];
+        let error = TIFFReader::new(Cursor::new(truncated)).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::CorruptData);
+
+        let bytes: &[u8] = include_bytes!("../samples/picoawards_le.tiff");
+        let limits = Limits {
+            max_entries: 4,
+            ..Limits::default()
+        };
+        let error = TIFFReader::with_limits(Cursor::new(bytes), limits).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::LimitExceeded);
+
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        let error = read.select_directory(3).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidArgument);
+        assert_eq!(TiffError::Unsupported("LZW").kind(), ErrorKind::UnsupportedFeature);
+        let error = TiffError::from(io::Error::from(io::ErrorKind::InvalidData));
+        assert_eq!(error.kind(), ErrorKind::CorruptData);
+        let error = TiffError::from(io::Error::from(io::ErrorKind::BrokenPipe));
+        assert_eq!(error.kind(), ErrorKind::Io);
+    }
+}
@@ -0,0 +1,233 @@
+//! This module handles scanning TIFF files as a stream of events.
+use error::{CorruptError, Result, TiffError};
+use options::ReaderOptions;
+use reader::{probe, IFDEntry, IFDIterator, TiffInfo};
+use std::collections::VecDeque;
+use std::io::{Read, Seek};
+use tag::Tag;
+use value::TIFFValue;
+use warning::Warning;
+
+/// An event emitted by `EventReader`.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// The header was read.
+    HeaderParsed(TiffInfo),
+    /// Directory `index` starts at `offset` and holds `entries` entries.
+    DirectoryStart {
+        index: usize,
+        offset: u64,
+        entries: usize,
+    },
+    /// An entry of the current directory. Its value is only read when asked
+    /// with `EventReader::read_value`.
+    Entry(IFDEntry),
+    /// The location of strip or tile `index` of the current directory.
+    ImageDataRef { index: usize, offset: u64, length: u64 },
+    /// The current directory ends, `next` is the offset of the following one,
+    /// or 0 for the last directory.
+    DirectoryEnd { next: u64 },
+}
+
+/// Scans a file one directory at a time, emitting events instead of building
+/// the whole directory tree like `TIFFReader` does.
+///
+/// Only the current directory is kept in memory, which suits very large files
+/// and pipelines interested in a few tags.
+pub struct EventReader<'a, R: Read + Seek + 'a> {
+    directories: IFDIterator<'a, R>,
+    info: TiffInfo,
+    options: ReaderOptions,
+    header_parsed: bool,
+    index: usize,
+    entries: VecDeque<IFDEntry>,
+    segments: Vec<(u64, u64)>,
+    next_segment: usize,
+    directory_end: Option<u64>,
+    failed: bool,
+}
+
+impl<'a, R: Read + Seek> EventReader<'a, R> {
+    /// Creates an event reader, reading the header of `reader` right away.
+    pub fn new(reader: &'a mut R) -> Result<EventReader<'a, R>> {
+        EventReader::with_options(reader, ReaderOptions::default())
+    }
+
+    /// Like `new`, with custom parsing options.
+    pub fn with_options(reader: &'a mut R, options: ReaderOptions) -> Result<EventReader<'a, R>> {
+        let info = probe(&mut *reader)?;
+        if info.big_tiff {
+            return Err(TiffError::Unsupported("BigTIFF files"));
+        }
+
+        Ok(EventReader {
+            directories: IFDIterator::new(reader, info.first_ifd_offset, info.endian, &options),
+            info,
+            options,
+            header_parsed: false,
+            index: 0,
+            entries: VecDeque::new(),
+            segments: Vec::new(),
+            next_segment: 0,
+            directory_end: None,
+            failed: false,
+        })
+    }
+
+    /// Reads the value of `entry`, typically received with an `Entry` event.
+    pub fn read_value(&mut self, entry: &IFDEntry) -> Result<TIFFValue> {
+        let endian = self.info.endian;
+        TIFFValue::new_from_entry(self.directories.reader_mut(), entry, endian, &self.options)
+    }
+
+    /// The anomalies tolerated in the directories scanned so far.
+    pub fn warnings(&self) -> &[Warning] {
+        self.directories.warnings()
+    }
+
+    /// Reads the offsets and byte counts of the strips or tiles of `entries`.
+    fn read_segments(&mut self, entries: &[IFDEntry]) -> Result<Vec<(u64, u64)>> {
+        let find = |tag| entries.iter().find(|entry| entry.tag == tag).cloned();
+        let layout = match (find(Tag::TileOffsets), find(Tag::TileByteCounts)) {
+            (Some(offsets), Some(counts)) => Some((offsets, counts)),
+            _ => match (find(Tag::StripOffsets), find(Tag::StripByteCounts)) {
+                (Some(offsets), Some(counts)) => Some((offsets, counts)),
+                _ => None,
+            },
+        };
+
+        let (offsets, counts) = match layout {
+            Some(layout) => layout,
+            None => return Ok(Vec::new()),
+        };
+        let offsets = self.read_value(&offsets)?.as_unsigned();
+        let counts = self.read_value(&counts)?.as_unsigned();
+        match (offsets, counts) {
+            (Some(offsets), Some(counts)) => Ok(offsets.into_iter().zip(counts).collect()),
+            _ => Err(CorruptError::Invalid("Missing or invalid strip layout").into()),
+        }
+    }
+
+    fn next_event(&mut self) -> Option<Result<Event>> {
+        if !self.header_parsed {
+            self.header_parsed = true;
+            return Some(Ok(Event::HeaderParsed(self.info)));
+        }
+
+        if let Some(entry) = self.entries.pop_front() {
+            return Some(Ok(Event::Entry(entry)));
+        }
+
+        if let Some(&(offset, length)) = self.segments.get(self.next_segment) {
+            let index = self.next_segment;
+            self.next_segment += 1;
+            return Some(Ok(Event::ImageDataRef {
+                index,
+                offset,
+                length,
+            }));
+        }
+
+        if let Some(next) = self.directory_end.take() {
+            self.index += 1;
+            return Some(Ok(Event::DirectoryEnd { next }));
+        }
+
+        let offset = self.directories.next_offset();
+        let ifd = match self.directories.next()? {
+            Ok(ifd) => ifd,
+            Err(e) => return Some(Err(e)),
+        };
+        let entries = ifd.entries();
+        self.segments = match self.read_segments(entries) {
+            Ok(segments) => segments,
+            Err(e) => return Some(Err(e)),
+        };
+        self.next_segment = 0;
+        self.entries = entries.iter().cloned().collect();
+        self.directory_end = Some(self.directories.next_offset());
+
+        Some(Ok(Event::DirectoryStart {
+            index: self.index,
+            offset,
+            entries: entries.len(),
+        }))
+    }
+}
+
+impl<'a, R: Read + Seek> Iterator for EventReader<'a, R> {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Result<Event>> {
+        if self.failed {
+            return None;
+        }
+
+        let event = self.next_event();
+        if let Some(Err(_)) = event {
+            // Stop after the first error
+            self.failed = true;
+        }
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::TIFFReader;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_events() {
+        let bytes: &[u8] = include_bytes!("../samples/picoawards_le.tiff");
+        let mut cursor = Cursor::new(bytes);
+        let mut events = EventReader::new(&mut cursor).unwrap();
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+
+        match events.next().unwrap().unwrap() {
+            Event::HeaderParsed(info) => assert_eq!(info.first_ifd_offset, 15326),
+            event => panic!("Unexpected event {:?}", event),
+        }
+        match events.next().unwrap().unwrap() {
+            Event::DirectoryStart { index, offset, entries } => {
+                assert_eq!((index, offset, entries), (0, 15326, 15))
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+
+        let mut entries = 0;
+        let mut segments = 0;
+        while let Some(event) = events.next() {
+            match event.unwrap() {
+                Event::Entry(entry) => {
+                    entries += 1;
+                    if entry.tag == Tag::ImageWidth {
+                        let width = events.read_value(&entry).unwrap();
+                        assert_eq!(width.unsigned_at(0), Some(436));
+                    }
+                }
+                Event::ImageDataRef { index, offset, length } => {
+                    assert_eq!(index, segments);
+                    let expected = read.read_array_element(Tag::StripOffsets, index);
+                    assert_eq!(offset, expected.unwrap());
+                    let expected = read.read_array_element(Tag::StripByteCounts, index);
+                    assert_eq!(length, expected.unwrap());
+                    segments += 1;
+                }
+                Event::DirectoryEnd { next } => assert_eq!(next, 0),
+                event => panic!("Unexpected event {:?}", event),
+            }
+        }
+        assert_eq!((entries, segments), (15, 61));
+    }
+
+    #[test]
+    fn test_events_error() {
+        let mut cursor = Cursor::new(b"II*\0\xff\0\0\0".to_vec());
+        let events = EventReader::new(&mut cursor).unwrap();
+        let results: Vec<_> = events.collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[1].is_err());
+    }
+}
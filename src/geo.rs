@@ -0,0 +1,262 @@
+//! This module handles the georeferencing of GeoTIFF files.
+use reader::TIFFReader;
+use std::io::{Read, Seek};
+use tag::{GdalNoData, Tag};
+use value::TIFFValue;
+use writer::Directory;
+
+const GT_MODEL_TYPE: u16 = 1024;
+const GT_RASTER_TYPE: u16 = 1025;
+const GT_CITATION: u16 = 1026;
+const GEOGRAPHIC_TYPE: u16 = 2048;
+const PROJECTED_CS_TYPE: u16 = 3072;
+
+/// A coordinate reference system, identified by its EPSG code.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Crs {
+    /// A projected system, such as UTM zones (EPSG:32631).
+    Projected(u16),
+    /// A geographic system in degrees, such as WGS 84 (EPSG:4326).
+    Geographic(u16),
+}
+
+/// An affine transform between raster space (column, row) and the model
+/// space of the coordinate reference system of a GeoTIFF.
+///
+/// The coefficients follow the GDAL order: a CRS position is
+/// `(c[0] + c[1] * x + c[2] * y, c[3] + c[4] * x + c[5] * y)`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GeoTransform {
+    coefficients: [f64; 6],
+}
+
+impl GeoTransform {
+    /// Creates a transform from its coefficients, in the GDAL order.
+    pub fn new(coefficients: [f64; 6]) -> GeoTransform {
+        GeoTransform { coefficients }
+    }
+
+    /// Creates the transform of a north-up image whose upper-left corner is at
+    /// `origin`, with pixels of `pixel_size` model units.
+    pub fn from_origin(origin: (f64, f64), pixel_size: (f64, f64)) -> GeoTransform {
+        GeoTransform::new([origin.0, pixel_size.0, 0.0, origin.1, 0.0, -pixel_size.1])
+    }
+
+    /// The coefficients of the transform, in the GDAL order.
+    pub fn coefficients(&self) -> [f64; 6] {
+        self.coefficients
+    }
+
+    /// Converts a raster position, in pixels, to model coordinates.
+    pub fn pixel_to_crs(&self, x: f64, y: f64) -> (f64, f64) {
+        let c = &self.coefficients;
+        (c[0] + c[1] * x + c[2] * y, c[3] + c[4] * x + c[5] * y)
+    }
+
+    /// Converts model coordinates to a raster position, in pixels, or `None`
+    /// when the transform cannot be inverted.
+    pub fn crs_to_pixel(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+        let c = &self.coefficients;
+        let determinant = c[1] * c[5] - c[2] * c[4];
+        if determinant == 0.0 || !determinant.is_finite() {
+            return None;
+        }
+
+        let (dx, dy) = (x - c[0], y - c[3]);
+        Some((
+            (c[5] * dx - c[2] * dy) / determinant,
+            (c[1] * dy - c[4] * dx) / determinant,
+        ))
+    }
+
+    /// Builds the transform from the `ModelTransformation` matrix, 16 values
+    /// in row-major order.
+    fn from_matrix(matrix: &[f64]) -> Option<GeoTransform> {
+        if matrix.len() != 16 {
+            return None;
+        }
+        let m = matrix;
+        Some(GeoTransform::new([m[3], m[0], m[1], m[7], m[4], m[5]]))
+    }
+
+    /// Builds the transform from the first `ModelTiepoint` (I, J, K, X, Y, Z)
+    /// and the `ModelPixelScale` (Sx, Sy, Sz).
+    fn from_tiepoint(tiepoint: &[f64], scale: &[f64]) -> Option<GeoTransform> {
+        if tiepoint.len() < 6 || scale.len() < 2 {
+            return None;
+        }
+        let (i, j, x, y) = (tiepoint[0], tiepoint[1], tiepoint[3], tiepoint[4]);
+        let (sx, sy) = (scale[0], scale[1]);
+        Some(GeoTransform::new([x - i * sx, sx, 0.0, y + j * sy, 0.0, -sy]))
+    }
+}
+
+impl Directory {
+    /// Georeferences the directory: sets the `GeoKeyDirectory` and
+    /// `GeoAsciiParams` describing `crs`, and either `ModelTiepoint` and
+    /// `ModelPixelScale`, or `ModelTransformation` when `transform` rotates.
+    ///
+    /// No GeoKey needs a double once the system is given by its EPSG code, so
+    /// any `GeoDoubleParams` is removed.
+    pub fn set_geo_reference(&mut self, transform: &GeoTransform, crs: Crs) {
+        let c = transform.coefficients();
+        if c[2] == 0.0 && c[4] == 0.0 {
+            self.remove(Tag::ModelTransformation);
+            let tiepoint = vec![0.0, 0.0, 0.0, c[0], c[3], 0.0];
+            self.set(Tag::ModelTiepoint, TIFFValue::Double(tiepoint));
+            self.set(Tag::ModelPixelScale, TIFFValue::Double(vec![c[1], -c[5], 0.0]));
+        } else {
+            self.remove(Tag::ModelTiepoint);
+            self.remove(Tag::ModelPixelScale);
+            let matrix = vec![
+                c[1], c[2], 0.0, c[0], //
+                c[4], c[5], 0.0, c[3], //
+                0.0, 0.0, 0.0, 0.0, //
+                0.0, 0.0, 0.0, 1.0,
+            ];
+            self.set(Tag::ModelTransformation, TIFFValue::Double(matrix));
+        }
+
+        let (model_type, key, code) = match crs {
+            Crs::Projected(code) => (1, PROJECTED_CS_TYPE, code),
+            Crs::Geographic(code) => (2, GEOGRAPHIC_TYPE, code),
+        };
+        let citation = format!("EPSG:{}|", code);
+        let ascii_location = u16::from(Tag::GeoAsciiParams);
+
+        // Header (version 1.1.0, key count), then keys sorted by id:
+        // id, location (0 when the value is inline), count, value or index
+        let keys = vec![
+            1, 1, 0, 4, //
+            GT_MODEL_TYPE, 0, 1, model_type, //
+            GT_RASTER_TYPE, 0, 1, 1, // RasterPixelIsArea
+            GT_CITATION, ascii_location, citation.len() as u16, 0, //
+            key, 0, 1, code,
+        ];
+        self.set(Tag::GeoKeyDirectory, TIFFValue::Short(keys));
+        self.set(Tag::GeoAsciiParams, TIFFValue::Ascii(vec![citation]));
+        self.remove(Tag::GeoDoubleParams);
+    }
+
+    /// Sets the value of the samples holding no data, `GDAL_NODATA`.
+    pub fn set_no_data(&mut self, value: f64) {
+        self.set_field(&GdalNoData(value.to_string()));
+    }
+}
+
+impl<R: Read + Seek> TIFFReader<R> {
+    fn read_doubles(&mut self, tag: Tag) -> Option<Vec<f64>> {
+        match self.read_tag(tag).ok()?? {
+            TIFFValue::Double(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// The georeferencing of the current directory, from its
+    /// `ModelTransformation`, or its `ModelTiepoint` and `ModelPixelScale`.
+    pub fn geo_transform(&mut self) -> Option<GeoTransform> {
+        if let Some(matrix) = self.read_doubles(Tag::ModelTransformation) {
+            return GeoTransform::from_matrix(&matrix);
+        }
+
+        let tiepoint = self.read_doubles(Tag::ModelTiepoint)?;
+        let scale = self.read_doubles(Tag::ModelPixelScale)?;
+        GeoTransform::from_tiepoint(&tiepoint, &scale)
+    }
+
+    /// The value of the samples holding no data in the current directory,
+    /// from its `GDAL_NODATA`.
+    pub fn no_data(&mut self) -> Option<f64> {
+        self.get_field::<GdalNoData>()?.0.trim().parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::tests::write_then_read;
+    use tag::ImageWidth;
+    use writer::Directory;
+
+    #[test]
+    fn test_tiepoint_transform() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(100));
+        let tiepoint = vec![10.0, 20.0, 0.0, 440_720.0, 3_751_320.0, 0.0];
+        directory.set(Tag::ModelTiepoint, TIFFValue::Double(tiepoint));
+        directory.set(Tag::ModelPixelScale, TIFFValue::Double(vec![60.0, 30.0, 0.0]));
+
+        let transform = write_then_read(&directory, &[]).geo_transform().unwrap();
+        assert_eq!(transform.pixel_to_crs(10.0, 20.0), (440_720.0, 3_751_320.0));
+        assert_eq!(transform.pixel_to_crs(11.0, 22.0), (440_780.0, 3_751_260.0));
+        assert_eq!(transform.crs_to_pixel(440_780.0, 3_751_260.0), Some((11.0, 22.0)));
+    }
+
+    #[test]
+    fn test_matrix_transform() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(100));
+        let matrix = vec![
+            2.0, 1.0, 0.0, 100.0, //
+            1.0, -2.0, 0.0, 50.0, //
+            0.0, 0.0, 0.0, 0.0, //
+            0.0, 0.0, 0.0, 1.0,
+        ];
+        directory.set(Tag::ModelTransformation, TIFFValue::Double(matrix));
+
+        let transform = write_then_read(&directory, &[]).geo_transform().unwrap();
+        assert_eq!(transform.coefficients(), [100.0, 2.0, 1.0, 50.0, 1.0, -2.0]);
+        let (x, y) = transform.pixel_to_crs(3.0, 4.0);
+        assert_eq!((x, y), (110.0, 45.0));
+        assert_eq!(transform.crs_to_pixel(x, y), Some((3.0, 4.0)));
+    }
+
+    #[test]
+    fn test_set_geo_reference() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(100));
+        let transform = GeoTransform::from_origin((440_720.0, 3_751_320.0), (60.0, 60.0));
+        directory.set_geo_reference(&transform, Crs::Projected(26711));
+
+        let mut read = write_then_read(&directory, &[]);
+        assert_eq!(read.geo_transform(), Some(transform));
+        match read.read_tag(Tag::GeoKeyDirectory).unwrap() {
+            Some(TIFFValue::Short(keys)) => {
+                assert_eq!(keys.len(), 4 * 5);
+                assert_eq!(&keys[16..], [PROJECTED_CS_TYPE, 0, 1, 26711]);
+            }
+            value => panic!("Unexpected value {:?}", value),
+        }
+        match read.read_tag(Tag::GeoAsciiParams).unwrap() {
+            Some(TIFFValue::Ascii(citation)) => assert_eq!(citation, ["EPSG:26711|"]),
+            value => panic!("Unexpected value {:?}", value),
+        }
+
+        // Rotated transforms need the full matrix
+        let rotated = GeoTransform::new([10.0, 1.0, 0.5, 20.0, 0.5, -1.0]);
+        directory.set_geo_reference(&rotated, Crs::Geographic(4326));
+        assert!(directory.get(Tag::ModelTiepoint).is_none());
+        assert_eq!(write_then_read(&directory, &[]).geo_transform(), Some(rotated));
+    }
+
+    #[test]
+    fn test_no_data() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(100));
+        assert_eq!(write_then_read(&directory, &[]).no_data(), None);
+        directory.set_no_data(-9999.0);
+        assert_eq!(write_then_read(&directory, &[]).no_data(), Some(-9999.0));
+        directory.set_field(&GdalNoData(" nan".to_string()));
+        assert!(write_then_read(&directory, &[]).no_data().unwrap().is_nan());
+        directory.set_field(&GdalNoData("none".to_string()));
+        assert_eq!(write_then_read(&directory, &[]).no_data(), None);
+    }
+
+    #[test]
+    fn test_singular_transform() {
+        let transform = GeoTransform::new([0.0, 1.0, 2.0, 0.0, 2.0, 4.0]);
+        assert_eq!(transform.crs_to_pixel(1.0, 1.0), None);
+        let transform = GeoTransform::from_origin((5.0, 10.0), (1.0, 2.0));
+        assert_eq!(transform.pixel_to_crs(1.0, 1.0), (6.0, 8.0));
+    }
+}
@@ -0,0 +1,264 @@
+//! This module decodes the GPS directory into coordinates, and builds it
+//! from them.
+use chrono::{self, Datelike, Timelike};
+use reader::TIFFReader;
+use std::io::{Read, Seek};
+use tag::Tag;
+use value::{Rational, TIFFValue};
+use writer::Directory;
+
+const GPS_VERSION_ID: u16 = 0;
+const GPS_LATITUDE_REF: u16 = 1;
+const GPS_LATITUDE: u16 = 2;
+const GPS_LONGITUDE_REF: u16 = 3;
+const GPS_LONGITUDE: u16 = 4;
+const GPS_ALTITUDE_REF: u16 = 5;
+const GPS_ALTITUDE: u16 = 6;
+const GPS_TIME_STAMP: u16 = 7;
+const GPS_MAP_DATUM: u16 = 0x12;
+const GPS_DATE_STAMP: u16 = 0x1d;
+
+/// Denominator of the seconds and meters written, for millimeter precision.
+const PRECISION: u32 = 1000;
+
+/// Converts degrees, minutes and seconds to decimal degrees.
+fn decimal_degrees(value: &TIFFValue) -> Option<f64> {
+    let dms = match value {
+        TIFFValue::Rational(dms) if dms.len() == 3 => dms,
+        _ => return None,
+    };
+
+    let mut degrees = 0.0;
+    for (part, unit) in dms.iter().zip(&[1.0, 60.0, 3600.0]) {
+        if part.denom == 0 {
+            return None;
+        }
+        degrees += f64::from(part.num) / f64::from(part.denom) / unit;
+    }
+    Some(degrees)
+}
+
+/// Reads a coordinate, negative when its reference is `negative_ref`.
+fn coordinate(gps: &Directory, value: u16, reference: u16, negative_ref: &str) -> Option<f64> {
+    let degrees = decimal_degrees(gps.get(Tag::Unknown(value))?)?;
+    match gps.get(Tag::Unknown(reference))? {
+        TIFFValue::Ascii(reference) if reference.first()? == negative_ref => Some(-degrees),
+        TIFFValue::Ascii(_) => Some(degrees),
+        _ => None,
+    }
+}
+
+/// Converts decimal degrees to positive degrees, minutes and seconds.
+fn dms(degrees: f64) -> Vec<Rational<u32>> {
+    let thousandths = (degrees.abs() * 3600.0 * f64::from(PRECISION)).round() as u64;
+    let seconds = thousandths / u64::from(PRECISION);
+    vec![
+        Rational { num: (seconds / 3600) as u32, denom: 1 },
+        Rational { num: (seconds / 60 % 60) as u32, denom: 1 },
+        Rational { num: (thousandths % (60 * u64::from(PRECISION))) as u32, denom: PRECISION },
+    ]
+}
+
+/// A position to write as a GPS directory, with `Directory::set_gps`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpsInfo {
+    latitude: f64,
+    longitude: f64,
+    altitude: Option<f64>,
+    timestamp: Option<chrono::NaiveDateTime>,
+    map_datum: String,
+}
+
+impl GpsInfo {
+    /// A position given in signed decimal degrees, positive to the north and
+    /// east, on the WGS-84 datum. Returns `None` for coordinates out of range.
+    pub fn new(latitude: f64, longitude: f64) -> Option<GpsInfo> {
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            return None;
+        }
+        Some(GpsInfo {
+            latitude,
+            longitude,
+            altitude: None,
+            timestamp: None,
+            map_datum: "WGS-84".to_string(),
+        })
+    }
+
+    /// Sets the altitude in meters, negative below sea level.
+    pub fn altitude(mut self, meters: f64) -> GpsInfo {
+        self.altitude = Some(meters).filter(|meters| meters.is_finite());
+        self
+    }
+
+    /// Sets the UTC time of the position fix.
+    pub fn timestamp(mut self, time: chrono::NaiveDateTime) -> GpsInfo {
+        self.timestamp = Some(time);
+        self
+    }
+
+    /// Sets the geodetic datum of the coordinates.
+    pub fn map_datum(mut self, datum: &str) -> GpsInfo {
+        self.map_datum = datum.to_string();
+        self
+    }
+
+    /// The GPS directory holding the position.
+    pub fn to_directory(&self) -> Directory {
+        let ascii = |s: &str| TIFFValue::Ascii(vec![s.to_string()]);
+        let mut gps = Directory::new();
+        gps.set(Tag::Unknown(GPS_VERSION_ID), TIFFValue::Byte(vec![2, 3, 0, 0]));
+        let reference = if self.latitude < 0.0 { "S" } else { "N" };
+        gps.set(Tag::Unknown(GPS_LATITUDE_REF), ascii(reference));
+        gps.set(Tag::Unknown(GPS_LATITUDE), TIFFValue::Rational(dms(self.latitude)));
+        let reference = if self.longitude < 0.0 { "W" } else { "E" };
+        gps.set(Tag::Unknown(GPS_LONGITUDE_REF), ascii(reference));
+        gps.set(Tag::Unknown(GPS_LONGITUDE), TIFFValue::Rational(dms(self.longitude)));
+
+        if let Some(altitude) = self.altitude {
+            let reference = if altitude < 0.0 { 1 } else { 0 };
+            gps.set(Tag::Unknown(GPS_ALTITUDE_REF), TIFFValue::Byte(vec![reference]));
+            let num = (altitude.abs() * f64::from(PRECISION)).round() as u32;
+            let altitude = Rational { num, denom: PRECISION };
+            gps.set(Tag::Unknown(GPS_ALTITUDE), TIFFValue::Rational(vec![altitude]));
+        }
+
+        if let Some(time) = self.timestamp {
+            let millis = time.nanosecond().min(999_999_999) / 1_000_000;
+            let seconds = time.second() * PRECISION + millis;
+            let stamp = vec![
+                Rational { num: time.hour(), denom: 1 },
+                Rational { num: time.minute(), denom: 1 },
+                Rational { num: seconds, denom: PRECISION },
+            ];
+            gps.set(Tag::Unknown(GPS_TIME_STAMP), TIFFValue::Rational(stamp));
+            let date = format!("{:04}:{:02}:{:02}", time.year(), time.month(), time.day());
+            gps.set(Tag::Unknown(GPS_DATE_STAMP), ascii(&date));
+        }
+
+        gps.set(Tag::Unknown(GPS_MAP_DATUM), ascii(&self.map_datum));
+        gps
+    }
+}
+
+impl Directory {
+    /// Geotags the directory with `gps`, written as its GPS directory.
+    pub fn set_gps(&mut self, gps: &GpsInfo) {
+        self.set_sub_directory(Tag::GPSInfo, gps.to_directory());
+    }
+}
+
+impl<R: Read + Seek> TIFFReader<R> {
+    /// The latitude and longitude of the current directory in signed decimal
+    /// degrees, positive to the north and east.
+    pub fn gps_position(&mut self) -> Option<(f64, f64)> {
+        let gps = self.read_sub_directory(Tag::GPSInfo).ok()??;
+        let latitude = coordinate(&gps, GPS_LATITUDE, GPS_LATITUDE_REF, "S")?;
+        let longitude = coordinate(&gps, GPS_LONGITUDE, GPS_LONGITUDE_REF, "W")?;
+        Some((latitude, longitude))
+    }
+
+    /// The altitude of the current directory in meters, negative below sea
+    /// level.
+    pub fn gps_altitude(&mut self) -> Option<f64> {
+        let gps = self.read_sub_directory(Tag::GPSInfo).ok()??;
+        let altitude = match gps.get(Tag::Unknown(GPS_ALTITUDE))? {
+            TIFFValue::Rational(altitude) if altitude.first()?.denom != 0 => {
+                f64::from(altitude[0].num) / f64::from(altitude[0].denom)
+            }
+            _ => return None,
+        };
+
+        // A missing reference means above sea level
+        match gps.get(Tag::Unknown(GPS_ALTITUDE_REF)) {
+            Some(TIFFValue::Byte(reference)) if reference.first() == Some(&1) => Some(-altitude),
+            _ => Some(altitude),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use endian::Endian;
+    use reader::tests::write_then_read;
+    use std::io::Cursor;
+    use tag::ImageWidth;
+    use writer::TIFFWriter;
+
+    fn rational(num: u32, denom: u32) -> Rational<u32> {
+        Rational { num, denom }
+    }
+
+    #[test]
+    fn test_gps_position() {
+        let mut gps = Directory::new();
+        let ascii = |s: &str| TIFFValue::Ascii(vec![s.to_string()]);
+        gps.set(Tag::Unknown(GPS_LATITUDE_REF), ascii("N"));
+        let latitude = vec![rational(48, 1), rational(51, 1), rational(2997, 100)];
+        gps.set(Tag::Unknown(GPS_LATITUDE), TIFFValue::Rational(latitude));
+        gps.set(Tag::Unknown(GPS_LONGITUDE_REF), ascii("W"));
+        let longitude = vec![rational(2, 1), rational(21, 1), rational(0, 1)];
+        gps.set(Tag::Unknown(GPS_LONGITUDE), TIFFValue::Rational(longitude));
+        gps.set(Tag::Unknown(GPS_ALTITUDE_REF), TIFFValue::Byte(vec![1]));
+        gps.set(Tag::Unknown(GPS_ALTITUDE), TIFFValue::Rational(vec![rational(71, 2)]));
+
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(1));
+        directory.set_sub_directory(Tag::GPSInfo, gps);
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Big).unwrap();
+        writer.write_directory(&directory).unwrap();
+
+        let bytes = writer.into_inner().into_inner();
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        let (latitude, longitude) = read.gps_position().unwrap();
+        assert!((latitude - 48.858325).abs() < 1e-9);
+        assert!((longitude + 2.35).abs() < 1e-9);
+        assert_eq!(read.gps_altitude(), Some(-35.5));
+    }
+
+    #[test]
+    fn test_write_gps() {
+        let time = chrono::NaiveDate::from_ymd_opt(2020, 7, 14)
+            .and_then(|date| date.and_hms_milli_opt(12, 30, 5, 250))
+            .unwrap();
+        let gps = GpsInfo::new(-33.856784, 151.215297)
+            .unwrap()
+            .altitude(-12.3456)
+            .timestamp(time);
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(1));
+        directory.set_gps(&gps);
+        let mut read = write_then_read(&directory, &[]);
+        let (latitude, longitude) = read.gps_position().unwrap();
+        assert!((latitude + 33.856784).abs() < 1e-6);
+        assert!((longitude - 151.215297).abs() < 1e-6);
+        assert_eq!(read.gps_altitude(), Some(-12.346));
+
+        let gps = read.read_sub_directory(Tag::GPSInfo).unwrap().unwrap();
+        match gps.get(Tag::Unknown(GPS_TIME_STAMP)) {
+            Some(TIFFValue::Rational(stamp)) => {
+                assert_eq!(stamp[..2], [rational(12, 1), rational(30, 1)]);
+                assert_eq!(stamp[2], rational(5250, 1000));
+            }
+            value => panic!("Unexpected time stamp {:?}", value),
+        }
+        match gps.get(Tag::Unknown(GPS_DATE_STAMP)) {
+            Some(TIFFValue::Ascii(date)) => assert_eq!(date[0], "2020:07:14"),
+            value => panic!("Unexpected date stamp {:?}", value),
+        }
+        assert_eq!(gps.get(Tag::Unknown(GPS_VERSION_ID)).unwrap().as_unsigned().unwrap().len(), 4);
+
+        assert_eq!(dms(59.999_999_9), [rational(60, 1), rational(0, 1), rational(0, 1000)]);
+        assert!(GpsInfo::new(91.0, 0.0).is_none());
+        assert!(GpsInfo::new(0.0, f64::NAN).is_none());
+    }
+
+    #[test]
+    fn test_no_gps() {
+        let bytes: &[u8] = include_bytes!("../samples/picoawards_le.tiff");
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.gps_position(), None);
+        assert_eq!(read.gps_altitude(), None);
+    }
+}
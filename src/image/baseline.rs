@@ -0,0 +1,311 @@
+//! Decoding of baseline images to 8-bit RGBA pixels, whatever their color
+//! space and sample size.
+use std::io::{Read, Seek};
+
+use endian::Endian;
+use error::{CorruptError, Result, TiffError};
+use tag::*;
+
+use super::{ycbcr, Image, ImageData};
+
+/// The luma coefficients of `YCbCrCoefficients` when it is absent, from CCIR
+/// Recommendation 601-1.
+const DEFAULT_LUMA: [f32; 3] = [0.299, 0.587, 0.114];
+
+/// Pixels decoded by `Image::decode`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub length: u32,
+    /// Red, green, blue and alpha samples of each pixel, row after row.
+    pub data: Vec<u8>,
+}
+
+/// Reads sample `index` of a row of packed samples of `bits` bits, 16-bit
+/// samples being stored in the `endian` byte order.
+fn sample_at(row: &[u8], index: usize, bits: u16, endian: Endian) -> u32 {
+    match bits {
+        8 => u32::from(row[index]),
+        16 => {
+            let bytes = [row[2 * index], row[2 * index + 1]];
+            u32::from(match endian {
+                Endian::Big => u16::from_be_bytes(bytes),
+                Endian::Little => u16::from_le_bytes(bytes),
+            })
+        }
+        _ => {
+            let bits = usize::from(bits);
+            let position = index * bits;
+            let shift = 8 - bits - position % 8;
+            u32::from(row[position / 8] >> shift) & ((1 << bits) - 1)
+        }
+    }
+}
+
+impl<'a, R: Read + Seek> Image<'a, R> {
+    /// Decodes the image to 8-bit RGBA pixels, row after row. `read_data`
+    /// returns the samples as they are stored instead.
+    ///
+    /// Bilevel, grayscale, palette, RGB, CMYK and YCbCr images of 1, 2, 4, 8
+    /// or 16-bit unsigned samples are supported. The sample following the
+    /// color ones is used as alpha when `ExtraSamples` marks it as such,
+    /// pixels being opaque otherwise. Rows missing from truncated files are
+    /// transparent, as are pixels whose samples all equal the NoData value,
+    /// which are left out of the scaling to 8 bits.
+    pub fn decode(&mut self) -> Result<RgbaImage> {
+        let bits = self.bits_per_sample[0];
+        let same_bits = self.bits_per_sample.iter().all(|b| *b == bits);
+        if !same_bits || ![1, 2, 4, 8, 16].contains(&bits) {
+            return Err(TiffError::Unsupported(
+                "Samples other than 1, 2, 4, 8 or 16-bit",
+            ));
+        }
+        let unsigned = self
+            .reader
+            .get_field::<SampleFormat>()
+            .unwrap_or_default()
+            .0
+            .iter()
+            .all(|format| *format == SampleFormatValue::UnsignedInteger);
+        if !unsigned {
+            return Err(TiffError::Unsupported(
+                "Samples other than unsigned integers",
+            ));
+        }
+
+        let photometric = self
+            .reader
+            .get_field::<PhotometricInterpretation>()
+            .unwrap_or(PhotometricInterpretation::BlackIsZero);
+        let colors = match photometric {
+            PhotometricInterpretation::WhiteIsZero
+            | PhotometricInterpretation::BlackIsZero
+            | PhotometricInterpretation::PaletteColor => 1,
+            PhotometricInterpretation::RGB | PhotometricInterpretation::YCbCr => 3,
+            PhotometricInterpretation::CMYK => 4,
+            _ => return Err(TiffError::Unsupported("PhotometricInterpretation")),
+        };
+        let samples = self.bands();
+        if samples < colors {
+            return Err(CorruptError::Invalid("Fewer samples than the color space needs").into());
+        }
+        let alpha = match self.reader.get_field::<ExtraSamples>() {
+            Some(ref extra) if samples > colors => extra.0.first().cloned(),
+            _ => None,
+        };
+        let associated = alpha == Some(ExtraSampleDataValue::AssociatedAlpha);
+        let alpha = associated || alpha == Some(ExtraSampleDataValue::UnassociatedAlpha);
+
+        let max = (1u32 << bits) - 1;
+        let colormap = match photometric {
+            PhotometricInterpretation::PaletteColor => {
+                let colormap = self
+                    .reader
+                    .get_field::<ColorMap>()
+                    .ok_or(CorruptError::Invalid("Missing ColorMap"))?
+                    .0;
+                if colormap.len() != 3 << bits {
+                    return Err(CorruptError::Invalid("Invalid ColorMap length").into());
+                }
+                colormap
+            }
+            _ => Vec::new(),
+        };
+
+        let endian = self.reader.endianness();
+        // Already inverted while decoding
+        let inverted = self.context.inverted();
+        let row_bytes = self.scanline_bytes() as usize;
+        let width = self.width as usize;
+        let no_data = self.no_data;
+        let ImageData { data, rows } = self.read_data()?;
+
+        let to_byte = |value: u32| ((value * 255 + max / 2) / max) as u8;
+        let mut output = vec![0; width * self.length as usize * 4];
+        let mut pixel = vec![0; samples];
+        let mut empty = Vec::new();
+        for (y, (row, pixels)) in data
+            .chunks_exact(row_bytes)
+            .zip(output.chunks_exact_mut(width * 4))
+            .take(rows as usize)
+            .enumerate()
+        {
+            for (x, rgba) in pixels.chunks_exact_mut(4).enumerate() {
+                for (sample, value) in pixel.iter_mut().enumerate() {
+                    *value = sample_at(row, x * samples + sample, bits, endian);
+                }
+                if no_data.is_some_and(|no_data| {
+                    pixel.iter().all(|sample| f64::from(*sample) == no_data)
+                }) {
+                    // Left transparent
+                    empty.push(y * width + x);
+                    continue;
+                }
+                let (red, green, blue) = match photometric {
+                    PhotometricInterpretation::WhiteIsZero if !inverted => {
+                        let gray = to_byte(max - pixel[0]);
+                        (gray, gray, gray)
+                    }
+                    PhotometricInterpretation::PaletteColor => {
+                        let index = pixel[0] as usize;
+                        let entries = colormap.len() / 3;
+                        let color = |plane: usize| (colormap[plane * entries + index] >> 8) as u8;
+                        (color(0), color(1), color(2))
+                    }
+                    PhotometricInterpretation::CMYK => {
+                        let black = 255 - u32::from(to_byte(pixel[3]));
+                        let ink =
+                            |value: u32| ((255 - u32::from(to_byte(value))) * black / 255) as u8;
+                        (ink(pixel[0]), ink(pixel[1]), ink(pixel[2]))
+                    }
+                    PhotometricInterpretation::BlackIsZero
+                    | PhotometricInterpretation::WhiteIsZero => {
+                        let gray = to_byte(pixel[0]);
+                        (gray, gray, gray)
+                    }
+                    // RGB, and YCbCr converted below
+                    _ => (to_byte(pixel[0]), to_byte(pixel[1]), to_byte(pixel[2])),
+                };
+                let opacity = if alpha { to_byte(pixel[colors]) } else { 255 };
+                rgba.copy_from_slice(&[red, green, blue, opacity]);
+            }
+        }
+
+        if photometric == PhotometricInterpretation::YCbCr {
+            let luma = self
+                .reader
+                .read_tag(Tag::YCbCrCoefficients)?
+                .and_then(|value| value.as_f64())
+                .filter(|luma| luma.len() == 3 && luma[1] != 0.0)
+                .map_or(DEFAULT_LUMA, |luma| {
+                    [luma[0] as f32, luma[1] as f32, luma[2] as f32]
+                });
+            let count = rows as usize * width;
+            let ycbcr: Vec<u8> = output
+                .chunks_exact(4)
+                .take(count)
+                .flat_map(|pixel| pixel[..3].to_vec())
+                .collect();
+            let mut rgb = vec![0; ycbcr.len()];
+            ycbcr::ycbcr_to_rgb(&ycbcr, &mut rgb, luma);
+            for (pixel, color) in output.chunks_exact_mut(4).zip(rgb.chunks_exact(3)) {
+                pixel[..3].copy_from_slice(color);
+            }
+            for index in empty {
+                output[index * 4..index * 4 + 3].fill(0);
+            }
+        }
+
+        if associated {
+            for pixel in output.chunks_exact_mut(4).filter(|pixel| pixel[3] > 0) {
+                let alpha = u32::from(pixel[3]);
+                for color in &mut pixel[..3] {
+                    *color = (u32::from(*color) * 255 / alpha).min(255) as u8;
+                }
+            }
+        }
+
+        Ok(RgbaImage {
+            width: self.width,
+            length: self.length,
+            data: output,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Image;
+    use super::*;
+    use reader::tests::write_then_read;
+    use value::TIFFValue;
+    use writer::Directory;
+
+    /// Decodes a single strip image of `width` x 1 pixels described by `fields`.
+    fn decode(width: u32, bits: Vec<u16>, fields: Directory, strip: Vec<u8>) -> Vec<u8> {
+        let mut directory = fields;
+        directory.set_field(&ImageWidth(width));
+        directory.set_field(&ImageLength(1));
+        directory.set_field(&SamplesPerPixel(bits.len() as u16));
+        directory.set_field(&BitsPerSample(bits));
+        let mut read = write_then_read(&directory, &[strip]);
+        let decoded = Image::new(&mut read).unwrap().decode().unwrap();
+        assert_eq!((decoded.width, decoded.length), (width, 1));
+        decoded.data
+    }
+
+    fn fields(photometric: PhotometricInterpretation) -> Directory {
+        let mut directory = Directory::new();
+        directory.set_field(&photometric);
+        directory
+    }
+
+    #[test]
+    fn test_decode_gray() {
+        let gray = fields(PhotometricInterpretation::BlackIsZero);
+        let decoded = decode(3, vec![4], gray.clone(), vec![0x0f, 0x80]);
+        assert_eq!(
+            decoded,
+            [0, 0, 0, 255, 255, 255, 255, 255, 136, 136, 136, 255]
+        );
+        let decoded = decode(1, vec![16], gray, vec![0x00, 0x80]);
+        assert_eq!(decoded, [128, 128, 128, 255]);
+
+        let white = fields(PhotometricInterpretation::WhiteIsZero);
+        let decoded = decode(2, vec![1], white, vec![0b1000_0000]);
+        assert_eq!(decoded, [0, 0, 0, 255, 255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_decode_colors() {
+        let mut palette = fields(PhotometricInterpretation::PaletteColor);
+        let mut colormap = vec![0; 12];
+        colormap[1] = 0xff00;
+        colormap[4 + 2] = 0x8000;
+        palette.set_field(&ColorMap(colormap));
+        let decoded = decode(2, vec![2], palette, vec![0b0110_0000]);
+        assert_eq!(decoded, [255, 0, 0, 255, 0, 128, 0, 255]);
+
+        let mut rgba = fields(PhotometricInterpretation::RGB);
+        rgba.set(Tag::ExtraSamples, TIFFValue::Short(vec![2]));
+        let decoded = decode(1, vec![8, 8, 8, 8], rgba.clone(), vec![10, 20, 30, 40]);
+        assert_eq!(decoded, [10, 20, 30, 40]);
+        rgba.set(Tag::ExtraSamples, TIFFValue::Short(vec![1]));
+        let decoded = decode(1, vec![8, 8, 8, 8], rgba, vec![10, 20, 30, 51]);
+        assert_eq!(decoded, [50, 100, 150, 51]);
+
+        let cmyk = fields(PhotometricInterpretation::CMYK);
+        let decoded = decode(1, vec![8, 8, 8, 8], cmyk, vec![255, 0, 0, 51]);
+        assert_eq!(decoded, [0, 204, 204, 255]);
+
+        let mut ycbcr = fields(PhotometricInterpretation::YCbCr);
+        ycbcr.set(Tag::YCbCrSubSampling, TIFFValue::Short(vec![1, 1]));
+        let decoded = decode(2, vec![8, 8, 8], ycbcr, vec![255, 128, 128, 0, 128, 128]);
+        assert_eq!(decoded, [255, 255, 255, 255, 0, 0, 0, 255]);
+
+        let mask = fields(PhotometricInterpretation::TransparencyMask);
+        let mut directory = mask;
+        directory.set_field(&ImageWidth(1));
+        directory.set_field(&ImageLength(1));
+        directory.set_field(&BitsPerSample(vec![1]));
+        let mut read = write_then_read(&directory, &[vec![0]]);
+        assert!(Image::new(&mut read).unwrap().decode().is_err());
+    }
+
+    #[test]
+    fn test_decode_no_data() {
+        // 0xfff0 would be stretched to 255 without the NoData value
+        let mut gray = fields(PhotometricInterpretation::BlackIsZero);
+        gray.set_no_data(65520.0);
+        let decoded = decode(2, vec![16], gray, vec![0xf0, 0xff, 0x00, 0x80]);
+        assert_eq!(decoded, [0, 0, 0, 0, 128, 128, 128, 255]);
+
+        // Only pixels whose samples all hold the value, even through YCbCr
+        let mut ycbcr = fields(PhotometricInterpretation::YCbCr);
+        ycbcr.set(Tag::YCbCrSubSampling, TIFFValue::Short(vec![1, 1]));
+        ycbcr.set_no_data(0.0);
+        let strip = vec![0, 0, 0, 0, 128, 128];
+        let decoded = decode(2, vec![8, 8, 8], ycbcr, strip);
+        assert_eq!(decoded, [0, 0, 0, 0, 0, 0, 0, 255]);
+    }
+}
@@ -0,0 +1,128 @@
+//! Description of color filter arrays, or Bayer mosaics.
+use error::{CorruptError, Result};
+use reader::TIFFReader;
+use std::io::{Read, Seek};
+use tag::Tag;
+
+/// The color of a cell of a color filter array.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CfaColor {
+    Red,
+    Green,
+    Blue,
+    Cyan,
+    Magenta,
+    Yellow,
+    White,
+    Other(u8),
+}
+
+impl CfaColor {
+    fn from_code(code: u8) -> CfaColor {
+        match code {
+            0 => CfaColor::Red,
+            1 => CfaColor::Green,
+            2 => CfaColor::Blue,
+            3 => CfaColor::Cyan,
+            4 => CfaColor::Magenta,
+            5 => CfaColor::Yellow,
+            6 => CfaColor::White,
+            code => CfaColor::Other(code),
+        }
+    }
+
+    fn letter(self) -> Option<char> {
+        match self {
+            CfaColor::Red => Some('R'),
+            CfaColor::Green => Some('G'),
+            CfaColor::Blue => Some('B'),
+            CfaColor::Cyan => Some('C'),
+            CfaColor::Magenta => Some('M'),
+            CfaColor::Yellow => Some('Y'),
+            CfaColor::White => Some('W'),
+            CfaColor::Other(_) => None,
+        }
+    }
+}
+
+/// The repeating pattern of a color filter array.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CfaPattern {
+    /// The number of rows of the pattern.
+    pub rows: u16,
+    /// The number of columns of the pattern.
+    pub columns: u16,
+    /// The color of each cell, row by row.
+    pub colors: Vec<CfaColor>,
+}
+
+impl CfaPattern {
+    /// The color of the filter over the pixel at column `x` and row `y`.
+    pub fn color_at(&self, x: u32, y: u32) -> CfaColor {
+        let row = (y % u32::from(self.rows)) as usize;
+        let column = (x % u32::from(self.columns)) as usize;
+        self.colors[row * usize::from(self.columns) + column]
+    }
+
+    /// The pattern as a string such as `"RGGB"`, as expected by most
+    /// demosaicing libraries.
+    pub fn name(&self) -> Option<String> {
+        self.colors.iter().map(|color| color.letter()).collect()
+    }
+}
+
+/// The raw samples of a color filter array image with its pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mosaic {
+    pub pattern: CfaPattern,
+    /// The number of bits of each sample.
+    pub bits_per_sample: u16,
+    /// Packed samples of the recovered rows, one per pixel.
+    pub data: Vec<u8>,
+    /// Number of complete rows recovered.
+    pub rows: u32,
+}
+
+impl<R: Read + Seek> TIFFReader<R> {
+    /// The color filter array pattern of the current directory, if any.
+    ///
+    /// When `CFAPlaneColor` is present, the codes of `CFAPattern` are mapped
+    /// through it.
+    pub fn cfa_pattern(&mut self) -> Result<Option<CfaPattern>> {
+        let (dimensions, codes) = match (
+            self.read_unsigned_tag(Tag::CFARepeatPatternDim)?,
+            self.read_unsigned_tag(Tag::CFAPattern)?,
+        ) {
+            (Some(dimensions), Some(codes)) => (dimensions, codes),
+            _ => return Ok(None),
+        };
+
+        let invalid = || CorruptError::Invalid("Invalid CFA pattern");
+        if dimensions.len() != 2 || dimensions.contains(&0) || dimensions.iter().any(|d| *d > 16) {
+            return Err(invalid().into());
+        }
+        let (rows, columns) = (dimensions[0] as u16, dimensions[1] as u16);
+        if codes.len() != usize::from(rows) * usize::from(columns) {
+            return Err(invalid().into());
+        }
+
+        let plane_colors = self.read_unsigned_tag(Tag::CFAPlaneColor)?;
+        let mut colors = Vec::with_capacity(codes.len());
+        for code in codes {
+            let code = match &plane_colors {
+                Some(planes) => *planes.get(code as usize).ok_or_else(invalid)?,
+                None => code,
+            };
+            colors.push(CfaColor::from_code(code as u8));
+        }
+        Ok(Some(CfaPattern {
+            rows,
+            columns,
+            colors,
+        }))
+    }
+
+    fn read_unsigned_tag(&mut self, tag: Tag) -> Result<Option<Vec<u64>>> {
+        Ok(self.read_tag(tag)?.and_then(|value| value.as_unsigned()))
+    }
+}
@@ -0,0 +1,214 @@
+//! The decoding state shared by the strips or tiles of an image.
+use compression::Codec;
+use endian::Endian;
+#[cfg(feature = "jpeg")]
+use compression::OldJpeg;
+use error::Result;
+
+use super::{predictor, ycbcr};
+
+/// How the strips or tiles of an image are decoded, derived once from its
+/// fields and reused for every segment.
+#[derive(Debug, Clone)]
+pub struct DecodeContext {
+    codec: Codec,
+    /// The byte order of the samples when horizontal differencing is undone
+    /// after decompression.
+    predictor: Option<Endian>,
+    /// Size in bytes of the samples.
+    sample_bytes: usize,
+    /// Size in bytes of a decompressed segment row and number of samples per
+    /// pixel, for each plane, or for the single plane of chunky images.
+    planes: Vec<(usize, usize)>,
+    /// Width in pixels of the segments.
+    width: u32,
+    /// Horizontal and vertical chroma subsampling of `YCbCr` segments.
+    subsampling: Option<(u32, u32)>,
+    /// Whether the bits of the stored bytes are filled least significant
+    /// first, as `FillOrder` 2.
+    reversed_bits: bool,
+    /// Whether the first sample of each pixel is inverted after decoding.
+    inverted: bool,
+}
+
+impl DecodeContext {
+    /// Creates the context of segments `segment_width` pixels wide, with
+    /// samples of `bits_per_sample` bits. Differenced samples should all be
+    /// 8, 16 or 32-bit.
+    pub fn new(
+        compression: u16,
+        predictor: Option<Endian>,
+        segment_width: u32,
+        bits_per_sample: &[u16],
+        planar: bool,
+    ) -> DecodeContext {
+        let row_bytes = |bits: u64| (u64::from(segment_width) * bits).div_ceil(8) as usize;
+        let planes = if planar {
+            bits_per_sample
+                .iter()
+                .map(|bits| (row_bytes(u64::from(*bits)), 1))
+                .collect()
+        } else {
+            let bits = bits_per_sample.iter().map(|bits| u64::from(*bits)).sum();
+            vec![(row_bytes(bits), bits_per_sample.len())]
+        };
+        DecodeContext {
+            codec: Codec::new(compression, segment_width),
+            predictor,
+            sample_bytes: usize::from(bits_per_sample[0] / 8).max(1),
+            planes,
+            width: segment_width,
+            subsampling: None,
+            reversed_bits: false,
+            inverted: false,
+        }
+    }
+
+    /// Upsamples the chroma of decoded chunky `YCbCr` segments, subsampled
+    /// `horizontal` times across and `vertical` times down, so that they hold
+    /// whole `Y Cb Cr` pixels like other chunky segments.
+    pub fn set_subsampling(&mut self, horizontal: u32, vertical: u32) {
+        self.subsampling = Some((horizontal, vertical));
+    }
+
+    /// Reverses the bits of each stored byte before decompression, for
+    /// segments filled least significant bit first.
+    pub fn set_reversed_bits(&mut self) {
+        self.reversed_bits = true;
+    }
+
+    /// Sets whether the first sample of each pixel, or every sample of the
+    /// first plane of planar segments, is inverted after decoding. It must be
+    /// an unsigned integer, of a whole number of bytes when there are other
+    /// samples in the pixel.
+    pub fn set_inverted(&mut self, inverted: bool) {
+        self.inverted = inverted;
+    }
+
+    /// Whether the first sample of each pixel is inverted after decoding.
+    pub fn inverted(&self) -> bool {
+        self.inverted
+    }
+
+    /// Sets the `T4Options` or `T6Options` of fax segments, and whether
+    /// their image is `BlackIsZero`.
+    pub fn set_fax_options(&mut self, options: u32, black_is_zero: bool) {
+        self.codec.set_fax_options(options, black_is_zero);
+    }
+
+    /// Sets the `JPEGTables` shared by JPEG segments.
+    pub fn set_jpeg_tables(&mut self, tables: Vec<u8>) {
+        self.codec.set_jpeg_tables(tables);
+    }
+
+    /// Sets the tables and headers shared by old-style JPEG segments.
+    #[cfg(feature = "jpeg")]
+    pub fn set_old_jpeg(&mut self, jpeg: OldJpeg) {
+        self.codec.set_old_jpeg(jpeg);
+    }
+
+    /// The `Compression` of the segments.
+    pub fn compression(&self) -> u16 {
+        self.codec.compression()
+    }
+
+    /// Whether the segments use horizontal differencing.
+    pub fn predictor(&self) -> bool {
+        self.predictor.is_some()
+    }
+
+    /// Size in bytes of a decompressed row of a segment of `plane`.
+    pub fn row_bytes(&self, plane: usize) -> usize {
+        self.planes[plane].0
+    }
+
+    /// Size in bytes of a decompressed segment of `plane` storing `rows` rows,
+    /// before its chroma is upsampled.
+    fn stored_size(&self, plane: usize, rows: u32) -> usize {
+        let rows = rows as usize;
+        match self.subsampling {
+            Some((horizontal, vertical)) => {
+                let (horizontal, vertical) = (horizontal as usize, vertical as usize);
+                let blocks = (self.width as usize).div_ceil(horizontal) * rows.div_ceil(vertical);
+                blocks * (horizontal * vertical + 2)
+            }
+            None => self.planes[plane].0 * rows,
+        }
+    }
+
+    /// Decompresses a segment of `plane` storing `rows` rows and undoes its
+    /// predictor. Rows cut short are left as they are.
+    pub fn decode(&self, plane: usize, rows: u32, mut bytes: Vec<u8>) -> Result<Vec<u8>> {
+        if self.reversed_bits {
+            for byte in &mut bytes {
+                *byte = byte.reverse_bits();
+            }
+        }
+        let mut decoded = self.codec.decompress(bytes, rows, self.stored_size(plane, rows))?;
+        if let Some(endian) = self.predictor {
+            let (row_bytes, samples) = self.planes[plane];
+            for row in decoded.chunks_exact_mut(row_bytes) {
+                match self.sample_bytes {
+                    1 => predictor::undo_horizontal_differencing(row, samples),
+                    bytes => {
+                        predictor::undo_horizontal_differencing_wide(row, samples, bytes, endian)
+                    }
+                }
+            }
+        }
+        if self.inverted && plane == 0 {
+            // Flipping every bit of an unsigned integer subtracts it from
+            // its maximum, whatever its size and byte order
+            let (row_bytes, samples) = self.planes[0];
+            if samples == 1 {
+                decoded.iter_mut().for_each(|byte| *byte = !*byte);
+            } else {
+                let pixel_bytes = row_bytes / self.width as usize;
+                for pixel in decoded.chunks_exact_mut(pixel_bytes) {
+                    pixel[..self.sample_bytes].iter_mut().for_each(|byte| *byte = !*byte);
+                }
+            }
+        }
+        if let Some(subsampling) = self.subsampling {
+            decoded = ycbcr::upsample(&decoded, self.width, subsampling);
+        }
+        Ok(decoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let context = DecodeContext::new(1, Some(Endian::Little), 3, &[8, 8], false);
+        assert_eq!(context.row_bytes(0), 6);
+        let decoded = context.decode(0, 1, vec![1, 2, 1, 1, 1, 1, 5, 5, 1, 1]).unwrap();
+        assert_eq!(decoded, [1, 2, 2, 3, 3, 4, 5, 5, 1, 1]);
+        let context = DecodeContext::new(1, Some(Endian::Big), 2, &[16], false);
+        assert_eq!(context.decode(0, 1, vec![1, 255, 0, 2]).unwrap(), [1, 255, 2, 1]);
+
+        let context = DecodeContext::new(1, None, 3, &[4, 16], true);
+        assert_eq!((context.row_bytes(0), context.row_bytes(1)), (2, 6));
+        assert!(DecodeContext::new(34712, None, 3, &[8], false).decode(0, 1, vec![]).is_err());
+
+        let context = DecodeContext::new(5, None, 7, &[8], false);
+        let lzw = vec![0x80, 0x10, 0x48, 0x50, 0x28, 0x24, 0x04];
+        assert_eq!(context.decode(0, 1, lzw).unwrap(), b"ABABABA");
+
+        let mut context = DecodeContext::new(1, None, 8, &[1], false);
+        context.set_reversed_bits();
+        assert_eq!(context.decode(0, 1, vec![0b0000_0101]).unwrap(), [0b1010_0000]);
+
+        let mut context = DecodeContext::new(1, None, 2, &[16, 8], false);
+        context.set_inverted(true);
+        let decoded = context.decode(0, 1, vec![0, 1, 2, 3, 4, 5]).unwrap();
+        assert_eq!(decoded, [255, 254, 2, 252, 251, 5]);
+
+        let mut context = DecodeContext::new(1, None, 2, &[8, 8, 8], false);
+        context.set_subsampling(2, 1);
+        let decoded = context.decode(0, 1, vec![1, 2, 3, 4]).unwrap();
+        assert_eq!(decoded, [1, 3, 4, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,68 @@
+//! Transparency masks, the 1-bit subfiles telling which pixels of the
+//! preceding image are displayed.
+
+/// A decoded transparency mask, one bit per pixel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransparencyMask {
+    width: u32,
+    length: u32,
+    /// Rows of bits, most significant first, each starting on a byte.
+    bits: Vec<u8>,
+}
+
+impl TransparencyMask {
+    /// Creates a mask from rows of bits, most significant first, where 1
+    /// marks an opaque pixel. Each row starts on a byte boundary.
+    ///
+    /// Returns `None` when `bits` is shorter than `length` rows.
+    pub fn new(width: u32, length: u32, bits: Vec<u8>) -> Option<TransparencyMask> {
+        let row_bytes = (width as usize).div_ceil(8);
+        if bits.len() < row_bytes * length as usize {
+            return None;
+        }
+        Some(TransparencyMask {
+            width,
+            length,
+            bits,
+        })
+    }
+
+    /// The number of columns of the mask.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The number of rows of the mask.
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    /// Whether the pixel at column `x` and row `y` is displayed. Pixels
+    /// outside of the mask are transparent.
+    pub fn is_opaque(&self, x: u32, y: u32) -> bool {
+        if x >= self.width || y >= self.length {
+            return false;
+        }
+        let row_bytes = (self.width as usize).div_ceil(8);
+        let byte = self.bits[y as usize * row_bytes + x as usize / 8];
+        byte & (0x80 >> (x % 8)) != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_opaque() {
+        let mask = TransparencyMask::new(10, 2, vec![0b1000_0000, 0b0100_0000, 0, 0xff]).unwrap();
+        assert!(mask.is_opaque(0, 0));
+        assert!(!mask.is_opaque(1, 0));
+        assert!(mask.is_opaque(9, 0));
+        assert!(!mask.is_opaque(8, 0));
+        assert!(mask.is_opaque(8, 1));
+        assert!(!mask.is_opaque(10, 1));
+        assert!(!mask.is_opaque(0, 2));
+        assert!(TransparencyMask::new(10, 3, vec![0; 4]).is_none());
+    }
+}
@@ -0,0 +1,1571 @@
+//! Access to the pixel data of a directory.
+use std::io::{Read, Seek};
+use std::mem;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use cancel::{self, CancellationToken};
+#[cfg(feature = "jpeg")]
+use compression::OldJpeg;
+use endian::Endian;
+use error::{CorruptError, Result, TiffError};
+use layout::Segment;
+use planar;
+use reader::{DirectoryHandle, TIFFReader};
+#[cfg(feature = "prefetch")]
+use source::Prefetcher;
+use tag::*;
+use value::TIFFValue;
+use writer::invalid_input;
+
+mod baseline;
+mod cfa;
+mod context;
+mod mask;
+mod rows;
+mod predictor;
+mod statistics;
+mod unpack;
+mod ycbcr;
+
+use self::context::DecodeContext;
+use self::statistics::Accumulator;
+
+pub use self::baseline::RgbaImage;
+pub use self::cfa::{CfaColor, CfaPattern, Mosaic};
+pub use self::mask::TransparencyMask;
+pub use self::rows::Rows;
+pub use self::statistics::BandStatistics;
+
+/// Image samples decoded from the strips of a directory, as bytes or as
+/// typed samples.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageData<T = u8> {
+    /// Packed samples of the recovered rows.
+    pub data: Vec<T>,
+    /// Number of complete rows recovered. It is lower than the image length
+    /// when the strip data is truncated.
+    pub rows: u32,
+}
+
+/// The image stored in the current directory of a `TIFFReader`.
+pub struct Image<'a, R: Read + Seek + 'a> {
+    reader: &'a mut TIFFReader<R>,
+    width: u32,
+    length: u32,
+    rows_per_strip: u32,
+    /// Length in pixels of the tiles of tiled images.
+    tile_length: Option<u32>,
+    bits_per_sample: Vec<u16>,
+    planar: bool,
+    context: DecodeContext,
+    /// The value of the samples holding no data.
+    no_data: Option<f64>,
+    /// The prefetcher of the source and the number of segments to read ahead.
+    #[cfg(feature = "prefetch")]
+    read_ahead: Option<(Prefetcher, usize)>,
+}
+
+impl<'a, R: Read + Seek> Image<'a, R> {
+    /// Reads the layout of the image stored in the directory of `handle`,
+    /// which the accessors of `reader` then work on.
+    pub fn from_directory(
+        reader: &'a mut TIFFReader<R>,
+        handle: &DirectoryHandle,
+    ) -> Result<Image<'a, R>> {
+        reader.select_directory(handle.index())?;
+        Image::new(reader)
+    }
+
+    /// Reads the layout of the image stored in the current directory of `reader`.
+    pub fn new(reader: &'a mut TIFFReader<R>) -> Result<Image<'a, R>> {
+        let width = reader
+            .get_field::<ImageWidth>()
+            .ok_or(CorruptError::Invalid("Missing ImageWidth"))?
+            .0;
+        let length = reader
+            .get_field::<ImageLength>()
+            .ok_or(CorruptError::Invalid("Missing ImageLength"))?
+            .0;
+        if width == 0 || length == 0 {
+            return Err(CorruptError::Invalid("Image has no pixels").into());
+        }
+
+        let rows_per_strip = reader
+            .get_field::<RowsPerStrip>()
+            .map_or(length, |e| e.0)
+            .max(1)
+            .min(length);
+        let samples = reader
+            .get_field::<SamplesPerPixel>()
+            .unwrap_or_default()
+            .0;
+        let bits_per_sample = reader
+            .get_field::<BitsPerSample>()
+            .map(|e| e.0)
+            .unwrap_or_else(|| vec![1; usize::from(samples)]);
+        if bits_per_sample.is_empty() || bits_per_sample.contains(&0) {
+            return Err(CorruptError::Invalid("Invalid BitsPerSample").into());
+        }
+
+        let planar = bits_per_sample.len() > 1
+            && reader.get_field::<PlanarConfiguration>() == Some(PlanarConfiguration::Planar);
+
+        let compression = reader
+            .read_tag(Tag::Compression)?
+            .and_then(|value| value.as_unsigned())
+            .and_then(|value| value.first().cloned())
+            .map_or(1, |value| value as u16);
+        let predictor = reader.get_field::<Predictor>() == Some(Predictor::HorizontalDifferencing);
+        let uniform = bits_per_sample.iter().all(|b| *b == bits_per_sample[0]);
+        if predictor && !(uniform && [8, 16, 32].contains(&bits_per_sample[0])) {
+            return Err(TiffError::Unsupported("Predictor with samples of unequal or odd sizes"));
+        }
+        let tiles = (reader.get_field::<TileWidth>(), reader.get_field::<TileLength>());
+        let (segment_width, tile_length) = match tiles {
+            (Some(tile_width), Some(tile_length)) if tile_width.0 > 0 => {
+                (tile_width.0, Some(tile_length.0))
+            }
+            _ => (width, None),
+        };
+        let byte_order = if predictor { Some(reader.endianness()) } else { None };
+        let mut context =
+            DecodeContext::new(compression, byte_order, segment_width, &bits_per_sample, planar);
+        if reader.get_field::<FillOrder>() == Some(FillOrder::LowerColumnsToLowerOrderBits) {
+            context.set_reversed_bits();
+        }
+
+        let photometric = reader.get_field::<PhotometricInterpretation>();
+        if (2..=4).contains(&compression) {
+            if bits_per_sample != [1] {
+                return Err(CorruptError::Invalid("Fax compression of non-bilevel samples").into());
+            }
+            let options = match compression {
+                4 => reader.get_field::<T6Options>().map(|options| options.0),
+                _ => reader.get_field::<T4Options>().map(|options| options.0),
+            };
+            let options = options.unwrap_or(0);
+            let black_is_zero = photometric == Some(PhotometricInterpretation::BlackIsZero);
+            context.set_fax_options(options, black_is_zero);
+        }
+        if compression == 6 || compression == 7 {
+            if bits_per_sample.iter().any(|b| *b != 8) {
+                return Err(TiffError::Unsupported("JPEG with samples other than 8-bit"));
+            }
+            match reader.read_tag(Tag::JPEGTables)? {
+                Some(TIFFValue::Undefined(tables)) | Some(TIFFValue::Byte(tables)) => {
+                    context.set_jpeg_tables(tables)
+                }
+                _ => {}
+            }
+        }
+        if compression == 50001 && bits_per_sample.iter().any(|b| *b != 8) {
+            return Err(TiffError::Unsupported("WebP with samples other than 8-bit"));
+        }
+        #[cfg(feature = "jpeg")]
+        {
+            if compression == 6 {
+                let segment_length = tile_length.unwrap_or(rows_per_strip);
+                let jpeg = OldJpeg::read(reader, segment_width, segment_length)?;
+                context.set_old_jpeg(jpeg);
+            }
+        }
+
+        // Chroma subsampling only applies to chunky YCbCr images
+        if photometric == Some(PhotometricInterpretation::YCbCr) && bits_per_sample == [8, 8, 8] {
+            let subsampling = reader.read_tag(Tag::YCbCrSubSampling)?;
+            let factor = |index| {
+                subsampling
+                    .as_ref()
+                    .and_then(|value| value.unsigned_at(index))
+                    .map_or(2, |factor| factor as u32)
+            };
+            let (horizontal, vertical) = (factor(0), factor(1));
+            let valid = [1, 2, 4].contains(&horizontal) && [1, 2, 4].contains(&vertical);
+            if !valid || vertical > horizontal {
+                return Err(CorruptError::Invalid("Invalid YCbCrSubSampling").into());
+            }
+            // JPEG decoding upsamples the chroma already
+            let jpeg = compression == 6 || compression == 7;
+            if (horizontal, vertical) != (1, 1) && !planar && !jpeg {
+                if predictor {
+                    return Err(TiffError::Unsupported("Predictor with subsampled YCbCr"));
+                }
+                context.set_subsampling(horizontal, vertical);
+            }
+        }
+        let no_data = reader.no_data();
+
+        Ok(Image {
+            reader,
+            width,
+            length,
+            rows_per_strip,
+            tile_length,
+            bits_per_sample,
+            planar,
+            context,
+            no_data,
+            #[cfg(feature = "prefetch")]
+            read_ahead: None,
+        })
+    }
+
+    /// The number of columns of the image.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The number of rows of the image.
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+
+    /// The number of samples, or bands, of each pixel.
+    pub fn bands(&self) -> usize {
+        self.bits_per_sample.len()
+    }
+
+    /// The number of pixels of the image.
+    pub fn pixel_count(&self) -> u64 {
+        u64::from(self.width) * u64::from(self.length)
+    }
+
+    /// The size in bits of a pixel, all its samples included.
+    pub fn bits_per_pixel(&self) -> u64 {
+        self.bits_per_sample.iter().map(|b| u64::from(*b)).sum()
+    }
+
+    /// Size in bytes of a row of decoded samples, padded to a byte boundary.
+    pub fn scanline_bytes(&self) -> u64 {
+        self.estimated_region_size(self.width, 1)
+    }
+
+    /// Size in bytes of the samples `read_data` returns for the whole image,
+    /// computed from its layout without decoding anything.
+    ///
+    /// While decoding, the decompressed strips are held along with the output,
+    /// so the peak memory use is about twice this size.
+    pub fn estimated_decoded_size(&self) -> u64 {
+        self.estimated_region_size(self.width, self.length)
+    }
+
+    /// Size in bytes of the decoded samples of a region of `width` columns
+    /// and `length` rows, with rows starting on a byte boundary.
+    pub fn estimated_region_size(&self, width: u32, length: u32) -> u64 {
+        (u64::from(width) * self.bits_per_pixel()).div_ceil(8) * u64::from(length)
+    }
+
+    /// The value of the samples holding no data, from the `GDAL_NODATA` of
+    /// the directory unless set with `set_no_data`.
+    pub fn no_data(&self) -> Option<f64> {
+        self.no_data
+    }
+
+    /// Sets the value of the samples holding no data, such as a fill value
+    /// known to the caller, or ignores it with `None`.
+    pub fn set_no_data(&mut self, value: Option<f64>) {
+        self.no_data = value;
+    }
+
+    /// Sets whether the samples of `WhiteIsZero` images are inverted while
+    /// decoding, so that they read as `BlackIsZero` ones. Any extra sample,
+    /// such as alpha, is left as it is. Other images are not affected.
+    ///
+    /// The gray samples must be unsigned integers, of a whole number of bytes
+    /// when followed by extra samples in chunky images.
+    pub fn set_white_is_zero_inverted(&mut self, inverted: bool) -> Result<()> {
+        let photometric = self.reader.get_field::<PhotometricInterpretation>();
+        if photometric != Some(PhotometricInterpretation::WhiteIsZero) {
+            return Ok(());
+        }
+        let unsigned = self
+            .reader
+            .get_field::<SampleFormat>()
+            .unwrap_or_default()
+            .get(0)
+            .is_none_or(|format| format == SampleFormatValue::UnsignedInteger);
+        let bits = self.bits_per_sample[0];
+        let aligned = self.bands() == 1 || self.planar || bits.is_multiple_of(8);
+        if !unsigned || !aligned {
+            return Err(TiffError::Unsupported("Inversion of signed, float or packed samples"));
+        }
+        self.context.set_inverted(inverted);
+        Ok(())
+    }
+
+    /// Schedules, with `prefetcher`, the `count` strips or tiles following
+    /// each one read while decoding, so that they are fetched while it is
+    /// decoded. Strips are then read one at a time rather than in runs. The
+    /// reader must be built over the `PrefetchSource` of `prefetcher`.
+    #[cfg(feature = "prefetch")]
+    pub fn set_read_ahead(&mut self, prefetcher: Option<Prefetcher>, count: usize) {
+        self.read_ahead = prefetcher.map(|prefetcher| (prefetcher, count));
+    }
+
+    /// Reads the stored bytes of `segments[index]`, after scheduling the
+    /// read-ahead of the following ones.
+    fn read_segment(&mut self, segments: &[Segment], index: usize) -> Result<Vec<u8>> {
+        #[cfg(feature = "prefetch")]
+        {
+            if let Some((prefetcher, count)) = &self.read_ahead {
+                let next: Vec<(u64, u64)> = segments
+                    .iter()
+                    .skip(index + 1)
+                    .take(*count)
+                    .map(|next| (next.offset, next.byte_count))
+                    .collect();
+                let segment = &segments[index];
+                prefetcher.read_ahead((segment.offset, segment.byte_count), &next);
+            }
+        }
+
+        cancel::check(&self.reader.options().cancellation)?;
+        let segment = &segments[index];
+        let mut bytes = Vec::new();
+        self.reader.read_bytes_into(segment.offset, segment.byte_count, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Reads the stored bytes of the `count` strips starting at `first` like
+    /// `read_partial_strips_within`, at most `sizes` bytes of each when given.
+    /// With read-ahead, the strips are read one at a time, after scheduling
+    /// the following ones of their plane.
+    fn read_strips(
+        &mut self,
+        first: usize,
+        count: usize,
+        sizes: Option<&[u64]>,
+    ) -> Result<Vec<Vec<u8>>> {
+        #[cfg(feature = "prefetch")]
+        {
+            if let Some((prefetcher, ahead)) = self.read_ahead.clone() {
+                let per_plane = self.strips_per_plane();
+                let plane_end = (first / per_plane + 1) * per_plane;
+                let uncompressed = self.context.compression() == 1;
+                let mut strips = Vec::with_capacity(count);
+                for index in first..first + count {
+                    let size = sizes.map(|sizes| sizes[index - first]);
+                    let current = self.reader.partial_strip_range(index, size)?;
+                    // Strips past the end of StripOffsets are left to fail when read
+                    let next: Vec<(u64, u64)> = (index + 1..plane_end)
+                        .take(ahead)
+                        .filter_map(|strip| {
+                            let rows = u64::from(self.strip_rows(strip % per_plane));
+                            let row_bytes = self.context.row_bytes(strip / per_plane) as u64;
+                            let size = Some(row_bytes * rows).filter(|_| uncompressed);
+                            self.reader.partial_strip_range(strip, size).ok()
+                        })
+                        .collect();
+                    prefetcher.read_ahead(current, &next);
+
+                    let size = sizes.map(|sizes| &sizes[index - first..=index - first]);
+                    let strip = self.reader.read_partial_strips_within(index..index + 1, size)?;
+                    strips.extend(strip);
+                }
+                return Ok(strips);
+            }
+        }
+
+        self.reader.read_partial_strips_within(first..first + count, sizes)
+    }
+
+    /// Size in bytes of the samples, which must all be unsigned integers of
+    /// 8, 16 or 32 bits.
+    fn unsigned_sample_size(&mut self) -> Result<usize> {
+        let bits = self.bits_per_sample[0];
+        let unsigned = self
+            .reader
+            .get_field::<SampleFormat>()
+            .unwrap_or_default()
+            .0
+            .iter()
+            .all(|format| *format == SampleFormatValue::UnsignedInteger);
+        let same_bits = self.bits_per_sample.iter().all(|b| *b == bits);
+        if !unsigned || !same_bits || ![8, 16, 32].contains(&bits) {
+            return Err(TiffError::Unsupported("Samples other than 8, 16 or 32-bit unsigned"));
+        }
+        Ok(usize::from(bits / 8))
+    }
+
+    /// Number of strips holding the rows of a single band of a planar image.
+    fn strips_per_plane(&self) -> usize {
+        self.length.div_ceil(self.rows_per_strip) as usize
+    }
+
+    /// Number of rows stored in strip `index`, the last strip may be shorter.
+    fn strip_rows(&self, index: usize) -> u32 {
+        let first_row = index as u64 * u64::from(self.rows_per_strip);
+        let remaining = u64::from(self.length).saturating_sub(first_row);
+        remaining.min(u64::from(self.rows_per_strip)) as u32
+    }
+
+    /// Number of rows stored in `segment`: tiles are whole even past the
+    /// bottom of the image, while the last strip may be shorter.
+    fn segment_rows(&self, segment: &Segment) -> u32 {
+        self.tile_length.unwrap_or(segment.length)
+    }
+
+    /// Decodes the image samples, strip after strip.
+    ///
+    /// Decoding stops at the first strip which is cut short, keeping every
+    /// complete row read so far, so that truncated files can be partially
+    /// recovered. Compare `ImageData::rows` to `length()` to detect it.
+    ///
+    /// With the `rayon` feature, strips are read sequentially and decompressed
+    /// in parallel.
+    pub fn read_data(&mut self) -> Result<ImageData> {
+        let mut data = Vec::new();
+        let rows = self.read_data_into(&mut data)?;
+        Ok(ImageData { data, rows })
+    }
+
+    /// Like `read_data`, but replaces the content of `data` instead of
+    /// allocating a new buffer, and returns the number of rows recovered.
+    ///
+    /// The samples of planar images are interleaved, as in chunky images.
+    pub fn read_data_into(&mut self, data: &mut Vec<u8>) -> Result<u32> {
+        if self.planar {
+            let bands: Vec<usize> = (0..self.bands()).collect();
+            return self.read_interleaved(&bands, data);
+        }
+
+        let strip_count = self.reader.strip_count()?;
+        self.decode_strips(0, strip_count, 0, data)
+    }
+
+    /// Returns an iterator over the decoded rows of the image, which only
+    /// holds a strip at a time whatever `RowsPerStrip`. The samples of planar
+    /// images are interleaved, as in chunky images.
+    ///
+    /// Iteration stops after the last complete row of a strip cut short, or
+    /// after the first error.
+    pub fn rows<'b>(&'b mut self) -> Rows<'b, 'a, R> {
+        Rows::new(self)
+    }
+
+    /// Decodes the image at 1/`factor` of its resolution, keeping the top
+    /// left pixel of each `factor` × `factor` block, for a factor of 1, 2, 4
+    /// or 8. The result has `width().div_ceil(factor)` columns, with rows laid
+    /// out as in `read_data`.
+    ///
+    /// Strips holding none of the kept rows are skipped, and the others are
+    /// decoded one at a time, so that previews of large images only take
+    /// the memory of the preview and of a strip.
+    pub fn read_reduced(&mut self, factor: u32) -> Result<ImageData> {
+        if ![1, 2, 4, 8].contains(&factor) {
+            return Err(invalid_input("Reduction factor other than 1, 2, 4 or 8").into());
+        }
+        let bits = self.bits_per_pixel() as usize;
+        let width = self.width.div_ceil(factor) as usize;
+        let row_bytes = self.scanline_bytes() as usize;
+        let reduced_row = (width * bits).div_ceil(8);
+        let step = factor as usize * bits;
+
+        let mut data = Vec::with_capacity(reduced_row * self.length.div_ceil(factor) as usize);
+        let mut rows = 0;
+        let mut strip = Vec::new();
+        for index in 0..self.strips_per_plane() {
+            let first = index as u32 * self.rows_per_strip;
+            let count = self.strip_rows(index);
+            let kept = (first.div_ceil(factor) * factor..first + count).step_by(factor as usize);
+            if kept.len() == 0 {
+                continue;
+            }
+            let decoded = self.read_strip_rows(index, &mut strip)?;
+            for row in kept {
+                if row - first >= decoded {
+                    return Ok(ImageData { data, rows });
+                }
+                let source = &strip[(row - first) as usize * row_bytes..];
+                let start = data.len();
+                data.resize(start + reduced_row, 0);
+                for x in 0..width {
+                    planar::copy_bits(source, x * step, &mut data[start..], x * bits, bits);
+                }
+                rows += 1;
+            }
+        }
+        Ok(ImageData { data, rows })
+    }
+
+    /// Like `read_data`, but expands samples of 1, 2 or 4 bits to one byte
+    /// each, so that bilevel and low bit depth images can be used as any 8-bit
+    /// one. Values are not scaled: 1-bit samples are 0 or 1.
+    ///
+    /// All samples must have the same size, of 8 bits at most.
+    pub fn read_unpacked(&mut self) -> Result<ImageData> {
+        let bits = self.bits_per_sample[0];
+        if bits > 8 || self.bits_per_sample.iter().any(|b| *b != bits) {
+            return Err(TiffError::Unsupported("Unpacking samples over 8-bit or of unequal sizes"));
+        }
+        let ImageData { data, rows } = self.read_data()?;
+        if bits == 8 {
+            return Ok(ImageData { data, rows });
+        }
+        let row_samples = self.width as usize * self.bands();
+        let data = unpack::unpack(&data, bits, row_samples);
+        Ok(ImageData { data, rows })
+    }
+
+    /// Like `read_data`, for images of 16-bit integer samples, which are
+    /// returned in the native byte order whatever the one of the file.
+    /// Signed samples are returned as their bit patterns, to be cast to
+    /// `i16`.
+    pub fn read_u16(&mut self) -> Result<ImageData<u16>> {
+        self.check_samples(16, false)?;
+        let endian = self.reader.endianness();
+        let ImageData { data, rows } = self.read_data()?;
+        let data = data
+            .chunks_exact(2)
+            .map(|bytes| match endian {
+                Endian::Big => u16::from_be_bytes([bytes[0], bytes[1]]),
+                Endian::Little => u16::from_le_bytes([bytes[0], bytes[1]]),
+            })
+            .collect();
+        Ok(ImageData { data, rows })
+    }
+
+    /// Like `read_data`, for images of 32-bit floating point samples, as
+    /// `SampleFormat` 3 marks them.
+    pub fn read_f32(&mut self) -> Result<ImageData<f32>> {
+        self.check_samples(32, true)?;
+        let endian = self.reader.endianness();
+        let ImageData { data, rows } = self.read_data()?;
+        let data = data
+            .chunks_exact(4)
+            .map(|bytes| {
+                let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+                match endian {
+                    Endian::Big => f32::from_be_bytes(bytes),
+                    Endian::Little => f32::from_le_bytes(bytes),
+                }
+            })
+            .collect();
+        Ok(ImageData { data, rows })
+    }
+
+    /// Like `read_f32`, for images of 64-bit floating point samples.
+    pub fn read_f64(&mut self) -> Result<ImageData<f64>> {
+        self.check_samples(64, true)?;
+        let endian = self.reader.endianness();
+        let ImageData { data, rows } = self.read_data()?;
+        let data = data
+            .chunks_exact(8)
+            .map(|bytes| {
+                let mut array = [0; 8];
+                array.copy_from_slice(bytes);
+                match endian {
+                    Endian::Big => f64::from_be_bytes(array),
+                    Endian::Little => f64::from_le_bytes(array),
+                }
+            })
+            .collect();
+        Ok(ImageData { data, rows })
+    }
+
+    /// Checks that the samples all have `bits` bits and are floating point
+    /// numbers when `float` is set, integers otherwise.
+    fn check_samples(&mut self, bits: u16, float: bool) -> Result<()> {
+        let formats = self.reader.get_field::<SampleFormat>().unwrap_or_default();
+        let typed = (0..self.bands()).all(|band| {
+            let format = formats.get(band).unwrap_or(SampleFormatValue::UnsignedInteger);
+            (format == SampleFormatValue::IEEEFloatingPoint) == float
+        });
+        if !typed || self.bits_per_sample.iter().any(|b| *b != bits) {
+            return Err(TiffError::Unsupported("Samples of another size or format"));
+        }
+        Ok(())
+    }
+
+    /// Decodes the samples of `bands`, interleaved in the given order.
+    ///
+    /// Only the strips of the selected bands are read from planar images, so
+    /// extracting a band of a multispectral scene does not require decoding
+    /// the others. Samples of chunky images must be a whole number of bytes.
+    pub fn read_bands(&mut self, bands: &[usize]) -> Result<ImageData> {
+        if bands.iter().any(|band| *band >= self.bands()) {
+            return Err(TiffError::BandIndexOutOfBounds);
+        }
+        if self.planar {
+            let mut data = Vec::new();
+            let rows = self.read_interleaved(bands, &mut data)?;
+            return Ok(ImageData { data, rows });
+        }
+        if self.bits_per_sample.iter().any(|bits| bits % 8 != 0) {
+            return Err(TiffError::Unsupported("Band selection of samples not byte aligned"));
+        }
+        let sizes: Vec<usize> = self
+            .bits_per_sample
+            .iter()
+            .map(|bits| usize::from(bits / 8))
+            .collect();
+
+        let mut pixels = Vec::new();
+        let rows = self.read_data_into(&mut pixels)?;
+
+        // Offsets of the samples of each band within a pixel
+        let offsets: Vec<usize> = sizes
+            .iter()
+            .scan(0, |offset, size| {
+                *offset += size;
+                Some(*offset - size)
+            })
+            .collect();
+        let pixel_size: usize = sizes.iter().sum();
+        let size: usize = bands.iter().map(|band| sizes[*band]).sum();
+        let count = rows as usize * self.width as usize;
+        let mut data = Vec::with_capacity(count * size);
+        for pixel in pixels.chunks_exact(pixel_size).take(count) {
+            for band in bands {
+                data.extend_from_slice(&pixel[offsets[*band]..offsets[*band] + sizes[*band]]);
+            }
+        }
+
+        Ok(ImageData { data, rows })
+    }
+
+    /// Decodes strip `index` of the image, of every plane of planar images
+    /// interleaved, into `data`, and returns the number of rows recovered.
+    fn read_strip_rows(&mut self, index: usize, data: &mut Vec<u8>) -> Result<u32> {
+        if !self.planar {
+            return self.decode_strips(index, 1, 0, data);
+        }
+        let strips = self.strips_per_plane();
+        let mut planes = Vec::with_capacity(self.bands());
+        let mut rows = self.strip_rows(index);
+        for plane in 0..self.bands() {
+            let mut samples = Vec::new();
+            rows = rows.min(self.decode_strips(plane * strips + index, 1, plane, &mut samples)?);
+            planes.push(samples);
+        }
+        let planes: Vec<&[u8]> = planes.iter().map(|plane| &plane[..]).collect();
+        planar::interleave_into(&planes, &self.bits_per_sample, self.width, rows, data)?;
+        Ok(rows)
+    }
+
+    /// Decodes the planes of `bands` of a planar image and interleaves them
+    /// into `data`, keeping the rows recovered in every plane, whose number
+    /// is returned.
+    fn read_interleaved(&mut self, bands: &[usize], data: &mut Vec<u8>) -> Result<u32> {
+        let mut planes = Vec::with_capacity(bands.len());
+        let mut rows = self.length;
+        for band in bands {
+            let mut plane = Vec::new();
+            rows = rows.min(self.read_plane(*band, &mut plane)?);
+            planes.push(plane);
+        }
+
+        let planes: Vec<&[u8]> = planes.iter().map(|plane| &plane[..]).collect();
+        let bits: Vec<u16> = bands.iter().map(|band| self.bits_per_sample[*band]).collect();
+        planar::interleave_into(&planes, &bits, self.width, rows, data)?;
+        Ok(rows)
+    }
+
+    /// Decodes the samples of the region of `width` columns and `length` rows
+    /// whose top left pixel is at column `x` and row `y`, reading only the
+    /// strips or tiles it intersects.
+    ///
+    /// The samples of planar images are interleaved, as in chunky images.
+    /// Rows of pixels narrower than a byte start on a byte boundary, as in
+    /// `read_data`. The chroma of subsampled `YCbCr` images is upsampled
+    /// over whole blocks, so that any region gets the `Y Cb Cr` samples of
+    /// each of its pixels.
+    pub fn read_region(&mut self, x: u32, y: u32, width: u32, length: u32) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.read_region_into(x, y, width, length, &mut data)?;
+        Ok(data)
+    }
+
+    /// Like `read_region`, but replaces the content of `data` instead of
+    /// allocating a new buffer.
+    pub fn read_region_into(
+        &mut self,
+        x: u32,
+        y: u32,
+        width: u32,
+        length: u32,
+        data: &mut Vec<u8>,
+    ) -> Result<()> {
+        let right = u64::from(x) + u64::from(width);
+        let bottom = u64::from(y) + u64::from(length);
+        let outside = right > u64::from(self.width) || bottom > u64::from(self.length);
+        if width == 0 || length == 0 || outside {
+            return Err(invalid_input("Region outside of the image").into());
+        }
+        // Size in bits of the pixels of each plane
+        let plane_bits: Vec<usize> = if self.planar {
+            self.bits_per_sample.iter().map(|bits| usize::from(*bits)).collect()
+        } else {
+            vec![self.bits_per_pixel() as usize]
+        };
+        let segments: Vec<Segment> = self
+            .reader
+            .segments()?
+            .filter(|s| {
+                u64::from(s.x) < right
+                    && x < s.x + s.width
+                    && u64::from(s.y) < bottom
+                    && y < s.y + s.length
+            })
+            .collect();
+
+        let region_rows: Vec<usize> = plane_bits
+            .iter()
+            .map(|bits| (width as usize * bits).div_ceil(8))
+            .collect();
+        // Chunky regions are decoded straight into `data`
+        let mut regions: Vec<Vec<u8>> = region_rows.iter().map(|_| Vec::new()).collect();
+        if !self.planar {
+            regions[0] = mem::take(data);
+        }
+        for (region, row_bytes) in regions.iter_mut().zip(&region_rows) {
+            region.clear();
+            region.resize(row_bytes * length as usize, 0);
+        }
+        for (index, segment) in segments.iter().enumerate() {
+            let bytes = self.read_segment(&segments, index)?;
+            let rows = self.segment_rows(segment);
+            let decoded = self.context.decode(segment.plane, rows, bytes)?;
+            self.reader.count_decoded(decoded.len() as u64)?;
+            let row_bytes = self.context.row_bytes(segment.plane);
+            let (bits, region_row) = (plane_bits[segment.plane], region_rows[segment.plane]);
+            let region = &mut regions[segment.plane];
+
+            let left = x.max(segment.x);
+            let count = (x + width).min(segment.x + segment.width) - left;
+            let count = count as usize * bits;
+            for row in y.max(segment.y)..(y + length).min(segment.y + segment.length) {
+                let source = (row - segment.y) as usize * row_bytes * 8
+                    + (left - segment.x) as usize * bits;
+                let target = (row - y) as usize * region_row * 8 + (left - x) as usize * bits;
+                if decoded.len() * 8 < source + count {
+                    return Err(CorruptError::Invalid("Truncated strip or tile").into());
+                }
+                planar::copy_bits(&decoded, source, region, target, count);
+            }
+        }
+
+        if !self.planar {
+            *data = regions.swap_remove(0);
+            return Ok(());
+        }
+        let planes: Vec<&[u8]> = regions.iter().map(|plane| &plane[..]).collect();
+        planar::interleave_into(&planes, &self.bits_per_sample, width, length, data)
+    }
+
+    /// Computes the minimum, maximum and mean of the samples of each band,
+    /// along with their histogram when `histogram` is set, decoding a single
+    /// strip or tile at a time. Samples equal to the NoData value are left
+    /// out.
+    ///
+    /// Samples must be unsigned integers of 8, 16 or 32 bits. Histograms are
+    /// only counted for 8 and 16-bit samples.
+    pub fn statistics(&mut self, histogram: bool) -> Result<Vec<BandStatistics>> {
+        let size = self.unsigned_sample_size()?;
+        let bits = self.bits_per_sample[0];
+        let samples = if self.planar { 1 } else { self.bands() };
+        let endian = self.reader.endianness();
+        let mut bands: Vec<Accumulator> = (0..self.bands())
+            .map(|_| Accumulator::new(bits, histogram))
+            .collect();
+
+        let segments: Vec<Segment> = self.reader.segments()?.collect();
+        for (index, segment) in segments.iter().enumerate() {
+            let bytes = self.read_segment(&segments, index)?;
+            let rows = self.segment_rows(segment);
+            let decoded = self.context.decode(segment.plane, rows, bytes)?;
+            self.reader.count_decoded(decoded.len() as u64)?;
+
+            let row_bytes = self.context.row_bytes(segment.plane);
+            let used = segment.width as usize * samples * size;
+            for row in 0..segment.length as usize {
+                let start = row * row_bytes;
+                let row = decoded
+                    .get(start..start + used)
+                    .ok_or(CorruptError::Invalid("Truncated strip or tile"))?;
+                for (index, sample) in row.chunks_exact(size).enumerate() {
+                    let band = if self.planar { segment.plane } else { index % samples };
+                    let sample = statistics::read_sample(sample, endian);
+                    if self.no_data != Some(f64::from(sample)) {
+                        bands[band].add(sample);
+                    }
+                }
+            }
+        }
+        Ok(bands.into_iter().map(Accumulator::finish).collect())
+    }
+
+    /// Reads the raw samples of a color filter array image along with its
+    /// pattern, leaving demosaicing to the caller. Returns `None` when the
+    /// image is not a color filter array.
+    pub fn read_mosaic(&mut self) -> Result<Option<Mosaic>> {
+        let photometric = self.reader.get_field::<PhotometricInterpretation>();
+        if photometric != Some(PhotometricInterpretation::CFA) {
+            return Ok(None);
+        }
+        if self.bands() != 1 {
+            return Err(CorruptError::Invalid("CFA image with several samples per pixel").into());
+        }
+        let pattern = self
+            .reader
+            .cfa_pattern()?
+            .ok_or(CorruptError::Invalid("Missing CFAPattern"))?;
+
+        let ImageData { data, rows } = self.read_data()?;
+        Ok(Some(Mosaic {
+            pattern,
+            bits_per_sample: self.bits_per_sample[0],
+            data,
+            rows,
+        }))
+    }
+
+    /// Decodes the transparency mask stored in the current directory, or
+    /// returns `None` when it is not a mask. Rows missing from truncated
+    /// files are transparent.
+    pub fn read_transparency_mask(&mut self) -> Result<Option<TransparencyMask>> {
+        let photometric = self.reader.get_field::<PhotometricInterpretation>();
+        if photometric != Some(PhotometricInterpretation::TransparencyMask) {
+            return Ok(None);
+        }
+        if self.bits_per_sample != [1] {
+            return Err(CorruptError::Invalid("Transparency mask samples are not 1-bit").into());
+        }
+
+        let mut data = self.read_data()?.data;
+        data.resize(self.scanline_bytes() as usize * self.length as usize, 0);
+        Ok(TransparencyMask::new(self.width, self.length, data))
+    }
+
+    /// Decodes the image samples and builds the mask of the pixels holding
+    /// data, those whose samples are not all equal to the NoData value. Rows
+    /// missing from truncated files hold no data. Returns `None` when there
+    /// is no NoData value.
+    ///
+    /// Samples must be unsigned integers of 8, 16 or 32 bits. The mask can be
+    /// given to `read_data_with_mask` to get an alpha band.
+    pub fn read_validity_mask(&mut self) -> Result<Option<TransparencyMask>> {
+        let no_data = match self.no_data {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let size = self.unsigned_sample_size()?;
+        let endian = self.reader.endianness();
+        let ImageData { data, rows } = self.read_data()?;
+
+        let row_bytes = (self.width as usize).div_ceil(8);
+        let mut bits = vec![0; row_bytes * self.length as usize];
+        let count = rows as usize * self.width as usize;
+        let pixels = data.chunks_exact(size * self.bands()).take(count);
+        for (index, pixel) in pixels.enumerate() {
+            let valid = pixel
+                .chunks_exact(size)
+                .any(|sample| f64::from(statistics::read_sample(sample, endian)) != no_data);
+            if valid {
+                let (x, y) = (index % self.width as usize, index / self.width as usize);
+                bits[y * row_bytes + x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        Ok(TransparencyMask::new(self.width, self.length, bits))
+    }
+
+    /// Decodes the image samples like `read_data`, adding to each pixel an
+    /// alpha sample from `mask`, the maximum value where it is opaque and 0
+    /// elsewhere.
+    ///
+    /// The mask must have the size of the image, whose samples must all have
+    /// the same whole number of bytes.
+    pub fn read_data_with_mask(&mut self, mask: &TransparencyMask) -> Result<ImageData> {
+        if mask.width() != self.width || mask.length() != self.length {
+            return Err(invalid_input("Mask size differs from the image size").into());
+        }
+        let bits = self.bits_per_sample[0];
+        if !bits.is_multiple_of(8) || self.bits_per_sample.iter().any(|b| *b != bits) {
+            return Err(TiffError::Unsupported("Alpha for samples of different or partial bytes"));
+        }
+        let size = usize::from(bits / 8);
+
+        let ImageData { data, rows } = self.read_data()?;
+        let pixel_size = size * self.bands();
+        let mut output = Vec::with_capacity(data.len() / pixel_size * (pixel_size + size));
+        for (index, pixel) in data.chunks_exact(pixel_size).enumerate() {
+            let (x, y) = (index as u32 % self.width, index as u32 / self.width);
+            let alpha = if mask.is_opaque(x, y) { 0xff } else { 0 };
+            output.extend_from_slice(pixel);
+            output.extend((0..size).map(|_| alpha));
+        }
+        Ok(ImageData { data: output, rows })
+    }
+
+    /// Decodes the samples of `band` of a planar image into `data`, and
+    /// returns the number of rows recovered.
+    fn read_plane(&mut self, band: usize, data: &mut Vec<u8>) -> Result<u32> {
+        let strips = self.strips_per_plane();
+        self.decode_strips(band * strips, strips, band, data)
+    }
+
+    /// Reads the uncompressed strips starting at `first`, of `sizes` bytes,
+    /// checking `StripByteCounts` against them. Extra bytes are left out and
+    /// strips declared shorter are padded with zeros, while strips cut short
+    /// by the end of the file are kept as they are.
+    fn read_uncompressed_strips(&mut self, first: usize, sizes: &[u64]) -> Result<Vec<Vec<u8>>> {
+        let mut strips = self.read_strips(first, sizes.len(), Some(sizes))?;
+        let counts = self.reader.read_unsigned(Tag::StripByteCounts)?;
+        for (index, (strip, size)) in strips.iter_mut().zip(sizes).enumerate() {
+            let declared = counts[first + index];
+            if declared != *size {
+                self.reader.warn_strip_size(first + index, *size, declared);
+            }
+            if declared < *size && strip.len() as u64 == declared {
+                strip.resize(*size as usize, 0);
+            }
+        }
+        Ok(strips)
+    }
+
+    /// Decodes `count` strips of `plane` starting at `first` into `data`, and
+    /// returns the number of complete rows recovered.
+    fn decode_strips(
+        &mut self,
+        first: usize,
+        count: usize,
+        plane: usize,
+        data: &mut Vec<u8>,
+    ) -> Result<u32> {
+        // Index of the first strip within its plane
+        let skipped = first % self.strips_per_plane();
+        let used = (0..count)
+            .take_while(|index| self.strip_rows(skipped + *index) > 0)
+            .count();
+        let row_bytes = self.context.row_bytes(plane) as u64;
+        let strips = if self.context.compression() == 1 {
+            let sizes: Vec<u64> = (0..used)
+                .map(|index| row_bytes * u64::from(self.strip_rows(skipped + index)))
+                .collect();
+            self.read_uncompressed_strips(first, &sizes)?
+        } else {
+            self.read_strips(first, used, None)?
+        };
+        debug!(
+            "Decoding {} strips with compression {}, predictor {}",
+            used,
+            self.context.compression(),
+            self.context.predictor()
+        );
+        let rows: Vec<u32> = (0..used).map(|index| self.strip_rows(skipped + index)).collect();
+        let cancellation = &self.reader.options().cancellation;
+        let strips = decode_all(&self.context, cancellation, plane, &rows, strips)?;
+        let size = strips.iter().map(|strip| strip.len() as u64).sum();
+        self.reader.count_decoded(size)?;
+
+        data.clear();
+        let mut rows = 0;
+        for (index, bytes) in strips.iter().enumerate() {
+            let expected = u64::from(self.strip_rows(skipped + index));
+            let complete = (bytes.len() as u64 / row_bytes).min(expected);
+            data.extend_from_slice(&bytes[..(complete * row_bytes) as usize]);
+            rows += complete as u32;
+
+            if complete < expected {
+                warn!("Strip {} is truncated, keeping {} rows", first + index, rows);
+                break;
+            }
+        }
+
+        Ok(rows)
+    }
+}
+
+#[cfg(not(feature = "rayon"))]
+fn decode_all(
+    context: &DecodeContext,
+    cancellation: &Option<CancellationToken>,
+    plane: usize,
+    rows: &[u32],
+    strips: Vec<Vec<u8>>,
+) -> Result<Vec<Vec<u8>>> {
+    strips
+        .into_iter()
+        .zip(rows)
+        .map(|(bytes, rows)| {
+            cancel::check(cancellation)?;
+            context.decode(plane, *rows, bytes)
+        })
+        .collect()
+}
+
+#[cfg(feature = "rayon")]
+fn decode_all(
+    context: &DecodeContext,
+    cancellation: &Option<CancellationToken>,
+    plane: usize,
+    rows: &[u32],
+    strips: Vec<Vec<u8>>,
+) -> Result<Vec<Vec<u8>>> {
+    strips
+        .into_par_iter()
+        .zip(rows)
+        .map(|(bytes, rows)| {
+            cancel::check(cancellation)?;
+            context.decode(plane, *rows, bytes)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use limits::Limits;
+    use reader::tests::{le_header, push_ifd, write_then_read};
+    use std::io::Cursor;
+    use std::slice;
+    use warning::Warning;
+    use writer::{Directory, TIFFWriter};
+
+    /// Lays out a 3x2 image of `bands` 8-bit bands, one row per strip, where
+    /// the sample of band `b` at pixel `p` is `16 * b + p`.
+    fn multi_band_image(bands: u16, planar: bool) -> (Directory, Vec<Vec<u8>>) {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(3));
+        directory.set_field(&ImageLength(2));
+        directory.set_field(&RowsPerStrip(1));
+        directory.set_field(&SamplesPerPixel(bands));
+        directory.set_field(&BitsPerSample(vec![8; usize::from(bands)]));
+
+        let bands = bands as u8;
+        let strips = if planar {
+            directory.set_field(&PlanarConfiguration::Planar);
+            (0..2 * bands)
+                .map(|index| {
+                    let (band, row) = (index / 2, index % 2);
+                    (0..3).map(|column| 16 * band + 3 * row + column).collect()
+                })
+                .collect()
+        } else {
+            (0..2)
+                .map(|row| {
+                    (0..3)
+                        .flat_map(|column| (0..bands).map(move |b| 16 * b + 3 * row + column))
+                        .collect()
+                })
+                .collect()
+        };
+        (directory, strips)
+    }
+
+    /// Builds an uncompressed 4x4 grayscale image stored in two strips of two rows.
+    fn two_strips_image() -> Vec<u8> {
+        let mut bytes = le_header();
+        let entries = [
+            (256, 3, 1, 4),
+            (257, 3, 1, 4),
+            (258, 3, 1, 8),
+            (259, 3, 1, 1),
+            (262, 3, 1, 1),
+            (273, 4, 2, 110),
+            (278, 3, 1, 2),
+            (279, 4, 2, 118),
+        ];
+        push_ifd(&mut bytes, &entries, 0);
+
+        for value in &[126u32, 134, 8, 8] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend((0..16).map(|e| e as u8));
+        bytes
+    }
+
+    #[test]
+    fn test_read_data() {
+        let mut read = TIFFReader::new(Cursor::new(two_strips_image())).unwrap();
+        let mut image = Image::new(&mut read).unwrap();
+        let decoded = image.read_data().unwrap();
+        assert_eq!(decoded.rows, 4);
+        assert_eq!(decoded.data, (0..16).collect::<Vec<u8>>());
+
+        let mut data = vec![0xff; 64];
+        assert_eq!(image.read_data_into(&mut data).unwrap(), 4);
+        assert_eq!(data, decoded.data);
+
+        let mut strip = Vec::new();
+        read.read_strip_into(1, &mut strip).unwrap();
+        assert_eq!(strip, (8..16).collect::<Vec<u8>>());
+        let mut slice = [0xff; 10];
+        assert_eq!(read.read_strip_to_slice(1, &mut slice).unwrap(), 8);
+        assert_eq!(slice[..8], strip[..]);
+        assert_eq!(slice[8..], [0xff, 0xff]);
+        assert!(read.read_strip_to_slice(1, &mut slice[..7]).is_err());
+        let first = read.read_strip(0).unwrap();
+        assert_eq!(read.read_partial_strips(0..2).unwrap(), vec![first, strip]);
+        assert!(read.read_partial_strips(1..3).is_err());
+    }
+
+    #[test]
+    fn test_read_region() {
+        let mut read = TIFFReader::new(Cursor::new(two_strips_image())).unwrap();
+        let mut image = Image::new(&mut read).unwrap();
+        // Rows 1 to 2 straddle both strips
+        assert_eq!(image.read_region(1, 1, 2, 2).unwrap(), [5, 6, 9, 10]);
+        assert_eq!(image.read_region(0, 3, 4, 1).unwrap(), [12, 13, 14, 15]);
+        assert!(image.read_region(3, 0, 2, 1).is_err());
+        assert!(image.read_region(0, 0, 0, 1).is_err());
+
+        let mut data = Vec::with_capacity(64);
+        image.read_region_into(0, 3, 4, 1, &mut data).unwrap();
+        assert_eq!(data, [12, 13, 14, 15]);
+        image.read_region_into(1, 1, 2, 2, &mut data).unwrap();
+        assert_eq!((&data[..], data.capacity()), (&[5, 6, 9, 10][..], 64));
+
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(10));
+        directory.set_field(&ImageLength(2));
+        directory.set_field(&BitsPerSample(vec![1]));
+        directory.set_field(&PhotometricInterpretation::BlackIsZero);
+        let mut read = write_then_read(&directory, &[vec![0b1011_0011, 0b1100_0000, 0xff, 0x00]]);
+        let mut image = Image::new(&mut read).unwrap();
+        assert_eq!(image.read_region(2, 0, 7, 2).unwrap(), [0b1100_1110, 0b1111_1100]);
+    }
+
+    #[test]
+    fn test_read_subsampled_region() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(3));
+        directory.set_field(&ImageLength(2));
+        directory.set_field(&SamplesPerPixel(3));
+        directory.set_field(&BitsPerSample(vec![8, 8, 8]));
+        directory.set_field(&PhotometricInterpretation::YCbCr);
+        // Two blocks of 2x2 pixels, the second one padded
+        let strip = vec![1, 2, 3, 4, 50, 60, 5, 0, 6, 0, 70, 80];
+        let mut read = write_then_read(&directory, slice::from_ref(&strip));
+        let mut image = Image::new(&mut read).unwrap();
+        let expected = [2, 50, 60, 5, 70, 80, 4, 50, 60, 6, 70, 80];
+        assert_eq!(image.read_region(1, 0, 2, 2).unwrap(), expected);
+        assert_eq!(image.read_region(2, 1, 1, 1).unwrap(), [6, 70, 80]);
+        assert_eq!(image.read_data().unwrap().data.len(), 18);
+
+        directory.set(Tag::YCbCrSubSampling, TIFFValue::Short(vec![1, 2]));
+        let mut read = write_then_read(&directory, &[strip]);
+        assert!(Image::new(&mut read).is_err());
+    }
+
+    #[test]
+    fn test_read_fax() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(10));
+        directory.set_field(&ImageLength(2));
+        directory.set_field(&BitsPerSample(vec![1]));
+        directory.set_field(&Compression::ModifiedHuffmanCompression);
+        directory.set_field(&PhotometricInterpretation::WhiteIsZero);
+        // 3 white, 2 black and 5 white pixels, then 10 black pixels
+        let strip = vec![0x8f, 0x00, 0x35, 0x08];
+        let mut read = write_then_read(&directory, slice::from_ref(&strip));
+        let data = Image::new(&mut read).unwrap().read_data().unwrap();
+        assert_eq!((data.data, data.rows), (vec![0x18, 0x00, 0xff, 0xc0], 2));
+
+        directory.set_field(&PhotometricInterpretation::BlackIsZero);
+        let mut read = write_then_read(&directory, slice::from_ref(&strip));
+        let data = Image::new(&mut read).unwrap().read_data().unwrap();
+        assert_eq!(data.data, [0xe7, 0xff, 0x00, 0x3f]);
+
+        directory.set_field(&BitsPerSample(vec![8]));
+        let mut read = write_then_read(&directory, &[strip]);
+        assert!(Image::new(&mut read).is_err());
+    }
+
+    #[test]
+    fn test_read_unpacked() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(3));
+        directory.set_field(&ImageLength(2));
+        directory.set_field(&BitsPerSample(vec![2]));
+        directory.set_field(&PhotometricInterpretation::BlackIsZero);
+        let strip = vec![0b0001_1000, 0b1000_0100];
+        let mut read = write_then_read(&directory, slice::from_ref(&strip));
+        let data = Image::new(&mut read).unwrap().read_unpacked().unwrap();
+        assert_eq!((data.data, data.rows), (vec![0, 1, 2, 2, 0, 1], 2));
+
+        // The same samples, filled least significant bit first
+        directory.set(Tag::FillOrder, TIFFValue::Short(vec![2]));
+        let reversed = strip.iter().map(|byte| byte.reverse_bits()).collect();
+        let mut read = write_then_read(&directory, &[reversed]);
+        let data = Image::new(&mut read).unwrap().read_unpacked().unwrap();
+        assert_eq!(data.data, [0, 1, 2, 2, 0, 1]);
+    }
+
+    #[test]
+    fn test_white_is_zero_inverted() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(2));
+        directory.set_field(&ImageLength(1));
+        directory.set_field(&SamplesPerPixel(2));
+        directory.set_field(&BitsPerSample(vec![8, 8]));
+        directory.set_field(&PhotometricInterpretation::WhiteIsZero);
+        let mut read = write_then_read(&directory, &[vec![0, 10, 200, 20]]);
+        let mut image = Image::new(&mut read).unwrap();
+        image.set_white_is_zero_inverted(true).unwrap();
+        assert_eq!(image.read_data().unwrap().data, [255, 10, 55, 20]);
+        assert_eq!(image.read_region(1, 0, 1, 1).unwrap(), [55, 20]);
+        // Converted once only
+        let gray = [255, 255, 255, 255, 55, 55, 55, 255];
+        assert_eq!(image.decode().unwrap().data, gray);
+        image.set_white_is_zero_inverted(false).unwrap();
+        assert_eq!(image.read_data().unwrap().data, [0, 10, 200, 20]);
+    }
+
+    #[test]
+    fn test_read_u16() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(2));
+        directory.set_field(&ImageLength(1));
+        directory.set_field(&BitsPerSample(vec![16]));
+        directory.set_field(&PhotometricInterpretation::BlackIsZero);
+        for endian in [Endian::Little, Endian::Big] {
+            let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), endian).unwrap();
+            writer.write_image(&directory, 1, |_| Ok(vec![1, 2, 3, 4])).unwrap();
+            let mut read = TIFFReader::new(Cursor::new(writer.into_inner().into_inner())).unwrap();
+            let data = Image::new(&mut read).unwrap().read_u16().unwrap();
+            let expected = match endian {
+                Endian::Little => [0x0201, 0x0403],
+                Endian::Big => [0x0102, 0x0304],
+            };
+            assert_eq!((data.data, data.rows), (expected.to_vec(), 1));
+        }
+
+        directory.set_field(&BitsPerSample(vec![8]));
+        let mut read = write_then_read(&directory, &[vec![1, 2]]);
+        assert!(Image::new(&mut read).unwrap().read_u16().is_err());
+    }
+
+    #[test]
+    fn test_read_floats() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(2));
+        directory.set_field(&ImageLength(1));
+        directory.set_field(&BitsPerSample(vec![32]));
+        directory.set_field(&PhotometricInterpretation::BlackIsZero);
+        directory.set(Tag::SampleFormat, TIFFValue::Short(vec![3]));
+        let strip = [1.5f32.to_le_bytes(), (-2.25f32).to_le_bytes()].concat();
+        let mut read = write_then_read(&directory, &[strip]);
+        let mut image = Image::new(&mut read).unwrap();
+        assert_eq!(image.read_f32().unwrap().data, [1.5, -2.25]);
+        assert!(image.read_f64().is_err());
+        assert!(image.read_u16().is_err());
+
+        directory.set_field(&BitsPerSample(vec![64]));
+        let strip = [0.1f64.to_le_bytes(), 1e300f64.to_le_bytes()].concat();
+        let mut read = write_then_read(&directory, &[strip]);
+        let data = Image::new(&mut read).unwrap().read_f64().unwrap();
+        assert_eq!((data.data, data.rows), (vec![0.1, 1e300], 1));
+    }
+
+    #[test]
+    fn test_truncated_strip() {
+        // Keep a row and a half of the last strip
+        let mut bytes = two_strips_image();
+        bytes.truncate(bytes.len() - 2);
+
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert!(read.read_strip(1).is_err());
+        assert_eq!(read.read_partial_strip(1).unwrap().len(), 6);
+
+        let mut image = Image::new(&mut read).unwrap();
+        let decoded = image.read_data().unwrap();
+        assert_eq!(decoded.rows, 3);
+        assert_eq!(decoded.data, (0..12).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_short_reads() {
+        /// Returns at most 3 bytes per read, like a pipe or a socket.
+        struct ShortReader(Cursor<Vec<u8>>);
+
+        impl Read for ShortReader {
+            fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
+                let length = buf.len().min(3);
+                self.0.read(&mut buf[..length])
+            }
+        }
+
+        impl Seek for ShortReader {
+            fn seek(&mut self, pos: ::std::io::SeekFrom) -> ::std::io::Result<u64> {
+                self.0.seek(pos)
+            }
+        }
+
+        // Strips are read in full, however short each read, before decoding
+        let mut read = TIFFReader::new(ShortReader(Cursor::new(two_strips_image()))).unwrap();
+        let decoded = Image::new(&mut read).unwrap().read_data().unwrap();
+        assert_eq!((decoded.data, decoded.rows), ((0..16).collect(), 4));
+
+        let mut bytes = two_strips_image();
+        bytes.truncate(bytes.len() - 2);
+        let mut read = TIFFReader::new(ShortReader(Cursor::new(bytes))).unwrap();
+        let decoded = Image::new(&mut read).unwrap().read_data().unwrap();
+        assert_eq!((decoded.data, decoded.rows), ((0..12).collect(), 3));
+    }
+
+    #[test]
+    fn test_rows() {
+        let mut read = TIFFReader::new(Cursor::new(two_strips_image())).unwrap();
+        let mut image = Image::new(&mut read).unwrap();
+        let rows: Vec<Vec<u8>> = image.rows().collect::<Result<_>>().unwrap();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[2], [8, 9, 10, 11]);
+
+        let mut bytes = two_strips_image();
+        bytes.truncate(bytes.len() - 2);
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(Image::new(&mut read).unwrap().rows().count(), 3);
+
+        for planar in [false, true] {
+            let (directory, strips) = multi_band_image(3, planar);
+            let mut read = write_then_read(&directory, &strips);
+            let mut image = Image::new(&mut read).unwrap();
+            let rows: Vec<u8> = image.rows().flat_map(|row| row.unwrap()).collect();
+            assert_eq!(rows, image.read_data().unwrap().data);
+        }
+    }
+
+    #[test]
+    fn test_read_reduced() {
+        let mut read = TIFFReader::new(Cursor::new(two_strips_image())).unwrap();
+        let mut image = Image::new(&mut read).unwrap();
+        let reduced = image.read_reduced(2).unwrap();
+        assert_eq!((reduced.data, reduced.rows), (vec![0, 2, 8, 10], 2));
+        assert_eq!(image.read_reduced(8).unwrap().data, [0]);
+        assert_eq!(image.read_reduced(1).unwrap(), image.read_data().unwrap());
+        assert!(image.read_reduced(3).is_err());
+
+        // Three 2-bit pixels of two samples, one pixel kept
+        let (mut directory, _) = multi_band_image(2, false);
+        directory.set_field(&BitsPerSample(vec![2, 2]));
+        let mut read = write_then_read(&directory, &[vec![0b1110_0100, 0x40], vec![0x1b, 0]]);
+        let reduced = Image::new(&mut read).unwrap().read_reduced(4).unwrap();
+        assert_eq!((reduced.data, reduced.rows), (vec![0b1110_0000], 1));
+    }
+
+    #[test]
+    fn test_read_bands() {
+        for planar in [false, true] {
+            let (directory, strips) = multi_band_image(12, planar);
+            let mut read = write_then_read(&directory, &strips);
+            let mut image = Image::new(&mut read).unwrap();
+            assert_eq!(image.bands(), 12);
+
+            let band = image.read_bands(&[7]).unwrap();
+            assert_eq!(band.rows, 2);
+            assert_eq!(band.data, (112..118).collect::<Vec<u8>>());
+
+            let bands = image.read_bands(&[11, 0]).unwrap();
+            assert_eq!(&bands.data[..4], [176, 0, 177, 1]);
+            assert!(image.read_bands(&[12]).is_err());
+
+            let all = image.read_data().unwrap();
+            assert_eq!(all.data.len(), 72);
+            assert_eq!(&all.data[12..15], [1, 17, 33]);
+
+            let region = image.read_region(1, 1, 2, 1).unwrap();
+            assert_eq!(region.len(), 24);
+            assert_eq!(&region[..3], [4, 20, 36]);
+            assert_eq!(&region[12..15], [5, 21, 37]);
+
+            // The buffers of the caller are reused
+            let mut data = Vec::with_capacity(128);
+            assert_eq!(image.read_data_into(&mut data).unwrap(), 2);
+            assert_eq!((&data[..], data.capacity()), (&all.data[..], 128));
+            image.read_region_into(1, 1, 2, 1, &mut data).unwrap();
+            assert_eq!((&data[..], data.capacity()), (&region[..], 128));
+        }
+    }
+
+    #[test]
+    fn test_read_band_short_plane() {
+        // The last row of the last band is missing, the first row of the
+        // first band has an extra byte
+        let (directory, mut strips) = multi_band_image(3, true);
+        strips[5].truncate(1);
+        strips[0].push(99);
+        let mut read = write_then_read(&directory, &strips);
+        let mut image = Image::new(&mut read).unwrap();
+        assert_eq!(image.read_bands(&[1]).unwrap().rows, 2);
+        let bands = image.read_bands(&[0, 2]).unwrap();
+        assert_eq!(bands.rows, 2);
+        assert_eq!(bands.data, [0, 32, 1, 33, 2, 34, 3, 35, 4, 0, 5, 0]);
+        let mismatch = |strip, expected, declared| Warning::StripSizeMismatch {
+            directory: 0,
+            strip,
+            expected,
+            declared,
+        };
+        assert_eq!(read.warnings(), [mismatch(0, 3, 4), mismatch(5, 3, 1)]);
+    }
+
+    #[test]
+    fn test_read_mosaic() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(4));
+        directory.set_field(&ImageLength(2));
+        directory.set_field(&BitsPerSample(vec![8]));
+        directory.set_field(&PhotometricInterpretation::CFA);
+        directory.set(Tag::CFARepeatPatternDim, TIFFValue::Short(vec![2, 2]));
+        directory.set(Tag::CFAPattern, TIFFValue::Byte(vec![1, 0, 2, 1]));
+        let mut read = write_then_read(&directory, &[(0..8).collect()]);
+        let mosaic = Image::new(&mut read).unwrap().read_mosaic().unwrap().unwrap();
+        assert_eq!(mosaic.pattern.name().unwrap(), "GRBG");
+        assert_eq!(mosaic.pattern.color_at(3, 0), CfaColor::Red);
+        assert_eq!(mosaic.pattern.color_at(2, 3), CfaColor::Blue);
+        assert_eq!((mosaic.bits_per_sample, mosaic.rows), (8, 2));
+        assert_eq!(mosaic.data, (0..8).collect::<Vec<u8>>());
+
+        // Pattern codes refer to the plane colors
+        directory.set(Tag::CFAPlaneColor, TIFFValue::Byte(vec![2, 1, 0]));
+        let mut read = write_then_read(&directory, &[(0..8).collect()]);
+        assert_eq!(read.cfa_pattern().unwrap().unwrap().name().unwrap(), "GBRG");
+
+        let mut read = TIFFReader::new(Cursor::new(two_strips_image())).unwrap();
+        assert_eq!(Image::new(&mut read).unwrap().read_mosaic().unwrap(), None);
+    }
+
+    #[test]
+    fn test_transparency_mask() {
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(3));
+        directory.set_field(&ImageLength(2));
+        directory.set_field(&BitsPerSample(vec![8, 8]));
+        let pixels: Vec<u8> = (1..=12).collect();
+        writer.write_image(&directory, 1, |_| Ok(pixels.clone())).unwrap();
+        directory.set_field(&NewSubfileType(4));
+        directory.set_field(&BitsPerSample(vec![1]));
+        directory.set_field(&PhotometricInterpretation::TransparencyMask);
+        // The second row is missing
+        directory.set_field(&RowsPerStrip(1));
+        writer.write_image(&directory, 1, |_| Ok(vec![0b1010_0000])).unwrap();
+
+        let mut read = TIFFReader::new(Cursor::new(writer.into_inner().into_inner())).unwrap();
+        assert_eq!(Image::new(&mut read).unwrap().read_transparency_mask().unwrap(), None);
+        read.select_directory(1).unwrap();
+        let mask = Image::new(&mut read).unwrap().read_transparency_mask().unwrap().unwrap();
+        assert_eq!((mask.width(), mask.length()), (3, 2));
+        assert!(mask.is_opaque(0, 0) && !mask.is_opaque(1, 0) && mask.is_opaque(2, 0));
+        assert!(!mask.is_opaque(0, 1));
+
+        read.select_directory(0).unwrap();
+        let data = Image::new(&mut read).unwrap().read_data_with_mask(&mask).unwrap();
+        let expected = [1, 2, 255, 3, 4, 0, 5, 6, 255, 7, 8, 0, 9, 10, 0, 11, 12, 0];
+        assert_eq!(data.data, expected);
+        let small = TransparencyMask::new(3, 1, vec![0xff]).unwrap();
+        assert!(Image::new(&mut read).unwrap().read_data_with_mask(&small).is_err());
+    }
+
+    #[test]
+    fn test_statistics() {
+        for planar in [false, true] {
+            let (directory, strips) = multi_band_image(2, planar);
+            let mut read = write_then_read(&directory, &strips);
+            let statistics = Image::new(&mut read).unwrap().statistics(true).unwrap();
+            assert_eq!(statistics.len(), 2);
+            assert_eq!((statistics[0].min, statistics[0].max), (0, 5));
+            assert_eq!((statistics[1].min, statistics[1].max), (16, 21));
+            assert_eq!((statistics[1].count, statistics[1].mean), (6, 18.5));
+            let histogram = statistics[1].histogram.as_ref().unwrap();
+            assert_eq!((histogram[15], histogram[16], histogram[21]), (0, 1, 1));
+        }
+
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(2));
+        directory.set_field(&ImageLength(1));
+        directory.set_field(&BitsPerSample(vec![16]));
+        let mut read = write_then_read(&directory, &[vec![0, 1, 2, 1]]);
+        let statistics = Image::new(&mut read).unwrap().statistics(false).unwrap();
+        assert_eq!((statistics[0].min, statistics[0].max), (256, 258));
+        assert_eq!(statistics[0].histogram, None);
+
+        directory.set_field(&BitsPerSample(vec![4]));
+        let mut read = write_then_read(&directory, &[vec![0]]);
+        assert!(Image::new(&mut read).unwrap().statistics(false).is_err());
+    }
+
+    #[test]
+    fn test_no_data() {
+        let (mut directory, strips) = multi_band_image(2, false);
+        directory.set_no_data(16.0);
+        let mut read = write_then_read(&directory, &strips);
+        let mut image = Image::new(&mut read).unwrap();
+        assert_eq!(image.no_data(), Some(16.0));
+        let statistics = image.statistics(false).unwrap();
+        assert_eq!((statistics[0].count, statistics[0].max), (6, 5));
+        assert_eq!((statistics[1].count, statistics[1].min), (5, 17));
+        // No pixel has both samples equal to 16
+        let mask = image.read_validity_mask().unwrap().unwrap();
+        assert!((0..6).all(|index| mask.is_opaque(index % 3, index / 3)));
+
+        image.set_no_data(Some(0.0));
+        assert_eq!(image.statistics(false).unwrap()[0].min, 1);
+        let (directory, strips) = multi_band_image(1, false);
+        let mut read = write_then_read(&directory, &strips);
+        let mut image = Image::new(&mut read).unwrap();
+        assert_eq!(image.read_validity_mask().unwrap(), None);
+        image.set_no_data(Some(4.0));
+        let mask = image.read_validity_mask().unwrap().unwrap();
+        assert!(mask.is_opaque(0, 1) && !mask.is_opaque(1, 1) && mask.is_opaque(2, 1));
+        assert!(mask.is_opaque(0, 0));
+    }
+
+    #[test]
+    fn test_decoded_size_limit() {
+        // Each of the 64 strips holds a single 256-byte row
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(256));
+        directory.set_field(&ImageLength(64));
+        directory.set_field(&RowsPerStrip(1));
+        directory.set_field(&BitsPerSample(vec![8]));
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        writer.write_image(&directory, 64, |_| Ok(vec![1; 256])).unwrap();
+        let bytes = writer.into_inner().into_inner();
+
+        let limits = Limits {
+            max_decoded_size: 4096,
+            ..Limits::default()
+        };
+        let mut read = TIFFReader::with_limits(Cursor::new(&bytes), limits.clone()).unwrap();
+        let result = Image::new(&mut read).unwrap().read_data();
+        assert!(matches!(result, Err(TiffError::Limit(_))));
+
+        let mut read = TIFFReader::with_limits(Cursor::new(&bytes), limits).unwrap();
+        let error = (0..64).map(|index| read.read_strip(index)).find(Result::is_err);
+        assert!(matches!(error, Some(Err(TiffError::Limit(_)))));
+
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(Image::new(&mut read).unwrap().read_data().unwrap().data.len(), 64 * 256);
+    }
+
+    #[test]
+    fn test_estimated_size() {
+        let mut read = TIFFReader::new(Cursor::new(two_strips_image())).unwrap();
+        let mut image = Image::new(&mut read).unwrap();
+        assert_eq!(image.estimated_decoded_size(), 16);
+        assert_eq!(image.estimated_region_size(3, 2), 6);
+        assert_eq!(image.read_data().unwrap().data.len(), 16);
+
+        let (directory, strips) = multi_band_image(12, true);
+        let mut read = write_then_read(&directory, &strips);
+        let image = Image::new(&mut read).unwrap();
+        assert_eq!(image.estimated_decoded_size(), 72);
+        assert_eq!(image.bits_per_pixel(), 96);
+        assert_eq!(image.scanline_bytes(), 36);
+        assert_eq!(image.pixel_count(), 6);
+
+        // Rows of three 4-bit pixels are padded to two bytes
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(3));
+        directory.set_field(&ImageLength(2));
+        directory.set_field(&BitsPerSample(vec![4]));
+        let mut read = write_then_read(&directory, &[vec![0; 4]]);
+        let image = Image::new(&mut read).unwrap();
+        assert_eq!((image.bits_per_pixel(), image.scanline_bytes()), (4, 2));
+        assert_eq!(image.estimated_decoded_size(), 4);
+    }
+}
@@ -0,0 +1,146 @@
+//! Reversal of the horizontal differencing predictor.
+use endian::Endian;
+
+/// Undoes horizontal differencing on a row of 8-bit samples, where each
+/// sample is stored as the difference with the same sample of the previous pixel.
+pub fn undo_horizontal_differencing(row: &mut [u8], samples: usize) {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    {
+        if samples == 1 || samples == 2 || samples == 4 {
+            // Safe: SSE2 is part of the x86_64 baseline
+            unsafe { sse2::undo_horizontal_differencing(row, samples) };
+            return;
+        }
+    }
+
+    undo_horizontal_differencing_scalar(row, samples);
+}
+
+fn undo_horizontal_differencing_scalar(row: &mut [u8], samples: usize) {
+    for index in samples..row.len() {
+        row[index] = row[index].wrapping_add(row[index - samples]);
+    }
+}
+
+/// Undoes horizontal differencing on a row of 16 or 32-bit samples stored in
+/// `endian` byte order, adding them as integers of their own width.
+pub fn undo_horizontal_differencing_wide(
+    row: &mut [u8],
+    samples: usize,
+    sample_bytes: usize,
+    endian: Endian,
+) {
+    let stride = samples * sample_bytes;
+    let end = row.len() / sample_bytes * sample_bytes;
+    for index in (stride..end).step_by(sample_bytes) {
+        let (previous, current) = row.split_at_mut(index);
+        let previous = &previous[index - stride..];
+        if sample_bytes == 2 {
+            let read = |bytes: &[u8]| match endian {
+                Endian::Big => u16::from_be_bytes([bytes[0], bytes[1]]),
+                Endian::Little => u16::from_le_bytes([bytes[0], bytes[1]]),
+            };
+            let sum = read(current).wrapping_add(read(previous));
+            let sum = match endian {
+                Endian::Big => sum.to_be_bytes(),
+                Endian::Little => sum.to_le_bytes(),
+            };
+            current[..2].copy_from_slice(&sum);
+        } else {
+            let read = |bytes: &[u8]| {
+                let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+                match endian {
+                    Endian::Big => u32::from_be_bytes(bytes),
+                    Endian::Little => u32::from_le_bytes(bytes),
+                }
+            };
+            let sum = read(current).wrapping_add(read(previous));
+            let sum = match endian {
+                Endian::Big => sum.to_be_bytes(),
+                Endian::Little => sum.to_le_bytes(),
+            };
+            current[..4].copy_from_slice(&sum);
+        }
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod sse2 {
+    use std::arch::x86_64::*;
+
+    /// Computes running sums of 16 bytes at once, for 1, 2 or 4 samples per pixel.
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn undo_horizontal_differencing(row: &mut [u8], samples: usize) {
+        let mut carry = _mm_setzero_si128();
+        let done = row.len() / 16 * 16;
+        for chunk in row[..done].chunks_exact_mut(16) {
+            let mut sum = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+            // Prefix sum of the pixels inside the vector
+            if samples == 1 {
+                sum = _mm_add_epi8(sum, _mm_slli_si128::<1>(sum));
+            }
+            if samples <= 2 {
+                sum = _mm_add_epi8(sum, _mm_slli_si128::<2>(sum));
+            }
+            sum = _mm_add_epi8(sum, _mm_slli_si128::<4>(sum));
+            sum = _mm_add_epi8(sum, _mm_slli_si128::<8>(sum));
+            sum = _mm_add_epi8(sum, carry);
+            _mm_storeu_si128(chunk.as_mut_ptr() as *mut __m128i, sum);
+
+            // Broadcast the last pixel to the whole vector
+            carry = match samples {
+                1 => _mm_set1_epi8(chunk[15] as i8),
+                2 => _mm_set1_epi16(i16::from_le_bytes([chunk[14], chunk[15]])),
+                _ => _mm_set1_epi32(i32::from_le_bytes([
+                    chunk[12], chunk[13], chunk[14], chunk[15],
+                ])),
+            };
+        }
+
+        for index in done.max(samples)..row.len() {
+            row[index] = row[index].wrapping_add(row[index - samples]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_horizontal_differencing() {
+        let mut row = vec![10, 20, 1, 2, 1, 2, 255, 254];
+        undo_horizontal_differencing(&mut row, 2);
+        assert_eq!(row, vec![10, 20, 11, 22, 12, 24, 11, 22]);
+
+        // Compare against the scalar path on lengths crossing the vector size
+        let mut seed = 0x2545_f491u32;
+        for samples in 1..6 {
+            for len in &[0, 3, 16, 17, 47, 100] {
+                let row: Vec<u8> = (0..*len)
+                    .map(|_| {
+                        seed ^= seed << 13;
+                        seed ^= seed >> 17;
+                        seed ^= seed << 5;
+                        seed as u8
+                    }).collect();
+                let mut expected = row.clone();
+                undo_horizontal_differencing_scalar(&mut expected, samples);
+                let mut actual = row;
+                undo_horizontal_differencing(&mut actual, samples);
+                assert_eq!(actual, expected, "{} samples, {} bytes", samples, len);
+            }
+        }
+    }
+
+    #[test]
+    fn test_undo_horizontal_differencing_wide() {
+        let mut row = vec![0x01, 0x00, 0x00, 0x01, 0xff, 0xff, 0x00, 0x02];
+        undo_horizontal_differencing_wide(&mut row, 2, 2, Endian::Big);
+        assert_eq!(row, vec![0x01, 0x00, 0x00, 0x01, 0x00, 0xff, 0x00, 0x03]);
+
+        let mut row = vec![0xff, 0xff, 0xff, 0xff, 0x02, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x01];
+        undo_horizontal_differencing_wide(&mut row, 1, 4, Endian::Little);
+        assert_eq!(row, vec![0xff, 0xff, 0xff, 0xff, 0x01, 0, 0, 0, 0x04, 0, 0, 0x01]);
+    }
+}
@@ -0,0 +1,59 @@
+//! Row by row decoding of stripped images.
+use std::io::{Read, Seek};
+
+use error::Result;
+
+use super::Image;
+
+/// An iterator over the decoded rows of an image, returned by `Image::rows`.
+pub struct Rows<'b, 'a: 'b, R: Read + Seek + 'a> {
+    image: &'b mut Image<'a, R>,
+    /// Index of the next strip to decode.
+    strip: usize,
+    /// The rows of the last decoded strip.
+    data: Vec<u8>,
+    /// Offset of the next row in `data`.
+    position: usize,
+    row_bytes: usize,
+    /// Whether a strip was cut short or failed to decode.
+    done: bool,
+}
+
+impl<'b, 'a, R: Read + Seek> Rows<'b, 'a, R> {
+    pub(super) fn new(image: &'b mut Image<'a, R>) -> Rows<'b, 'a, R> {
+        let row_bytes = image.scanline_bytes() as usize;
+        Rows {
+            image,
+            strip: 0,
+            data: Vec::new(),
+            position: 0,
+            row_bytes,
+            done: false,
+        }
+    }
+}
+
+impl<'b, 'a, R: Read + Seek> Iterator for Rows<'b, 'a, R> {
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Result<Vec<u8>>> {
+        while self.position + self.row_bytes > self.data.len() {
+            if self.done || self.strip >= self.image.strips_per_plane() {
+                return None;
+            }
+            let expected = self.image.strip_rows(self.strip);
+            match self.image.read_strip_rows(self.strip, &mut self.data) {
+                Ok(rows) => self.done = rows < expected,
+                Err(error) => {
+                    self.done = true;
+                    return Some(Err(error));
+                }
+            }
+            self.strip += 1;
+            self.position = 0;
+        }
+        let row = self.data[self.position..self.position + self.row_bytes].to_vec();
+        self.position += self.row_bytes;
+        Some(Ok(row))
+    }
+}
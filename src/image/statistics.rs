@@ -0,0 +1,105 @@
+//! Statistics of the decoded samples of each band of an image.
+use endian::Endian;
+
+/// The statistics of the samples of a band, computed by `Image::statistics`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BandStatistics {
+    /// The number of samples of the band.
+    pub count: u64,
+    pub min: u32,
+    pub max: u32,
+    pub mean: f64,
+    /// The number of samples of each value, for bands of 8 or 16 bits when
+    /// requested.
+    pub histogram: Option<Vec<u64>>,
+}
+
+/// Gathers the statistics of a band one sample at a time.
+#[derive(Debug, Clone)]
+pub(crate) struct Accumulator {
+    count: u64,
+    min: u32,
+    max: u32,
+    sum: u128,
+    histogram: Option<Vec<u64>>,
+}
+
+impl Accumulator {
+    /// Creates an accumulator for samples of `bits` bits, counting each value
+    /// when `histogram` is set and the samples are at most 16 bits.
+    pub fn new(bits: u16, histogram: bool) -> Accumulator {
+        Accumulator {
+            count: 0,
+            min: u32::MAX,
+            max: 0,
+            sum: 0,
+            histogram: if histogram && bits <= 16 {
+                Some(vec![0; 1 << bits])
+            } else {
+                None
+            },
+        }
+    }
+
+    pub fn add(&mut self, sample: u32) {
+        self.count += 1;
+        self.min = self.min.min(sample);
+        self.max = self.max.max(sample);
+        self.sum += u128::from(sample);
+        if let Some(histogram) = self.histogram.as_mut() {
+            histogram[sample as usize] += 1;
+        }
+    }
+
+    /// The statistics of the samples added, all zero when there are none.
+    pub fn finish(self) -> BandStatistics {
+        let mean = if self.count == 0 {
+            0.0
+        } else {
+            self.sum as f64 / self.count as f64
+        };
+        BandStatistics {
+            count: self.count,
+            min: if self.count == 0 { 0 } else { self.min },
+            max: self.max,
+            mean,
+            histogram: self.histogram,
+        }
+    }
+}
+
+/// Reads a sample of 1, 2 or 4 bytes stored in the `endian` byte order.
+pub(crate) fn read_sample(bytes: &[u8], endian: Endian) -> u32 {
+    match (bytes.len(), endian) {
+        (1, _) => u32::from(bytes[0]),
+        (2, Endian::Big) => u32::from(u16::from_be_bytes([bytes[0], bytes[1]])),
+        (2, Endian::Little) => u32::from(u16::from_le_bytes([bytes[0], bytes[1]])),
+        (_, Endian::Big) => u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        (_, Endian::Little) => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accumulator() {
+        let mut band = Accumulator::new(8, true);
+        for sample in &[3, 7, 3, 11] {
+            band.add(*sample);
+        }
+        let statistics = band.finish();
+        assert_eq!((statistics.count, statistics.min, statistics.max), (4, 3, 11));
+        assert_eq!(statistics.mean, 6.0);
+        let histogram = statistics.histogram.unwrap();
+        assert_eq!(histogram.len(), 256);
+        assert_eq!((histogram[3], histogram[7], histogram[0]), (2, 1, 0));
+
+        let empty = Accumulator::new(32, true).finish();
+        assert_eq!((empty.min, empty.max, empty.mean), (0, 0, 0.0));
+        assert_eq!(empty.histogram, None);
+        assert_eq!(read_sample(&[1, 2], Endian::Big), 0x102);
+        assert_eq!(read_sample(&[1, 0, 0, 2], Endian::Little), 0x0200_0001);
+    }
+}
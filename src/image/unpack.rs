@@ -0,0 +1,30 @@
+//! Expansion of samples narrower than a byte.
+
+/// Expands rows of `row_samples` samples of 1, 2 or 4 bits, packed most
+/// significant bit first with each row starting on a byte, to one byte per
+/// sample. Values are kept as they are, between 0 and `2^bits - 1`.
+pub fn unpack(data: &[u8], bits: u16, row_samples: usize) -> Vec<u8> {
+    let bits = usize::from(bits);
+    let row_bytes = (row_samples * bits).div_ceil(8);
+    let mask = (1u8 << bits) - 1;
+    let mut samples = Vec::with_capacity(data.len() / row_bytes.max(1) * row_samples);
+    for row in data.chunks_exact(row_bytes) {
+        samples.extend((0..row_samples).map(|index| {
+            let position = index * bits;
+            row[position / 8] >> (8 - bits - position % 8) & mask
+        }));
+    }
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unpack() {
+        assert_eq!(unpack(&[0b1010_0000, 0b0100_0000], 1, 3), [1, 0, 1, 0, 1, 0]);
+        assert_eq!(unpack(&[0b1110_0100, 0b1100_0000], 2, 5), [3, 2, 1, 0, 3]);
+        assert_eq!(unpack(&[0x7f, 0x30], 4, 3), [7, 15, 3]);
+    }
+}
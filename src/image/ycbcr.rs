@@ -0,0 +1,158 @@
+//! Conversion of YCbCr samples to RGB.
+
+/// Converts chunky `Y Cb Cr` triplets of `input` to `R G B` triplets of `output`,
+/// given the `LumaRed`, `LumaGreen` and `LumaBlue` coefficients.
+///
+/// Chroma samples are centered on 128, as with the default `ReferenceBlackWhite`.
+pub fn ycbcr_to_rgb(input: &[u8], output: &mut [u8], luma: [f32; 3]) {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    {
+        // Safe: SSE2 is part of the x86_64 baseline
+        let done = unsafe { sse2::ycbcr_to_rgb(input, output, luma) };
+        ycbcr_to_rgb_scalar(&input[done..], &mut output[done..], luma);
+    }
+
+    #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+    ycbcr_to_rgb_scalar(input, output, luma);
+}
+
+fn ycbcr_to_rgb_scalar(input: &[u8], output: &mut [u8], luma: [f32; 3]) {
+    let [red, green, blue] = luma;
+    for (pixel, rgb) in input.chunks_exact(3).zip(output.chunks_exact_mut(3)) {
+        let y = f32::from(pixel[0]);
+        let cb = f32::from(pixel[1]) - 128.0;
+        let cr = f32::from(pixel[2]) - 128.0;
+
+        let r = cr * (2.0 - 2.0 * red) + y;
+        let b = cb * (2.0 - 2.0 * blue) + y;
+        let g = (y - blue * b - red * r) / green;
+
+        rgb[0] = to_sample(r);
+        rgb[1] = to_sample(g);
+        rgb[2] = to_sample(b);
+    }
+}
+
+/// Expands samples subsampled `horizontal` times across and `vertical` times
+/// down into chunky `Y Cb Cr` pixels, in rows of `width` pixels.
+///
+/// The input is made of blocks of `horizontal * vertical` luma samples, row
+/// after row, followed by a `Cb` and a `Cr` sample shared by the whole block.
+/// Blocks cover the rows of `width` pixels from left to right, the last one
+/// being padded. A trailing incomplete row of blocks is dropped.
+pub fn upsample(input: &[u8], width: u32, (horizontal, vertical): (u32, u32)) -> Vec<u8> {
+    let (width, horizontal, vertical) = (width as usize, horizontal as usize, vertical as usize);
+    let across = width.div_ceil(horizontal);
+    let luma = horizontal * vertical;
+    let block_rows = input.len() / (across * (luma + 2));
+
+    let mut output = vec![0; block_rows * vertical * width * 3];
+    let blocks = input.chunks_exact(luma + 2).take(block_rows * across);
+    for (index, block) in blocks.enumerate() {
+        let (left, top) = (index % across * horizontal, index / across * vertical);
+        let (cb, cr) = (block[luma], block[luma + 1]);
+        for row in 0..vertical {
+            for column in 0..horizontal.min(width - left) {
+                let target = ((top + row) * width + left + column) * 3;
+                let y = block[row * horizontal + column];
+                output[target..target + 3].copy_from_slice(&[y, cb, cr]);
+            }
+        }
+    }
+    output
+}
+
+fn to_sample(value: f32) -> u8 {
+    (value.clamp(0.0, 255.0) + 0.5) as u8
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod sse2 {
+    use std::arch::x86_64::*;
+
+    /// Converts pixels four at a time and returns the number of bytes converted.
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn ycbcr_to_rgb(input: &[u8], output: &mut [u8], luma: [f32; 3]) -> usize {
+        let [red, green, blue] = luma;
+        let (red, green, blue) = (_mm_set1_ps(red), _mm_set1_ps(green), _mm_set1_ps(blue));
+        let two = _mm_set1_ps(2.0);
+        let red_factor = _mm_sub_ps(two, _mm_mul_ps(two, red));
+        let blue_factor = _mm_sub_ps(two, _mm_mul_ps(two, blue));
+        let center = _mm_set1_ps(128.0);
+        let (zero, max, half) = (_mm_setzero_ps(), _mm_set1_ps(255.0), _mm_set1_ps(0.5));
+
+        let pixels = input.len().min(output.len()) / 3;
+        let blocks = pixels / 4;
+        for block in 0..blocks {
+            let start = block * 12;
+            let mut planes = [[0f32; 4]; 3];
+            for (index, pixel) in input[start..start + 12].chunks_exact(3).enumerate() {
+                for plane in 0..3 {
+                    planes[plane][index] = f32::from(pixel[plane]);
+                }
+            }
+
+            let y = _mm_loadu_ps(planes[0].as_ptr());
+            let cb = _mm_sub_ps(_mm_loadu_ps(planes[1].as_ptr()), center);
+            let cr = _mm_sub_ps(_mm_loadu_ps(planes[2].as_ptr()), center);
+
+            let r = _mm_add_ps(_mm_mul_ps(cr, red_factor), y);
+            let b = _mm_add_ps(_mm_mul_ps(cb, blue_factor), y);
+            let g = _mm_sub_ps(_mm_sub_ps(y, _mm_mul_ps(blue, b)), _mm_mul_ps(red, r));
+            let g = _mm_div_ps(g, green);
+
+            let mut rgb = [[0i32; 4]; 3];
+            for (plane, value) in [r, g, b].iter().enumerate() {
+                let clamped = _mm_add_ps(_mm_min_ps(_mm_max_ps(*value, zero), max), half);
+                _mm_storeu_si128(
+                    rgb[plane].as_mut_ptr() as *mut __m128i,
+                    _mm_cvttps_epi32(clamped),
+                );
+            }
+
+            for (index, pixel) in output[start..start + 12].chunks_exact_mut(3).enumerate() {
+                for plane in 0..3 {
+                    pixel[plane] = rgb[plane][index] as u8;
+                }
+            }
+        }
+
+        blocks * 12
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LUMA: [f32; 3] = [0.299, 0.587, 0.114];
+
+    #[test]
+    fn test_ycbcr_to_rgb() {
+        let input = [255, 128, 128, 0, 128, 128, 76, 85, 255];
+        let mut output = [0; 9];
+        ycbcr_to_rgb(&input, &mut output, LUMA);
+        assert_eq!(output, [255, 255, 255, 0, 0, 0, 254, 0, 0]);
+
+        // Compare against the scalar path on lengths crossing the block size
+        let input: Vec<u8> = (0..3 * 37).map(|e| (e * 97 % 256) as u8).collect();
+        let mut expected = vec![0; input.len()];
+        ycbcr_to_rgb_scalar(&input, &mut expected, LUMA);
+        let mut actual = vec![0; input.len()];
+        ycbcr_to_rgb(&input, &mut actual, LUMA);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_upsample() {
+        // Three pixels wide, two blocks of 2x2 pixels
+        let input = [1, 2, 3, 4, 50, 60, 5, 0, 6, 0, 70, 80];
+        let expected = [
+            1, 50, 60, 2, 50, 60, 5, 70, 80, //
+            3, 50, 60, 4, 50, 60, 6, 70, 80,
+        ];
+        assert_eq!(upsample(&input, 3, (2, 2)), expected);
+        assert_eq!(upsample(&input[..11], 3, (2, 2)), []);
+        assert_eq!(upsample(&input[..6], 2, (4, 1)), [1, 50, 60, 2, 50, 60]);
+    }
+}
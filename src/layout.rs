@@ -0,0 +1,367 @@
+//! This module describes how the image data of a directory is split into
+//! strips or tiles, so that both can be walked the same way.
+use error::{CorruptError, Result};
+use reader::TIFFReader;
+use std::io::{Read, Seek};
+use std::vec;
+use tag::*;
+
+/// How the image data of a directory is split into segments.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Layout {
+    /// Bands of `rows_per_strip` rows spanning the image width, the last one
+    /// may be shorter.
+    Strips { rows_per_strip: u32 },
+    /// Rectangles of `width` × `length` pixels, the ones on the right and
+    /// bottom edges being padded past the image.
+    Tiles { width: u32, length: u32 },
+}
+
+impl Layout {
+    /// Size in bytes of `segment` once decompressed, made of pixels of
+    /// `bits_per_pixel` bits, with rows starting on a byte boundary.
+    ///
+    /// Tiles always have their full size, padding included, while strips
+    /// only hold the rows within the image.
+    pub fn segment_size(&self, segment: &Segment, bits_per_pixel: u64) -> u64 {
+        let (width, length) = match *self {
+            Layout::Strips { .. } => (segment.width, segment.length),
+            Layout::Tiles { width, length } => (width, length),
+        };
+        (u64::from(width) * bits_per_pixel).div_ceil(8) * u64::from(length)
+    }
+}
+
+/// A strip or tile of the image data.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Segment {
+    /// Index of the segment in `StripOffsets` or `TileOffsets`.
+    pub index: usize,
+    /// Band of the segment in planar images, 0 otherwise.
+    pub plane: usize,
+    /// Column of the first pixel of the segment.
+    pub x: u32,
+    /// Row of the first pixel of the segment.
+    pub y: u32,
+    /// Number of columns of the segment within the image.
+    pub width: u32,
+    /// Number of rows of the segment within the image.
+    pub length: u32,
+    /// Offset of the stored segment in the file.
+    pub offset: u64,
+    /// Size in bytes of the stored segment.
+    pub byte_count: u64,
+}
+
+/// An iterator over the segments of a directory, in storage order.
+pub struct Segments {
+    layout: Layout,
+    image_width: u32,
+    image_length: u32,
+    locations: vec::IntoIter<(u64, u64)>,
+    index: usize,
+}
+
+impl Segments {
+    /// Number of segments across and down a single plane.
+    fn grid(&self) -> (usize, usize) {
+        match self.layout {
+            Layout::Strips { rows_per_strip } => {
+                (1, self.image_length.div_ceil(rows_per_strip) as usize)
+            }
+            Layout::Tiles { width, length } => (
+                self.image_width.div_ceil(width) as usize,
+                self.image_length.div_ceil(length) as usize,
+            ),
+        }
+    }
+}
+
+impl Iterator for Segments {
+    type Item = Segment;
+
+    fn next(&mut self) -> Option<Segment> {
+        let (offset, byte_count) = self.locations.next()?;
+        let index = self.index;
+        self.index += 1;
+
+        let (across, down) = self.grid();
+        let (segment_width, segment_length) = match self.layout {
+            Layout::Strips { rows_per_strip } => (self.image_width, rows_per_strip),
+            Layout::Tiles { width, length } => (width, length),
+        };
+        let position = index % (across * down);
+        let x = (position % across) as u32 * segment_width;
+        let y = (position / across) as u32 * segment_length;
+        Some(Segment {
+            index,
+            plane: index / (across * down),
+            x,
+            y,
+            width: segment_width.min(self.image_width - x),
+            length: segment_length.min(self.image_length - y),
+            offset,
+            byte_count,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.locations.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Segments {}
+
+impl<R: Read + Seek> TIFFReader<R> {
+    /// The layout of the image data of the current directory, tiled when it
+    /// has both `TileWidth` and `TileLength`.
+    pub fn layout(&mut self) -> Result<Layout> {
+        let tile_width = self.get_field::<TileWidth>().map(|e| e.0);
+        let tile_length = self.get_field::<TileLength>().map(|e| e.0);
+        if let (Some(width), Some(length)) = (tile_width, tile_length) {
+            if width == 0 || length == 0 {
+                return Err(CorruptError::Invalid("Invalid tile size").into());
+            }
+            return Ok(Layout::Tiles { width, length });
+        }
+
+        let length = self
+            .get_field::<ImageLength>()
+            .ok_or(CorruptError::Invalid("Missing ImageLength"))?
+            .0;
+        let rows_per_strip = self
+            .get_field::<RowsPerStrip>()
+            .map_or(length, |e| e.0)
+            .clamp(1, length.max(1));
+        Ok(Layout::Strips { rows_per_strip })
+    }
+
+    /// The strips or tiles of the current directory, with the pixels they
+    /// cover and where they are stored.
+    pub fn segments(&mut self) -> Result<Segments> {
+        let layout = self.layout()?;
+        let image_width = self
+            .get_field::<ImageWidth>()
+            .ok_or(CorruptError::Invalid("Missing ImageWidth"))?
+            .0;
+        let image_length = self
+            .get_field::<ImageLength>()
+            .ok_or(CorruptError::Invalid("Missing ImageLength"))?
+            .0;
+
+        let (offsets_tag, counts_tag) = match layout {
+            Layout::Strips { .. } => (Tag::StripOffsets, Tag::StripByteCounts),
+            Layout::Tiles { .. } => (Tag::TileOffsets, Tag::TileByteCounts),
+        };
+        // One plane per band of planar images, as `Image` decodes them
+        let samples = self.get_field::<SamplesPerPixel>().unwrap_or_default().0;
+        let bands = self
+            .get_field::<BitsPerSample>()
+            .map_or(usize::from(samples), |e| e.0.len());
+        let planes = match self.get_field::<PlanarConfiguration>() {
+            Some(PlanarConfiguration::Planar) if bands > 1 => bands,
+            _ => 1,
+        };
+        let offsets = self.read_unsigned(offsets_tag)?;
+        let counts = self.read_unsigned(counts_tag)?;
+        if offsets.len() != counts.len() {
+            return Err(CorruptError::Invalid("Segment offsets and byte counts differ").into());
+        }
+
+        let segments = Segments {
+            layout,
+            image_width,
+            image_length,
+            locations: offsets.into_iter().zip(counts).collect::<Vec<_>>().into_iter(),
+            index: 0,
+        };
+        let (across, down) = segments.grid();
+        if image_width == 0 || image_length == 0 || segments.len() != across * down * planes {
+            return Err(CorruptError::Invalid("Segment count does not match the layout").into());
+        }
+        Ok(segments)
+    }
+
+    /// The size in bytes each strip or tile of the current directory should
+    /// have once decompressed, from the image size, the layout and the sample
+    /// sizes, in storage order.
+    pub fn expected_segment_sizes(&mut self) -> Result<Vec<u64>> {
+        let layout = self.layout()?;
+        let samples = self.get_field::<SamplesPerPixel>().unwrap_or_default().0;
+        let bits = self
+            .get_field::<BitsPerSample>()
+            .map(|e| e.0)
+            .unwrap_or_else(|| vec![1; usize::from(samples)]);
+        let planar = bits.len() > 1
+            && self.get_field::<PlanarConfiguration>() == Some(PlanarConfiguration::Planar);
+        let pixel_bits: u64 = bits.iter().map(|b| u64::from(*b)).sum();
+
+        self.segments()?
+            .map(|segment| {
+                let bits_per_pixel = if planar {
+                    let bits = bits.get(segment.plane);
+                    u64::from(*bits.ok_or(CorruptError::Invalid("More planes than samples"))?)
+                } else {
+                    pixel_bits
+                };
+                Ok(layout.segment_size(&segment, bits_per_pixel))
+            })
+            .collect()
+    }
+
+    /// The strips or tiles of directory `index`, as `segments` returns them,
+    /// without changing the current directory.
+    pub fn segments_info(&mut self, index: usize) -> Result<Vec<Segment>> {
+        let current = self.directory_index();
+        self.select_directory(index)?;
+        let segments = self.segments().map(|segments| segments.collect());
+        self.select_directory(current)?;
+        segments
+    }
+}
+
+/// The pairs of segments whose stored bytes overlap, by index, such as the
+/// strips of a file sharing data on purpose or by corruption.
+pub fn segment_overlaps(segments: &[Segment]) -> Vec<(usize, usize)> {
+    let sorted = stored_order(segments);
+    let mut overlaps = Vec::new();
+    for (position, first) in sorted.iter().enumerate() {
+        let end = first.offset + first.byte_count;
+        for second in sorted[position + 1..].iter().take_while(|s| s.offset < end) {
+            overlaps.push((first.index.min(second.index), first.index.max(second.index)));
+        }
+    }
+    overlaps.sort_unstable();
+    overlaps
+}
+
+/// The byte ranges between the stored segments, as offsets and lengths, in
+/// file order. Besides padding, they often hold directories and values.
+pub fn segment_gaps(segments: &[Segment]) -> Vec<(u64, u64)> {
+    let mut gaps = Vec::new();
+    let mut end = None;
+    for segment in stored_order(segments) {
+        if let Some(end) = end.filter(|end| segment.offset > *end) {
+            gaps.push((end, segment.offset - end));
+        }
+        let stop = segment.offset + segment.byte_count;
+        end = Some(end.map_or(stop, |end: u64| end.max(stop)));
+    }
+    gaps
+}
+
+/// The segments holding data, sorted by offset.
+fn stored_order(segments: &[Segment]) -> Vec<&Segment> {
+    let mut sorted: Vec<&Segment> = segments.iter().filter(|s| s.byte_count > 0).collect();
+    sorted.sort_by_key(|s| (s.offset, s.index));
+    sorted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Image;
+    use reader::tests::write_then_read;
+    use writer::Directory;
+
+    /// `count` segments, the `i`-th made of `i + 1` bytes of value `i`.
+    fn stored(count: usize) -> Vec<Vec<u8>> {
+        (0..count).map(|i| vec![i as u8; i + 1]).collect()
+    }
+
+    #[test]
+    fn test_strip_segments() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(5));
+        directory.set_field(&ImageLength(7));
+        directory.set_field(&RowsPerStrip(3));
+        let mut read = write_then_read(&directory, &stored(3));
+
+        assert_eq!(read.layout().unwrap(), Layout::Strips { rows_per_strip: 3 });
+        let segments: Vec<_> = read.segments().unwrap().collect();
+        let rectangles: Vec<_> = segments.iter().map(|s| (s.y, s.length)).collect();
+        assert_eq!(rectangles, [(0, 3), (3, 3), (6, 1)]);
+        assert!(segments.iter().all(|s| s.x == 0 && s.width == 5 && s.plane == 0));
+        assert_eq!(segments[2].byte_count, 3);
+        // Bilevel by default, a byte per row
+        assert_eq!(read.expected_segment_sizes().unwrap(), [3, 3, 1]);
+        let mut buffer = Vec::new();
+        read.read_bytes_into(segments[2].offset, segments[2].byte_count, &mut buffer).unwrap();
+        assert_eq!(buffer, [2, 2, 2]);
+    }
+
+    #[test]
+    fn test_extra_segments() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(8));
+        directory.set_field(&ImageLength(1));
+        directory.set_field(&BitsPerSample(vec![8]));
+        let mut read = write_then_read(&directory, &stored(2));
+        assert!(read.segments().is_err());
+        let mut image = Image::new(&mut read).unwrap();
+        assert!(image.read_region(0, 0, 8, 1).is_err());
+        assert!(image.statistics(false).is_err());
+
+        // A plane more than the bands
+        directory.set_field(&BitsPerSample(vec![8, 8]));
+        directory.set_field(&PlanarConfiguration::Planar);
+        let mut read = write_then_read(&directory, &stored(3));
+        assert!(read.segments().is_err());
+        assert!(Image::new(&mut read).unwrap().read_region(0, 0, 8, 1).is_err());
+    }
+
+    fn segment(index: usize, offset: u64, byte_count: u64) -> Segment {
+        Segment {
+            index,
+            plane: 0,
+            x: 0,
+            y: 0,
+            width: 1,
+            length: 1,
+            offset,
+            byte_count,
+        }
+    }
+
+    #[test]
+    fn test_overlaps_and_gaps() {
+        let segments = [
+            segment(0, 100, 10),
+            segment(1, 8, 20),
+            segment(2, 20, 4),
+            segment(3, 40, 0),
+            segment(4, 104, 2),
+            segment(5, 30, 10),
+        ];
+        assert_eq!(segment_overlaps(&segments), [(0, 4), (1, 2)]);
+        assert_eq!(segment_gaps(&segments), [(28, 2), (40, 60)]);
+        assert!(segment_gaps(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_tile_segments() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(20));
+        directory.set_field(&ImageLength(10));
+        directory.set_field(&TileWidth(16));
+        directory.set_field(&TileLength(16));
+        directory.set_field(&BitsPerSample(vec![8, 8]));
+        directory.set_field(&PlanarConfiguration::Planar);
+        let mut read = write_then_read(&directory, &stored(4));
+
+        assert_eq!(read.layout().unwrap(), Layout::Tiles { width: 16, length: 16 });
+        let segments: Vec<_> = read.segments().unwrap().collect();
+        let rectangles: Vec<_> = segments.iter().map(|s| (s.plane, s.x, s.width)).collect();
+        assert_eq!(rectangles, [(0, 0, 16), (0, 16, 4), (1, 0, 16), (1, 16, 4)]);
+        assert!(segments.iter().all(|s| s.y == 0 && s.length == 10));
+        assert_eq!(segments[3].index, 3);
+        assert_eq!(read.expected_segment_sizes().unwrap(), [256; 4]);
+
+        assert_eq!(read.segments_info(0).unwrap(), segments);
+        assert!(read.segments_info(1).is_err());
+
+        // A tile is missing
+        let mut read = write_then_read(&directory, &stored(3));
+        assert!(read.segments().is_err());
+    }
+}
@@ -1,18 +1,119 @@
-#![feature(int_to_from_bytes)]
 //! A TIFF6.0 library that helps to deal with tiff files.
 //!
 //! # Reading
 //! The library provides a low-level interface helping to deal with the tree structure and another
 
 extern crate chrono;
+#[cfg(feature = "checksum")]
+extern crate crc32fast;
+#[cfg(feature = "flate2")]
+extern crate flate2;
+#[cfg(feature = "webp")]
+extern crate image_webp;
+#[cfg(feature = "jpeg")]
+extern crate jpeg_decoder;
+#[cfg(feature = "log")]
 #[macro_use]
-extern crate error_chain;
+extern crate log;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "xml")]
+extern crate roxmltree;
+#[cfg(feature = "zstd")]
+extern crate ruzstd;
+#[cfg(feature = "http")]
+extern crate ureq;
+#[cfg(feature = "checksum")]
+extern crate xxhash_rust;
 
+// Without the `log` feature, the logging macros only type check their arguments
+#[cfg(not(feature = "log"))]
+macro_rules! debug {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! trace {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! warn {
+    ($($arg:tt)*) => {{
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+mod cancel;
+#[cfg(feature = "checksum")]
+mod checksum;
+mod compression;
+mod descriptor;
+mod document;
+mod encode;
 mod endian;
+mod error;
+mod events;
+mod geo;
+mod gps;
+mod image;
+mod layout;
+mod limits;
+#[cfg(feature = "xml")]
+mod ome;
+mod options;
+mod planar;
+mod quantize;
 mod reader;
+mod slide;
+mod source;
+mod thumbnail;
+mod validate;
 mod value;
+mod warning;
+mod writer;
 
-pub use endian::{BE, LE};
+pub use cancel::CancellationToken;
+#[cfg(feature = "checksum")]
+pub use checksum::{ChecksumAlgorithm, Checksums, CHECKSUM_TAG};
+pub use descriptor::{DynField, FieldDescriptor, FieldTable};
+pub use document::{Page, TiffDocument};
+pub use encode::Bilevel;
+pub use endian::{Endian, BE, LE};
+pub use error::{CorruptError, ErrorKind, Result, TiffError};
+pub use events::{Event, EventReader};
+pub use geo::{Crs, GeoTransform};
+pub use gps::GpsInfo;
+pub use image::{
+    BandStatistics, CfaColor, CfaPattern, Image, ImageData, Mosaic, RgbaImage, Rows,
+    TransparencyMask,
+};
+pub use layout::{segment_gaps, segment_overlaps, Layout, Segment, Segments};
+pub use limits::Limits;
+#[cfg(feature = "xml")]
+pub use ome::{DimensionOrder, OmeImage, OmeMetadata, OmePlane};
+pub use options::{DuplicateTagPolicy, ReaderOptions, StringEncoding};
+pub use planar::{deinterleave, interleave};
 
 pub mod tag;
-pub use reader::TIFFReader;
+pub use slide::{AperioDescription, SlideImage, SlideImageKind, WholeSlide};
+pub use reader::{
+    probe, Directories, DirectoryHandle, DirectoryStatistics, IFDEntry, TIFFReader, TiffInfo,
+};
+#[cfg(feature = "http")]
+pub use source::HttpRangeReader;
+#[cfg(feature = "prefetch")]
+pub use source::{PrefetchSource, Prefetcher};
+pub use source::{
+    CachedRangeReader, OffsetSource, RangeReader, RangeSource, SequentialSource,
+};
+pub use validate::{validate, Issue, Severity, ValidationReport};
+pub use value::{Rational, TIFFValue};
+pub use warning::Warning;
+pub use writer::{Directory, RawEntry, SegmentSource, TIFFWriter};
@@ -0,0 +1,49 @@
+//! Resource limits applied while reading a TIFF file.
+
+/// Bounds on the resources a `TIFFReader` may use, so that a small
+/// malicious file cannot make the library allocate huge amounts of memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum number of directories followed in the IFD chain.
+    pub max_directories: usize,
+    /// Maximum number of entries in a single directory.
+    pub max_entries: usize,
+    /// Maximum size in bytes of a single decoded value.
+    pub max_value_size: u64,
+    /// Maximum number of bytes decoded over the lifetime of the reader: the
+    /// values, the strips and tiles read and their decompressed data.
+    pub max_decoded_size: u64,
+}
+
+impl Limits {
+    /// Limits which never reject any input.
+    pub fn unlimited() -> Limits {
+        Limits {
+            max_directories: usize::MAX,
+            max_entries: usize::MAX,
+            max_value_size: u64::MAX,
+            max_decoded_size: u64::MAX,
+        }
+    }
+
+    /// Conservative limits suited to processing untrusted uploads.
+    pub fn untrusted() -> Limits {
+        Limits {
+            max_directories: 1024,
+            max_entries: 1024,
+            max_value_size: 16 * 1024 * 1024,
+            max_decoded_size: 256 * 1024 * 1024,
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_directories: 1 << 16,
+            max_entries: usize::MAX,
+            max_value_size: 256 * 1024 * 1024,
+            max_decoded_size: u64::MAX,
+        }
+    }
+}
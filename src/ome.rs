@@ -0,0 +1,303 @@
+//! This module parses the OME-XML metadata of OME-TIFF files, which describes
+//! how the directories of a microscopy stack map to channels, focal planes and
+//! time points.
+use error::{CorruptError, Result};
+use reader::TIFFReader;
+use roxmltree::{Document, Node};
+use std::io::{Read, Seek};
+use tag::ImageDescription;
+
+/// The order in which planes are stored, fastest varying dimension first
+/// after the X and Y axes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DimensionOrder {
+    XYZCT,
+    XYZTC,
+    XYCTZ,
+    XYCZT,
+    XYTCZ,
+    XYTZC,
+}
+
+impl DimensionOrder {
+    fn parse(order: &str) -> Option<DimensionOrder> {
+        match order {
+            "XYZCT" => Some(DimensionOrder::XYZCT),
+            "XYZTC" => Some(DimensionOrder::XYZTC),
+            "XYCTZ" => Some(DimensionOrder::XYCTZ),
+            "XYCZT" => Some(DimensionOrder::XYCZT),
+            "XYTCZ" => Some(DimensionOrder::XYTCZ),
+            "XYTZC" => Some(DimensionOrder::XYTZC),
+            _ => None,
+        }
+    }
+
+    /// The positions of the C, Z and T axes in `[c, z, t]` coordinates,
+    /// fastest varying first.
+    fn axes(self) -> [usize; 3] {
+        match self {
+            DimensionOrder::XYZCT => [1, 0, 2],
+            DimensionOrder::XYZTC => [1, 2, 0],
+            DimensionOrder::XYCTZ => [0, 2, 1],
+            DimensionOrder::XYCZT => [0, 1, 2],
+            DimensionOrder::XYTCZ => [2, 0, 1],
+            DimensionOrder::XYTZC => [2, 1, 0],
+        }
+    }
+}
+
+/// A plane of an OME image, located by its channel, focal plane and time
+/// point indices.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OmePlane {
+    pub c: u32,
+    pub z: u32,
+    pub t: u32,
+    /// The index of the directory holding the plane.
+    pub directory: usize,
+}
+
+/// A multi-dimensional image described by OME-XML.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OmeImage {
+    pub name: Option<String>,
+    pub size_x: u32,
+    pub size_y: u32,
+    pub size_c: u32,
+    pub size_z: u32,
+    pub size_t: u32,
+    pub dimension_order: DimensionOrder,
+    /// The name of each channel, if given.
+    pub channels: Vec<Option<String>>,
+    /// The planes stored in the file.
+    pub planes: Vec<OmePlane>,
+}
+
+impl OmeImage {
+    /// The index of the directory holding plane `(c, z, t)`, if stored in
+    /// the file.
+    pub fn directory(&self, c: u32, z: u32, t: u32) -> Option<usize> {
+        self.planes
+            .iter()
+            .find(|plane| (plane.c, plane.z, plane.t) == (c, z, t))
+            .map(|plane| plane.directory)
+    }
+
+    fn sizes(&self) -> [u32; 3] {
+        [self.size_c, self.size_z, self.size_t]
+    }
+
+    /// The `[c, z, t]` coordinates of the plane at `index` in storage order.
+    fn coordinates(&self, mut index: u32) -> [u32; 3] {
+        let sizes = self.sizes();
+        let mut coordinates = [0; 3];
+        for axis in &self.dimension_order.axes() {
+            coordinates[*axis] = index % sizes[*axis];
+            index /= sizes[*axis];
+        }
+        coordinates
+    }
+
+    /// The index in storage order of the plane at `[c, z, t]`.
+    fn plane_index(&self, coordinates: [u32; 3]) -> u32 {
+        let sizes = self.sizes();
+        self.dimension_order
+            .axes()
+            .iter()
+            .rev()
+            .fold(0, |index, axis| index * sizes[*axis] + coordinates[*axis])
+    }
+
+    fn plane_count(&self) -> u32 {
+        self.size_c * self.size_z * self.size_t
+    }
+}
+
+/// The OME-XML metadata of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OmeMetadata {
+    pub images: Vec<OmeImage>,
+}
+
+fn invalid() -> CorruptError {
+    CorruptError::Invalid("Invalid OME-XML")
+}
+
+fn attribute<T: ::std::str::FromStr>(node: Node, name: &str) -> Result<Option<T>> {
+    match node.attribute(name) {
+        Some(value) => Ok(Some(value.parse().map_err(|_| invalid())?)),
+        None => Ok(None),
+    }
+}
+
+fn size(pixels: Node, name: &str) -> Result<u32> {
+    match attribute(pixels, name)? {
+        Some(0) | None => Err(invalid().into()),
+        Some(size) => Ok(size),
+    }
+}
+
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+/// Maps the planes of `image` to directories following its `TiffData`
+/// elements.
+fn map_planes(image: &mut OmeImage, pixels: Node) -> Result<()> {
+    let count = image.plane_count();
+    let tiff_data: Vec<Node> = children(pixels, "TiffData").collect();
+    if tiff_data.is_empty() {
+        // Planes are stored in order from the first directory
+        image.planes = (0..count).map(|index| plane(image, index, index as usize)).collect();
+        return Ok(());
+    }
+
+    for data in tiff_data {
+        let ifd: Option<usize> = attribute(data, "IFD")?;
+        let first = [
+            attribute(data, "FirstC")?.unwrap_or(0),
+            attribute(data, "FirstZ")?.unwrap_or(0),
+            attribute(data, "FirstT")?.unwrap_or(0),
+        ];
+        let first_index = image.plane_index(first);
+        let plane_count = match attribute(data, "PlaneCount")? {
+            Some(plane_count) => plane_count,
+            None if ifd.is_some() || data.attributes().len() > 0 => 1,
+            None => count,
+        };
+        let ifd = ifd.unwrap_or(0);
+        for offset in 0..plane_count.min(count.saturating_sub(first_index)) {
+            let plane = plane(image, first_index + offset, ifd + offset as usize);
+            image.planes.push(plane);
+        }
+    }
+    Ok(())
+}
+
+fn plane(image: &OmeImage, index: u32, directory: usize) -> OmePlane {
+    let [c, z, t] = image.coordinates(index);
+    OmePlane { c, z, t, directory }
+}
+
+impl OmeMetadata {
+    /// Parses an OME-XML document.
+    pub fn parse(xml: &str) -> Result<OmeMetadata> {
+        let document = Document::parse(xml).map_err(|_| invalid())?;
+        let root = document.root_element();
+        if root.tag_name().name() != "OME" {
+            return Err(invalid().into());
+        }
+
+        let mut images = Vec::new();
+        for node in children(root, "Image") {
+            let pixels = children(node, "Pixels").next().ok_or_else(invalid)?;
+            let dimension_order = pixels
+                .attribute("DimensionOrder")
+                .and_then(DimensionOrder::parse)
+                .ok_or_else(invalid)?;
+            let mut image = OmeImage {
+                name: node.attribute("Name").map(str::to_string),
+                size_x: size(pixels, "SizeX")?,
+                size_y: size(pixels, "SizeY")?,
+                size_c: size(pixels, "SizeC")?,
+                size_z: size(pixels, "SizeZ")?,
+                size_t: size(pixels, "SizeT")?,
+                dimension_order,
+                channels: children(pixels, "Channel")
+                    .map(|channel| channel.attribute("Name").map(str::to_string))
+                    .collect(),
+                planes: Vec::new(),
+            };
+            map_planes(&mut image, pixels)?;
+            images.push(image);
+        }
+        Ok(OmeMetadata { images })
+    }
+}
+
+impl<R: Read + Seek> TIFFReader<R> {
+    /// Parses the OME-XML metadata stored in the `ImageDescription` of the
+    /// current directory, usually the first one. Returns `None` when the
+    /// description is missing or is not OME-XML.
+    pub fn ome_metadata(&mut self) -> Result<Option<OmeMetadata>> {
+        let description = match self.get_field::<ImageDescription>() {
+            Some(description) => description.0,
+            None => return Ok(None),
+        };
+        if !description.contains("<OME") {
+            return Ok(None);
+        }
+        OmeMetadata::parse(&description).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::tests::write_then_read;
+    use std::io::Cursor;
+    use tag::ImageWidth;
+    use writer::Directory;
+
+    const OME: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<OME xmlns="http://www.openmicroscopy.org/Schemas/OME/2016-06">
+  <Image ID="Image:0" Name="cells">
+    <Pixels ID="Pixels:0" DimensionOrder="XYZCT" Type="uint8"
+            SizeX="4" SizeY="3" SizeC="2" SizeZ="3" SizeT="1">
+      <Channel ID="Channel:0:0" Name="DAPI"/>
+      <Channel ID="Channel:0:1"/>
+      <TiffData IFD="0" PlaneCount="3"/>
+      <TiffData IFD="3" FirstC="1" PlaneCount="3"/>
+    </Pixels>
+  </Image>
+</OME>"#;
+
+    #[test]
+    fn test_parse() {
+        let metadata = OmeMetadata::parse(OME).unwrap();
+        assert_eq!(metadata.images.len(), 1);
+        let image = &metadata.images[0];
+        assert_eq!(image.name.as_deref(), Some("cells"));
+        assert_eq!((image.size_x, image.size_y), (4, 3));
+        assert_eq!((image.size_c, image.size_z, image.size_t), (2, 3, 1));
+        assert_eq!(image.dimension_order, DimensionOrder::XYZCT);
+        assert_eq!(image.channels, [Some("DAPI".to_string()), None]);
+        assert_eq!(image.planes.len(), 6);
+        assert_eq!(image.directory(0, 2, 0), Some(2));
+        assert_eq!(image.directory(1, 0, 0), Some(3));
+        assert_eq!(image.directory(1, 2, 0), Some(5));
+        assert_eq!(image.directory(0, 0, 1), None);
+    }
+
+    #[test]
+    fn test_implicit_planes() {
+        let xml = r#"<OME><Image><Pixels DimensionOrder="XYCTZ"
+            SizeX="1" SizeY="1" SizeC="2" SizeZ="2" SizeT="3"/></Image></OME>"#;
+        let image = &OmeMetadata::parse(xml).unwrap().images[0];
+        assert_eq!(image.planes.len(), 12);
+        assert_eq!(image.directory(1, 0, 0), Some(1));
+        assert_eq!(image.directory(0, 0, 1), Some(2));
+        assert_eq!(image.directory(1, 1, 2), Some(11));
+
+        assert!(OmeMetadata::parse("<OME><Image/></OME>").is_err());
+        assert!(OmeMetadata::parse("<OME").is_err());
+    }
+
+    #[test]
+    fn test_ome_metadata() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(4));
+        directory.set_field(&ImageDescription(OME.to_string()));
+        let mut read = write_then_read(&directory, &[]);
+        let metadata = read.ome_metadata().unwrap().unwrap();
+        assert_eq!(metadata.images[0].planes.len(), 6);
+
+        let bytes: &[u8] = include_bytes!("../samples/ycbcr-cat.tif");
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.ome_metadata().unwrap(), None);
+    }
+}
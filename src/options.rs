@@ -0,0 +1,99 @@
+//! Options controlling how a `TIFFReader` parses its input.
+use cancel::CancellationToken;
+use error::{CorruptError, Result};
+use limits::Limits;
+use std::borrow::Cow;
+use std::io;
+
+/// What to do when a tag appears several times in the same directory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum DuplicateTagPolicy {
+    /// Keep the first occurrence, like libtiff does.
+    #[default]
+    FirstWins,
+    /// Keep the last occurrence.
+    LastWins,
+    /// Reject the file.
+    Error,
+}
+
+/// How the strings of ASCII values are checked, on read and on write.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum StringEncoding {
+    /// Only 7-bit ASCII, as the specification requires.
+    Ascii,
+    /// UTF-8, which many writers use, rejecting invalid sequences.
+    #[default]
+    Utf8,
+    /// UTF-8, replacing invalid sequences (often Latin-1 text) with U+FFFD
+    /// when reading, and non-ASCII characters with `?` when writing.
+    Lossy,
+}
+
+impl StringEncoding {
+    /// Decodes a string read from an ASCII value, without its terminator.
+    pub(crate) fn decode(self, bytes: &[u8]) -> Result<String> {
+        match self {
+            StringEncoding::Ascii if !bytes.is_ascii() => {
+                Err(CorruptError::Invalid("Non-ASCII character in ASCII value").into())
+            }
+            StringEncoding::Ascii | StringEncoding::Utf8 => String::from_utf8(bytes.to_vec())
+                .map_err(|e| CorruptError::InvalidAscii(e).into()),
+            StringEncoding::Lossy => Ok(String::from_utf8_lossy(bytes).into_owned()),
+        }
+    }
+
+    /// Encodes a string to write in an ASCII value. NUL characters are
+    /// rejected, as they terminate strings.
+    pub(crate) fn encode(self, string: &str) -> io::Result<Cow<'_, str>> {
+        let invalid = |message| Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        if string.contains('\0') {
+            return invalid("NUL character in ASCII value");
+        }
+        match self {
+            StringEncoding::Ascii if !string.is_ascii() => {
+                invalid("Non-ASCII character in ASCII value")
+            }
+            StringEncoding::Lossy if !string.is_ascii() => Ok(Cow::Owned(
+                string
+                    .chars()
+                    .map(|c| if c.is_ascii() { c } else { '?' })
+                    .collect(),
+            )),
+            _ => Ok(Cow::Borrowed(string)),
+        }
+    }
+}
+
+/// Options controlling how a `TIFFReader` parses its input.
+#[derive(Debug, Clone)]
+pub struct ReaderOptions {
+    /// Resource limits enforced while reading.
+    pub limits: Limits,
+    /// How the strings of ASCII values are decoded.
+    pub strings: StringEncoding,
+    /// How repeated tags inside a directory are resolved.
+    pub duplicate_tags: DuplicateTagPolicy,
+    /// Reject malformed entries, such as entries with a count of zero,
+    /// instead of reading them as empty values.
+    pub strict: bool,
+    /// Keep decoded values in memory, so that reading a field again does
+    /// not hit the input.
+    pub cache_values: bool,
+    /// Stops decoding and copying the image data between strips or tiles
+    /// once cancelled.
+    pub cancellation: Option<CancellationToken>,
+}
+
+impl Default for ReaderOptions {
+    fn default() -> ReaderOptions {
+        ReaderOptions {
+            limits: Limits::default(),
+            strings: StringEncoding::default(),
+            duplicate_tags: DuplicateTagPolicy::default(),
+            strict: false,
+            cache_values: true,
+            cancellation: None,
+        }
+    }
+}
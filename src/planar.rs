@@ -0,0 +1,157 @@
+//! This module converts sample buffers between the chunky layout, where the
+//! samples of a pixel are stored together, and the planar layout, where each
+//! band is stored separately.
+//!
+//! In both layouts rows start on a byte boundary, and samples narrower than
+//! a byte are packed most significant bit first.
+use error::Result;
+use writer::invalid_input;
+
+/// Size in bytes of a row of `width` pixels of `bits` bits.
+fn row_bytes(width: u32, bits: u64) -> usize {
+    (u64::from(width) * bits).div_ceil(8) as usize
+}
+
+/// Copies `count` bits from bit `from` of `source` to bit `to` of `target`.
+pub(crate) fn copy_bits(source: &[u8], from: usize, target: &mut [u8], to: usize, count: usize) {
+    if from.is_multiple_of(8) && to.is_multiple_of(8) && count.is_multiple_of(8) {
+        let bytes = count / 8;
+        target[to / 8..to / 8 + bytes].copy_from_slice(&source[from / 8..from / 8 + bytes]);
+        return;
+    }
+    for bit in 0..count {
+        let (source_bit, target_bit) = (from + bit, to + bit);
+        let set = source[source_bit / 8] & (0x80 >> (source_bit % 8)) != 0;
+        let mask = 0x80 >> (target_bit % 8);
+        if set {
+            target[target_bit / 8] |= mask;
+        } else {
+            target[target_bit / 8] &= !mask;
+        }
+    }
+}
+
+/// Checks that there is a sample size per band and that each is valid.
+fn check_bits(bits_per_sample: &[u16]) -> Result<()> {
+    if bits_per_sample.is_empty() || bits_per_sample.contains(&0) {
+        return Err(invalid_input("Invalid bits per sample").into());
+    }
+    Ok(())
+}
+
+/// Interleaves `planes`, one buffer per band of `rows` rows of `width`
+/// samples, into chunky pixels. `bits_per_sample` gives the size of the
+/// samples of each band.
+pub fn interleave(
+    planes: &[&[u8]],
+    bits_per_sample: &[u16],
+    width: u32,
+    rows: u32,
+) -> Result<Vec<u8>> {
+    let mut chunky = Vec::new();
+    interleave_into(planes, bits_per_sample, width, rows, &mut chunky)?;
+    Ok(chunky)
+}
+
+/// Like `interleave`, but replaces the content of `chunky` instead of
+/// allocating a new buffer.
+pub(crate) fn interleave_into(
+    planes: &[&[u8]],
+    bits_per_sample: &[u16],
+    width: u32,
+    rows: u32,
+    chunky: &mut Vec<u8>,
+) -> Result<()> {
+    check_bits(bits_per_sample)?;
+    if planes.len() != bits_per_sample.len() {
+        return Err(invalid_input("Planes and bits per sample differ in number").into());
+    }
+    let plane_rows: Vec<usize> = bits_per_sample
+        .iter()
+        .map(|bits| row_bytes(width, u64::from(*bits)))
+        .collect();
+    let rows = rows as usize;
+    if planes.iter().zip(&plane_rows).any(|(plane, size)| plane.len() < size * rows) {
+        return Err(invalid_input("Plane shorter than the image").into());
+    }
+
+    let pixel_bits: u64 = bits_per_sample.iter().map(|bits| u64::from(*bits)).sum();
+    let chunky_row = row_bytes(width, pixel_bits);
+    chunky.clear();
+    chunky.resize(chunky_row * rows, 0);
+    for row in 0..rows {
+        let mut target = row * chunky_row * 8;
+        for x in 0..width as usize {
+            for ((plane, bits), size) in planes.iter().zip(bits_per_sample).zip(&plane_rows) {
+                let bits = usize::from(*bits);
+                copy_bits(plane, row * size * 8 + x * bits, chunky, target, bits);
+                target += bits;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Splits `rows` rows of `width` chunky pixels into one buffer per band.
+/// `bits_per_sample` gives the size of the samples of each band.
+pub fn deinterleave(
+    chunky: &[u8],
+    bits_per_sample: &[u16],
+    width: u32,
+    rows: u32,
+) -> Result<Vec<Vec<u8>>> {
+    check_bits(bits_per_sample)?;
+    let pixel_bits: u64 = bits_per_sample.iter().map(|bits| u64::from(*bits)).sum();
+    let chunky_row = row_bytes(width, pixel_bits);
+    let rows = rows as usize;
+    if chunky.len() < chunky_row * rows {
+        return Err(invalid_input("Buffer shorter than the image").into());
+    }
+
+    let plane_rows: Vec<usize> = bits_per_sample
+        .iter()
+        .map(|bits| row_bytes(width, u64::from(*bits)))
+        .collect();
+    let mut planes: Vec<Vec<u8>> = plane_rows.iter().map(|size| vec![0; size * rows]).collect();
+    for row in 0..rows {
+        let mut source = row * chunky_row * 8;
+        for x in 0..width as usize {
+            for ((plane, bits), size) in planes.iter_mut().zip(bits_per_sample).zip(&plane_rows) {
+                let bits = usize::from(*bits);
+                copy_bits(chunky, source, plane, row * size * 8 + x * bits, bits);
+                source += bits;
+            }
+        }
+    }
+    Ok(planes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bytes() {
+        // Two pixels of an 8-bit band and a 16-bit band, on two rows
+        let chunky = [1, 10, 11, 2, 20, 21, 3, 30, 31, 4, 40, 41];
+        let planes = deinterleave(&chunky, &[8, 16], 2, 2).unwrap();
+        assert_eq!(planes, [vec![1, 2, 3, 4], vec![10, 11, 20, 21, 30, 31, 40, 41]]);
+        let planes: Vec<&[u8]> = planes.iter().map(|plane| &plane[..]).collect();
+        assert_eq!(interleave(&planes, &[8, 16], 2, 2).unwrap(), chunky);
+
+        assert!(interleave(&planes[..1], &[8, 16], 2, 2).is_err());
+        assert!(interleave(&planes, &[8, 16], 2, 3).is_err());
+        assert!(deinterleave(&chunky, &[8, 0], 2, 2).is_err());
+    }
+
+    #[test]
+    fn test_packed_bits() {
+        // Three pixels of a 1-bit band and a 4-bit band, rows padded to bytes
+        let ones = [0b1010_0000, 0b0110_0000];
+        let nibbles = [0x12, 0x30, 0x45, 0x60];
+        let chunky = interleave(&[&ones, &nibbles], &[1, 4], 3, 2).unwrap();
+        assert_eq!(chunky, [0b1000_1000, 0b1010_0110, 0b0010_0101, 0b0110_1100]);
+        let planes = deinterleave(&chunky, &[1, 4], 3, 2).unwrap();
+        assert_eq!(planes, [ones.to_vec(), nibbles.to_vec()]);
+    }
+}
@@ -0,0 +1,137 @@
+//! This module reduces RGB images to a palette with the median cut algorithm.
+use std::collections::HashMap;
+
+/// A set of distinct colors with their number of occurrences.
+type ColorBox = Vec<([u8; 3], u32)>;
+
+/// The channel with the widest range of values in `colors`, and that range.
+fn widest_channel(colors: &ColorBox) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = colors.iter().map(|(color, _)| color[channel]);
+            let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (channel, range)
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap_or((0, 0))
+}
+
+/// Splits `colors` in two halves of about the same number of pixels along
+/// its widest channel.
+fn split(mut colors: ColorBox) -> (ColorBox, ColorBox) {
+    let (channel, _) = widest_channel(&colors);
+    colors.sort_by_key(|(color, _)| color[channel]);
+
+    let total: u64 = colors.iter().map(|(_, count)| u64::from(*count)).sum();
+    let mut seen = 0;
+    let mut median = 1;
+    for (index, (_, count)) in colors.iter().enumerate() {
+        seen += u64::from(*count);
+        if seen * 2 >= total {
+            median = index + 1;
+            break;
+        }
+    }
+    let median = median.min(colors.len() - 1);
+    let upper = colors.split_off(median);
+    (colors, upper)
+}
+
+/// The average color of `colors`, weighted by their occurrences.
+fn average(colors: &ColorBox) -> [u8; 3] {
+    let total: u64 = colors.iter().map(|(_, count)| u64::from(*count)).sum();
+    let mut average = [0; 3];
+    for (channel, value) in average.iter_mut().enumerate() {
+        let sum: u64 = colors
+            .iter()
+            .map(|(color, count)| u64::from(color[channel]) * u64::from(*count))
+            .sum();
+        *value = ((sum + total / 2) / total) as u8;
+    }
+    average
+}
+
+/// Reduces chunky 8-bit `R G B` pixels to at most `max_colors` colors, and
+/// returns the palette along with the palette index of each pixel.
+///
+/// Images with few enough distinct colors keep them exactly.
+pub(crate) fn quantize(rgb: &[u8], max_colors: usize) -> (Vec<[u8; 3]>, Vec<u8>) {
+    let mut histogram: HashMap<[u8; 3], u32> = HashMap::new();
+    for pixel in rgb.chunks_exact(3) {
+        *histogram.entry([pixel[0], pixel[1], pixel[2]]).or_insert(0) += 1;
+    }
+    let mut colors: ColorBox = histogram.into_iter().collect();
+    colors.sort_unstable();
+
+    let mut boxes = vec![colors];
+    while boxes.len() < max_colors {
+        // Split the box with the widest range over the most pixels next
+        let next = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .max_by_key(|(_, colors)| {
+                let pixels: u64 = colors.iter().map(|(_, count)| u64::from(*count)).sum();
+                u64::from(widest_channel(colors).1) * pixels
+            })
+            .map(|(index, _)| index);
+        let index = match next {
+            Some(index) => index,
+            None => break,
+        };
+        let (lower, upper) = split(boxes.swap_remove(index));
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+
+    let mut indices = HashMap::new();
+    let mut palette = Vec::with_capacity(boxes.len());
+    for (index, colors) in boxes.iter().enumerate() {
+        for (color, _) in colors {
+            indices.insert(*color, index as u8);
+        }
+        palette.push(average(colors));
+    }
+
+    let pixels = rgb
+        .chunks_exact(3)
+        .map(|pixel| indices[&[pixel[0], pixel[1], pixel[2]]])
+        .collect();
+    (palette, pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_palette() {
+        let rgb = [255, 0, 0, 0, 0, 255, 255, 0, 0, 9, 9, 9];
+        let (palette, pixels) = quantize(&rgb, 256);
+        assert_eq!(palette.len(), 3);
+        for (pixel, index) in rgb.chunks(3).zip(&pixels) {
+            assert_eq!(pixel, palette[usize::from(*index)]);
+        }
+    }
+
+    #[test]
+    fn test_median_cut() {
+        // A gray gradient, mostly dark
+        let mut rgb = Vec::new();
+        for value in 0..=255u8 {
+            let count = if value < 64 { 10 } else { 1 };
+            for _ in 0..count {
+                rgb.extend_from_slice(&[value; 3]);
+            }
+        }
+
+        let (palette, pixels) = quantize(&rgb, 16);
+        assert_eq!(palette.len(), 16);
+        assert_eq!(pixels.len(), rgb.len() / 3);
+        assert!(palette.iter().filter(|color| color[0] < 64).count() >= 8);
+        for (pixel, index) in rgb.chunks(3).zip(&pixels) {
+            let color = palette[usize::from(*index)];
+            assert!((i32::from(pixel[0]) - i32::from(color[0])).abs() <= 16);
+        }
+    }
+}
@@ -1,19 +1,48 @@
-use endian::{Endian, EndianReader, Long, LongLong, Short};
-use std::io::{Read, Seek, SeekFrom};
+use endian::{Endian, EndianReader, Long, Short};
+use error::{CorruptError, Result, TiffError};
+use std::borrow::Cow;
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "mmap")]
+use std::{fs::File, path::Path};
 
-use std::collections::hash_map::Keys;
-use std::collections::HashMap;
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
 
-use std::iter::Iterator;
+use std::collections::{HashMap, HashSet};
 
-use tag::{Field, Tag};
+use std::iter::Iterator;
+use std::ops::Range;
+use std::slice::ChunksExact;
+
+use cancel;
+use limits::Limits;
+use options::{DuplicateTagPolicy, ReaderOptions, StringEncoding};
+use source::{OffsetSource, SequentialSource};
+use tag::{Field, FieldType, Tag};
 use value::{Rational, TIFFValue};
+use warning::Warning;
+use writer::{invalid_input, Directory};
 const TIFF_LE: u16 = 0x4949;
 const TIFF_BE: u16 = 0x4D4D;
 
+/// Fields locating data in the file, meaningless once copied to another.
+const LOCATION_TAGS: [Tag; 8] = [
+    Tag::StripOffsets,
+    Tag::StripByteCounts,
+    Tag::TileOffsets,
+    Tag::TileByteCounts,
+    Tag::FreeOffsets,
+    Tag::FreeByteCounts,
+    Tag::JPEGInterchangeFormat,
+    Tag::JPEGInterchangeFormatLength,
+];
+
+/// Fields pointing to child directories.
+const POINTER_TAGS: [Tag; 3] = [Tag::ExifIFD, Tag::GPSInfo, Tag::InteroperabilityIFD];
+
 /// An `IFDEntry` represents an **image file directory**
 /// mentionned inside the tiff specification. This is the base
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub struct IFDEntry {
     pub tag: Tag,
     pub value_type: u16,
@@ -21,72 +50,199 @@ pub struct IFDEntry {
     pub value_offset: u32,
 }
 
+impl IFDEntry {
+    /// Size in bytes of one element of the entry type.
+    fn type_size(&self) -> u64 {
+        FieldType::from_id(self.value_type).map_or(1, FieldType::size)
+    }
+
+    /// Size in bytes of the whole entry value.
+    pub fn value_size(&self) -> u64 {
+        u64::from(self.count) * self.type_size()
+    }
+
+    /// Returns `true` when the value is small enough to be stored
+    /// inside the entry instead of at `value_offset`.
+    pub fn is_inline(&self) -> bool {
+        self.value_size() <= 4
+    }
+}
+
+/// Size and layout statistics of a single directory, useful to diagnose
+/// bloated or fragmented files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryStatistics {
+    /// Number of entries in the directory.
+    pub entry_count: usize,
+    /// Total size in bytes of the values stored outside the directory.
+    pub out_of_line_bytes: u64,
+    /// Number of strips referenced by `StripOffsets`.
+    pub strip_count: usize,
+    /// Number of tiles referenced by `TileOffsets`.
+    pub tile_count: usize,
+    /// Lowest and highest byte offsets used by out-of-line values and image data.
+    pub data_extent: Option<(u64, u64)>,
+}
+
+impl DirectoryStatistics {
+    fn extend_extent(&mut self, start: u64, size: u64) {
+        let end = start + size;
+        self.data_extent = match self.data_extent {
+            Some((min, max)) => Some((min.min(start), max.max(end))),
+            None => Some((start, end)),
+        };
+    }
+}
+
 #[derive(Debug)]
 pub struct IFD {
-    entries: HashMap<Tag, IFDEntry>,
+    /// Entries in the order they are stored in the file.
+    entries: Vec<IFDEntry>,
+    /// Tag codes and positions in `entries`, sorted by tag code.
+    sorted: Vec<(u16, usize)>,
 }
 
-impl<'a> IFD {
+impl IFD {
+    fn new(entries: Vec<IFDEntry>) -> IFD {
+        let mut sorted: Vec<(u16, usize)> = entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| (u16::from(entry.tag), index))
+            .collect();
+        sorted.sort();
+        IFD { entries, sorted }
+    }
+
     pub fn get_entry_from_tag(&self, tag: Tag) -> Option<&IFDEntry> {
-        self.entries.get(&tag)
+        let code = u16::from(tag);
+        let position = self.sorted.binary_search_by_key(&code, |e| e.0).ok()?;
+        Some(&self.entries[self.sorted[position].1])
+    }
+
+    /// The tags of the directory, in the order they are stored in the file.
+    pub fn all_tags(&self) -> impl Iterator<Item = Tag> + '_ {
+        self.entries.iter().map(|entry| entry.tag)
     }
 
-    pub fn all_tags(&self) -> Keys<Tag, IFDEntry> {
-        self.entries.keys()
+    /// The entries of the directory, in the order they are stored in the file.
+    pub fn entries(&self) -> &[IFDEntry] {
+        &self.entries
+    }
+}
+
+/// Returns the total length of a stream, leaving its position unchanged,
+/// or `None` when it cannot be determined.
+fn stream_len<R: Seek>(reader: &mut R) -> Option<u64> {
+    let position = reader.stream_position().ok()?;
+    let len = reader.seek(SeekFrom::End(0)).ok()?;
+    reader.seek(SeekFrom::Start(position)).ok()?;
+    Some(len)
+}
+
+/// Checks that `size` bytes starting at `offset` lie inside a stream of length `len`.
+fn check_range(len: Option<u64>, offset: u64, size: u64) -> Result<()> {
+    match len {
+        Some(len) if offset.saturating_add(size) > len => {
+            Err(CorruptError::OffsetBeyondEndOfFile(offset).into())
+        }
+        _ => Ok(()),
     }
 }
 
 pub struct IFDIterator<'a, R: Read + Seek + 'a> {
-    reader: EndianReader<'a, R>,
-    next_entry: usize,
-    position: usize,
+    reader: &'a mut R,
+    endian: Endian,
+    next_entry: u64,
+    visited: HashSet<u64>,
+    options: ReaderOptions,
+    len: Option<u64>,
+    warnings: Vec<Warning>,
 }
 
 impl<'a, R: Read + Seek> IFDIterator<'a, R>
 where
     R: 'a,
 {
-    pub fn new(reader: &'a mut R, first_ifd_offset: usize, endian: Endian) -> IFDIterator<R> {
-        reader.seek(SeekFrom::Start(0)).ok();
-
+    pub fn new(
+        reader: &'a mut R,
+        first_ifd_offset: u64,
+        endian: Endian,
+        options: &ReaderOptions,
+    ) -> IFDIterator<'a, R> {
+        let len = stream_len(reader);
         IFDIterator {
-            reader: EndianReader::new(reader, endian),
+            reader,
+            endian,
             next_entry: first_ifd_offset,
-            position: 0,
+            visited: HashSet::new(),
+            options: options.clone(),
+            len,
+            warnings: Vec::new(),
         }
     }
-}
 
-impl<'a, R: Read + Seek> Iterator for IFDIterator<'a, R> {
-    type Item = IFD;
+    fn read_ifd(&mut self) -> Result<Option<IFD>> {
+        // An offset of 0 terminates the chain
+        if self.next_entry == 0 {
+            return Ok(None);
+        }
 
-    fn next(&mut self) -> Option<IFD> {
-        // Go to next entry
-        let next = if self.position == 0 {
-            SeekFrom::Start(self.next_entry as u64)
-        } else {
-            SeekFrom::Current(self.next_entry as i64)
-        };
+        if !self.visited.insert(self.next_entry) {
+            return Err(CorruptError::Invalid("IFD chain contains a cycle").into());
+        }
 
-        self.position = self.reader.seek(next).ok()? as usize;
+        if self.visited.len() > self.options.limits.max_directories {
+            return Err(TiffError::Limit("Too many directories"));
+        }
+
+        // Go to next entry
+        check_range(self.len, self.next_entry, 2)?;
+        self.reader.seek(SeekFrom::Start(self.next_entry))?;
 
         // Read Count
-        let entry_count: u16 = self.reader.read_short().ok()?;
+        let entry_count: u16 = EndianReader::new(self.reader, self.endian).read_short()?;
+        debug!(
+            "Directory {} at offset {} has {} entries",
+            self.visited.len() - 1,
+            self.next_entry,
+            entry_count
+        );
         if entry_count < 1 {
-            return None;
+            return Ok(None);
+        }
+
+        if usize::from(entry_count) > self.options.limits.max_entries {
+            return Err(TiffError::Limit("Too many entries in directory"));
         }
 
-        let mut map = HashMap::<Tag, IFDEntry>::new();
+        check_range(self.len, self.next_entry, 2 + 12 * u64::from(entry_count) + 4)?;
+
+        // Read the entries and the next offset at once, then parse them from memory
+        let mut table = vec![0; 12 * usize::from(entry_count) + 4];
+        self.reader.read_exact(&mut table)?;
+        let mut cursor = Cursor::new(table);
+        let mut table = EndianReader::new(&mut cursor, self.endian);
+
+        let mut entries = Vec::with_capacity(usize::from(entry_count));
+        let mut positions = HashMap::<Tag, usize>::new();
+        let mut previous_tag = 0;
+        let mut sorted = true;
         for _i in 0..entry_count {
             // Tag
-            let tag: u16 = self.reader.read_short().ok()?;
+            let tag: u16 = table.read_short()?;
 
             // Type
-            let value_type_raw: u16 = self.reader.read_short().ok()?;
+            let value_type_raw: u16 = table.read_short()?;
 
             // Count
-            let count: u32 = self.reader.read_long().ok()?;
-            let value_offset: u32 = self.reader.read_long().ok()?;
+            let count: u32 = table.read_long()?;
+            let value_offset: u32 = table.read_long()?;
+
+            // The specification requires entries sorted by ascending tag
+            if tag < previous_tag {
+                sorted = false;
+            }
+            previous_tag = tag;
 
             let tag_value = Tag::from(tag);
             let entry = IFDEntry {
@@ -96,27 +252,69 @@ impl<'a, R: Read + Seek> Iterator for IFDIterator<'a, R> {
                 value_offset,
             };
 
-            map.insert(tag_value, entry);
+            if let Some(position) = positions.get(&tag_value) {
+                self.warn(Warning::DuplicateTag {
+                    directory: self.visited.len() - 1,
+                    tag: tag_value,
+                });
+
+                match self.options.duplicate_tags {
+                    DuplicateTagPolicy::FirstWins => {}
+                    DuplicateTagPolicy::LastWins => entries[*position] = entry,
+                    DuplicateTagPolicy::Error => {
+                        return Err(CorruptError::Invalid("Duplicate tag in directory").into())
+                    }
+                }
+                continue;
+            }
+
+            positions.insert(tag_value, entries.len());
+            entries.push(entry);
+        }
+
+        if !sorted {
+            self.warn(Warning::UnsortedEntries {
+                directory: self.visited.len() - 1,
+            });
         }
 
-        let next: u32 = self.reader.read_long().ok()?;
-        self.next_entry = next as usize;
+        let next: u32 = table.read_long()?;
+        self.next_entry = u64::from(next);
 
-        Some(IFD { entries: map })
+        Ok(Some(IFD::new(entries)))
+    }
+
+    fn warn(&mut self, warning: Warning) {
+        warn!("{:?}", warning);
+        self.warnings.push(warning);
+    }
+
+    /// Offset of the next directory to read, 0 once the chain is exhausted.
+    pub(crate) fn next_offset(&self) -> u64 {
+        self.next_entry
     }
-}
 
-error_chain!{
-      foreign_links {
-        Io(::std::io::Error);
-        AsciiFormat(::std::string::FromUtf8Error);
+    pub(crate) fn reader_mut(&mut self) -> &mut R {
+        self.reader
     }
-    errors {
-        InvalidTIFFFile(v: &'static str) {
-            description("Invalid TIFF file"),
-            display("INvalid TIFF File: {}", v),
+
+    pub(crate) fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+}
+
+impl<'a, R: Read + Seek> Iterator for IFDIterator<'a, R> {
+    type Item = Result<IFD>;
+
+    fn next(&mut self) -> Option<Result<IFD>> {
+        match self.read_ifd() {
+            Ok(ifd) => ifd.map(Ok),
+            Err(e) => {
+                // Stop iterating after the first error
+                self.next_entry = 0;
+                Some(Err(e))
+            }
         }
-        DirectoryIndexOutOfBounds
     }
 }
 
@@ -125,15 +323,24 @@ impl TIFFValue {
         reader: &mut R,
         entry: &IFDEntry,
         endian: Endian,
+        options: &ReaderOptions,
     ) -> Result<TIFFValue> {
+        if entry.count == 0 {
+            if options.strict {
+                return Err(CorruptError::Invalid("Entry has no value").into());
+            }
+            return Ok(TIFFValue::empty(entry.value_type));
+        }
+
         match entry.value_type {
             1 => {
-                let bytes = TIFFValue::read_n_bytes(reader, entry, entry.count as usize)?;
+                let size = TIFFValue::checked_size(entry)?;
+                let bytes = TIFFValue::read_n_bytes(reader, entry, size, endian)?;
                 Ok(TIFFValue::Byte(bytes))
             }
 
             2 => {
-                let values = TIFFValue::read_ascii(reader, entry)?;
+                let values = TIFFValue::read_ascii(reader, entry, endian, options.strings)?;
                 Ok(TIFFValue::Ascii(values))
             }
 
@@ -153,7 +360,8 @@ impl TIFFValue {
             }
 
             6 => {
-                let mut bytes = TIFFValue::read_n_bytes(reader, entry, entry.count as usize)?;
+                let size = TIFFValue::checked_size(entry)?;
+                let bytes = TIFFValue::read_n_bytes(reader, entry, size, endian)?;
                 let result = bytes.iter().map(|i| *i as i8).collect();
                 Ok(TIFFValue::SByte(result))
             }
@@ -172,47 +380,87 @@ impl TIFFValue {
                 Ok(TIFFValue::SRational(values))
             }
             11 => {
-                let values: Vec<u32> = TIFFValue::read_long(reader, entry, endian)?;
-                let result = values.iter().map(|i| f32::from_bits(*i)).collect();
-                Ok(TIFFValue::Float(result))
+                let values = TIFFValue::read_float(reader, entry, endian)?;
+                Ok(TIFFValue::Float(values))
             }
             12 => {
-                let values: Vec<u64> = TIFFValue::read_long_long(reader, entry, endian)?;
-                let result = values.iter().map(|i| f64::from_bits(*i)).collect();
-                Ok(TIFFValue::Double(result))
+                let values = TIFFValue::read_double(reader, entry, endian)?;
+                Ok(TIFFValue::Double(values))
             }
             _ => {
-                let bytes = TIFFValue::read_n_bytes(reader, entry, entry.count as usize)?;
+                let size = TIFFValue::checked_size(entry)?;
+                let bytes = TIFFValue::read_n_bytes(reader, entry, size, endian)?;
                 Ok(TIFFValue::Undefined(bytes))
             }
         }
     }
 
-    fn value_type_id(&self) -> u16 {
+    /// An empty value of the variant matching `value_type`.
+    fn empty(value_type: u16) -> TIFFValue {
+        match value_type {
+            1 => TIFFValue::Byte(Vec::new()),
+            2 => TIFFValue::Ascii(Vec::new()),
+            3 => TIFFValue::Short(Vec::new()),
+            4 => TIFFValue::Long(Vec::new()),
+            5 => TIFFValue::Rational(Vec::new()),
+            6 => TIFFValue::SByte(Vec::new()),
+            8 => TIFFValue::SShort(Vec::new()),
+            9 => TIFFValue::SLong(Vec::new()),
+            10 => TIFFValue::SRational(Vec::new()),
+            11 => TIFFValue::Float(Vec::new()),
+            12 => TIFFValue::Double(Vec::new()),
+            _ => TIFFValue::Undefined(Vec::new()),
+        }
+    }
+
+    /// The type of the value.
+    pub fn field_type(&self) -> FieldType {
         match self {
-            TIFFValue::Byte(_) => 1,
-            TIFFValue::Ascii(_) => 2,
-            TIFFValue::Short(_) => 3,
-            TIFFValue::Long(_) => 4,
-            TIFFValue::Rational(_) => 5,
-            TIFFValue::SByte(_) => 6,
-            TIFFValue::Undefined(_) => 7,
-            TIFFValue::SShort(_) => 8,
-            TIFFValue::SLong(_) => 9,
-            TIFFValue::SRational(_) => 10,
-            TIFFValue::Float(_) => 11,
-            TIFFValue::Double(_) => 12,
+            TIFFValue::Byte(_) => FieldType::Byte,
+            TIFFValue::Ascii(_) => FieldType::Ascii,
+            TIFFValue::Short(_) => FieldType::Short,
+            TIFFValue::Long(_) => FieldType::Long,
+            TIFFValue::Rational(_) => FieldType::Rational,
+            TIFFValue::SByte(_) => FieldType::SByte,
+            TIFFValue::Undefined(_) => FieldType::Undefined,
+            TIFFValue::SShort(_) => FieldType::SShort,
+            TIFFValue::SLong(_) => FieldType::SLong,
+            TIFFValue::SRational(_) => FieldType::SRational,
+            TIFFValue::Float(_) => FieldType::Float,
+            TIFFValue::Double(_) => FieldType::Double,
         }
     }
 
+    pub(crate) fn value_type_id(&self) -> u16 {
+        self.field_type().id()
+    }
+
+    /// Size in bytes of the entry value, rejecting sizes which cannot fit
+    /// inside a classic TIFF file.
+    fn checked_size(entry: &IFDEntry) -> Result<usize> {
+        let size = entry.value_size();
+        if !entry.is_inline() && u64::from(entry.value_offset) + size > u64::from(u32::MAX)
+        {
+            return Err(
+                CorruptError::Invalid("Value size exceeds the maximum file size").into(),
+            );
+        }
+        Ok(size as usize)
+    }
+
     fn read_n_bytes<R: Read + Seek>(
         reader: &mut R,
         entry: &IFDEntry,
         size: usize,
+        endian: Endian,
     ) -> Result<Vec<u8>> {
         if size <= 4 {
-            let bytes = &entry.value_offset.to_bytes();
-            Ok(bytes.to_vec())
+            // Restore the bytes in the order they were stored in the file
+            let bytes = match endian {
+                Endian::Big => entry.value_offset.to_be_bytes(),
+                Endian::Little => entry.value_offset.to_le_bytes(),
+            };
+            Ok(bytes[..size].to_vec())
         } else {
             reader.seek(SeekFrom::Start(u64::from(entry.value_offset)))?;
             let mut vec: Vec<u8> = vec![0; size];
@@ -221,13 +469,37 @@ impl TIFFValue {
         }
     }
 
-    fn read_ascii<R: Read + Seek>(reader: &mut R, entry: &IFDEntry) -> Result<Vec<String>> {
-        let bytes = TIFFValue::read_n_bytes(reader, entry, entry.count as usize)?;
+    /// Splits `bytes` in chunks of `size` bytes, failing if some bytes are left over.
+    fn exact_chunks(bytes: &[u8], size: usize) -> Result<ChunksExact<'_, u8>> {
+        let chunks = bytes.chunks_exact(size);
+        if chunks.remainder().is_empty() {
+            Ok(chunks)
+        } else {
+            Err(CorruptError::Invalid("Truncated value").into())
+        }
+    }
 
-        // Splits by null cahracter
-        bytes
-            .split(|e| *e == b'0')
-            .map(|a| String::from_utf8(a.to_vec()).map_err(|e| ErrorKind::AsciiFormat(e).into()))
+    fn read_ascii<R: Read + Seek>(
+        reader: &mut R,
+        entry: &IFDEntry,
+        endian: Endian,
+        strings: StringEncoding,
+    ) -> Result<Vec<String>> {
+        let size = TIFFValue::checked_size(entry)?;
+        let bytes = TIFFValue::read_n_bytes(reader, entry, size, endian)?;
+        if bytes.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Each string is NUL terminated, drop the last terminator before splitting
+        let content = match bytes.split_last() {
+            Some((0, rest)) => rest,
+            _ => &bytes[..],
+        };
+
+        content
+            .split(|e| *e == 0)
+            .map(|a| strings.decode(a))
             .collect()
     }
 
@@ -237,18 +509,12 @@ impl TIFFValue {
         endian: Endian,
     ) -> Result<Vec<T>> {
         let mut conv_buff: [u8; 2] = [0; 2];
-        let size = entry.count * 2;
-        let mut bytes = TIFFValue::read_n_bytes(reader, entry, size as usize)?;
-
-        if endian == Endian::Big && size <= 4 {
-            bytes.reverse()
-        }
+        let size = TIFFValue::checked_size(entry)?;
+        let bytes = TIFFValue::read_n_bytes(reader, entry, size, endian)?;
 
-        let elements: Vec<T> = bytes
-            .chunks(2)
+        let elements: Vec<T> = TIFFValue::exact_chunks(&bytes, 2)?
             .map(|e| {
                 conv_buff.copy_from_slice(e);
-
                 endian.short_from_bytes::<T>(conv_buff)
             }).collect();
 
@@ -261,15 +527,10 @@ impl TIFFValue {
         endian: Endian,
     ) -> Result<Vec<T>> {
         let mut conv_buff: [u8; 4] = [0; 4];
-        let size = entry.count * 4;
-        let mut bytes = TIFFValue::read_n_bytes(reader, entry, size as usize)?;
-
-        if endian == Endian::Big && size <= 4 {
-            bytes.reverse()
-        }
+        let size = TIFFValue::checked_size(entry)?;
+        let bytes = TIFFValue::read_n_bytes(reader, entry, size, endian)?;
 
-        let elements: Vec<T> = bytes
-            .chunks(4)
+        let elements: Vec<T> = TIFFValue::exact_chunks(&bytes, 4)?
             .map(|e| {
                 conv_buff.copy_from_slice(e);
                 endian.long_from_bytes::<T>(conv_buff)
@@ -277,25 +538,33 @@ impl TIFFValue {
         Ok(elements)
     }
 
-    fn read_long_long<R: Read + Seek, T: LongLong>(
+    fn read_float<R: Read + Seek>(
         reader: &mut R,
         entry: &IFDEntry,
         endian: Endian,
-    ) -> Result<Vec<T>> {
-        let mut conv_buff: [u8; 8] = [0; 8];
-        let size = entry.count * 8;
-        let mut bytes = TIFFValue::read_n_bytes(reader, entry, size as usize)?;
+    ) -> Result<Vec<f32>> {
+        let size = TIFFValue::checked_size(entry)?;
+        let bytes = TIFFValue::read_n_bytes(reader, entry, size, endian)?;
+        let count = TIFFValue::exact_chunks(&bytes, 4)?.len();
 
-        if endian == Endian::Big && size <= 8 {
-            bytes.reverse()
-        }
+        let mut cursor = Cursor::new(bytes);
+        let mut floats = EndianReader::new(&mut cursor, endian);
+        let elements = (0..count).map(|_| floats.read_float()).collect::<io::Result<_>>()?;
+        Ok(elements)
+    }
 
-        let elements: Vec<T> = bytes
-            .chunks(8)
-            .map(|e| {
-                conv_buff.copy_from_slice(e);
-                endian.longlong_from_bytes::<T>(conv_buff)
-            }).collect();
+    fn read_double<R: Read + Seek>(
+        reader: &mut R,
+        entry: &IFDEntry,
+        endian: Endian,
+    ) -> Result<Vec<f64>> {
+        let size = TIFFValue::checked_size(entry)?;
+        let bytes = TIFFValue::read_n_bytes(reader, entry, size, endian)?;
+        let count = TIFFValue::exact_chunks(&bytes, 8)?.len();
+
+        let mut cursor = Cursor::new(bytes);
+        let mut doubles = EndianReader::new(&mut cursor, endian);
+        let elements = (0..count).map(|_| doubles.read_double()).collect::<io::Result<_>>()?;
         Ok(elements)
     }
 
@@ -304,83 +573,150 @@ impl TIFFValue {
         entry: &IFDEntry,
         endian: Endian,
     ) -> Result<Vec<Rational<T>>> {
-        let size = entry.count * 8;
-        let mut conv_buff: [u8; 4] = [0; 4];
-        let bytes = TIFFValue::read_n_bytes(reader, entry, size as usize)?;
+        let mut num_buff: [u8; 4] = [0; 4];
+        let mut denom_buff: [u8; 4] = [0; 4];
+        let size = TIFFValue::checked_size(entry)?;
+        let bytes = TIFFValue::read_n_bytes(reader, entry, size, endian)?;
 
-        let elements: Vec<T> = bytes
-            .chunks(4)
+        let elements: Vec<Rational<T>> = TIFFValue::exact_chunks(&bytes, 8)?
             .map(|e| {
-                conv_buff.copy_from_slice(e);
-                endian.long_from_bytes::<T>(conv_buff)
+                num_buff.copy_from_slice(&e[..4]);
+                denom_buff.copy_from_slice(&e[4..]);
+                Rational {
+                    num: endian.long_from_bytes::<T>(num_buff),
+                    denom: endian.long_from_bytes::<T>(denom_buff),
+                }
             }).collect();
-
-        Ok(elements
-            .chunks(2)
-            .map(|e| Rational {
-                num: e[0],
-                denom: e[1],
-            }).collect())
+        Ok(elements)
     }
 }
 
+/// Basic information read from a TIFF header.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TiffInfo {
+    /// The byte order of the file.
+    pub endian: Endian,
+    /// Whether the file uses the BigTIFF (64-bit offsets) layout.
+    pub big_tiff: bool,
+    /// Offset of the first image file directory.
+    pub first_ifd_offset: u64,
+}
+
+/// Reads only the TIFF header (8 bytes, or 16 bytes for BigTIFF) without
+/// parsing any directory, which makes it suitable for fast file-type sniffing.
+pub fn probe<R: Read>(mut reader: R) -> Result<TiffInfo> {
+    // Check order raw validation
+    let mut order_bytes = [0, 0];
+    reader.read_exact(&mut order_bytes)?;
+
+    let endian = match u16::from_be_bytes(order_bytes) {
+        TIFF_LE => Endian::Little,
+        TIFF_BE => Endian::Big,
+        _ => {
+            return Err(CorruptError::Invalid("Invalid magic endian bytes").into());
+        }
+    };
+
+    let mut reader = EndianReader::new(&mut reader, endian);
+    let big_tiff = match reader.read_short::<u16>()? {
+        42 => false,
+        43 => true,
+        _ => return Err(CorruptError::Invalid("Invalid magic byte").into()),
+    };
+
+    let first_ifd_offset = if big_tiff {
+        let offset_size: u16 = reader.read_short()?;
+        let padding: u16 = reader.read_short()?;
+        if offset_size != 8 || padding != 0 {
+            return Err(CorruptError::Invalid("Invalid BigTIFF header").into());
+        }
+        reader.read_longlong()?
+    } else {
+        u64::from(reader.read_long::<u32>()?)
+    };
+
+    Ok(TiffInfo {
+        endian,
+        big_tiff,
+        first_ifd_offset,
+    })
+}
+
 pub struct TIFFReader<R> {
     inner: R,
     ifds: Vec<IFD>,
     endian: Endian,
     current_directory_index: usize,
+    options: ReaderOptions,
+    decoded_bytes: u64,
+    len: Option<u64>,
+    warnings: Vec<Warning>,
+    value_cache: HashMap<(usize, Tag), TIFFValue>,
 }
 
 impl<R: Read + Seek> TIFFReader<R> {
     /// Creates a new TIFF reader from the input `Read` type.
-    pub fn new(mut reader: R) -> Result<TIFFReader<R>> {
-        // Check order raw validation
-        let mut order_bytes = [0, 0];
-        reader.read_exact(&mut order_bytes)?;
-
-        let order_raw = u16::to_be(u16::from_bytes(order_bytes));
-        let order = match order_raw {
-            TIFF_LE => Endian::Little,
-            TIFF_BE => Endian::Big,
-            _ => {
-                return Err(ErrorKind::InvalidTIFFFile("Invalid magic endian bytes").into());
-            }
-        };
-
-        // Valid magic number for tiff
-        let mut tiff_magic_raw = [0, 0];
-        reader.read_exact(&mut tiff_magic_raw)?;
-        let tiff_magic = match order {
-            Endian::Big => u16::from_be(u16::from_bytes(tiff_magic_raw)),
-            Endian::Little => u16::from_le(u16::from_bytes(tiff_magic_raw)),
-        };
+    pub fn new(reader: R) -> Result<TIFFReader<R>> {
+        TIFFReader::with_limits(reader, Limits::default())
+    }
 
-        if tiff_magic != 42u16 {
-            return Err(ErrorKind::InvalidTIFFFile("Invalid magic byte").into());
-        }
+    /// Creates a TIFF reader hardened for untrusted input, such as user uploads
+    /// processed server-side.
+    ///
+    /// On top of the checks always performed (IFD cycle detection, overflow-checked
+    /// sizes, offsets validated against the stream length), the reader enforces
+    /// `Limits::untrusted()`. Malformed input is reported through `Err` or `None`
+    /// results and never causes a panic or an unbounded allocation.
+    pub fn open_untrusted(reader: R) -> Result<TIFFReader<R>> {
+        TIFFReader::with_limits(reader, Limits::untrusted())
+    }
 
-        // Read
-        let mut offset_bytes: [u8; 4] = [0; 4];
-        reader.read_exact(&mut offset_bytes)?;
+    /// Creates a new TIFF reader enforcing the given resource limits.
+    pub fn with_limits(reader: R, limits: Limits) -> Result<TIFFReader<R>> {
+        TIFFReader::with_options(
+            reader,
+            ReaderOptions {
+                limits,
+                ..ReaderOptions::default()
+            },
+        )
+    }
 
-        let offset = match order {
-            Endian::Big => u32::from_be(u32::from_bytes(offset_bytes)),
-            Endian::Little => u32::from_le(u32::from_bytes(offset_bytes)),
-        };
+    /// Creates a new TIFF reader with the given options.
+    pub fn with_options(mut reader: R, options: ReaderOptions) -> Result<TIFFReader<R>> {
+        let info = probe(&mut reader)?;
+        if info.big_tiff {
+            return Err(TiffError::Unsupported("BigTIFF files"));
+        }
+        let order = info.endian;
+        let offset = info.first_ifd_offset;
 
-        let ifds: Vec<IFD> = IFDIterator::new(&mut reader, offset as usize, order).collect();
+        let mut directories = IFDIterator::new(&mut reader, offset, order, &options);
+        let ifds = directories.by_ref().collect::<Result<Vec<IFD>>>()?;
+        let warnings = directories.warnings;
+        let len = stream_len(&mut reader);
         if ifds.is_empty() {
-            Err(ErrorKind::InvalidTIFFFile("TIFF file should have one least one directory").into())
+            Err(CorruptError::Invalid("TIFF file should have one least one directory").into())
         } else {
             Ok(TIFFReader {
                 inner: reader,
                 ifds,
                 endian: order,
                 current_directory_index: 0,
+                options,
+                decoded_bytes: 0,
+                len,
+                warnings,
+                value_cache: HashMap::new(),
             })
         }
     }
 
+    /// The options the input is parsed with.
+    pub fn options(&self) -> &ReaderOptions {
+        &self.options
+    }
+
     /// Returns the endianness of the processed input.
     pub fn endianness(&self) -> Endian {
         self.endian
@@ -389,37 +725,700 @@ impl<R: Read + Seek> TIFFReader<R> {
     /// Look for a specific tag in all IFDS.
     pub fn get_field<T: Field>(&mut self) -> Option<T> {
         // Check if we have an entry inside any of the directory
+        self.with_tag_value(T::tag(), T::decode_from_value).ok()??
+    }
+
+    /// Calls `f` with the value of `tag` in the current directory, if present.
+    ///
+    /// Values are cached per directory unless `ReaderOptions::cache_values` is unset.
+    fn with_tag_value<T, F>(&mut self, tag: Tag, f: F) -> Result<Option<T>>
+    where
+        F: FnOnce(&TIFFValue) -> T,
+    {
+        let key = (self.current_directory_index, tag);
+        if let Some(value) = self.value_cache.get(&key) {
+            return Ok(Some(f(value)));
+        }
 
-        let tag = T::tag();
-        let ifd_entry = self.ifds[self.current_directory_index].get_entry_from_tag(tag)?;
-        let value = TIFFValue::new_from_entry(&mut self.inner, ifd_entry, self.endian).ok()?;
-        T::decode_from_value(&value)
+        let entry = match self.ifds[self.current_directory_index].get_entry_from_tag(tag) {
+            Some(entry) => *entry,
+            None => return Ok(None),
+        };
+        let value = self.read_value(&entry)?;
+        let result = f(&value);
+        if self.options.cache_values {
+            self.value_cache.insert(key, value);
+        }
+        Ok(Some(result))
     }
 
-    /// Set the current reading TIFF directory
+    /// Reads the value of `entry`, enforcing the reader limits.
+    fn read_value(&mut self, entry: &IFDEntry) -> Result<TIFFValue> {
+        let size = entry.value_size();
+        if size > self.options.limits.max_value_size {
+            return Err(TiffError::Limit("Value is too large"));
+        }
+
+        self.count_decoded(size)?;
+        if !entry.is_inline() {
+            check_range(self.len, u64::from(entry.value_offset), size)?;
+        }
+
+        TIFFValue::new_from_entry(&mut self.inner, entry, self.endian, &self.options)
+    }
+
+    /// Adds `size` bytes to those decoded over the lifetime of the reader,
+    /// failing once they exceed `max_decoded_size`.
+    pub(crate) fn count_decoded(&mut self, size: u64) -> Result<()> {
+        self.decoded_bytes = self.decoded_bytes.saturating_add(size);
+        if self.decoded_bytes > self.options.limits.max_decoded_size {
+            return Err(TiffError::Limit("Too many bytes decoded"));
+        }
+        Ok(())
+    }
+
+    /// Reads the value of `tag` in the current directory, if present.
+    pub(crate) fn read_tag(&mut self, tag: Tag) -> Result<Option<TIFFValue>> {
+        self.with_tag_value(tag, |value| value.clone())
+    }
+
+    /// Reads an array of unsigned integers, such as strip offsets, of the current directory.
+    pub(crate) fn read_unsigned(&mut self, tag: Tag) -> Result<Vec<u64>> {
+        self.with_tag_value(tag, TIFFValue::as_unsigned)?
+            .and_then(|value| value)
+            .ok_or_else(|| CorruptError::Invalid("Missing or invalid strip layout").into())
+    }
+
+    /// Offset and size in bytes of strip `index` of the current directory.
+    fn strip_location(&mut self, index: usize) -> Result<(u64, u64)> {
+        let offset = self.read_array_element(Tag::StripOffsets, index)?;
+        let size = self.read_array_element(Tag::StripByteCounts, index)?;
+        Ok((offset, size))
+    }
+
+    /// Offset and size in bytes of tile `index` of the current directory.
+    fn tile_location(&mut self, index: usize) -> Result<(u64, u64)> {
+        let offset = self.read_array_element(Tag::TileOffsets, index)?;
+        let size = self.read_array_element(Tag::TileByteCounts, index)?;
+        Ok((offset, size))
+    }
+
+    /// Reads element `index` of an unsigned integer array of the current
+    /// directory, such as `TileOffsets`, without loading the whole array
+    /// unless it is already cached.
+    pub fn read_array_element(&mut self, tag: Tag, index: usize) -> Result<u64> {
+        if let Some(value) = self.value_cache.get(&(self.current_directory_index, tag)) {
+            return value
+                .unsigned_at(index)
+                .ok_or(TiffError::SegmentIndexOutOfBounds);
+        }
+
+        let entry = *self.ifds[self.current_directory_index]
+            .get_entry_from_tag(tag)
+            .ok_or(CorruptError::Invalid("Missing array field"))?;
+        if index >= entry.count as usize {
+            return Err(TiffError::SegmentIndexOutOfBounds);
+        }
+
+        let size = match entry.value_type {
+            3 => 2,
+            4 => 4,
+            _ => return Err(CorruptError::Invalid("Invalid array field type").into()),
+        };
+        let mut bytes = [0; 4];
+        if entry.is_inline() {
+            let inline = match self.endian {
+                Endian::Big => entry.value_offset.to_be_bytes(),
+                Endian::Little => entry.value_offset.to_le_bytes(),
+            };
+            bytes[..size].copy_from_slice(&inline[index * size..(index + 1) * size]);
+        } else {
+            let offset = u64::from(entry.value_offset) + (index * size) as u64;
+            check_range(self.len, offset, size as u64)?;
+            self.inner.seek(SeekFrom::Start(offset))?;
+            self.inner.read_exact(&mut bytes[..size])?;
+        }
+
+        Ok(match size {
+            2 => u64::from(self.endian.short_from_bytes::<u16>([bytes[0], bytes[1]])),
+            _ => u64::from(self.endian.long_from_bytes::<u32>(bytes)),
+        })
+    }
+
+    /// Replaces the content of `buffer` with `size` bytes read at `offset`.
+    pub(crate) fn read_bytes_into(
+        &mut self,
+        offset: u64,
+        size: u64,
+        buffer: &mut Vec<u8>,
+    ) -> Result<()> {
+        if size > self.options.limits.max_value_size {
+            return Err(TiffError::Limit("Strip is too large"));
+        }
+        self.count_decoded(size)?;
+
+        trace!("Reading {} bytes at offset {}", size, offset);
+        self.inner.seek(SeekFrom::Start(offset))?;
+        buffer.clear();
+        buffer.resize(size as usize, 0);
+        self.inner.read_exact(buffer)?;
+        Ok(())
+    }
+
+    /// Copies `size` bytes read at `offset` to `output`, in bounded chunks
+    /// rather than through a buffer of the whole range.
+    pub(crate) fn copy_bytes(
+        &mut self,
+        offset: u64,
+        size: u64,
+        output: &mut dyn Write,
+    ) -> Result<()> {
+        cancel::check(&self.options.cancellation)?;
+        check_range(self.len, offset, size)?;
+        self.count_decoded(size)?;
+        self.inner.seek(SeekFrom::Start(offset))?;
+        let copied = io::copy(&mut (&mut self.inner).take(size), output)?;
+        if copied < size {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(())
+    }
+
+    /// The number of strips of the current directory.
+    pub fn strip_count(&mut self) -> Result<usize> {
+        self.ifds[self.current_directory_index]
+            .get_entry_from_tag(Tag::StripOffsets)
+            .map(|entry| entry.count as usize)
+            .ok_or_else(|| CorruptError::Invalid("Missing StripOffsets").into())
+    }
+
+    /// Reads the raw bytes of strip `index` of the current directory, as stored in the file.
+    pub fn read_strip(&mut self, index: usize) -> Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        self.read_strip_into(index, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Like `read_strip`, but reuses the allocation of `buffer`, whose content is replaced.
+    pub fn read_strip_into(&mut self, index: usize, buffer: &mut Vec<u8>) -> Result<()> {
+        let (offset, size) = self.strip_location(index)?;
+        check_range(self.len, offset, size)?;
+        self.read_bytes_into(offset, size, buffer)
+    }
+
+    /// Reads the raw bytes of strip `index` into the start of `buffer`, which
+    /// must be at least as large as its `StripByteCounts`, and returns their
+    /// number. Nothing is allocated.
+    pub fn read_strip_to_slice(&mut self, index: usize, buffer: &mut [u8]) -> Result<usize> {
+        let (offset, size) = self.strip_location(index)?;
+        check_range(self.len, offset, size)?;
+        if size > buffer.len() as u64 {
+            return Err(invalid_input("Buffer smaller than the strip").into());
+        }
+        self.count_decoded(size)?;
+        let size = size as usize;
+        self.inner.seek(SeekFrom::Start(offset))?;
+        self.inner.read_exact(&mut buffer[..size])?;
+        Ok(size)
+    }
+
+    /// Reads the raw bytes of the strips in `range`, like `read_partial_strip`.
+    ///
+    /// Strips stored back-to-back in the file are fetched with a single read
+    /// request and split in memory.
+    pub fn read_partial_strips(&mut self, range: Range<usize>) -> Result<Vec<Vec<u8>>> {
+        self.read_partial_strips_within(range, None)
+    }
+
+    /// Like `read_partial_strips`, but reads at most `sizes[i]` bytes of the
+    /// `i`-th strip of `range` when `sizes` is given.
+    pub(crate) fn read_partial_strips_within(
+        &mut self,
+        range: Range<usize>,
+        sizes: Option<&[u64]>,
+    ) -> Result<Vec<Vec<u8>>> {
+        let offsets = self.read_unsigned(Tag::StripOffsets)?;
+        let mut counts = self.read_unsigned(Tag::StripByteCounts)?;
+        if range.end > offsets.len().min(counts.len()) {
+            return Err(TiffError::SegmentIndexOutOfBounds);
+        }
+        if let Some(sizes) = sizes {
+            for (count, size) in counts[range.clone()].iter_mut().zip(sizes) {
+                *count = (*count).min(*size);
+            }
+        }
+
+        let mut strips = Vec::with_capacity(range.len());
+        let mut run = Vec::new();
+        let mut index = range.start;
+        while index < range.end {
+            cancel::check(&self.options.cancellation)?;
+            // Extend the run while the next strip starts where the previous one ends
+            let start = offsets[index];
+            let mut end = start + counts[index];
+            let mut next = index + 1;
+            while next < range.end
+                && offsets[next] == end
+                && end - start + counts[next] <= self.options.limits.max_value_size
+            {
+                cancel::check(&self.options.cancellation)?;
+                end += counts[next];
+                next += 1;
+            }
+
+            let available = match self.len {
+                Some(len) => end.min(len).saturating_sub(start),
+                None => end - start,
+            };
+            trace!("Reading strips {}..{} at once", index, next);
+            self.read_bytes_into(start, available, &mut run)?;
+
+            for strip in index..next {
+                let from = ((offsets[strip] - start) as usize).min(run.len());
+                let to = (from + counts[strip] as usize).min(run.len());
+                strips.push(run[from..to].to_vec());
+            }
+            index = next;
+        }
+
+        Ok(strips)
+    }
+
+    /// Like `read_strip`, but returns the bytes present in the file when the
+    /// strip is cut short by the end of the file.
+    pub fn read_partial_strip(&mut self, index: usize) -> Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        self.read_partial_strip_into(index, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Like `read_partial_strip`, but reuses the allocation of `buffer`, whose
+    /// content is replaced.
+    pub fn read_partial_strip_into(&mut self, index: usize, buffer: &mut Vec<u8>) -> Result<()> {
+        let (offset, available) = self.partial_strip_range(index, None)?;
+        self.read_bytes_into(offset, available, buffer)
+    }
+
+    /// Offset and number of bytes present in the file of strip `index`, of at
+    /// most `size` bytes when given.
+    pub(crate) fn partial_strip_range(
+        &mut self,
+        index: usize,
+        size: Option<u64>,
+    ) -> Result<(u64, u64)> {
+        let (offset, count) = self.strip_location(index)?;
+        let count = size.map_or(count, |size| count.min(size));
+        let available = match self.len {
+            Some(len) => count.min(len.saturating_sub(offset)),
+            None => count,
+        };
+        Ok((offset, available))
+    }
+
+    /// The index of the current reading TIFF directory
+    pub fn directory_index(&self) -> usize {
+        self.current_directory_index
+    }
+
+    /// Selects the directory that field accessors such as `get_field`, strip
+    /// and tile reads and `Image::new` work on.
+    #[deprecated(note = "use `directories` with `Image::from_directory` or `with_directory`")]
     pub fn set_directory_index(&mut self, index: usize) -> Result<()> {
-        if index > self.ifds.len() - 1 {
-            Err(ErrorKind::DirectoryIndexOutOfBounds.into())
+        self.select_directory(index)
+    }
+
+    /// Makes directory `index` the one field accessors, strip and tile reads
+    /// and `Image::new` work on.
+    pub(crate) fn select_directory(&mut self, index: usize) -> Result<()> {
+        if index >= self.ifds.len() {
+            Err(TiffError::DirectoryIndexOutOfBounds)
         } else {
             self.current_directory_index = index;
             Ok(())
         }
     }
 
+    /// Runs `f` with the field accessors, strip and tile reads of the reader
+    /// working on the directory of `handle`, then goes back to the directory
+    /// they worked on before.
+    pub fn with_directory<T, F>(&mut self, handle: &DirectoryHandle, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut TIFFReader<R>) -> Result<T>,
+    {
+        let current = self.current_directory_index;
+        self.select_directory(handle.index)?;
+        let result = f(self);
+        self.current_directory_index = current;
+        result
+    }
+
+    /// Reads the child directory referenced by the pointer field `tag` of the
+    /// current directory, such as `ExifIFD` or `GPSInfo`, with all its values.
+    pub fn read_sub_directory(&mut self, tag: Tag) -> Result<Option<Directory>> {
+        let offset = match self.read_tag(tag)? {
+            Some(value) => value
+                .unsigned_at(0)
+                .ok_or(CorruptError::Invalid("Invalid directory pointer"))?,
+            None => return Ok(None),
+        };
+
+        let ifd = match self.read_ifd_at(offset)? {
+            Some(ifd) => ifd,
+            None => return Ok(None),
+        };
+        self.read_values(ifd.entries()).map(Some)
+    }
+
+    /// Reads the directory at `index` with all its values, those locating
+    /// the image data included.
+    pub fn directory(&mut self, index: usize) -> Result<DirectoryHandle> {
+        let entries = self
+            .ifds
+            .get(index)
+            .ok_or(TiffError::DirectoryIndexOutOfBounds)?
+            .entries()
+            .to_vec();
+        let fields = self.read_values(&entries)?;
+        Ok(DirectoryHandle { index, fields })
+    }
+
+    /// Returns an iterator over the directories of the file, in order, each
+    /// read by `directory`.
+    pub fn directories(&mut self) -> Directories<'_, R> {
+        Directories {
+            reader: self,
+            index: 0,
+        }
+    }
+
+    /// Reads the values of `entries` into a `Directory`.
+    fn read_values(&mut self, entries: &[IFDEntry]) -> Result<Directory> {
+        let mut directory = Directory::new();
+        for entry in entries {
+            let value =
+                TIFFValue::new_from_entry(&mut self.inner, entry, self.endian, &self.options)?;
+            directory.set(entry.tag, value);
+        }
+        Ok(directory)
+    }
+
+    /// Copies the fields of the current directory into a `Directory`, to be
+    /// written again along with new image data.
+    ///
+    /// The fields locating data in this file, such as `StripOffsets` or
+    /// `FreeOffsets`, are left out. The EXIF, GPS and interoperability
+    /// directories are copied as child directories.
+    pub fn read_directory(&mut self) -> Result<Directory> {
+        let entries = self.ifds[self.current_directory_index].entries().to_vec();
+        self.copy_entries(&entries, 0)
+    }
+
+    fn copy_entries(&mut self, entries: &[IFDEntry], depth: usize) -> Result<Directory> {
+        let mut directory = Directory::new();
+        for entry in entries {
+            if LOCATION_TAGS.contains(&entry.tag) {
+                continue;
+            }
+            let value =
+                TIFFValue::new_from_entry(&mut self.inner, entry, self.endian, &self.options)?;
+            if !POINTER_TAGS.contains(&entry.tag) {
+                directory.set(entry.tag, value);
+                continue;
+            }
+            // Pointer loops are cut, the known directories nest only once
+            let offset = value
+                .unsigned_at(0)
+                .ok_or(CorruptError::Invalid("Invalid directory pointer"))?;
+            if depth < 2 {
+                if let Some(ifd) = self.read_ifd_at(offset)? {
+                    let child = self.copy_entries(ifd.entries(), depth + 1)?;
+                    directory.set_sub_directory(entry.tag, child);
+                }
+            }
+        }
+        Ok(directory)
+    }
+
+    /// Reads the directory at `offset`, without following its next link.
+    fn read_ifd_at(&mut self, offset: u64) -> Result<Option<IFD>> {
+        let mut ifds = IFDIterator::new(&mut self.inner, offset, self.endian, &self.options);
+        ifds.next().transpose()
+    }
+
     /// The underlying directories
     pub fn ifds(&self) -> &Vec<IFD> {
         &self.ifds
     }
+
+    /// The recoverable issues found while parsing the file, and while
+    /// decoding its images.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Records that `StripByteCounts` declares `declared` bytes for strip
+    /// `strip` of the current directory instead of `expected`, once.
+    pub(crate) fn warn_strip_size(&mut self, strip: usize, expected: u64, declared: u64) {
+        let warning = Warning::StripSizeMismatch {
+            directory: self.current_directory_index,
+            strip,
+            expected,
+            declared,
+        };
+        if !self.warnings.contains(&warning) {
+            warn!("{:?}", warning);
+            self.warnings.push(warning);
+        }
+    }
+
+    /// Computes size and layout statistics of the directory at `index`.
+    pub fn directory_statistics(&mut self, index: usize) -> Result<DirectoryStatistics> {
+        let ifd = self
+            .ifds
+            .get(index)
+            .ok_or(TiffError::DirectoryIndexOutOfBounds)?;
+
+        let mut stats = DirectoryStatistics {
+            entry_count: ifd.entries.len(),
+            out_of_line_bytes: 0,
+            strip_count: 0,
+            tile_count: 0,
+            data_extent: None,
+        };
+
+        for entry in ifd.entries.iter().filter(|e| !e.is_inline()) {
+            stats.out_of_line_bytes += entry.value_size();
+            stats.extend_extent(u64::from(entry.value_offset), entry.value_size());
+        }
+
+        let segments = [
+            (Tag::StripOffsets, Tag::StripByteCounts),
+            (Tag::TileOffsets, Tag::TileByteCounts),
+        ];
+        for (offsets_tag, counts_tag) in &segments {
+            let offsets_entry = self.ifds[index].get_entry_from_tag(*offsets_tag).cloned();
+            let counts_entry = self.ifds[index].get_entry_from_tag(*counts_tag).cloned();
+
+            let offsets = match offsets_entry {
+                Some(entry) => self.read_value(&entry)?.as_unsigned().unwrap_or_default(),
+                None => continue,
+            };
+            let counts = match counts_entry {
+                Some(entry) => self.read_value(&entry)?.as_unsigned().unwrap_or_default(),
+                None => Vec::new(),
+            };
+
+            if *offsets_tag == Tag::StripOffsets {
+                stats.strip_count = offsets.len();
+            } else {
+                stats.tile_count = offsets.len();
+            }
+
+            for (offset, count) in offsets.iter().zip(counts.iter()) {
+                stats.extend_extent(*offset, *count);
+            }
+        }
+
+        Ok(stats)
+    }
+}
+
+/// A directory of a file with all its values, returned by
+/// `TIFFReader::directories`.
+///
+/// Its image is decoded with `Image::from_directory`, and its strips or
+/// tiles are read within `TIFFReader::with_directory`.
+#[derive(Debug, Clone)]
+pub struct DirectoryHandle {
+    index: usize,
+    fields: Directory,
+}
+
+impl DirectoryHandle {
+    /// Position of the directory in the main chain of the file, from 0.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The value of `tag`, if present.
+    pub fn get(&self, tag: Tag) -> Option<&TIFFValue> {
+        self.fields.get(tag)
+    }
+
+    /// Decodes the field `T`, if present and valid.
+    pub fn get_field<T: Field>(&self) -> Option<T> {
+        self.fields.get_field()
+    }
+
+    /// The tags of the directory, in increasing order.
+    pub fn tags(&self) -> impl Iterator<Item = Tag> + '_ {
+        self.fields.tags()
+    }
+
+    /// The values of the directory, as read.
+    pub fn fields(&self) -> &Directory {
+        &self.fields
+    }
+}
+
+/// An iterator over the directories of a file, returned by
+/// `TIFFReader::directories`.
+pub struct Directories<'a, R: 'a> {
+    reader: &'a mut TIFFReader<R>,
+    index: usize,
+}
+
+impl<'a, R: Read + Seek> Iterator for Directories<'a, R> {
+    type Item = Result<DirectoryHandle>;
+
+    fn next(&mut self) -> Option<Result<DirectoryHandle>> {
+        if self.index >= self.reader.ifds.len() {
+            return None;
+        }
+        self.index += 1;
+        Some(self.reader.directory(self.index - 1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.reader.ifds.len() - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, R: Read + Seek> ExactSizeIterator for Directories<'a, R> {}
+
+impl<'a> TIFFReader<Cursor<&'a [u8]>> {
+    /// Creates a TIFF reader over a file already loaded in memory, for
+    /// environments without a file system such as WebAssembly.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<TIFFReader<Cursor<&'a [u8]>>> {
+        TIFFReader::new(Cursor::new(bytes))
+    }
+
+    /// Borrows `size` bytes starting at `offset` from the input buffer.
+    fn slice(&self, offset: u64, size: u64) -> Result<&'a [u8]> {
+        let data: &'a [u8] = self.inner.get_ref();
+        check_range(self.len, offset, size)?;
+        data.get(offset as usize..(offset + size) as usize)
+            .ok_or_else(|| CorruptError::OffsetBeyondEndOfFile(offset).into())
+    }
+
+    /// Borrows the raw bytes of strip `index` of the current directory from
+    /// the input buffer, without copying them.
+    pub fn strip_slice(&mut self, index: usize) -> Result<&'a [u8]> {
+        let (offset, size) = self.strip_location(index)?;
+        self.slice(offset, size)
+    }
+
+    /// Borrows the raw bytes of tile `index` of the current directory from
+    /// the input buffer, without copying them.
+    pub fn tile_slice(&mut self, index: usize) -> Result<&'a [u8]> {
+        let (offset, size) = self.tile_location(index)?;
+        self.slice(offset, size)
+    }
+
+    /// The raw bytes of the value of `tag` in the current directory, in file
+    /// byte order. Values stored outside of the directory are borrowed from
+    /// the input buffer.
+    pub fn value_bytes(&mut self, tag: Tag) -> Result<Option<Cow<'a, [u8]>>> {
+        let entry = match self.ifds[self.current_directory_index].get_entry_from_tag(tag) {
+            Some(entry) => *entry,
+            None => return Ok(None),
+        };
+
+        let size = TIFFValue::checked_size(&entry)?;
+        if entry.is_inline() {
+            let bytes = TIFFValue::read_n_bytes(&mut self.inner, &entry, size, self.endian)?;
+            Ok(Some(Cow::Owned(bytes)))
+        } else {
+            let bytes = self.slice(u64::from(entry.value_offset), size as u64)?;
+            Ok(Some(Cow::Borrowed(bytes)))
+        }
+    }
+}
+
+impl<R: Read + Seek> TIFFReader<OffsetSource<R>> {
+    /// Creates a TIFF reader over a file embedded in `reader` at `base_offset`,
+    /// such as an EXIF blob or the preview of a raw camera file. All offsets of
+    /// the file are taken relative to its header.
+    pub fn new_at_offset(reader: R, base_offset: u64) -> Result<TIFFReader<OffsetSource<R>>> {
+        TIFFReader::new(OffsetSource::new(reader, base_offset)?)
+    }
+}
+
+impl<R: Read> TIFFReader<SequentialSource<R>> {
+    /// Creates a TIFF reader over a stream that cannot seek, such as a pipe or
+    /// a socket, reading it only as far as needed and keeping what was read.
+    ///
+    /// Offsets are not checked against the length of the stream, which is
+    /// unknown: data past its end is reported when read.
+    pub fn from_stream(reader: R) -> Result<TIFFReader<SequentialSource<R>>> {
+        TIFFReader::new(SequentialSource::new(reader))
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl TIFFReader<Cursor<Mmap>> {
+    /// Opens the file at `path` by mapping it in memory, so that values and
+    /// strips are copied straight from the mapping instead of being read
+    /// through a system call each.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated, by this process or any
+    /// other, while the reader is alive: the mapped bytes would change under
+    /// the reader, which is undefined behavior, and reads past a truncated end
+    /// raise `SIGBUS`. See `memmap2::Mmap::map`.
+    pub unsafe fn open_mmap<P: AsRef<Path>>(path: P) -> Result<TIFFReader<Cursor<Mmap>>> {
+        let file = File::open(path)?;
+        // Upheld by the caller
+        let map = Mmap::map(&file)?;
+        TIFFReader::new(Cursor::new(map))
+    }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
 
     use super::*;
     use endian::Endian;
+    use image::Image;
     use std::io::Cursor;
     use tag::*;
     use value::Rational;
+    use writer::TIFFWriter;
+
+    /// Appends a little-endian directory to `bytes`.
+    pub(crate) fn push_ifd(bytes: &mut Vec<u8>, entries: &[(u16, u16, u32, u32)], next: u32) {
+        bytes.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        for (tag, value_type, count, value) in entries {
+            bytes.extend_from_slice(&tag.to_le_bytes());
+            bytes.extend_from_slice(&value_type.to_le_bytes());
+            bytes.extend_from_slice(&count.to_le_bytes());
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&next.to_le_bytes());
+    }
+
+    /// Builds a little-endian header pointing to a first directory at offset 8.
+    pub(crate) fn le_header() -> Vec<u8> {
+        vec![0x49, 0x49, 42, 0, 8, 0, 0, 0]
+    }
+
+    /// Writes `directory` to a little-endian file, with `strips` as its strips
+    /// or tiles unless empty, and opens the file.
+    pub(crate) fn write_then_read(
+        directory: &Directory,
+        strips: &[Vec<u8>],
+    ) -> TIFFReader<Cursor<Vec<u8>>> {
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        if strips.is_empty() {
+            writer.write_directory(directory).unwrap();
+        } else {
+            let source = |index: usize| Ok(strips[index].clone());
+            writer.write_image(directory, strips.len(), source).unwrap();
+        }
+        TIFFReader::new(Cursor::new(writer.into_inner().into_inner())).unwrap()
+    }
 
     macro_rules! ensure_field {
         ($read:expr, $type:ty) => {
@@ -428,6 +1427,529 @@ mod tests {
                 .expect(stringify!("We expect to be able to read" $type))
         };
     }
+    #[test]
+    fn test_probe() {
+        let bytes: &[u8] = include_bytes!("../samples/arbitro_be.tiff");
+        let info = probe(Cursor::new(bytes)).unwrap();
+        assert_eq!(info.endian, Endian::Big);
+        assert!(!info.big_tiff);
+        assert_eq!(info.first_ifd_offset, 6400);
+
+        let big: &[u8] = &[0x49, 0x49, 43, 0, 8, 0, 0, 0, 16, 0, 0, 0, 0, 0, 0, 0];
+        let info = probe(Cursor::new(big)).unwrap();
+        assert_eq!(info.endian, Endian::Little);
+        assert!(info.big_tiff);
+        assert_eq!(info.first_ifd_offset, 16);
+
+        assert!(probe(Cursor::new(&b"GIF89a\0\0"[..])).is_err());
+    }
+
+    #[test]
+    fn test_float_values() {
+        // A float stored in the entry and a double right after the directory
+        let mut bytes = le_header();
+        push_ifd(&mut bytes, &[(65000, 11, 1, 0.5f32.to_bits()), (65001, 12, 1, 38)], 0);
+        bytes.extend_from_slice(&(-1.25f64).to_le_bytes());
+
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        let directory = read.read_directory().unwrap();
+        let float = directory.get(Tag::Unknown(65000)).unwrap();
+        assert_eq!(float.field_type(), FieldType::Float);
+        assert_eq!(float.as_f64().unwrap(), [0.5]);
+        let double = directory.get(Tag::Unknown(65001)).unwrap();
+        assert_eq!(double.field_type(), FieldType::Double);
+        assert_eq!(double.as_f64().unwrap(), [-1.25]);
+    }
+
+    #[test]
+    fn test_ifd_chain() {
+        // Two directories of one entry each: 8 + 18 = 26
+        let mut bytes = le_header();
+        push_ifd(&mut bytes, &[(256, 3, 1, 10)], 26);
+        push_ifd(&mut bytes, &[(256, 3, 1, 20)], 0);
+
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.ifds().len(), 2);
+        assert_eq!(ensure_field!(read, ImageWidth).0, 10);
+        read.select_directory(1).unwrap();
+        assert_eq!(ensure_field!(read, ImageWidth).0, 20);
+    }
+
+    #[test]
+    fn test_directories() {
+        let mut bytes = le_header();
+        push_ifd(&mut bytes, &[(256, 3, 1, 10)], 26);
+        push_ifd(&mut bytes, &[(256, 3, 1, 20), (257, 3, 1, 5)], 0);
+
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        let directories = read.directories().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(directories.len(), 2);
+        assert_eq!(directories[0].get_field::<ImageWidth>().unwrap().0, 10);
+        assert_eq!(directories[1].get_field::<ImageWidth>().unwrap().0, 20);
+        assert_eq!(
+            directories[1].tags().collect::<Vec<_>>(),
+            [Tag::ImageWidth, Tag::ImageLength]
+        );
+        assert_eq!(directories[1].index(), 1);
+        assert!(read.directory(2).is_err());
+
+        // The current directory is left alone
+        let length = |read: &mut TIFFReader<_>| Ok(ensure_field!(read, ImageLength));
+        assert_eq!(read.with_directory(&directories[1], length).unwrap().0, 5);
+        assert_eq!(ensure_field!(read, ImageWidth).0, 10);
+    }
+
+    #[test]
+    fn test_directory_images() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(2));
+        directory.set_field(&ImageLength(1));
+        directory.set_field(&BitsPerSample(vec![8]));
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        writer.write_image(&directory, 1, |_| Ok(vec![1, 2])).unwrap();
+        writer.write_image(&directory, 1, |_| Ok(vec![3, 4])).unwrap();
+
+        let mut read = TIFFReader::new(Cursor::new(writer.into_inner().into_inner())).unwrap();
+        let directories = read.directories().collect::<Result<Vec<_>>>().unwrap();
+        let strip = read.with_directory(&directories[1], |read| read.read_strip(0));
+        assert_eq!(strip.unwrap(), [3, 4]);
+        let decoded = Image::from_directory(&mut read, &directories[1]).unwrap().read_data();
+        assert_eq!(decoded.unwrap().data, [3, 4]);
+        let decoded = Image::from_directory(&mut read, &directories[0]).unwrap().read_data();
+        assert_eq!(decoded.unwrap().data, [1, 2]);
+    }
+
+    #[test]
+    fn test_buffered_ifd_reads() {
+        struct CountingReader<R> {
+            inner: R,
+            reads: usize,
+        }
+
+        impl<R: Read> Read for CountingReader<R> {
+            fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
+                self.reads += 1;
+                self.inner.read(buf)
+            }
+        }
+
+        impl<R: Seek> Seek for CountingReader<R> {
+            fn seek(&mut self, pos: SeekFrom) -> ::std::io::Result<u64> {
+                self.inner.seek(pos)
+            }
+        }
+
+        let bytes: &[u8] = include_bytes!("../samples/picoawards_le.tiff");
+        let mut reader = CountingReader {
+            inner: Cursor::new(bytes),
+            reads: 0,
+        };
+        let ifds = IFDIterator::new(&mut reader, 15326, Endian::Little, &ReaderOptions::default())
+            .collect::<Result<Vec<IFD>>>()
+            .unwrap();
+        assert_eq!(ifds[0].entries.len(), 15);
+        assert_eq!(reader.reads, 2);
+    }
+
+    #[test]
+    fn test_ifd_cycle() {
+        let mut bytes = le_header();
+        push_ifd(&mut bytes, &[(256, 3, 1, 10)], 26);
+        push_ifd(&mut bytes, &[(256, 3, 1, 20)], 8);
+
+        assert!(TIFFReader::new(Cursor::new(bytes)).is_err());
+    }
+
+    #[test]
+    fn test_value_size_overflow() {
+        // 0x2000_0000 doubles would overflow a 32-bit size computation
+        let mut bytes = le_header();
+        push_ifd(&mut bytes, &[(256, 3, 1, 10), (282, 12, 0x2000_0000, 26)], 0);
+
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert!(read.get_field::<XResolution>().is_none());
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut bytes = le_header();
+        push_ifd(&mut bytes, &[(256, 3, 1, 10), (257, 3, 1, 10)], 38);
+        push_ifd(&mut bytes, &[(256, 3, 1, 20)], 0);
+
+        let limits = Limits {
+            max_directories: 1,
+            ..Limits::default()
+        };
+        assert!(TIFFReader::with_limits(Cursor::new(bytes.clone()), limits).is_err());
+
+        let limits = Limits {
+            max_entries: 1,
+            ..Limits::default()
+        };
+        assert!(TIFFReader::with_limits(Cursor::new(bytes.clone()), limits).is_err());
+
+        let limits = Limits {
+            max_decoded_size: 2,
+            ..Limits::default()
+        };
+        let mut read = TIFFReader::with_limits(Cursor::new(bytes), limits).unwrap();
+        assert!(read.get_field::<ImageWidth>().is_some());
+        assert!(read.get_field::<ImageLength>().is_none());
+    }
+
+    #[test]
+    fn test_offset_beyond_end_of_file() {
+        let mut bytes = le_header();
+        push_ifd(&mut bytes, &[(256, 3, 1, 10), (282, 5, 1, 4096)], 0);
+
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        let entry = *read.ifds()[0].get_entry_from_tag(Tag::XResolution).unwrap();
+        match read.read_value(&entry) {
+            Err(TiffError::Corrupt(CorruptError::OffsetBeyondEndOfFile(4096))) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        let mut bytes = le_header();
+        push_ifd(&mut bytes, &[(256, 3, 1, 10)], 4096);
+        assert!(TIFFReader::new(Cursor::new(bytes)).is_err());
+    }
+
+    #[test]
+    fn test_short_payloads() {
+        let mut bytes = le_header();
+        push_ifd(
+            &mut bytes,
+            &[(256, 3, 1, 10), (258, 3, 2, 0x0010_0008), (282, 5, 0, 0)],
+            0,
+        );
+
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(ensure_field!(read, BitsPerSample).0, vec![8, 16]);
+
+        let entry = *read.ifds()[0].get_entry_from_tag(Tag::XResolution).unwrap();
+        match read.read_value(&entry) {
+            Ok(TIFFValue::Rational(ref values)) if values.is_empty() => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    /// Exercises every field decoder of the current directory.
+    fn read_all_fields<R: Read + Seek>(read: &mut TIFFReader<R>) {
+        read.get_field::<NewSubfileType>();
+        read.get_field::<SubfileType>();
+        read.get_field::<ImageWidth>();
+        read.get_field::<ImageLength>();
+        read.get_field::<BitsPerSample>();
+        read.get_field::<Compression>();
+        read.get_field::<PhotometricInterpretation>();
+        read.get_field::<ImageDescription>();
+        read.get_field::<StripOffsets>();
+        read.get_field::<SamplesPerPixel>();
+        read.get_field::<RowsPerStrip>();
+        read.get_field::<StripByteCounts>();
+        read.get_field::<XResolution>();
+        read.get_field::<YResolution>();
+        read.get_field::<PlanarConfiguration>();
+        read.get_field::<ResolutionUnit>();
+        read.get_field::<Predictor>();
+        read.get_field::<SampleFormat>();
+        read.get_field::<SMinSampleValue>();
+        read.get_field::<SMaxSampleValue>();
+        read.get_field::<DateTime>();
+        read.get_field::<ColorMap>();
+        read.get_field::<FillOrder>();
+        read.get_field::<Orientation>();
+        read.get_field::<GrayResponseUnit>();
+        read.get_field::<TileOffsets>();
+        read.get_field::<InkSet>();
+    }
+
+    #[test]
+    fn test_fuzz_untrusted() {
+        let samples: [&[u8]; 3] = [
+            include_bytes!("../samples/arbitro_be.tiff"),
+            include_bytes!("../samples/picoawards_le.tiff"),
+            include_bytes!("../samples/ycbcr-cat.tif"),
+        ];
+
+        // xorshift, to keep the mutations reproducible
+        let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for sample in &samples {
+            for _ in 0..500 {
+                let mut bytes = sample.to_vec();
+                let len = bytes.len();
+                let info = probe(Cursor::new(&bytes)).unwrap();
+                let ifd_start = info.first_ifd_offset as usize;
+                let count_bytes = [bytes[ifd_start], bytes[ifd_start + 1]];
+                let entry_count = usize::from(info.endian.short_from_bytes::<u16>(count_bytes));
+
+                // Rewrite the type and count of a random entry to small values
+                let entry = ifd_start + 2 + 12 * (next() as usize % entry_count);
+                let (value_type, count) = (next() as u16 % 14, next() as u32 % 3);
+                let (type_bytes, count_bytes) = match info.endian {
+                    Endian::Big => (value_type.to_be_bytes(), count.to_be_bytes()),
+                    Endian::Little => (value_type.to_le_bytes(), count.to_le_bytes()),
+                };
+                bytes[entry + 2..entry + 4].copy_from_slice(&type_bytes);
+                bytes[entry + 4..entry + 8].copy_from_slice(&count_bytes);
+
+                for _ in 0..(next() % 8) {
+                    // Favour the directory area, where mutations matter most
+                    let position = if next() % 4 == 0 {
+                        next() as usize % len
+                    } else {
+                        ifd_start + next() as usize % (len - ifd_start)
+                    };
+                    bytes[position] = next() as u8;
+                }
+                if next() % 8 == 0 {
+                    bytes.truncate(next() as usize % len);
+                }
+
+                if let Ok(mut read) = TIFFReader::open_untrusted(Cursor::new(bytes)) {
+                    for index in 0..read.ifds().len() {
+                        read.select_directory(index).unwrap();
+                        read_all_fields(&mut read);
+                        read.directory_statistics(index).ok();
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_ascii() {
+        let mut bytes = le_header();
+        push_ifd(&mut bytes, &[(270, 2, 12, 38), (271, 2, 4, 0x0000_e94c)], 0);
+        bytes.extend_from_slice(b"v1.0\0\0cat 0\0");
+
+        let mut read = TIFFReader::new(Cursor::new(bytes.clone())).unwrap();
+        let entry = *read.ifds()[0].get_entry_from_tag(Tag::ImageDescription).unwrap();
+        match read.read_value(&entry).unwrap() {
+            TIFFValue::Ascii(values) => assert_eq!(values, vec!["v1.0", "", "cat 0"]),
+            other => panic!("Unexpected value {:?}", other),
+        }
+
+        // "Lé" in Latin-1
+        assert!(read.get_field::<Make>().is_none());
+        let options = ReaderOptions {
+            strings: StringEncoding::Lossy,
+            ..ReaderOptions::default()
+        };
+        let mut read = TIFFReader::with_options(Cursor::new(bytes.clone()), options).unwrap();
+        assert_eq!(ensure_field!(read, Make).0, "L\u{fffd}");
+
+        // UTF-8 is only accepted when not restricted to ASCII
+        let utf8 = "\u{e9}t\u{e9}";
+        let mut bytes = le_header();
+        push_ifd(&mut bytes, &[(270, 2, 6, 26)], 0);
+        bytes.extend_from_slice(utf8.as_bytes());
+        bytes.push(0);
+        let mut read = TIFFReader::new(Cursor::new(bytes.clone())).unwrap();
+        assert_eq!(ensure_field!(read, ImageDescription).0, utf8);
+        let options = ReaderOptions {
+            strings: StringEncoding::Ascii,
+            ..ReaderOptions::default()
+        };
+        let mut read = TIFFReader::with_options(Cursor::new(bytes), options).unwrap();
+        assert!(read.read_tag(Tag::ImageDescription).is_err());
+    }
+
+    #[test]
+    fn test_value_cache() {
+        let bytes: &[u8] = include_bytes!("../samples/picoawards_le.tiff");
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        ensure_field!(read, StripOffsets);
+        let decoded_bytes = read.decoded_bytes;
+        assert!(decoded_bytes > 0);
+        ensure_field!(read, StripOffsets);
+        assert_eq!(read.decoded_bytes, decoded_bytes);
+
+        let options = ReaderOptions {
+            cache_values: false,
+            ..ReaderOptions::default()
+        };
+        let mut read = TIFFReader::with_options(Cursor::new(bytes), options).unwrap();
+        ensure_field!(read, StripOffsets);
+        ensure_field!(read, StripOffsets);
+        assert_eq!(read.decoded_bytes, 2 * decoded_bytes);
+    }
+
+    #[test]
+    fn test_read_array_element() {
+        let bytes: &[u8] = include_bytes!("../samples/picoawards_le.tiff");
+        let options = ReaderOptions {
+            cache_values: false,
+            ..ReaderOptions::default()
+        };
+        let mut read = TIFFReader::with_options(Cursor::new(bytes), options).unwrap();
+        let offsets = ensure_field!(read, StripOffsets).0;
+        for (index, offset) in offsets.iter().enumerate() {
+            let element = read.read_array_element(Tag::StripOffsets, index).unwrap();
+            assert_eq!(element, u64::from(*offset));
+        }
+        assert!(read.read_array_element(Tag::StripOffsets, 61).is_err());
+        assert_eq!(read.read_array_element(Tag::ImageWidth, 0).unwrap(), 436);
+        assert!(read.read_array_element(Tag::TileOffsets, 0).is_err());
+
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        ensure_field!(read, StripOffsets);
+        let element = read.read_array_element(Tag::StripOffsets, 60).unwrap();
+        assert_eq!(element, u64::from(offsets[60]));
+        assert_eq!(read.strip_count().unwrap(), 61);
+    }
+
+    #[test]
+    fn test_empty_values() {
+        let mut bytes = le_header();
+        push_ifd(&mut bytes, &[(256, 3, 0, 0), (270, 2, 0, 0), (282, 5, 0, 9999)], 0);
+
+        let mut read = TIFFReader::new(Cursor::new(bytes.clone())).unwrap();
+        let expected = [
+            (Tag::ImageWidth, 3),
+            (Tag::ImageDescription, 2),
+            (Tag::XResolution, 5),
+        ];
+        for (tag, expected) in &expected {
+            let entry = *read.ifds()[0].get_entry_from_tag(*tag).unwrap();
+            let value = read.read_value(&entry).unwrap();
+            assert_eq!(value.value_type_id(), *expected);
+            assert_eq!(value.as_unsigned().map_or(0, |v| v.len()), 0);
+        }
+        assert!(read.get_field::<ImageWidth>().is_none());
+        assert!(read.get_field::<XResolution>().is_none());
+
+        let options = ReaderOptions {
+            strict: true,
+            ..ReaderOptions::default()
+        };
+        let mut read = TIFFReader::with_options(Cursor::new(bytes), options).unwrap();
+        let entry = *read.ifds()[0].get_entry_from_tag(Tag::ImageWidth).unwrap();
+        assert!(read.read_value(&entry).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_tags() {
+        let mut bytes = le_header();
+        push_ifd(&mut bytes, &[(256, 3, 1, 10), (256, 3, 1, 20)], 0);
+
+        let mut read = TIFFReader::new(Cursor::new(bytes.clone())).unwrap();
+        assert_eq!(ensure_field!(read, ImageWidth).0, 10);
+        assert_eq!(
+            read.warnings(),
+            &[Warning::DuplicateTag {
+                directory: 0,
+                tag: Tag::ImageWidth
+            }]
+        );
+
+        let options = ReaderOptions {
+            duplicate_tags: DuplicateTagPolicy::LastWins,
+            ..ReaderOptions::default()
+        };
+        let mut read = TIFFReader::with_options(Cursor::new(bytes.clone()), options).unwrap();
+        assert_eq!(ensure_field!(read, ImageWidth).0, 20);
+
+        let options = ReaderOptions {
+            duplicate_tags: DuplicateTagPolicy::Error,
+            ..ReaderOptions::default()
+        };
+        assert!(TIFFReader::with_options(Cursor::new(bytes), options).is_err());
+    }
+
+    #[test]
+    fn test_unsorted_entries() {
+        let mut bytes = le_header();
+        push_ifd(&mut bytes, &[(257, 3, 1, 20), (256, 3, 1, 10)], 0);
+
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.warnings(), &[Warning::UnsortedEntries { directory: 0 }]);
+        let tags: Vec<Tag> = read.ifds()[0].all_tags().collect();
+        assert_eq!(tags, vec![Tag::ImageLength, Tag::ImageWidth]);
+        assert_eq!(ensure_field!(read, ImageWidth).0, 10);
+        assert_eq!(ensure_field!(read, ImageLength).0, 20);
+
+        let bytes: &[u8] = include_bytes!("../samples/picoawards_le.tiff");
+        let read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert!(read.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_borrowed_slices() {
+        let bytes: &[u8] = include_bytes!("../samples/picoawards_le.tiff");
+        let mut read = TIFFReader::from_bytes(bytes).unwrap();
+
+        let strip = read.strip_slice(3).unwrap();
+        assert_eq!(strip, &read.read_strip(3).unwrap()[..]);
+        assert!(read.strip_slice(61).is_err());
+
+        match read.value_bytes(Tag::StripOffsets).unwrap() {
+            Some(Cow::Borrowed(bytes)) => assert_eq!(bytes.len() % 61, 0),
+            other => panic!("Unexpected value {:?}", other),
+        }
+        match read.value_bytes(Tag::ImageWidth).unwrap() {
+            Some(Cow::Owned(bytes)) => assert_eq!(bytes, vec![0xb4, 0x01]),
+            other => panic!("Unexpected value {:?}", other),
+        }
+        assert!(read.value_bytes(Tag::TileOffsets).unwrap().is_none());
+        assert!(read.tile_slice(0).is_err());
+
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(32));
+        directory.set_field(&ImageLength(16));
+        directory.set_field(&TileWidth(16));
+        directory.set_field(&TileLength(16));
+        let mut writer =
+            ::writer::TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        writer.write_image(&directory, 2, |index| Ok(vec![index as u8; 256])).unwrap();
+        let bytes = writer.into_inner().into_inner();
+        let mut read = TIFFReader::from_bytes(&bytes).unwrap();
+        assert_eq!(read.tile_slice(1).unwrap(), &[1; 256][..]);
+        assert!(read.tile_slice(2).is_err());
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_open_mmap() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/samples/picoawards_le.tiff");
+        // The samples are never modified
+        let mut read = unsafe { TIFFReader::open_mmap(path) }.unwrap();
+        assert_eq!(ensure_field!(read, ImageWidth).0, 436);
+
+        let bytes: &[u8] = include_bytes!("../samples/picoawards_le.tiff");
+        let mut expected = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.read_strip(60).unwrap(), expected.read_strip(60).unwrap());
+    }
+
+    #[test]
+    fn test_read_directory() {
+        let bytes: &[u8] = include_bytes!("../samples/arbitro_be.tiff");
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        let mut directory = read.read_directory().unwrap();
+        assert!(!directory.has_field(Tag::StripOffsets));
+        assert!(!directory.has_field(Tag::StripByteCounts));
+        assert_eq!(directory.get_field::<ImageWidth>().unwrap().0, 174);
+        assert_eq!(directory.get_field::<RowsPerStrip>().unwrap().0, 38);
+
+        // Same metadata, new pixels, and an EXIF directory to copy back
+        let mut exif = Directory::new();
+        exif.set(Tag::ExposureTime, TIFFValue::Rational(vec![Rational { num: 1, denom: 8 }]));
+        directory.set_exif(exif);
+        let mut read = write_then_read(&directory, &[vec![7; 16]]);
+        assert_eq!(read.read_strip(0).unwrap(), [7; 16]);
+        let copy = read.read_directory().unwrap();
+        assert_eq!(copy.tags().collect::<Vec<_>>(), directory.tags().collect::<Vec<_>>());
+        let exif = copy.sub_directory(Tag::ExifIFD).unwrap();
+        assert!(exif.has_field(Tag::ExposureTime));
+    }
+
     #[test]
     fn test_sample_be() {
         let bytes: &[u8] = include_bytes!("../samples/arbitro_be.tiff");
@@ -506,6 +2028,18 @@ mod tests {
         let planar = ensure_field!(read, PlanarConfiguration);
         assert_eq!(planar, PlanarConfiguration::Chunky);
 
+        let stats = read.directory_statistics(0).unwrap();
+        assert_eq!(stats.entry_count, 15);
+        assert_eq!(stats.out_of_line_bytes, 510);
+        assert_eq!(stats.strip_count, 61);
+        assert_eq!(stats.tile_count, 0);
+        assert_eq!(stats.data_extent, Some((8, 15326)));
+
+        let strips = read.read_partial_strips(0..61).unwrap();
+        for (index, strip) in strips.iter().enumerate() {
+            assert_eq!(strip, &read.read_strip(index).unwrap());
+        }
+
         let subfile = ensure_field!(read, NewSubfileType);
         assert_eq!(false, subfile.is_reduced_image());
     }
@@ -546,5 +2080,8 @@ mod tests {
 
         let planar = ensure_field!(read, PlanarConfiguration);
         assert_eq!(planar, PlanarConfiguration::Chunky);
+
+        let description = ensure_field!(read, ImageDescription);
+        assert_eq!(description.0, "YCbCr conversion of cat.tif");
     }
 }
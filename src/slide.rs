@@ -0,0 +1,285 @@
+//! This module reads whole-slide images, the tiled pyramids of digital
+//! pathology scanners such as Aperio SVS files.
+//!
+//! The first directory holds the full resolution image, followed by tiled
+//! reductions of it. Stripped directories hold the thumbnail, the picture of
+//! the slide label and the macro picture of the whole slide.
+use error::{Result, TiffError};
+use image::Image;
+use reader::TIFFReader;
+use std::collections::BTreeMap;
+use std::io::{Read, Seek};
+use tag::*;
+
+/// The structured `ImageDescription` of Aperio SVS files, such as
+/// `Aperio Image Library v10.0.51\r\n46920x33014 [...] JPEG/RGB Q=30|AppMag = 20|MPP = 0.499`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AperioDescription {
+    /// The text before the first `|`, describing the library and the image.
+    pub header: String,
+    /// The `key = value` pairs following the header.
+    pub properties: BTreeMap<String, String>,
+}
+
+impl AperioDescription {
+    /// Parses `description`, or returns `None` when it is not written by the
+    /// Aperio library.
+    pub fn parse(description: &str) -> Option<AperioDescription> {
+        if !description.starts_with("Aperio") {
+            return None;
+        }
+        let mut parts = description.split('|');
+        let header = parts.next()?.trim().to_string();
+        let properties = parts
+            .filter_map(|part| {
+                let mut pair = part.splitn(2, '=');
+                let key = pair.next()?.trim();
+                let value = pair.next()?.trim();
+                Some((key.to_string(), value.to_string()))
+            })
+            .collect();
+        Some(AperioDescription { header, properties })
+    }
+
+    /// The value of property `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(|value| value.as_str())
+    }
+
+    /// The objective magnification the slide was scanned at, `AppMag`.
+    pub fn magnification(&self) -> Option<f64> {
+        self.get("AppMag")?.parse().ok()
+    }
+
+    /// The size of a pixel of the full resolution image in micrometers,
+    /// `MPP`.
+    pub fn microns_per_pixel(&self) -> Option<f64> {
+        self.get("MPP")?.parse().ok()
+    }
+}
+
+/// The role of an image of a whole-slide file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SlideImageKind {
+    /// A level of the pyramid, the first one being the full resolution.
+    Level,
+    /// A small preview of the full resolution image.
+    Thumbnail,
+    /// A picture of the slide label.
+    Label,
+    /// A low resolution picture of the whole slide.
+    Macro,
+}
+
+/// An image of a whole-slide file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlideImage {
+    /// The index of the directory holding the image.
+    pub directory: usize,
+    pub kind: SlideImageKind,
+    pub width: u32,
+    pub length: u32,
+}
+
+/// The images of a whole-slide file, read with `TIFFReader::whole_slide`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WholeSlide {
+    /// The description of the full resolution image, for Aperio files.
+    pub description: Option<AperioDescription>,
+    /// The levels of the pyramid, from the full resolution down.
+    pub levels: Vec<SlideImage>,
+    pub thumbnail: Option<SlideImage>,
+    pub label: Option<SlideImage>,
+    pub macro_image: Option<SlideImage>,
+}
+
+impl WholeSlide {
+    /// How many times level `level` is smaller than the full resolution.
+    pub fn downsample(&self, level: usize) -> Option<f64> {
+        let level = self.levels.get(level)?;
+        Some(f64::from(self.levels[0].width) / f64::from(level.width))
+    }
+
+    /// The level best suited to display the slide `downsample` times smaller
+    /// than the full resolution: the smallest one at least as large.
+    pub fn best_level(&self, downsample: f64) -> usize {
+        (0..self.levels.len())
+            .take_while(|level| self.downsample(*level).is_some_and(|d| d <= downsample))
+            .last()
+            .unwrap_or(0)
+    }
+
+    /// Decodes the samples of a region of level `level`, given in pixels of
+    /// that level, as `Image::read_region` does.
+    pub fn read_region<R: Read + Seek>(
+        &self,
+        reader: &mut TIFFReader<R>,
+        level: usize,
+        (x, y): (u32, u32),
+        (width, length): (u32, u32),
+    ) -> Result<Vec<u8>> {
+        let level = self.levels.get(level).ok_or(TiffError::DirectoryIndexOutOfBounds)?;
+        reader.select_directory(level.directory)?;
+        Image::new(reader)?.read_region(x, y, width, length)
+    }
+}
+
+impl<R: Read + Seek> TIFFReader<R> {
+    /// Identifies the images of a whole-slide file, or returns `None` when
+    /// its first directory is not tiled.
+    ///
+    /// Stripped images are told apart by the `label` or `macro` keywords of
+    /// their description, the one following the full resolution image being
+    /// the thumbnail otherwise. The current directory is left unchanged.
+    pub fn whole_slide(&mut self) -> Result<Option<WholeSlide>> {
+        let current = self.directory_index();
+        let slide = self.scan_slide();
+        self.select_directory(current)?;
+        slide
+    }
+
+    fn scan_slide(&mut self) -> Result<Option<WholeSlide>> {
+        let mut slide = WholeSlide {
+            description: None,
+            levels: Vec::new(),
+            thumbnail: None,
+            label: None,
+            macro_image: None,
+        };
+
+        for index in 0..self.ifds().len() {
+            self.select_directory(index)?;
+            let tiled = self.get_field::<TileWidth>().is_some();
+            if index == 0 && !tiled {
+                return Ok(None);
+            }
+            let width = self.get_field::<ImageWidth>().map_or(0, |e| e.0);
+            let length = self.get_field::<ImageLength>().map_or(0, |e| e.0);
+            let description = self.get_field::<ImageDescription>().map(|e| e.0);
+            if index == 0 {
+                slide.description = description.as_ref().and_then(|d| AperioDescription::parse(d));
+            }
+
+            let description = description.unwrap_or_default().to_lowercase();
+            let kind = if tiled {
+                SlideImageKind::Level
+            } else if description.contains("label") {
+                SlideImageKind::Label
+            } else if description.contains("macro") {
+                SlideImageKind::Macro
+            } else if index == 1 {
+                SlideImageKind::Thumbnail
+            } else {
+                continue;
+            };
+            let image = SlideImage {
+                directory: index,
+                kind,
+                width,
+                length,
+            };
+            match kind {
+                SlideImageKind::Level => slide.levels.push(image),
+                SlideImageKind::Thumbnail => slide.thumbnail = Some(image),
+                SlideImageKind::Label => slide.label = Some(image),
+                SlideImageKind::Macro => slide.macro_image = Some(image),
+            }
+        }
+
+        slide.levels.sort_by_key(|level| ::std::cmp::Reverse(level.width));
+        Ok(Some(slide))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use endian::Endian;
+    use std::io::Cursor;
+    use writer::{Directory, TIFFWriter};
+
+    const DESCRIPTION: &str = "Aperio Image Library v10.0.51\r\n\
+                               64x32 [0,100 64x32] (16x16) RAW Q=30|AppMag = 20|MPP = 0.4990";
+
+    /// A tiled gray image where each pixel holds `x + y * width`, modulo 256.
+    fn write_level(writer: &mut TIFFWriter<Cursor<Vec<u8>>>, width: u32, length: u32) {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(width));
+        directory.set_field(&ImageLength(length));
+        directory.set_field(&BitsPerSample(vec![8]));
+        directory.set_field(&TileWidth(16));
+        directory.set_field(&TileLength(16));
+        if width == 64 {
+            directory.set_field(&ImageDescription(DESCRIPTION.to_string()));
+        } else {
+            directory.set_field(&NewSubfileType(1));
+        }
+
+        let across = width.div_ceil(16);
+        let tiles = across * length.div_ceil(16);
+        let source = |index: usize| {
+            let (tile_x, tile_y) = (index as u32 % across * 16, index as u32 / across * 16);
+            let mut tile = Vec::new();
+            for y in tile_y..tile_y + 16 {
+                for x in tile_x..tile_x + 16 {
+                    tile.push((x + y * width) as u8);
+                }
+            }
+            Ok(tile)
+        };
+        writer.write_image(&directory, tiles as usize, source).unwrap();
+    }
+
+    fn write_strip(writer: &mut TIFFWriter<Cursor<Vec<u8>>>, description: &str) {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(2));
+        directory.set_field(&ImageLength(1));
+        directory.set_field(&NewSubfileType(1));
+        directory.set_field(&ImageDescription(description.to_string()));
+        writer.write_image(&directory, 1, |_| Ok(vec![0, 0])).unwrap();
+    }
+
+    #[test]
+    fn test_whole_slide() {
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        write_level(&mut writer, 64, 32);
+        write_strip(&mut writer, "Aperio Image Library v10.0.51\r\n64x32 -> 2x1");
+        write_level(&mut writer, 16, 8);
+        write_level(&mut writer, 32, 16);
+        write_strip(&mut writer, "Aperio Image Library v10.0.51\r\nlabel 2x1");
+        write_strip(&mut writer, "Aperio Image Library v10.0.51\r\nmacro 2x1");
+
+        let bytes = writer.into_inner().into_inner();
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        let slide = read.whole_slide().unwrap().unwrap();
+        let description = slide.description.as_ref().unwrap();
+        assert_eq!(description.magnification(), Some(20.0));
+        assert_eq!(description.microns_per_pixel(), Some(0.499));
+        assert!(description.header.ends_with("RAW Q=30"));
+
+        let levels: Vec<_> = slide.levels.iter().map(|l| (l.directory, l.width)).collect();
+        assert_eq!(levels, [(0, 64), (3, 32), (2, 16)]);
+        assert_eq!(slide.thumbnail.as_ref().unwrap().directory, 1);
+        assert_eq!(slide.label.as_ref().unwrap().directory, 4);
+        assert_eq!(slide.macro_image.as_ref().unwrap().directory, 5);
+        assert_eq!(slide.downsample(2), Some(4.0));
+        assert_eq!(slide.best_level(3.0), 1);
+        assert_eq!(slide.best_level(0.5), 0);
+
+        // A region spanning four tiles of the full resolution
+        let region = slide.read_region(&mut read, 0, (14, 6), (4, 14)).unwrap();
+        assert_eq!(region.len(), 56);
+        for (index, sample) in region.iter().enumerate() {
+            let (x, y) = (14 + index as u32 % 4, 6 + index as u32 / 4);
+            assert_eq!(u32::from(*sample), (x + y * 64) % 256);
+        }
+        let region = slide.read_region(&mut read, 1, (31, 15), (1, 1)).unwrap();
+        assert_eq!(region, [(31 + 15 * 32) as u8]);
+        assert!(slide.read_region(&mut read, 1, (30, 0), (4, 1)).is_err());
+        assert!(slide.read_region(&mut read, 3, (0, 0), (1, 1)).is_err());
+
+        let bytes: &[u8] = include_bytes!("../samples/arbitro_be.tiff");
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.whole_slide().unwrap(), None);
+    }
+}
@@ -0,0 +1,157 @@
+//! Block cache for range readers.
+use std::cmp;
+use std::collections::{HashMap, VecDeque};
+use std::io;
+
+use super::RangeReader;
+
+/// Wraps a `RangeReader` with a cache of fixed-size blocks, for sources where
+/// each request is expensive, such as object stores.
+///
+/// The blocks missing to serve a read are fetched with one request per run of
+/// consecutive blocks, and the least recently used blocks are evicted first.
+pub struct CachedRangeReader<T> {
+    inner: T,
+    block_size: u64,
+    capacity: usize,
+    blocks: HashMap<u64, Vec<u8>>,
+    recency: VecDeque<u64>,
+    size: Option<u64>,
+}
+
+impl<T: RangeReader> CachedRangeReader<T> {
+    /// Creates a cache keeping up to `capacity` blocks of `block_size` bytes.
+    pub fn new(inner: T, block_size: usize, capacity: usize) -> CachedRangeReader<T> {
+        CachedRangeReader {
+            inner,
+            block_size: cmp::max(block_size, 1) as u64,
+            capacity,
+            blocks: HashMap::new(),
+            recency: VecDeque::new(),
+            size: None,
+        }
+    }
+
+    /// Returns the underlying range reader.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn touch(&mut self, block: u64) {
+        if let Some(position) = self.recency.iter().position(|e| *e == block) {
+            self.recency.remove(position);
+        }
+        self.recency.push_back(block);
+    }
+
+    fn insert(&mut self, block: u64, bytes: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+        while self.blocks.len() >= self.capacity {
+            match self.recency.pop_front() {
+                Some(oldest) => self.blocks.remove(&oldest),
+                None => break,
+            };
+        }
+        self.blocks.insert(block, bytes);
+        self.touch(block);
+    }
+}
+
+impl<T: RangeReader> RangeReader for CachedRangeReader<T> {
+    fn size(&mut self) -> io::Result<u64> {
+        match self.size {
+            Some(size) => Ok(size),
+            None => {
+                let size = self.inner.size()?;
+                self.size = Some(size);
+                Ok(size)
+            }
+        }
+    }
+
+    fn read_range(&mut self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        let end = cmp::min(offset.saturating_add(length as u64), self.size()?);
+        if offset >= end {
+            return Ok(Vec::new());
+        }
+
+        let first = offset / self.block_size;
+        let last = (end - 1) / self.block_size;
+
+        // Fetch each run of missing blocks with a single request
+        let mut fetched = HashMap::new();
+        let mut block = first;
+        while block <= last {
+            if self.blocks.contains_key(&block) {
+                block += 1;
+                continue;
+            }
+
+            let mut run_end = block;
+            while run_end < last && !self.blocks.contains_key(&(run_end + 1)) {
+                run_end += 1;
+            }
+
+            let start = block * self.block_size;
+            let length = (run_end - block + 1) * self.block_size;
+            let bytes = self.inner.read_range(start, length as usize)?;
+            for (index, chunk) in bytes.chunks(self.block_size as usize).enumerate() {
+                fetched.insert(block + index as u64, chunk.to_vec());
+            }
+            block = run_end + 1;
+        }
+
+        let mut result = Vec::with_capacity((end - offset) as usize);
+        for block in first..=last {
+            let bytes = match fetched.get(&block).or_else(|| self.blocks.get(&block)) {
+                Some(bytes) => bytes,
+                None => break,
+            };
+            let block_start = block * self.block_size;
+            let from = cmp::min(offset.saturating_sub(block_start) as usize, bytes.len());
+            let to = cmp::min((end - block_start) as usize, bytes.len());
+            result.extend_from_slice(&bytes[from..to]);
+            if bytes.len() < self.block_size as usize {
+                break;
+            }
+        }
+
+        for block in first..=last {
+            match fetched.remove(&block) {
+                Some(bytes) => self.insert(block, bytes),
+                None if self.blocks.contains_key(&block) => self.touch(block),
+                None => {}
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::MemoryRangeReader;
+    use super::*;
+
+    #[test]
+    fn test_cached_range_reader() {
+        let bytes: &'static [u8] = include_bytes!("../../samples/arbitro_be.tiff");
+        let remote = MemoryRangeReader { bytes, requests: 0 };
+        let mut cache = CachedRangeReader::new(remote, 100, 4);
+
+        assert_eq!(cache.read_range(150, 200).unwrap(), &bytes[150..350]);
+        assert_eq!(cache.inner.requests, 1);
+
+        // Blocks 1 to 3 are cached, 0 and 4 are fetched separately
+        assert_eq!(cache.read_range(50, 400).unwrap(), &bytes[50..450]);
+        assert_eq!(cache.inner.requests, 3);
+        assert_eq!(cache.blocks.len(), 4);
+        assert!(!cache.blocks.contains_key(&0));
+
+        let size = bytes.len() as u64;
+        assert_eq!(cache.read_range(size - 10, 100).unwrap(), &bytes[bytes.len() - 10..]);
+        assert!(cache.read_range(size, 10).unwrap().is_empty());
+    }
+}
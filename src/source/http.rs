@@ -0,0 +1,211 @@
+//! HTTP range requests.
+use std::io::{self, Read};
+
+use ureq;
+
+use super::RangeReader;
+
+/// Fetches ranges of a remote file with HTTP range requests.
+pub struct HttpRangeReader {
+    url: String,
+    agent: ureq::Agent,
+    headers: Vec<(String, String)>,
+}
+
+impl HttpRangeReader {
+    /// Creates a reader for the file at `url`.
+    pub fn new<S: Into<String>>(url: S) -> HttpRangeReader {
+        HttpRangeReader::with_agent(url, ureq::Agent::new())
+    }
+
+    /// Creates a reader sending its requests through `agent`, to share
+    /// connections or configure timeouts.
+    pub fn with_agent<S: Into<String>>(url: S, agent: ureq::Agent) -> HttpRangeReader {
+        HttpRangeReader {
+            url: url.into(),
+            agent,
+            headers: Vec::new(),
+        }
+    }
+
+    /// Creates a reader for an object of an S3 bucket, through its
+    /// virtual-hosted style URL. Private objects need a presigned URL, passed
+    /// to `new` instead.
+    #[cfg(feature = "object-store")]
+    pub fn s3(region: &str, bucket: &str, key: &str) -> HttpRangeReader {
+        HttpRangeReader::new(format!(
+            "https://{}.s3.{}.amazonaws.com/{}",
+            bucket,
+            region,
+            encode_key(key)
+        ))
+    }
+
+    /// Creates a reader for an object of an S3 compatible store, such as
+    /// MinIO, through its path style URL.
+    #[cfg(feature = "object-store")]
+    pub fn s3_compatible(endpoint: &str, bucket: &str, key: &str) -> HttpRangeReader {
+        HttpRangeReader::new(format!(
+            "{}/{}/{}",
+            endpoint.trim_end_matches('/'),
+            bucket,
+            encode_key(key)
+        ))
+    }
+
+    /// Creates a reader for an object of a Google Cloud Storage bucket.
+    /// Private objects need an OAuth token, see `with_bearer_token`.
+    #[cfg(feature = "object-store")]
+    pub fn gcs(bucket: &str, object: &str) -> HttpRangeReader {
+        HttpRangeReader::new(format!(
+            "https://storage.googleapis.com/{}/{}",
+            bucket,
+            encode_key(object)
+        ))
+    }
+
+    /// Sends `name: value` with every request.
+    pub fn with_header(mut self, name: &str, value: &str) -> HttpRangeReader {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Authenticates every request with an OAuth bearer token.
+    pub fn with_bearer_token(self, token: &str) -> HttpRangeReader {
+        self.with_header("Authorization", &format!("Bearer {}", token))
+    }
+
+    fn request(&self, method: &str) -> ureq::Request {
+        self.headers
+            .iter()
+            .fold(self.agent.request(method, &self.url), |request, (name, value)| {
+                request.set(name, value)
+            })
+    }
+}
+
+/// Percent-encodes an object key, keeping `/` as the path separator.
+#[cfg(feature = "object-store")]
+fn encode_key(key: &str) -> String {
+    let mut encoded = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn to_io_error(error: ureq::Error) -> io::Error {
+    io::Error::other(error)
+}
+
+impl RangeReader for HttpRangeReader {
+    fn size(&mut self) -> io::Result<u64> {
+        let response = self.request("HEAD").call().map_err(to_io_error)?;
+        response
+            .header("Content-Length")
+            .and_then(|length| length.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length"))
+    }
+
+    fn read_range(&mut self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        if length == 0 {
+            return Ok(Vec::new());
+        }
+
+        let range = format!("bytes={}-{}", offset, offset + length as u64 - 1);
+        let response = self
+            .request("GET")
+            .set("Range", &range)
+            .call()
+            .map_err(to_io_error)?;
+
+        let partial = response.status() == 206;
+        let mut reader = response.into_reader();
+        let mut bytes = Vec::with_capacity(length);
+        if !partial {
+            // The server ignored the range and sends the whole file
+            io::copy(&mut reader.by_ref().take(offset), &mut io::sink())?;
+        }
+        reader.take(length as u64).read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serves `bytes` over HTTP, honoring `Range` headers, for `requests` requests.
+    fn serve(bytes: &'static [u8], requests: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for _ in 0..requests {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut range = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.to_lowercase().strip_prefix("range: bytes=") {
+                        let mut bounds = value.trim().split('-').map(|e| e.parse().unwrap());
+                        range = Some((bounds.next().unwrap(), bounds.next().unwrap()));
+                    }
+                }
+
+                let (status, body): (&str, &[u8]) = match range {
+                    Some((start, end)) => ("206 Partial Content", &bytes[start..=end]),
+                    None => ("200 OK", bytes),
+                };
+                let mut stream = stream;
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    if request.starts_with("HEAD") { bytes.len() } else { body.len() }
+                ).unwrap();
+                if !request.starts_with("HEAD") {
+                    stream.write_all(body).unwrap();
+                }
+            }
+        });
+        format!("http://{}/sample.tiff", address)
+    }
+
+    #[test]
+    fn test_http_range_reader() {
+        let bytes: &'static [u8] = include_bytes!("../../samples/arbitro_be.tiff");
+        let mut remote = HttpRangeReader::new(serve(bytes, 2));
+        assert_eq!(remote.size().unwrap(), bytes.len() as u64);
+        assert_eq!(remote.read_range(8, 16).unwrap(), &bytes[8..24]);
+    }
+
+    #[test]
+    #[cfg(feature = "object-store")]
+    fn test_object_store_urls() {
+        assert_eq!(
+            HttpRangeReader::s3("eu-west-1", "scans", "2018/page 1.tiff").url,
+            "https://scans.s3.eu-west-1.amazonaws.com/2018/page%201.tiff"
+        );
+        assert_eq!(
+            HttpRangeReader::s3_compatible("http://localhost:9000/", "scans", "a+b.tif").url,
+            "http://localhost:9000/scans/a%2Bb.tif"
+        );
+        let reader = HttpRangeReader::gcs("scans", "page.tif").with_bearer_token("token");
+        assert_eq!(reader.url, "https://storage.googleapis.com/scans/page.tif");
+        assert_eq!(reader.headers, [("Authorization".into(), "Bearer token".into())]);
+    }
+}
@@ -0,0 +1,199 @@
+//! Byte sources adapted to `Read + Seek`: remote files served by range,
+//! streams read sequentially and files embedded in others.
+use std::cmp;
+use std::io::{self, Read, Seek, SeekFrom};
+
+mod cache;
+#[cfg(feature = "http")]
+mod http;
+mod offset;
+#[cfg(feature = "prefetch")]
+mod prefetch;
+mod sequential;
+
+pub use self::cache::CachedRangeReader;
+#[cfg(feature = "http")]
+pub use self::http::HttpRangeReader;
+pub use self::offset::OffsetSource;
+#[cfg(feature = "prefetch")]
+pub use self::prefetch::{PrefetchSource, Prefetcher};
+pub use self::sequential::SequentialSource;
+
+/// A source able to fetch any range of its bytes on demand.
+pub trait RangeReader {
+    /// Total size in bytes of the source.
+    fn size(&mut self) -> io::Result<u64>;
+
+    /// Fetches `length` bytes starting at `offset`. Fewer bytes are returned
+    /// only when the range goes past the end of the source.
+    fn read_range(&mut self, offset: u64, length: usize) -> io::Result<Vec<u8>>;
+}
+
+/// Adapts a `RangeReader` to `Read + Seek`, so that a `TIFFReader` can be
+/// built over it.
+///
+/// Small reads, such as headers and directories, are served from blocks of
+/// `block_size` bytes, while larger reads, such as strips, are fetched at once.
+pub struct RangeSource<T> {
+    inner: T,
+    block_size: usize,
+    position: u64,
+    size: Option<u64>,
+    buffer: Vec<u8>,
+    buffer_offset: u64,
+}
+
+impl<T: RangeReader> RangeSource<T> {
+    /// Creates a source fetching blocks of 64 KiB.
+    pub fn new(inner: T) -> RangeSource<T> {
+        RangeSource::with_block_size(inner, 64 * 1024)
+    }
+
+    /// Creates a source fetching blocks of at least `block_size` bytes.
+    pub fn with_block_size(inner: T, block_size: usize) -> RangeSource<T> {
+        RangeSource {
+            inner,
+            block_size: cmp::max(block_size, 1),
+            position: 0,
+            size: None,
+            buffer: Vec::new(),
+            buffer_offset: 0,
+        }
+    }
+
+    /// Returns the underlying range reader.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn size(&mut self) -> io::Result<u64> {
+        match self.size {
+            Some(size) => Ok(size),
+            None => {
+                let size = self.inner.size()?;
+                self.size = Some(size);
+                Ok(size)
+            }
+        }
+    }
+
+    fn is_buffered(&self, position: u64) -> bool {
+        position >= self.buffer_offset && position - self.buffer_offset < self.buffer.len() as u64
+    }
+}
+
+impl<T: RangeReader> Read for RangeSource<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if !self.is_buffered(self.position) {
+            let size = self.size()?;
+            if self.position >= size {
+                return Ok(0);
+            }
+
+            let length = cmp::max(buf.len(), self.block_size) as u64;
+            let length = cmp::min(length, size - self.position) as usize;
+            self.buffer = self.inner.read_range(self.position, length)?;
+            self.buffer_offset = self.position;
+            if self.buffer.is_empty() {
+                return Ok(0);
+            }
+        }
+
+        let start = (self.position - self.buffer_offset) as usize;
+        let count = cmp::min(buf.len(), self.buffer.len() - start);
+        buf[..count].copy_from_slice(&self.buffer[start..start + count]);
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl<T: RangeReader> Seek for RangeSource<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => {
+                let size = self.size()?;
+                size.checked_add_signed(delta)
+            }
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::TIFFReader;
+    use std::io::Cursor;
+    use tag::*;
+
+    /// Serves ranges of an in-memory file, counting requests.
+    pub struct MemoryRangeReader {
+        pub bytes: &'static [u8],
+        pub requests: usize,
+    }
+
+    impl RangeReader for MemoryRangeReader {
+        fn size(&mut self) -> io::Result<u64> {
+            Ok(self.bytes.len() as u64)
+        }
+
+        fn read_range(&mut self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+            self.requests += 1;
+            let start = cmp::min(offset as usize, self.bytes.len());
+            let end = cmp::min(start + length, self.bytes.len());
+            Ok(self.bytes[start..end].to_vec())
+        }
+    }
+
+    #[test]
+    fn test_range_source() {
+        let bytes: &'static [u8] = include_bytes!("../../samples/picoawards_le.tiff");
+        let remote = MemoryRangeReader { bytes, requests: 0 };
+        let source = RangeSource::with_block_size(remote, 1024);
+        let mut read = TIFFReader::new(source).unwrap();
+        let mut expected = TIFFReader::new(Cursor::new(bytes)).unwrap();
+
+        assert_eq!(read.get_field::<ImageWidth>().unwrap().0, 436);
+        assert_eq!(read.read_strip(30).unwrap(), expected.read_strip(30).unwrap());
+        assert!(read.read_strip(61).is_err());
+    }
+
+    #[test]
+    fn test_range_source_requests() {
+        let bytes: &'static [u8] = include_bytes!("../../samples/picoawards_le.tiff");
+        let remote = MemoryRangeReader { bytes, requests: 0 };
+        let mut source = RangeSource::with_block_size(remote, 16);
+
+        let mut header = [0; 8];
+        source.read_exact(&mut header).unwrap();
+        source.seek(SeekFrom::Start(4)).unwrap();
+        source.read_exact(&mut header[..4]).unwrap();
+        assert_eq!(source.inner.requests, 1);
+
+        // Reads larger than a block are fetched at once
+        let mut strip = vec![0; 1000];
+        source.seek(SeekFrom::End(-1000)).unwrap();
+        source.read_exact(&mut strip).unwrap();
+        assert_eq!(&strip[..], &bytes[bytes.len() - 1000..]);
+        assert_eq!(source.inner.requests, 2);
+
+        assert_eq!(source.read(&mut strip).unwrap(), 0);
+        assert!(source.seek(SeekFrom::Current(-1_000_000)).is_err());
+    }
+}
@@ -0,0 +1,94 @@
+//! Files embedded in other containers.
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Exposes the bytes of a seekable source from `base` onwards as a stream of
+/// their own, so that a TIFF embedded in another container, such as an EXIF
+/// blob or a raw camera file, is read with offsets relative to its header.
+///
+/// The embedded file is taken to extend to the end of the container.
+pub struct OffsetSource<R> {
+    inner: R,
+    base: u64,
+}
+
+impl<R: Seek> OffsetSource<R> {
+    /// Creates a source starting at `base`, and seeks to its start.
+    pub fn new(mut inner: R, base: u64) -> io::Result<OffsetSource<R>> {
+        inner.seek(SeekFrom::Start(base))?;
+        Ok(OffsetSource { inner, base })
+    }
+
+    /// Offset of the embedded file in the container.
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    /// Returns the container.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for OffsetSource<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<R: Seek> Seek for OffsetSource<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid seek before the start of an embedded file",
+            )
+        };
+        let position = match pos {
+            SeekFrom::Start(offset) => {
+                let offset = self.base.checked_add(offset).ok_or_else(invalid)?;
+                self.inner.seek(SeekFrom::Start(offset))?
+            }
+            SeekFrom::End(delta) => {
+                let end = self.inner.seek(SeekFrom::End(0))?;
+                let position = end.checked_add_signed(delta).filter(|p| *p >= self.base);
+                self.inner.seek(SeekFrom::Start(position.ok_or_else(invalid)?))?
+            }
+            SeekFrom::Current(delta) => {
+                let current = self.inner.stream_position()?;
+                let position = current.checked_add_signed(delta).filter(|p| *p >= self.base);
+                self.inner.seek(SeekFrom::Start(position.ok_or_else(invalid)?))?
+            }
+        };
+        Ok(position - self.base)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::{ErrorKind, TiffError};
+    use reader::TIFFReader;
+    use std::io::Cursor;
+    use tag::*;
+
+    #[test]
+    fn test_offset_source() {
+        let bytes: &[u8] = include_bytes!("../../samples/arbitro_be.tiff");
+        let mut container = vec![0xaa; 100];
+        container.extend_from_slice(bytes);
+        container.extend_from_slice(&[0xbb; 10]);
+
+        let mut read = TIFFReader::new_at_offset(Cursor::new(&container[..]), 100).unwrap();
+        let mut expected = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.get_field::<ImageWidth>().unwrap().0, 174);
+        assert_eq!(read.read_strip(0).unwrap(), expected.read_strip(0).unwrap());
+        assert!(TIFFReader::new(Cursor::new(&container[..])).is_err());
+
+        let mut source = OffsetSource::new(Cursor::new(&container[..]), 100).unwrap();
+        assert_eq!(source.seek(SeekFrom::End(-10)).unwrap(), bytes.len() as u64);
+        assert_eq!(source.seek(SeekFrom::Current(-4)).unwrap(), bytes.len() as u64 - 4);
+        assert!(source.seek(SeekFrom::Current(-(bytes.len() as i64))).is_err());
+        let error = TiffError::from(source.seek(SeekFrom::Start(u64::MAX)).unwrap_err());
+        assert_eq!(error.kind(), ErrorKind::CorruptData);
+    }
+}
@@ -0,0 +1,250 @@
+//! Read-ahead of strips and tiles on a background thread.
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use super::{RangeReader, RangeSource};
+
+/// Ranges scheduled for prefetching, by offset and length, with their bytes
+/// once fetched.
+type Fetched = HashMap<(u64, u64), Option<io::Result<Vec<u8>>>>;
+
+/// The state shared by a `PrefetchSource`, its `Prefetcher`s and its worker.
+struct Shared {
+    fetched: Mutex<Fetched>,
+    ready: Condvar,
+}
+
+/// A `RangeReader` shared between the reading and the prefetching threads.
+struct SharedReader<T>(Arc<Mutex<T>>);
+
+impl<T: RangeReader> RangeReader for SharedReader<T> {
+    fn size(&mut self) -> io::Result<u64> {
+        lock(&self.0).size()
+    }
+
+    fn read_range(&mut self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        lock(&self.0).read_range(offset, length)
+    }
+}
+
+/// Locks `mutex`, even when a thread panicked while holding it.
+fn lock<T>(mutex: &Mutex<T>) -> ::std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Schedules ranges of a `PrefetchSource` to be fetched in the background.
+/// Clones schedule for the same source.
+#[derive(Clone)]
+pub struct Prefetcher {
+    shared: Arc<Shared>,
+    requests: Sender<(u64, u64)>,
+}
+
+impl Prefetcher {
+    /// Fetches `length` bytes at `offset` in the background, unless they are
+    /// already scheduled. The next read of exactly that range is served from
+    /// the prefetched bytes.
+    pub fn prefetch(&self, offset: u64, length: u64) {
+        let mut fetched = lock(&self.shared.fetched);
+        if length == 0 || fetched.contains_key(&(offset, length)) {
+            return;
+        }
+        if self.requests.send((offset, length)).is_ok() {
+            fetched.insert((offset, length), None);
+        }
+    }
+
+    /// Drops the ranges scheduled and not read yet, but for `keep`, and
+    /// schedules `next` with `prefetch`, so that ranges left unread by an
+    /// error or a partial decode do not pile up.
+    pub(crate) fn read_ahead(&self, keep: (u64, u64), next: &[(u64, u64)]) {
+        lock(&self.shared.fetched).retain(|range, _| *range == keep || next.contains(range));
+        for (offset, length) in next {
+            self.prefetch(*offset, *length);
+        }
+    }
+}
+
+/// Adapts a `RangeReader` to `Read + Seek` like `RangeSource`, fetching the
+/// ranges scheduled with its `Prefetcher` on a background thread, so that
+/// slow sources are read while the data already received is decoded.
+///
+/// Reads of ranges not scheduled go through a `RangeSource`.
+pub struct PrefetchSource<T> {
+    source: RangeSource<SharedReader<T>>,
+    prefetcher: Prefetcher,
+}
+
+impl<T: RangeReader + Send + 'static> PrefetchSource<T> {
+    /// Creates a source fetching blocks of `block_size` bytes outside of the
+    /// prefetched ranges, and starts its worker thread. The thread stops once
+    /// the source and its prefetchers are dropped.
+    pub fn new(inner: T, block_size: usize) -> PrefetchSource<T> {
+        let inner = Arc::new(Mutex::new(inner));
+        let shared = Arc::new(Shared {
+            fetched: Mutex::new(HashMap::new()),
+            ready: Condvar::new(),
+        });
+
+        let (requests, received) = mpsc::channel::<(u64, u64)>();
+        let worker = (Arc::clone(&inner), Arc::clone(&shared));
+        thread::spawn(move || {
+            let (inner, shared) = worker;
+            for range in received {
+                // Dropped, or fetched for an earlier request, since scheduled
+                if !matches!(lock(&shared.fetched).get(&range), Some(None)) {
+                    continue;
+                }
+                let bytes = lock(&inner).read_range(range.0, range.1 as usize);
+                if let Some(entry) = lock(&shared.fetched).get_mut(&range) {
+                    *entry = Some(bytes);
+                }
+                shared.ready.notify_all();
+            }
+        });
+
+        PrefetchSource {
+            source: RangeSource::with_block_size(SharedReader(inner), block_size),
+            prefetcher: Prefetcher { shared, requests },
+        }
+    }
+}
+
+impl<T> PrefetchSource<T> {
+    /// A handle scheduling ranges of this source.
+    pub fn prefetcher(&self) -> Prefetcher {
+        self.prefetcher.clone()
+    }
+
+    /// Takes the bytes of `length` bytes at `offset` if the range was
+    /// scheduled, waiting for them if they are still being fetched.
+    fn take_prefetched(&self, offset: u64, length: u64) -> Option<io::Result<Vec<u8>>> {
+        let shared = &self.prefetcher.shared;
+        let mut fetched = lock(&shared.fetched);
+        loop {
+            match fetched.get(&(offset, length)) {
+                None => return None,
+                Some(Some(_)) => return fetched.remove(&(offset, length))?,
+                Some(None) => {
+                    fetched = shared
+                        .ready
+                        .wait(fetched)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+                }
+            }
+        }
+    }
+}
+
+impl<T: RangeReader> Read for PrefetchSource<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let position = self.source.position;
+        match self.take_prefetched(position, buf.len() as u64) {
+            Some(bytes) => {
+                let bytes = bytes?;
+                let count = bytes.len().min(buf.len());
+                buf[..count].copy_from_slice(&bytes[..count]);
+                self.source.position += count as u64;
+                Ok(count)
+            }
+            None => self.source.read(buf),
+        }
+    }
+}
+
+impl<T: RangeReader> Seek for PrefetchSource<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.source.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::MemoryRangeReader;
+    use super::*;
+    use endian::Endian;
+    use image::Image;
+    use reader::TIFFReader;
+    use std::io::Cursor;
+    use tag::*;
+    use writer::{Directory, TIFFWriter};
+
+    #[test]
+    fn test_prefetch_source() {
+        let bytes: &'static [u8] = include_bytes!("../../samples/arbitro_be.tiff");
+        let remote = MemoryRangeReader { bytes, requests: 0 };
+        let mut source = PrefetchSource::new(remote, 16);
+        let prefetcher = source.prefetcher();
+        prefetcher.prefetch(1000, 400);
+        prefetcher.prefetch(1000, 400);
+
+        let mut buffer = vec![0; 400];
+        source.seek(SeekFrom::Start(1000)).unwrap();
+        source.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer[..], &bytes[1000..1400]);
+        assert_eq!(lock(&source.source.inner.0).requests, 1);
+        assert!(lock(&source.prefetcher.shared.fetched).is_empty());
+
+        // Ranges not scheduled are read through blocks
+        source.read_exact(&mut buffer[..4]).unwrap();
+        assert_eq!(&buffer[..4], &bytes[1400..1404]);
+        assert_eq!(lock(&source.source.inner.0).requests, 2);
+    }
+
+    /// A 4 x 8 image of one row per strip, whose samples are the row number.
+    fn strips_image() -> &'static [u8] {
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(4));
+        directory.set_field(&ImageLength(8));
+        directory.set_field(&BitsPerSample(vec![8]));
+        directory.set_field(&RowsPerStrip(1));
+        writer.write_image(&directory, 8, |index| Ok(vec![index as u8; 4])).unwrap();
+        Box::leak(writer.into_inner().into_inner().into_boxed_slice())
+    }
+
+    #[test]
+    fn test_read_ahead() {
+        let bytes = strips_image();
+        let remote = MemoryRangeReader { bytes, requests: 0 };
+        let source = PrefetchSource::new(remote, 4);
+        let prefetcher = source.prefetcher();
+        let shared = Arc::clone(&prefetcher.shared);
+        let mut read = TIFFReader::new(source).unwrap();
+        let mut image = Image::new(&mut read).unwrap();
+        image.set_read_ahead(Some(prefetcher), 2);
+        assert_eq!(image.read_region(1, 2, 2, 5).unwrap(), [2, 2, 3, 3, 4, 4, 5, 5, 6, 6]);
+
+        // Every strip scheduled was read from the prefetched bytes
+        assert!(lock(&shared.fetched).is_empty());
+        assert_eq!(image.read_region(0, 7, 4, 1).unwrap(), [7; 4]);
+    }
+
+    #[test]
+    fn test_read_ahead_strips() {
+        let remote = MemoryRangeReader { bytes: strips_image(), requests: 0 };
+        let source = PrefetchSource::new(remote, 4);
+        let prefetcher = source.prefetcher();
+        let shared = Arc::clone(&prefetcher.shared);
+        // Never read, and dropped once other ranges are scheduled
+        prefetcher.prefetch(0, 1);
+
+        let mut read = TIFFReader::new(source).unwrap();
+        let mut image = Image::new(&mut read).unwrap();
+        image.set_read_ahead(Some(prefetcher), 2);
+        let expected: Vec<u8> = (0..8).flat_map(|row| vec![row; 4]).collect();
+        assert_eq!(image.read_data().unwrap().data, expected);
+        assert!(lock(&shared.fetched).is_empty());
+
+        // Rows left unread keep no more than the strips read ahead
+        let rows: Vec<Vec<u8>> = image.rows().take(3).map(|row| row.unwrap()).collect();
+        assert_eq!(rows, [[0; 4], [1; 4], [2; 4]]);
+        assert_eq!(lock(&shared.fetched).len(), 2);
+        // and are dropped by the next decode
+        assert_eq!(image.read_region(0, 6, 4, 1).unwrap(), [6; 4]);
+        assert!(lock(&shared.fetched).is_empty());
+    }
+}
@@ -0,0 +1,138 @@
+//! Sources read from start to end, such as pipes and sockets.
+use std::cmp;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Adapts a plain `Read` to `Read + Seek`, so that a `TIFFReader` can be
+/// built over a stream that cannot seek.
+///
+/// The stream is only read as far as the furthest byte requested so far, and
+/// everything read is kept, as directories and values may point backwards.
+/// Its length is unknown until the end is reached, so seeking from the end
+/// fails.
+pub struct SequentialSource<R> {
+    inner: R,
+    buffer: Vec<u8>,
+    position: u64,
+    at_end: bool,
+}
+
+impl<R: Read> SequentialSource<R> {
+    /// Creates a source reading `inner` on demand.
+    pub fn new(inner: R) -> SequentialSource<R> {
+        SequentialSource {
+            inner,
+            buffer: Vec::new(),
+            position: 0,
+            at_end: false,
+        }
+    }
+
+    /// Number of bytes read from the stream so far.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Returns the underlying stream, positioned after the bytes read so far.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads the stream until `end` bytes are buffered, or until its end.
+    fn fill(&mut self, end: u64) -> io::Result<()> {
+        let buffered = self.buffer.len() as u64;
+        if self.at_end || end <= buffered {
+            return Ok(());
+        }
+        let wanted = end - buffered;
+        let read = (&mut self.inner).take(wanted).read_to_end(&mut self.buffer)?;
+        if (read as u64) < wanted {
+            self.at_end = true;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for SequentialSource<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.fill(self.position.saturating_add(buf.len() as u64))?;
+        if self.position >= self.buffer.len() as u64 {
+            return Ok(0);
+        }
+
+        let start = self.position as usize;
+        let count = cmp::min(buf.len(), self.buffer.len() - start);
+        buf[..count].copy_from_slice(&self.buffer[start..start + count]);
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl<R: Read> Seek for SequentialSource<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "Seek from the end of a sequential stream",
+                ))
+            }
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+        };
+
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::TIFFReader;
+    use std::io::Cursor;
+    use tag::*;
+
+    #[test]
+    fn test_sequential_source() {
+        let bytes: &[u8] = include_bytes!("../../samples/picoawards_le.tiff");
+        let mut read = TIFFReader::from_stream(bytes).unwrap();
+        let mut expected = TIFFReader::new(Cursor::new(bytes)).unwrap();
+
+        assert_eq!(read.get_field::<ImageWidth>().unwrap().0, 436);
+        assert_eq!(read.read_strip(30).unwrap(), expected.read_strip(30).unwrap());
+        assert_eq!(read.read_strip(2).unwrap(), expected.read_strip(2).unwrap());
+        assert!(read.read_strip(61).is_err());
+    }
+
+    #[test]
+    fn test_sequential_source_reads() {
+        let bytes: &[u8] = &[1, 2, 3, 4, 5, 6, 7, 8];
+        let mut source = SequentialSource::new(bytes);
+
+        // Nothing past the requested bytes is read
+        source.seek(SeekFrom::Start(2)).unwrap();
+        let mut buf = [0; 3];
+        source.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [3, 4, 5]);
+        assert_eq!(source.buffered(), 5);
+
+        source.seek(SeekFrom::Current(-5)).unwrap();
+        source.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3]);
+        assert_eq!(source.buffered(), 5);
+
+        source.seek(SeekFrom::Start(6)).unwrap();
+        assert_eq!(source.read(&mut buf).unwrap(), 2);
+        assert_eq!(source.read(&mut buf).unwrap(), 0);
+        assert!(source.seek(SeekFrom::End(0)).is_err());
+        assert!(source.seek(SeekFrom::Current(-100)).is_err());
+    }
+}
@@ -23,6 +23,33 @@ macro_rules! tags_id_definition {
         }
       }
 
+        impl From<Tag> for u16 {
+            fn from(tag: Tag) -> u16 {
+                match tag {
+                    $( Tag::$name => $value,)*
+                    Tag::Unknown(value) => value
+                }
+            }
+        }
+
+        impl Tag {
+            /// The name of the tag, or `None` for unknown tags.
+            pub fn name(self) -> Option<&'static str> {
+                match self {
+                    $( Tag::$name => Some(stringify!($name)),)*
+                    Tag::Unknown(_) => None
+                }
+            }
+
+            /// A short description of the tag, or `None` for unknown tags.
+            pub fn description(self) -> Option<&'static str> {
+                match self {
+                    $( Tag::$name => Some($desc),)*
+                    Tag::Unknown(_) => None
+                }
+            }
+        }
+
       impl Display for Tag {
           fn fmt(&self, f: &mut Formatter) -> Result<(),Error> {
               match self {
@@ -72,7 +99,18 @@ tags_id_definition! {
     HostComputer | 0x013c	=> "The computer and/or operating system in use at the time of image creation.",
     ColorMap | 0x0140	=> "A color map for palette color images.",
     ExtraSamples | 0x0152	=> "Description of extra components.",
+    CFARepeatPatternDim | 0x828d => "The number of rows and columns of the repeating pattern of a color filter array (TIFF/EP).",
+    CFAPattern | 0x828e => "The color filter of each cell of the repeating pattern, row by row (TIFF/EP).",
     Copyright | 0x8298 => "Copyright notice.",
+    ModelPixelScale | 0x830e => "The size of a raster pixel in model space units (GeoTIFF).",
+    ModelTiepoint | 0x8482 => "Raster to model space tie points (GeoTIFF).",
+    ModelTransformation | 0x85d8 => "The affine transformation from raster to model space (GeoTIFF).",
+    ExifIFD | 0x8769 => "A pointer to the Exif IFD.",
+    GeoKeyDirectory | 0x87af => "The GeoKey directory, describing the coordinate reference system (GeoTIFF).",
+    GeoDoubleParams | 0x87b0 => "The double valued GeoKeys (GeoTIFF).",
+    GeoAsciiParams | 0x87b1 => "The ASCII valued GeoKeys, separated by pipes (GeoTIFF).",
+    GPSInfo | 0x8825 => "A pointer to the GPS Info IFD.",
+    GdalNoData | 0xa481 => "The value of the samples holding no data, as text (GDAL).",
     Predictor | 0x13d => "This section defines a Predictor that greatly improves compression ratios for some images.",
     T4Options | 0x124 => "See Compression=3. This field is made up of a set of 32 flag bits. Unused bits must be set to 0. Bit 0 is the low-order bit.",
     T6Options | 0x125 => "See Compression=3. See Compression = 4. This field is made up of a set of 32 flag bits. Unused bits must be set to 0. Bit 0 is the low-order bit. The default value is 0 (all bits 0).",
@@ -111,6 +149,366 @@ tags_id_definition! {
     JPEGQTables | 0x207 => "This Field points to a list of offsets to the quantization tables, one per component.",
     JPEGDCTables | 0x208 => "This Field points to a list of offsets to the DC Huffman tables or the lossless Huffman tables, one per component",
     JPEGACTables | 0x209 => "This Field points to a list of offsets to the Huffman AC tables, one per component.",
+    JPEGTables | 0x15b => "The quantization and Huffman tables shared by the JPEG compressed strips or tiles, as an abbreviated JPEG stream.",
+    CFAPlaneColor | 0xc616 => "The color of each plane a color filter array pattern refers to (DNG).",
+    ExposureTime | 0x829a => "Exposure time, given in seconds.",
+    FNumber | 0x829d => "The F number.",
+    ExposureProgram | 0x8822 => "The class of the program used by the camera to set exposure when the picture is taken.",
+    SpectralSensitivity | 0x8824 => "The spectral sensitivity of each channel of the camera used.",
+    PhotographicSensitivity | 0x8827 => "The sensitivity of the camera or input device when the image was shot, such as the ISO speed.",
+    OECF | 0x8828 => "The Opto-Electric Conversion Function specified in ISO 14524.",
+    SensitivityType | 0x8830 => "Which of the parameters of ISO 12232 PhotographicSensitivity records.",
+    StandardOutputSensitivity | 0x8831 => "The standard output sensitivity value of the camera or input device, defined in ISO 12232.",
+    RecommendedExposureIndex | 0x8832 => "The recommended exposure index value of the camera or input device, defined in ISO 12232.",
+    ISOSpeed | 0x8833 => "The ISO speed value of the camera or input device, defined in ISO 12232.",
+    ISOSpeedLatitudeyyy | 0x8834 => "The ISO speed latitude yyy value of the camera or input device, defined in ISO 12232.",
+    ISOSpeedLatitudezzz | 0x8835 => "The ISO speed latitude zzz value of the camera or input device, defined in ISO 12232.",
+    ExifVersion | 0x9000 => "The version of the Exif standard supported.",
+    DateTimeOriginal | 0x9003 => "The date and time when the original image data was generated.",
+    DateTimeDigitized | 0x9004 => "The date and time when the image was stored as digital data.",
+    OffsetTime | 0x9010 => "The offset from UTC of DateTime.",
+    OffsetTimeOriginal | 0x9011 => "The offset from UTC of DateTimeOriginal.",
+    OffsetTimeDigitized | 0x9012 => "The offset from UTC of DateTimeDigitized.",
+    ComponentsConfiguration | 0x9101 => "The channels of each component of compressed data.",
+    CompressedBitsPerPixel | 0x9102 => "The compression mode used for a compressed image, in bits per pixel.",
+    ShutterSpeedValue | 0x9201 => "Shutter speed, in APEX units.",
+    ApertureValue | 0x9202 => "The lens aperture, in APEX units.",
+    BrightnessValue | 0x9203 => "The value of brightness, in APEX units.",
+    ExposureBiasValue | 0x9204 => "The exposure bias, in APEX units.",
+    MaxApertureValue | 0x9205 => "The smallest F number of the lens, in APEX units.",
+    SubjectDistance | 0x9206 => "The distance to the subject, given in meters.",
+    MeteringMode | 0x9207 => "The metering mode.",
+    LightSource | 0x9208 => "The kind of light source.",
+    Flash | 0x9209 => "The status of flash when the image was shot.",
+    FocalLength | 0x920a => "The actual focal length of the lens, in mm.",
+    SubjectArea | 0x9214 => "The location and area of the main subject in the overall scene.",
+    MakerNote | 0x927c => "Information recorded by the manufacturer, in a format of its own.",
+    UserComment | 0x9286 => "Keywords or comments on the image, prefixed by their character code.",
+    SubSecTime | 0x9290 => "Fractions of seconds of DateTime.",
+    SubSecTimeOriginal | 0x9291 => "Fractions of seconds of DateTimeOriginal.",
+    SubSecTimeDigitized | 0x9292 => "Fractions of seconds of DateTimeDigitized.",
+    Temperature | 0x9400 => "The temperature as the ambient situation at the shot, in degrees Celsius.",
+    Humidity | 0x9401 => "The humidity as the ambient situation at the shot, in percent.",
+    Pressure | 0x9402 => "The pressure as the ambient situation at the shot, in hPa.",
+    WaterDepth | 0x9403 => "The water depth as the ambient situation at the shot, in meters.",
+    Acceleration | 0x9404 => "The acceleration of the imaging device at the shot, in mGal.",
+    CameraElevationAngle | 0x9405 => "The elevation angle of the optical axis of the imaging device, in degrees.",
+    FlashpixVersion | 0xa000 => "The Flashpix format version supported.",
+    ColorSpace | 0xa001 => "The color space information tag, 1 for sRGB.",
+    PixelXDimension | 0xa002 => "The valid width of the meaningful image.",
+    PixelYDimension | 0xa003 => "The valid height of the meaningful image.",
+    RelatedSoundFile | 0xa004 => "The name of an audio file related to the image data.",
+    InteroperabilityIFD | 0xa005 => "A pointer to the Interoperability IFD.",
+    FlashEnergy | 0xa20b => "The strobe energy at the time the image is captured, in BCPS.",
+    SpatialFrequencyResponse | 0xa20c => "The spatial frequency table and SFR values, specified in ISO 12233.",
+    FocalPlaneXResolution | 0xa20e => "The number of pixels in the image width direction per FocalPlaneResolutionUnit on the camera focal plane.",
+    FocalPlaneYResolution | 0xa20f => "The number of pixels in the image height direction per FocalPlaneResolutionUnit on the camera focal plane.",
+    FocalPlaneResolutionUnit | 0xa210 => "The unit of FocalPlaneXResolution and FocalPlaneYResolution.",
+    SubjectLocation | 0xa214 => "The location of the main subject in the scene.",
+    ExposureIndex | 0xa215 => "The exposure index selected on the camera or input device.",
+    SensingMethod | 0xa217 => "The image sensor type on the camera or input device.",
+    FileSource | 0xa300 => "The image source, 3 for a digital camera.",
+    SceneType | 0xa301 => "The type of scene, 1 for a directly photographed image.",
+    ExifCFAPattern | 0xa302 => "The color filter array geometric pattern of the image sensor.",
+    CustomRendered | 0xa401 => "The use of special processing on image data, such as rendering geared to output.",
+    ExposureMode | 0xa402 => "The exposure mode set when the image was shot.",
+    WhiteBalance | 0xa403 => "The white balance mode set when the image was shot.",
+    DigitalZoomRatio | 0xa404 => "The digital zoom ratio when the image was shot.",
+    FocalLengthIn35mmFilm | 0xa405 => "The equivalent focal length assuming a 35mm film camera, in mm.",
+    SceneCaptureType | 0xa406 => "The type of scene that was shot.",
+    GainControl | 0xa407 => "The degree of overall image gain adjustment.",
+    Contrast | 0xa408 => "The direction of contrast processing applied by the camera.",
+    Saturation | 0xa409 => "The direction of saturation processing applied by the camera.",
+    Sharpness | 0xa40a => "The direction of sharpness processing applied by the camera.",
+    DeviceSettingDescription | 0xa40b => "The picture-taking conditions of a particular camera model.",
+    SubjectDistanceRange | 0xa40c => "The distance to the subject.",
+    ImageUniqueID | 0xa420 => "An identifier assigned uniquely to each image, as a 128-bit hexadecimal number.",
+    CameraOwnerName | 0xa430 => "The owner of the camera used to take the image.",
+    BodySerialNumber | 0xa431 => "The serial number of the body of the camera used to take the image.",
+    LensSpecification | 0xa432 => "The minimum and maximum focal lengths, and the minimum F numbers at both, of the lens.",
+    LensMake | 0xa433 => "The lens manufacturer.",
+    LensModel | 0xa434 => "The lens model name and model number.",
+    LensSerialNumber | 0xa435 => "The serial number of the interchangeable lens.",
+    CompositeImage | 0xa460 => "Whether the image is a composite image.",
+    SourceImageNumberOfCompositeImage | 0xa461 => "The number of source images captured for a composite image.",
+    SourceExposureTimesOfCompositeImage | 0xa462 => "The exposure times of the source images of a composite image.",
+    Gamma | 0xa500 => "The value of the coefficient gamma.",
+}
+
+/// The type of the values of an entry.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FieldType {
+    Byte,
+    Ascii,
+    Short,
+    Long,
+    Rational,
+    SByte,
+    Undefined,
+    SShort,
+    SLong,
+    SRational,
+    Float,
+    Double,
+}
+
+impl FieldType {
+    /// The type stored in entries with the identifier `id`.
+    pub fn from_id(id: u16) -> Option<FieldType> {
+        let field_type = match id {
+            1 => FieldType::Byte,
+            2 => FieldType::Ascii,
+            3 => FieldType::Short,
+            4 => FieldType::Long,
+            5 => FieldType::Rational,
+            6 => FieldType::SByte,
+            7 => FieldType::Undefined,
+            8 => FieldType::SShort,
+            9 => FieldType::SLong,
+            10 => FieldType::SRational,
+            11 => FieldType::Float,
+            12 => FieldType::Double,
+            _ => return None,
+        };
+        Some(field_type)
+    }
+
+    /// The identifier of the type, as stored in entries.
+    pub fn id(self) -> u16 {
+        match self {
+            FieldType::Byte => 1,
+            FieldType::Ascii => 2,
+            FieldType::Short => 3,
+            FieldType::Long => 4,
+            FieldType::Rational => 5,
+            FieldType::SByte => 6,
+            FieldType::Undefined => 7,
+            FieldType::SShort => 8,
+            FieldType::SLong => 9,
+            FieldType::SRational => 10,
+            FieldType::Float => 11,
+            FieldType::Double => 12,
+        }
+    }
+
+    /// Size in bytes of one element of the type.
+    pub fn size(self) -> u64 {
+        match self {
+            FieldType::Short | FieldType::SShort => 2,
+            FieldType::Long | FieldType::SLong | FieldType::Float => 4,
+            FieldType::Rational | FieldType::SRational | FieldType::Double => 8,
+            _ => 1,
+        }
+    }
+}
+
+/// The number of values the specification expects in a field.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ValueCount {
+    /// Exactly this number of values.
+    Fixed(u32),
+    /// One value per sample, as given by `SamplesPerPixel`.
+    PerSample,
+    /// A number depending on the image or the content, such as one value per
+    /// strip or the length of a string.
+    Variable,
+}
+
+impl Tag {
+    /// The numeric code of the tag.
+    pub fn code(self) -> u16 {
+        u16::from(self)
+    }
+
+    /// The value types the specification allows for the tag, empty for
+    /// unknown tags.
+    pub fn value_types(self) -> &'static [FieldType] {
+        use self::FieldType::*;
+        match self {
+            Tag::NewSubfileType | Tag::FreeOffsets | Tag::FreeByteCounts | Tag::TileOffsets => {
+                &[Long]
+            }
+            Tag::T4Options
+            | Tag::T6Options
+            | Tag::JPEGInterchangeFormat
+            | Tag::JPEGInterchangeFormatLength
+            | Tag::JPEGQTables
+            | Tag::JPEGDCTables
+            | Tag::JPEGACTables
+            | Tag::ExifIFD
+            | Tag::GPSInfo
+            | Tag::StandardOutputSensitivity
+            | Tag::RecommendedExposureIndex
+            | Tag::ISOSpeed
+            | Tag::ISOSpeedLatitudeyyy
+            | Tag::ISOSpeedLatitudezzz
+            | Tag::InteroperabilityIFD => &[Long],
+            Tag::ImageWidth
+            | Tag::ImageLength
+            | Tag::StripOffsets
+            | Tag::RowsPerStrip
+            | Tag::StripByteCounts
+            | Tag::TileWidth
+            | Tag::TileLength
+            | Tag::TileByteCounts
+            | Tag::PixelXDimension
+            | Tag::PixelYDimension => &[Short, Long],
+            Tag::ImageDescription
+            | Tag::Make
+            | Tag::Model
+            | Tag::Software
+            | Tag::DateTime
+            | Tag::Artist
+            | Tag::HostComputer
+            | Tag::Copyright
+            | Tag::DocumentName
+            | Tag::PageName
+            | Tag::InkNames
+            | Tag::TargetPrinter
+            | Tag::GeoAsciiParams
+            | Tag::GdalNoData
+            | Tag::SpectralSensitivity
+            | Tag::DateTimeOriginal
+            | Tag::DateTimeDigitized
+            | Tag::OffsetTime
+            | Tag::OffsetTimeOriginal
+            | Tag::OffsetTimeDigitized
+            | Tag::SubSecTime
+            | Tag::SubSecTimeOriginal
+            | Tag::SubSecTimeDigitized
+            | Tag::RelatedSoundFile
+            | Tag::ImageUniqueID
+            | Tag::CameraOwnerName
+            | Tag::BodySerialNumber
+            | Tag::LensMake
+            | Tag::LensModel
+            | Tag::LensSerialNumber => &[Ascii],
+            Tag::XResolution
+            | Tag::YResolution
+            | Tag::XPosition
+            | Tag::YPosition
+            | Tag::WhitePoint
+            | Tag::PrimaryChromaticities
+            | Tag::ReferenceBlackWhite
+            | Tag::YCbCrCoefficients
+            | Tag::ExposureTime
+            | Tag::FNumber
+            | Tag::CompressedBitsPerPixel
+            | Tag::ApertureValue
+            | Tag::MaxApertureValue
+            | Tag::SubjectDistance
+            | Tag::FocalLength
+            | Tag::Humidity
+            | Tag::Pressure
+            | Tag::Acceleration
+            | Tag::FlashEnergy
+            | Tag::FocalPlaneXResolution
+            | Tag::FocalPlaneYResolution
+            | Tag::ExposureIndex
+            | Tag::DigitalZoomRatio
+            | Tag::LensSpecification
+            | Tag::Gamma => &[Rational],
+            Tag::ModelPixelScale
+            | Tag::ModelTiepoint
+            | Tag::ModelTransformation
+            | Tag::GeoDoubleParams => &[Double],
+            Tag::CFAPattern | Tag::CFAPlaneColor => &[Byte],
+            Tag::DotRange => &[Byte, Short],
+            Tag::SMinSampleValue | Tag::SMaxSampleValue => {
+                &[Byte, Short, Long, SByte, SShort, SLong, Float, Double]
+            }
+            Tag::OECF
+            | Tag::ExifVersion
+            | Tag::ComponentsConfiguration
+            | Tag::MakerNote
+            | Tag::UserComment
+            | Tag::FlashpixVersion
+            | Tag::SpatialFrequencyResponse
+            | Tag::FileSource
+            | Tag::SceneType
+            | Tag::ExifCFAPattern
+            | Tag::DeviceSettingDescription
+            | Tag::SourceExposureTimesOfCompositeImage
+            | Tag::JPEGTables => &[Undefined],
+            Tag::ShutterSpeedValue
+            | Tag::BrightnessValue
+            | Tag::ExposureBiasValue
+            | Tag::Temperature
+            | Tag::WaterDepth
+            | Tag::CameraElevationAngle => &[SRational],
+            Tag::Unknown(_) => &[],
+            _ => &[Short],
+        }
+    }
+
+    /// The number of values the specification expects for the tag, or `None`
+    /// for unknown tags.
+    pub fn value_count(self) -> Option<ValueCount> {
+        use self::ValueCount::*;
+        let count = match self {
+            Tag::BitsPerSample
+            | Tag::MinSampleValue
+            | Tag::MaxSampleValue
+            | Tag::SampleFormat
+            | Tag::SMinSampleValue
+            | Tag::SMaxSampleValue
+            | Tag::JPEGLosslessPredictors
+            | Tag::JPEGPointTransforms
+            | Tag::JPEGQTables
+            | Tag::JPEGDCTables
+            | Tag::JPEGACTables => PerSample,
+            Tag::DateTime | Tag::DateTimeOriginal | Tag::DateTimeDigitized => Fixed(20),
+            Tag::PageNumber
+            | Tag::HalftoneHints
+            | Tag::WhitePoint
+            | Tag::YCbCrSubSampling
+            | Tag::CFARepeatPatternDim
+            | Tag::SubjectLocation
+            | Tag::SourceImageNumberOfCompositeImage => Fixed(2),
+            Tag::YCbCrCoefficients | Tag::ModelPixelScale => Fixed(3),
+            Tag::PrimaryChromaticities | Tag::TransferRange | Tag::ReferenceBlackWhite => {
+                Fixed(6)
+            }
+            Tag::ModelTransformation => Fixed(16),
+            Tag::StripOffsets
+            | Tag::StripByteCounts
+            | Tag::FreeOffsets
+            | Tag::FreeByteCounts
+            | Tag::GrayResponseCurve
+            | Tag::ColorMap
+            | Tag::ExtraSamples
+            | Tag::TileOffsets
+            | Tag::TileByteCounts
+            | Tag::DotRange
+            | Tag::TransferFunction
+            | Tag::CFAPattern
+            | Tag::CFAPlaneColor
+            | Tag::ModelTiepoint
+            | Tag::GeoKeyDirectory
+            | Tag::GeoDoubleParams
+            | Tag::PhotographicSensitivity
+            | Tag::OECF
+            | Tag::SubjectArea
+            | Tag::MakerNote
+            | Tag::UserComment
+            | Tag::SpatialFrequencyResponse
+            | Tag::ExifCFAPattern
+            | Tag::DeviceSettingDescription
+            | Tag::SourceExposureTimesOfCompositeImage
+            | Tag::JPEGTables => Variable,
+            Tag::ExifVersion
+            | Tag::ComponentsConfiguration
+            | Tag::FlashpixVersion
+            | Tag::LensSpecification => Fixed(4),
+            Tag::OffsetTime | Tag::OffsetTimeOriginal | Tag::OffsetTimeDigitized => Fixed(7),
+            Tag::RelatedSoundFile => Fixed(13),
+            Tag::ImageUniqueID => Fixed(33),
+            Tag::Unknown(_) => return None,
+            tag if tag.value_types() == [FieldType::Ascii] => Variable,
+            _ => Fixed(1),
+        };
+        Some(count)
+    }
 }
 
 pub trait Field: Sized {
@@ -136,7 +534,7 @@ macro_rules! ascii_value {
 
             fn decode_from_value(value: &TIFFValue) -> Option<$type> {
                 match value {
-                    TIFFValue::Ascii(el) => Some($type(el[0].clone())),
+                    TIFFValue::Ascii(el) => Some($type(el.first()?.clone())),
                     _ => None,
                 }
             }
@@ -161,8 +559,8 @@ macro_rules! short_long_value {
 
             fn decode_from_value(value: &TIFFValue) -> Option<$type> {
                 match value {
-                    TIFFValue::Short(el) => Some($type(u32::from(el[0]))),
-                    TIFFValue::Long(el) => Some($type(el[0])),
+                    TIFFValue::Short(el) => Some($type(u32::from(*el.first()?))),
+                    TIFFValue::Long(el) => Some($type(*el.first()?)),
                     _ => None,
                 }
             }
@@ -192,7 +590,7 @@ macro_rules! short_value {
 
             fn decode_from_value(value: &TIFFValue) -> Option<$type> {
                 match value {
-                    TIFFValue::Short(el) => Some($type(el[0] as u16)),
+                    TIFFValue::Short(el) => Some($type(*el.first()?)),
                     _ => None,
                 }
             }
@@ -217,7 +615,7 @@ macro_rules! long_value {
 
             fn decode_from_value(value: &TIFFValue) -> Option<$type> {
                 match value {
-                    TIFFValue::Long(el) => Some($type(el[0])),
+                    TIFFValue::Long(el) => Some($type(*el.first()?)),
                     _ => None,
                 }
             }
@@ -266,7 +664,7 @@ macro_rules! rational_value {
 
             fn decode_from_value(value: &TIFFValue) -> Option<$type> {
                 match value {
-                    TIFFValue::Rational(el) => Some($type(el[0])),
+                    TIFFValue::Rational(el) => Some($type(*el.first()?)),
                     _ => None,
                 }
             }
@@ -288,6 +686,8 @@ pub enum PhotometricInterpretation {
     TransparencyMask,
     CMYK,
     YCbCr,
+    /// A color filter array, or Bayer mosaic, described by `CFAPattern`.
+    CFA,
 }
 
 impl Field for PhotometricInterpretation {
@@ -296,14 +696,20 @@ impl Field for PhotometricInterpretation {
     }
 
     fn decode_from_value(value: &TIFFValue) -> Option<PhotometricInterpretation> {
-        match value {
-            TIFFValue::Short(el) if el[0] == 0 => Some(PhotometricInterpretation::WhiteIsZero),
-            TIFFValue::Short(el) if el[0] == 1 => Some(PhotometricInterpretation::BlackIsZero),
-            TIFFValue::Short(el) if el[0] == 2 => Some(PhotometricInterpretation::RGB),
-            TIFFValue::Short(el) if el[0] == 3 => Some(PhotometricInterpretation::PaletteColor),
-            TIFFValue::Short(el) if el[0] == 4 => Some(PhotometricInterpretation::TransparencyMask),
-            TIFFValue::Short(el) if el[0] == 5 => Some(PhotometricInterpretation::CMYK),
-            TIFFValue::Short(el) if el[0] == 6 => Some(PhotometricInterpretation::YCbCr),
+        let val = match value {
+            TIFFValue::Short(el) => *el.first()?,
+            _ => return None,
+        };
+
+        match val {
+            0 => Some(PhotometricInterpretation::WhiteIsZero),
+            1 => Some(PhotometricInterpretation::BlackIsZero),
+            2 => Some(PhotometricInterpretation::RGB),
+            3 => Some(PhotometricInterpretation::PaletteColor),
+            4 => Some(PhotometricInterpretation::TransparencyMask),
+            5 => Some(PhotometricInterpretation::CMYK),
+            6 => Some(PhotometricInterpretation::YCbCr),
+            32803 => Some(PhotometricInterpretation::CFA),
             _ => None,
         }
     }
@@ -317,6 +723,7 @@ impl Field for PhotometricInterpretation {
             PhotometricInterpretation::TransparencyMask => 4,
             PhotometricInterpretation::CMYK => 5,
             PhotometricInterpretation::YCbCr => 6,
+            PhotometricInterpretation::CFA => 32803,
         };
 
         Some(TIFFValue::Short(vec![short_value]))
@@ -355,10 +762,15 @@ impl Field for ResolutionUnit {
     }
 
     fn decode_from_value(value: &TIFFValue) -> Option<ResolutionUnit> {
-        match value {
-            TIFFValue::Short(el) if el[0] == 1 => Some(ResolutionUnit::None),
-            TIFFValue::Short(el) if el[0] == 2 => Some(ResolutionUnit::Inch),
-            TIFFValue::Short(el) if el[0] == 3 => Some(ResolutionUnit::Centimeter),
+        let val = match value {
+            TIFFValue::Short(el) => *el.first()?,
+            _ => return None,
+        };
+
+        match val {
+            1 => Some(ResolutionUnit::None),
+            2 => Some(ResolutionUnit::Inch),
+            3 => Some(ResolutionUnit::Centimeter),
             _ => None,
         }
     }
@@ -474,9 +886,14 @@ impl Field for PlanarConfiguration {
     }
 
     fn decode_from_value(value: &TIFFValue) -> Option<PlanarConfiguration> {
-        match value {
-            TIFFValue::Short(el) if el[0] == 1 => Some(PlanarConfiguration::Chunky),
-            TIFFValue::Short(el) if el[0] == 2 => Some(PlanarConfiguration::Planar),
+        let val = match value {
+            TIFFValue::Short(el) => *el.first()?,
+            _ => return None,
+        };
+
+        match val {
+            1 => Some(PlanarConfiguration::Chunky),
+            2 => Some(PlanarConfiguration::Planar),
             _ => None,
         }
     }
@@ -537,9 +954,14 @@ impl Field for Predictor {
     }
 
     fn decode_from_value(value: &TIFFValue) -> Option<Predictor> {
-        match value {
-            TIFFValue::Short(el) if el[0] == 1 => Some(Predictor::None),
-            TIFFValue::Short(el) if el[0] == 2 => Some(Predictor::HorizontalDifferencing),
+        let val = match value {
+            TIFFValue::Short(el) => *el.first()?,
+            _ => return None,
+        };
+
+        match val {
+            1 => Some(Predictor::None),
+            2 => Some(Predictor::HorizontalDifferencing),
             _ => None,
         }
     }
@@ -567,10 +989,15 @@ impl Field for SubfileType {
     }
 
     fn decode_from_value(value: &TIFFValue) -> Option<SubfileType> {
-        match value {
-            TIFFValue::Short(el) if el[0] == 1 => Some(SubfileType::FullResolutionImage),
-            TIFFValue::Short(el) if el[0] == 2 => Some(SubfileType::ReducedResolutionImage),
-            TIFFValue::Short(el) if el[3] == 3 => Some(SubfileType::SinglePageImage),
+        let val = match value {
+            TIFFValue::Short(el) => *el.first()?,
+            _ => return None,
+        };
+
+        match val {
+            1 => Some(SubfileType::FullResolutionImage),
+            2 => Some(SubfileType::ReducedResolutionImage),
+            3 => Some(SubfileType::SinglePageImage),
             _ => None,
         }
     }
@@ -606,10 +1033,23 @@ impl NewSubfileType {
 }
 
 /// Data can be stored either compressed or uncompressed.
+#[derive(Debug, Eq, PartialEq)]
 pub enum Compression {
     NoCompression,
     ModifiedHuffmanCompression,
+    CCITTGroup3Fax,
+    CCITTGroup4Fax,
+    LZW,
+    OldJpeg,
+    Jpeg,
+    AdobeDeflate,
     PackBits,
+    Deflate,
+    LZMA2,
+    Zstd,
+    WebP,
+    /// A scheme without a variant of its own.
+    Unknown(u16),
 }
 
 impl Field for Compression {
@@ -618,11 +1058,26 @@ impl Field for Compression {
     }
 
     fn decode_from_value(value: &TIFFValue) -> Option<Compression> {
-        match value {
-            TIFFValue::Short(val) if val[0] == 1 => Some(Compression::NoCompression),
-            TIFFValue::Short(val) if val[0] == 2 => Some(Compression::ModifiedHuffmanCompression),
-            TIFFValue::Short(val) if val[0] == 32773 => Some(Compression::PackBits),
-            _ => None,
+        let val = match value {
+            TIFFValue::Short(val) => *val.first()?,
+            _ => return None,
+        };
+
+        match val {
+            1 => Some(Compression::NoCompression),
+            2 => Some(Compression::ModifiedHuffmanCompression),
+            3 => Some(Compression::CCITTGroup3Fax),
+            4 => Some(Compression::CCITTGroup4Fax),
+            5 => Some(Compression::LZW),
+            6 => Some(Compression::OldJpeg),
+            7 => Some(Compression::Jpeg),
+            8 => Some(Compression::AdobeDeflate),
+            32773 => Some(Compression::PackBits),
+            32946 => Some(Compression::Deflate),
+            34925 => Some(Compression::LZMA2),
+            50000 => Some(Compression::Zstd),
+            50001 => Some(Compression::WebP),
+            other => Some(Compression::Unknown(other)),
         }
     }
 
@@ -630,7 +1085,18 @@ impl Field for Compression {
         let value = match self {
             Compression::NoCompression => 1,
             Compression::ModifiedHuffmanCompression => 2,
+            Compression::CCITTGroup3Fax => 3,
+            Compression::CCITTGroup4Fax => 4,
+            Compression::LZW => 5,
+            Compression::OldJpeg => 6,
+            Compression::Jpeg => 7,
+            Compression::AdobeDeflate => 8,
             Compression::PackBits => 32773,
+            Compression::Deflate => 32946,
+            Compression::LZMA2 => 34925,
+            Compression::Zstd => 50000,
+            Compression::WebP => 50001,
+            Compression::Unknown(value) => *value,
         };
 
         Some(TIFFValue::Short(vec![value]))
@@ -643,8 +1109,38 @@ ascii_value! {
     Tag::Software
 }
 
+/// Date and time of image creation.
+///
+/// TIFF datetimes have no time zone; they are read with a zero offset, and
+/// written in local time whatever their offset.
 pub struct DateTime(pub chrono::DateTime<chrono::FixedOffset>);
 
+/// The format of `DateTime` values, `YYYY:MM:DD HH:MM:SS`.
+const DATE_TIME_FORMAT: &str = "%Y:%m:%d %H:%M:%S";
+
+impl DateTime {
+    /// The current local date and time.
+    pub fn now() -> DateTime {
+        DateTime::from_naive(chrono::Local::now().naive_local())
+    }
+
+    /// A date and time without time zone, given a zero offset.
+    pub fn from_naive(time: chrono::NaiveDateTime) -> DateTime {
+        DateTime(time.and_utc().fixed_offset())
+    }
+
+    /// The date and time as written in the file, without its offset.
+    pub fn naive(&self) -> chrono::NaiveDateTime {
+        self.0.naive_local()
+    }
+}
+
+impl From<chrono::NaiveDateTime> for DateTime {
+    fn from(time: chrono::NaiveDateTime) -> DateTime {
+        DateTime::from_naive(time)
+    }
+}
+
 impl Field for DateTime {
     fn tag() -> Tag {
         Tag::DateTime
@@ -653,15 +1149,15 @@ impl Field for DateTime {
     fn decode_from_value(value: &TIFFValue) -> Option<DateTime> {
         match value {
             TIFFValue::Ascii(val) => {
-                let time = chrono::DateTime::parse_from_str(&val[0], "%Y:%m:%d %H:%M:%S").ok()?;
-                Some(DateTime(time))
+                let time = chrono::NaiveDateTime::parse_from_str(val.first()?, DATE_TIME_FORMAT).ok()?;
+                Some(DateTime::from_naive(time))
             }
             _ => None,
         }
     }
 
     fn encode_to_value(&self) -> Option<TIFFValue> {
-        Some(TIFFValue::Ascii(vec![self.0.to_string()]))
+        Some(TIFFValue::Ascii(vec![self.0.format(DATE_TIME_FORMAT).to_string()]))
     }
 }
 
@@ -685,7 +1181,7 @@ short_value!{
 /// according to the 0th Red, Green, Blue triplet.
 /// In a TIFF ColorMap, all the Red values come first, followed by the Green values, then the Blue values.
 /// In the ColorMap, black is represented by 0,0,0 and white is represented by 65535, 65535, 65535.
-pub struct ColorMap(Vec<u16>);
+pub struct ColorMap(pub Vec<u16>);
 
 impl Field for ColorMap {
     fn tag() -> Tag {
@@ -712,12 +1208,12 @@ pub enum ExtraSampleDataValue {
 }
 
 impl ExtraSampleDataValue {
-    fn from_value(value: u16) -> ExtraSampleDataValue {
+    fn from_value(value: u16) -> Option<ExtraSampleDataValue> {
         match value {
-            0 => ExtraSampleDataValue::Unspecified,
-            1 => ExtraSampleDataValue::AssociatedAlpha,
-            2 => ExtraSampleDataValue::UnassociatedAlpha,
-            _ => panic!("Invalid ExtraSampleDataValue"),
+            0 => Some(ExtraSampleDataValue::Unspecified),
+            1 => Some(ExtraSampleDataValue::AssociatedAlpha),
+            2 => Some(ExtraSampleDataValue::UnassociatedAlpha),
+            _ => None,
         }
     }
 
@@ -725,7 +1221,7 @@ impl ExtraSampleDataValue {
         match self {
             ExtraSampleDataValue::Unspecified => 0,
             ExtraSampleDataValue::AssociatedAlpha => 1,
-            ExtraSampleDataValue::UnassociatedAlpha => 3,
+            ExtraSampleDataValue::UnassociatedAlpha => 2,
         }
     }
 }
@@ -735,6 +1231,12 @@ ascii_value! {
       Copyright,
       Tag::Copyright
 }
+
+ascii_value! {
+    #[doc = "The value of the samples holding no data, such as `-9999` or `nan` (GDAL)."]
+    GdalNoData,
+    Tag::GdalNoData
+}
 /// Description of extra components.
 ///
 /// Specifies that each pixel has m extra components whose interpretation is defined by one of the values l
@@ -743,7 +1245,7 @@ ascii_value! {
 /// For example, full-color RGB data normally has SamplesPerPixel=3.
 /// If SamplesPerPixel is greater than 3, then the ExtraSamples field describes the meaning of the extra samples.
 /// If SamplesPerPixel is, say, 5 then ExtraSamples will contain 2 values, one for each extra sample.
-struct ExtraSamples(pub Vec<ExtraSampleDataValue>);
+pub struct ExtraSamples(pub Vec<ExtraSampleDataValue>);
 
 impl Field for ExtraSamples {
     fn tag() -> Tag {
@@ -759,7 +1261,7 @@ impl Field for ExtraSamples {
         let values: Vec<ExtraSampleDataValue> = raw
             .iter()
             .map(|e| ExtraSampleDataValue::from_value(*e))
-            .collect();
+            .collect::<Option<_>>()?;
         Some(ExtraSamples(values))
     }
 
@@ -783,9 +1285,14 @@ impl Field for FillOrder {
     }
 
     fn decode_from_value(value: &TIFFValue) -> Option<FillOrder> {
-        match value {
-            TIFFValue::Short(e) if e[0] == 1 => Some(FillOrder::LowerColumnsToHigherOrderBits),
-            TIFFValue::Short(e) if e[0] == 2 => Some(FillOrder::LowerColumnsToLowerOrderBits),
+        let val = match value {
+            TIFFValue::Short(e) => *e.first()?,
+            _ => return None,
+        };
+
+        match val {
+            1 => Some(FillOrder::LowerColumnsToHigherOrderBits),
+            2 => Some(FillOrder::LowerColumnsToLowerOrderBits),
             _ => None,
         }
     }
@@ -844,12 +1351,17 @@ impl Field for GrayResponseUnit {
     }
 
     fn decode_from_value(value: &TIFFValue) -> Option<GrayResponseUnit> {
-        match value {
-            TIFFValue::Short(e) if e[0] == 1 => Some(GrayResponseUnit::TenthsOfUnit),
-            TIFFValue::Short(e) if e[0] == 2 => Some(GrayResponseUnit::HundredthsOfUnit),
-            TIFFValue::Short(e) if e[0] == 3 => Some(GrayResponseUnit::ThousandthsOfUnit),
-            TIFFValue::Short(e) if e[0] == 4 => Some(GrayResponseUnit::TenThousandthsOfUnit),
-            TIFFValue::Short(e) if e[0] == 5 => Some(GrayResponseUnit::HundredThousandthsOfUnit),
+        let val = match value {
+            TIFFValue::Short(e) => *e.first()?,
+            _ => return None,
+        };
+
+        match val {
+            1 => Some(GrayResponseUnit::TenthsOfUnit),
+            2 => Some(GrayResponseUnit::HundredthsOfUnit),
+            3 => Some(GrayResponseUnit::ThousandthsOfUnit),
+            4 => Some(GrayResponseUnit::TenThousandthsOfUnit),
+            5 => Some(GrayResponseUnit::HundredThousandthsOfUnit),
             _ => None,
         }
     }
@@ -928,7 +1440,7 @@ impl Field for Orientation {
 
     fn decode_from_value(value: &TIFFValue) -> Option<Orientation> {
         let val = match value {
-            TIFFValue::Short(v) => v[0],
+            TIFFValue::Short(v) => *v.first()?,
             _ => return None,
         };
 
@@ -1041,7 +1553,7 @@ impl Field for InkSet {
 
     fn decode_from_value(value: &TIFFValue) -> Option<InkSet> {
         let val = match value {
-            TIFFValue::Short(val) => val[0],
+            TIFFValue::Short(val) => *val.first()?,
             _ => return None,
         };
 
@@ -1085,3 +1597,228 @@ ascii_value! {
     TargetPrinter,
     Tag::TargetPrinter
 }
+
+/// How the data of a sample is interpreted.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SampleFormatValue {
+    UnsignedInteger,
+    SignedInteger,
+    IEEEFloatingPoint,
+    Undefined,
+}
+
+impl SampleFormatValue {
+    fn from_value(value: u16) -> Option<SampleFormatValue> {
+        match value {
+            1 => Some(SampleFormatValue::UnsignedInteger),
+            2 => Some(SampleFormatValue::SignedInteger),
+            3 => Some(SampleFormatValue::IEEEFloatingPoint),
+            4 => Some(SampleFormatValue::Undefined),
+            _ => None,
+        }
+    }
+
+    fn to_value(self) -> u16 {
+        match self {
+            SampleFormatValue::UnsignedInteger => 1,
+            SampleFormatValue::SignedInteger => 2,
+            SampleFormatValue::IEEEFloatingPoint => 3,
+            SampleFormatValue::Undefined => 4,
+        }
+    }
+}
+
+/// The format of each sample of a pixel.
+///
+/// The specification allows a different format for each sample, although
+/// many files store a single value for all of them.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SampleFormat(pub Vec<SampleFormatValue>);
+
+impl SampleFormat {
+    /// The format of sample `index`, the single value stored applying to
+    /// every sample.
+    pub fn get(&self, index: usize) -> Option<SampleFormatValue> {
+        match self.0.len() {
+            1 => self.0.first().cloned(),
+            _ => self.0.get(index).cloned(),
+        }
+    }
+}
+
+impl Default for SampleFormat {
+    fn default() -> SampleFormat {
+        SampleFormat(vec![SampleFormatValue::UnsignedInteger])
+    }
+}
+
+impl Field for SampleFormat {
+    fn tag() -> Tag {
+        Tag::SampleFormat
+    }
+
+    fn decode_from_value(value: &TIFFValue) -> Option<SampleFormat> {
+        let raw = match value {
+            TIFFValue::Short(e) => e,
+            _ => return None,
+        };
+
+        let values: Vec<SampleFormatValue> = raw
+            .iter()
+            .map(|e| SampleFormatValue::from_value(*e))
+            .collect::<Option<_>>()?;
+        Some(SampleFormat(values))
+    }
+
+    fn encode_to_value(&self) -> Option<TIFFValue> {
+        let values = self.0.iter().map(|e| e.to_value()).collect();
+        Some(TIFFValue::Short(values))
+    }
+}
+
+macro_rules! sample_range_value {
+    ($(#[$attr:meta])* $type:ident, $tag:expr) => {
+        $(#[$attr])*
+        ///
+        /// The values may be of any numeric type, usually the type of the
+        /// samples, and are kept as stored.
+        #[derive(Debug, Clone)]
+        pub struct $type(pub TIFFValue);
+
+        impl $type {
+            /// The values, one per sample, converted to `f64`.
+            pub fn values(&self) -> Vec<f64> {
+                self.0.as_f64().unwrap_or_default()
+            }
+
+            /// The value of sample `index` converted to `f64`, the single
+            /// value stored applying to every sample.
+            pub fn get(&self, index: usize) -> Option<f64> {
+                let values = self.values();
+                match values.len() {
+                    1 => values.first().cloned(),
+                    _ => values.get(index).cloned(),
+                }
+            }
+        }
+
+        impl Field for $type {
+            fn tag() -> Tag {
+                $tag
+            }
+
+            fn decode_from_value(value: &TIFFValue) -> Option<$type> {
+                value.as_f64()?;
+                Some($type(value.clone()))
+            }
+
+            fn encode_to_value(&self) -> Option<TIFFValue> {
+                self.0.as_f64()?;
+                Some(self.0.clone())
+            }
+        }
+    };
+}
+
+sample_range_value! {
+    #[doc = "The minimum value of each sample, in the format of the samples."]
+    SMinSampleValue,
+    Tag::SMinSampleValue
+}
+
+sample_range_value! {
+    #[doc = "The maximum value of each sample, in the format of the samples."]
+    SMaxSampleValue,
+    Tag::SMaxSampleValue
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Timelike;
+    use reader::TIFFReader;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_tag_metadata() {
+        assert_eq!(Tag::ImageWidth.code(), 0x100);
+        assert_eq!(Tag::ImageWidth.name(), Some("ImageWidth"));
+        assert_eq!(Tag::Make.description(), Some("The scanner manufacturer."));
+        assert_eq!(Tag::Unknown(0xabcd).name(), None);
+        assert_eq!(Tag::Unknown(0xabcd).value_count(), None);
+        assert_eq!(Tag::BitsPerSample.value_count(), Some(ValueCount::PerSample));
+        assert_eq!(Tag::Software.value_count(), Some(ValueCount::Variable));
+        assert_eq!(Tag::Orientation.value_count(), Some(ValueCount::Fixed(1)));
+        assert_eq!(FieldType::from_id(FieldType::SRational.id()), Some(FieldType::SRational));
+        assert_eq!(Tag::from(0x829a), Tag::ExposureTime);
+        assert_eq!(Tag::LensModel.code(), 0xa434);
+        assert_eq!(Tag::ExposureBiasValue.value_types(), [FieldType::SRational]);
+        assert_eq!(Tag::ExifVersion.value_count(), Some(ValueCount::Fixed(4)));
+        assert_eq!(Tag::LensMake.value_count(), Some(ValueCount::Variable));
+
+        // The entries of the samples follow the specification
+        for sample in &[
+            &include_bytes!("../samples/picoawards_le.tiff")[..],
+            &include_bytes!("../samples/arbitro_be.tiff")[..],
+        ] {
+            let read = TIFFReader::new(Cursor::new(sample)).unwrap();
+            for entry in read.ifds()[0].entries() {
+                let field_type = FieldType::from_id(entry.value_type).unwrap();
+                let types = entry.tag.value_types();
+                assert!(types.is_empty() || types.contains(&field_type), "{:?}", entry);
+                if let Some(ValueCount::Fixed(count)) = entry.tag.value_count() {
+                    assert_eq!(entry.count, count, "{:?}", entry);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_date_time() {
+        let naive = chrono::NaiveDate::from_ymd_opt(2001, 2, 3)
+            .and_then(|date| date.and_hms_opt(4, 5, 6))
+            .unwrap();
+        let value = DateTime::from(naive).encode_to_value().unwrap();
+        match value {
+            TIFFValue::Ascii(ref strings) => assert_eq!(strings[0], "2001:02:03 04:05:06"),
+            _ => panic!("DateTime is not ASCII"),
+        }
+        assert_eq!(DateTime::decode_from_value(&value).unwrap().naive(), naive);
+
+        let now = DateTime::now();
+        let value = now.encode_to_value().unwrap();
+        let read = DateTime::decode_from_value(&value).unwrap();
+        assert_eq!(read.naive(), now.naive().with_nanosecond(0).unwrap());
+    }
+
+    #[test]
+    fn test_sample_format() {
+        let value = TIFFValue::Short(vec![1, 3, 2]);
+        let format = SampleFormat::decode_from_value(&value).unwrap();
+        assert_eq!(format.get(1), Some(SampleFormatValue::IEEEFloatingPoint));
+        assert_eq!(format.get(3), None);
+        assert!(SampleFormat::decode_from_value(&TIFFValue::Short(vec![1, 5])).is_none());
+        let single = SampleFormat(vec![SampleFormatValue::SignedInteger]);
+        assert_eq!(single.get(2), Some(SampleFormatValue::SignedInteger));
+        assert_eq!(single.encode_to_value().unwrap().as_unsigned(), Some(vec![2]));
+
+        let min = SMinSampleValue::decode_from_value(&TIFFValue::SShort(vec![-3, 0])).unwrap();
+        assert_eq!(min.values(), [-3.0, 0.0]);
+        let max = SMaxSampleValue(TIFFValue::Float(vec![1.5]));
+        assert_eq!(max.get(2), Some(1.5));
+        assert!(SMaxSampleValue::decode_from_value(&TIFFValue::Ascii(vec![])).is_none());
+    }
+
+    #[test]
+    fn test_compression() {
+        for (code, compression) in &[
+            (50000, Compression::Zstd),
+            (34925, Compression::LZMA2),
+            (65000, Compression::Unknown(65000)),
+        ] {
+            let value = TIFFValue::Short(vec![*code]);
+            assert_eq!(Compression::decode_from_value(&value).as_ref(), Some(compression));
+            assert_eq!(compression.encode_to_value().unwrap().as_unsigned(), Some(vec![u64::from(*code)]));
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! This module reduces images written by `TIFFWriter` into small previews,
+//! stored as reduced-resolution subfiles.
+use tag::*;
+use value::TIFFValue;
+use writer::Directory;
+
+/// Fields describing the samples, carried over to the thumbnail.
+const SAMPLE_TAGS: [Tag; 6] = [
+    Tag::PhotometricInterpretation,
+    Tag::SamplesPerPixel,
+    Tag::BitsPerSample,
+    Tag::ExtraSamples,
+    Tag::SampleFormat,
+    Tag::ColorMap,
+];
+
+/// Reduces the image of `directory`, stored in `data` as uncompressed
+/// strips, so that it fits in `max_size` × `max_size` pixels.
+///
+/// Returns the directory and the data of the thumbnail, or `None` when the
+/// image is already small enough, is itself a reduced image, or is not made
+/// of uncompressed chunky 8-bit samples.
+pub(crate) fn reduce(
+    directory: &Directory,
+    data: &[u8],
+    max_size: u32,
+) -> Option<(Directory, Vec<u8>)> {
+    let width = directory.get_field::<ImageWidth>()?.0;
+    let length = directory.get_field::<ImageLength>()?.0;
+    let reduced = directory
+        .get_field::<NewSubfileType>()
+        .is_some_and(|subfile| subfile.is_reduced_image());
+    let compressed = directory
+        .get(Tag::Compression)
+        .and_then(|value| value.unsigned_at(0))
+        .is_some_and(|compression| compression != 1);
+    let planar =
+        directory.get_field::<PlanarConfiguration>() == Some(PlanarConfiguration::Planar);
+    let predictor =
+        directory.get_field::<Predictor>() == Some(Predictor::HorizontalDifferencing);
+    let tiled = directory.has_field(Tag::TileWidth);
+    if reduced || compressed || planar || predictor || tiled {
+        return None;
+    }
+    let bits = directory.get_field::<BitsPerSample>()?.0;
+    if max_size == 0 || width.max(length) <= max_size || bits.iter().any(|b| *b != 8) {
+        return None;
+    }
+    let samples = bits.len();
+    let row_bytes = width as usize * samples;
+    if data.len() < row_bytes * length as usize {
+        return None;
+    }
+
+    // Palette indices cannot be averaged
+    let average = directory.get_field::<PhotometricInterpretation>()
+        != Some(PhotometricInterpretation::PaletteColor);
+    let scale = width.max(length).div_ceil(max_size);
+    let (thumb_width, thumb_length) = (width.div_ceil(scale), length.div_ceil(scale));
+    let mut thumbnail = Vec::with_capacity(thumb_width as usize * thumb_length as usize * samples);
+    for y in 0..thumb_length {
+        let rows = y * scale..((y + 1) * scale).min(length);
+        for x in 0..thumb_width {
+            let columns = x * scale..((x + 1) * scale).min(width);
+            for sample in 0..samples {
+                let value = |row: u32, column: u32| {
+                    u32::from(data[row as usize * row_bytes + column as usize * samples + sample])
+                };
+                if !average {
+                    thumbnail.push(value(rows.start, columns.start) as u8);
+                    continue;
+                }
+                let mut sum = 0;
+                for row in rows.clone() {
+                    sum += columns.clone().map(|column| value(row, column)).sum::<u32>();
+                }
+                let count = rows.len() as u32 * columns.len() as u32;
+                thumbnail.push(((sum + count / 2) / count) as u8);
+            }
+        }
+    }
+
+    let mut reduced = Directory::new();
+    for tag in &SAMPLE_TAGS {
+        if let Some(value) = directory.get(*tag) {
+            reduced.set(*tag, value.clone());
+        }
+    }
+    reduced.set_field(&NewSubfileType(1));
+    reduced.set_field(&ImageWidth(thumb_width));
+    reduced.set_field(&ImageLength(thumb_length));
+    reduced.set_field(&RowsPerStrip(thumb_length));
+    reduced.set(Tag::StripByteCounts, TIFFValue::Long(vec![thumbnail.len() as u32]));
+    Some((reduced, thumbnail))
+}
@@ -0,0 +1,396 @@
+//! Conformance checks against the baseline TIFF 6.0 specification.
+use std::io::{Read, Seek};
+
+use error::Result;
+use reader::TIFFReader;
+use tag::*;
+
+/// How serious a conformance issue is.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    /// The directory violates the specification.
+    Error,
+    /// The directory is readable, but relies on extensions or unusual values.
+    Warning,
+}
+
+/// A conformance issue found in a directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// Index of the directory the issue was found in.
+    pub directory: usize,
+    pub severity: Severity,
+    /// The field the issue relates to, if any.
+    pub tag: Option<Tag>,
+    pub message: String,
+}
+
+/// The issues found by `validate`.
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    /// Returns `true` when no error was found.
+    pub fn is_conformant(&self) -> bool {
+        self.issues.iter().all(|i| i.severity != Severity::Error)
+    }
+
+    fn push(&mut self, directory: usize, severity: Severity, tag: Tag, message: String) {
+        self.issues.push(Issue {
+            directory,
+            severity,
+            tag: Some(tag),
+            message,
+        });
+    }
+}
+
+/// Checks every directory of `reader` for baseline conformance: required fields
+/// per photometric class, value ranges, strip layout consistency and color map sizing.
+///
+/// The current directory of the reader is left unchanged.
+pub fn validate<R: Read + Seek>(reader: &mut TIFFReader<R>) -> Result<ValidationReport> {
+    let current = reader.directory_index();
+    let mut report = ValidationReport::default();
+
+    for directory in 0..reader.ifds().len() {
+        reader.select_directory(directory)?;
+        validate_directory(reader, directory, &mut report);
+    }
+
+    reader.select_directory(current)?;
+    Ok(report)
+}
+
+fn validate_directory<R: Read + Seek>(
+    reader: &mut TIFFReader<R>,
+    directory: usize,
+    report: &mut ValidationReport,
+) {
+    let has_field = |reader: &TIFFReader<R>, tag: Tag| {
+        reader.ifds()[directory].get_entry_from_tag(tag).is_some()
+    };
+
+    let photometric = reader.get_field::<PhotometricInterpretation>();
+    let tiled = has_field(reader, Tag::TileOffsets);
+
+    // Required fields without a default value
+    let mut required = vec![
+        Tag::ImageWidth,
+        Tag::ImageLength,
+        Tag::PhotometricInterpretation,
+        Tag::XResolution,
+        Tag::YResolution,
+    ];
+    if tiled {
+        required.extend_from_slice(&[
+            Tag::TileWidth,
+            Tag::TileLength,
+            Tag::TileOffsets,
+            Tag::TileByteCounts,
+        ]);
+    } else {
+        required.extend_from_slice(&[Tag::StripOffsets, Tag::StripByteCounts]);
+    }
+    match photometric {
+        Some(PhotometricInterpretation::RGB)
+        | Some(PhotometricInterpretation::CMYK)
+        | Some(PhotometricInterpretation::YCbCr) => {
+            required.extend_from_slice(&[Tag::BitsPerSample, Tag::SamplesPerPixel])
+        }
+        Some(PhotometricInterpretation::PaletteColor) => {
+            required.extend_from_slice(&[Tag::BitsPerSample, Tag::ColorMap])
+        }
+        _ => {}
+    }
+
+    for tag in required {
+        if !has_field(reader, tag) {
+            report.push(directory, Severity::Error, tag, "Required field is missing".into());
+        }
+    }
+
+    if has_field(reader, Tag::PhotometricInterpretation) && photometric.is_none() {
+        report.push(
+            directory,
+            Severity::Error,
+            Tag::PhotometricInterpretation,
+            "Unknown photometric interpretation".into(),
+        );
+    }
+
+    let baseline = matches!(
+        reader.get_field::<Compression>(),
+        Some(Compression::NoCompression)
+            | Some(Compression::ModifiedHuffmanCompression)
+            | Some(Compression::PackBits)
+    );
+    if has_field(reader, Tag::Compression) && !baseline {
+        report.push(
+            directory,
+            Severity::Warning,
+            Tag::Compression,
+            "Compression scheme is not part of baseline TIFF".into(),
+        );
+    }
+
+    let width = reader.get_field::<ImageWidth>().map(|e| e.0);
+    let length = reader.get_field::<ImageLength>().map(|e| e.0);
+    if width == Some(0) {
+        report.push(directory, Severity::Error, Tag::ImageWidth, "Image width is zero".into());
+    }
+    if length == Some(0) {
+        report.push(directory, Severity::Error, Tag::ImageLength, "Image length is zero".into());
+    }
+
+    let samples = reader
+        .get_field::<SamplesPerPixel>()
+        .unwrap_or_default()
+        .0;
+    let bits = reader
+        .get_field::<BitsPerSample>()
+        .map(|e| e.0)
+        .unwrap_or_else(|| vec![1; usize::from(samples)]);
+
+    if bits.len() != usize::from(samples) {
+        report.push(
+            directory,
+            Severity::Error,
+            Tag::BitsPerSample,
+            format!(
+                "{} BitsPerSample values for {} samples per pixel",
+                bits.len(),
+                samples
+            ),
+        );
+    }
+
+    match photometric {
+        Some(PhotometricInterpretation::RGB) => {
+            if samples < 3 {
+                report.push(
+                    directory,
+                    Severity::Error,
+                    Tag::SamplesPerPixel,
+                    "RGB images need at least 3 samples per pixel".into(),
+                );
+            }
+            if bits.iter().any(|b| *b != 8) {
+                report.push(
+                    directory,
+                    Severity::Warning,
+                    Tag::BitsPerSample,
+                    "Baseline RGB images use 8 bits per sample".into(),
+                );
+            }
+        }
+        Some(PhotometricInterpretation::PaletteColor) => {
+            let depth = bits.first().cloned().unwrap_or(1);
+            if depth != 4 && depth != 8 {
+                report.push(
+                    directory,
+                    Severity::Error,
+                    Tag::BitsPerSample,
+                    "Palette images use 4 or 8 bits per sample".into(),
+                );
+            } else if let Some(color_map) = reader.get_field::<ColorMap>() {
+                let expected = 3 << depth;
+                if color_map.0.len() != expected {
+                    report.push(
+                        directory,
+                        Severity::Error,
+                        Tag::ColorMap,
+                        format!(
+                            "ColorMap has {} values, expected {}",
+                            color_map.0.len(),
+                            expected
+                        ),
+                    );
+                }
+            }
+        }
+        Some(PhotometricInterpretation::WhiteIsZero)
+        | Some(PhotometricInterpretation::BlackIsZero)
+            if bits.iter().any(|b| *b != 1 && *b != 4 && *b != 8) =>
+        {
+            report.push(
+                directory,
+                Severity::Warning,
+                Tag::BitsPerSample,
+                "Baseline grayscale images use 1, 4 or 8 bits per sample".into(),
+            );
+        }
+        _ => {}
+    }
+
+    if !tiled {
+        if let (Some(width), Some(length)) = (width, length) {
+            validate_strips(reader, directory, width, length, &bits, report);
+        }
+    }
+}
+
+fn validate_strips<R: Read + Seek>(
+    reader: &mut TIFFReader<R>,
+    directory: usize,
+    width: u32,
+    length: u32,
+    bits: &[u16],
+    report: &mut ValidationReport,
+) {
+    // Already reported, and leaving no strip layout to check
+    if width == 0 || length == 0 {
+        return;
+    }
+    let offsets = match reader.get_field::<StripOffsets>() {
+        Some(offsets) => offsets.0,
+        None => return,
+    };
+    let counts = match reader.get_field::<StripByteCounts>() {
+        Some(counts) => counts.0,
+        None => return,
+    };
+
+    if offsets.len() != counts.len() {
+        report.push(
+            directory,
+            Severity::Error,
+            Tag::StripByteCounts,
+            format!(
+                "{} StripByteCounts values for {} strips",
+                counts.len(),
+                offsets.len()
+            ),
+        );
+        return;
+    }
+
+    let rows_per_strip = reader
+        .get_field::<RowsPerStrip>()
+        .map_or(u32::MAX, |e| e.0)
+        .max(1)
+        .min(length);
+    let planar = reader.get_field::<PlanarConfiguration>() == Some(PlanarConfiguration::Planar);
+    let planes = if planar { bits.len() } else { 1 };
+    let strips_per_plane = u64::from(length).div_ceil(u64::from(rows_per_strip));
+
+    let expected_strips = strips_per_plane * planes as u64;
+    if offsets.len() as u64 != expected_strips {
+        report.push(
+            directory,
+            Severity::Error,
+            Tag::StripOffsets,
+            format!(
+                "{} strips, expected {} from RowsPerStrip",
+                offsets.len(),
+                expected_strips
+            ),
+        );
+        return;
+    }
+
+    // Only uncompressed data has a predictable size
+    let uncompressed = match reader.get_field::<Compression>() {
+        Some(Compression::NoCompression) => true,
+        Some(_) => false,
+        None => reader.ifds()[directory]
+            .get_entry_from_tag(Tag::Compression)
+            .is_none(),
+    };
+    if !uncompressed {
+        return;
+    }
+
+    for (index, count) in counts.iter().enumerate() {
+        let plane_bits: u64 = if planar {
+            u64::from(bits[index / strips_per_plane as usize])
+        } else {
+            bits.iter().map(|b| u64::from(*b)).sum()
+        };
+        let row_bytes = (u64::from(width) * plane_bits).div_ceil(8);
+        let first_row = (index as u64 % strips_per_plane) * u64::from(rows_per_strip);
+        let rows = u64::from(rows_per_strip).min(u64::from(length) - first_row);
+
+        if u64::from(*count) < rows * row_bytes {
+            report.push(
+                directory,
+                Severity::Error,
+                Tag::StripByteCounts,
+                format!(
+                    "Strip {} has {} bytes, expected {}",
+                    index,
+                    count,
+                    rows * row_bytes
+                ),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::tests::{le_header, push_ifd};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_samples_conformance() {
+        let bytes: &[u8] = include_bytes!("../samples/picoawards_le.tiff");
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        let report = validate(&mut read).unwrap();
+        assert!(report.is_conformant(), "{:?}", report);
+    }
+
+    #[test]
+    fn test_invalid_directory() {
+        // Uncompressed 4x4 RGB with a 2 bytes strip and no resolution
+        let mut bytes = le_header();
+        let entries = [
+            (256, 3, 1, 4),
+            (257, 3, 1, 4),
+            (262, 3, 1, 2),
+            (273, 4, 1, 8),
+            (277, 3, 1, 3),
+            (279, 4, 1, 2),
+        ];
+        push_ifd(&mut bytes, &entries, 0);
+
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        let report = validate(&mut read).unwrap();
+        assert!(!report.is_conformant());
+
+        let tags: Vec<Option<Tag>> = report.issues.iter().map(|i| i.tag).collect();
+        assert!(tags.contains(&Some(Tag::XResolution)));
+        assert!(tags.contains(&Some(Tag::BitsPerSample)));
+        assert!(tags.contains(&Some(Tag::StripByteCounts)));
+    }
+
+    #[test]
+    fn test_malformed_strips() {
+        // Zero length
+        let mut bytes = le_header();
+        push_ifd(&mut bytes, &[(256, 3, 1, 4), (257, 3, 1, 0), (273, 4, 1, 8), (279, 4, 1, 4)], 0);
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        let report = validate(&mut read).unwrap();
+        assert!(report.issues.iter().any(|i| i.tag == Some(Tag::ImageLength)));
+
+        // Planar, with more byte counts than offsets
+        let mut bytes = le_header();
+        let entries = [
+            (256, 3, 1, 4),
+            (257, 3, 1, 1),
+            (258, 3, 2, 0x0008_0008),
+            (273, 3, 2, 0x0008_0008),
+            (277, 3, 1, 2),
+            (279, 3, 4, 98),
+            (284, 3, 1, 2),
+        ];
+        push_ifd(&mut bytes, &entries, 0);
+        bytes.extend_from_slice(&[4, 0, 4, 0, 4, 0, 4, 0]);
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        let report = validate(&mut read).unwrap();
+        assert!(report.issues.iter().any(|i| i.tag == Some(Tag::StripByteCounts)));
+    }
+}
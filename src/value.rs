@@ -1,4 +1,5 @@
 use endian::Long;
+use std::fmt::{self, Display, Formatter};
 
 /// A generic rational helper struct
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -9,7 +10,7 @@ pub struct Rational<T: Long> {
 
 /// A `TIFFValue` represents the primitives stores inside the
 /// TIFF file format
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TIFFValue {
     Byte(Vec<u8>),
     Ascii(Vec<String>),
@@ -24,3 +25,105 @@ pub enum TIFFValue {
     Float(Vec<f32>),
     Double(Vec<f64>),
 }
+
+impl TIFFValue {
+    /// Returns the elements widened to `u64` for unsigned integer values.
+    pub fn as_unsigned(&self) -> Option<Vec<u64>> {
+        match self {
+            TIFFValue::Byte(el) => Some(el.iter().map(|e| u64::from(*e)).collect()),
+            TIFFValue::Short(el) => Some(el.iter().map(|e| u64::from(*e)).collect()),
+            TIFFValue::Long(el) => Some(el.iter().map(|e| u64::from(*e)).collect()),
+            _ => None,
+        }
+    }
+
+    /// Returns the elements converted to `f64` for numeric values, rationals
+    /// being divided out.
+    pub fn as_f64(&self) -> Option<Vec<f64>> {
+        match self {
+            TIFFValue::Byte(el) => Some(el.iter().map(|e| f64::from(*e)).collect()),
+            TIFFValue::Short(el) => Some(el.iter().map(|e| f64::from(*e)).collect()),
+            TIFFValue::Long(el) => Some(el.iter().map(|e| f64::from(*e)).collect()),
+            TIFFValue::SByte(el) => Some(el.iter().map(|e| f64::from(*e)).collect()),
+            TIFFValue::SShort(el) => Some(el.iter().map(|e| f64::from(*e)).collect()),
+            TIFFValue::SLong(el) => Some(el.iter().map(|e| f64::from(*e)).collect()),
+            TIFFValue::Float(el) => Some(el.iter().map(|e| f64::from(*e)).collect()),
+            TIFFValue::Double(el) => Some(el.clone()),
+            TIFFValue::Rational(el) => Some(
+                el.iter()
+                    .map(|e| f64::from(e.num) / f64::from(e.denom))
+                    .collect(),
+            ),
+            TIFFValue::SRational(el) => Some(
+                el.iter()
+                    .map(|e| f64::from(e.num) / f64::from(e.denom))
+                    .collect(),
+            ),
+            TIFFValue::Ascii(_) | TIFFValue::Undefined(_) => None,
+        }
+    }
+
+    /// Returns element `index` widened to `u64` for unsigned integer values.
+    pub fn unsigned_at(&self, index: usize) -> Option<u64> {
+        match self {
+            TIFFValue::Byte(el) => el.get(index).map(|e| u64::from(*e)),
+            TIFFValue::Short(el) => el.get(index).map(|e| u64::from(*e)),
+            TIFFValue::Long(el) => el.get(index).map(|e| u64::from(*e)),
+            _ => None,
+        }
+    }
+}
+
+impl<T: Long + Display> Display for Rational<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.num, self.denom)
+    }
+}
+
+/// Writes `elements` separated by spaces.
+fn write_elements<T: Display>(f: &mut Formatter, elements: &[T]) -> fmt::Result {
+    for (index, element) in elements.iter().enumerate() {
+        if index > 0 {
+            f.write_str(" ")?;
+        }
+        write!(f, "{}", element)?;
+    }
+    Ok(())
+}
+
+/// Writes the elements separated by spaces, strings being quoted.
+impl Display for TIFFValue {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TIFFValue::Byte(el) | TIFFValue::Undefined(el) => write_elements(f, el),
+            TIFFValue::Ascii(el) => {
+                let quoted: Vec<String> = el.iter().map(|s| format!("{:?}", s)).collect();
+                write_elements(f, &quoted)
+            }
+            TIFFValue::Short(el) => write_elements(f, el),
+            TIFFValue::Long(el) => write_elements(f, el),
+            TIFFValue::Rational(el) => write_elements(f, el),
+            TIFFValue::SByte(el) => write_elements(f, el),
+            TIFFValue::SShort(el) => write_elements(f, el),
+            TIFFValue::SLong(el) => write_elements(f, el),
+            TIFFValue::SRational(el) => write_elements(f, el),
+            TIFFValue::Float(el) => write_elements(f, el),
+            TIFFValue::Double(el) => write_elements(f, el),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(TIFFValue::Short(vec![8, 8, 8]).to_string(), "8 8 8");
+        let rational = Rational { num: 72, denom: 1 };
+        assert_eq!(TIFFValue::Rational(vec![rational]).to_string(), "72/1");
+        let strings = vec!["a \"b\"".to_string(), "c".to_string()];
+        assert_eq!(TIFFValue::Ascii(strings).to_string(), "\"a \\\"b\\\"\" \"c\"");
+        assert_eq!(TIFFValue::Double(vec![]).to_string(), "");
+    }
+}
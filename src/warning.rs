@@ -0,0 +1,19 @@
+//! Non fatal issues found while reading a file.
+use tag::Tag;
+
+/// A recoverable spec violation detected while parsing a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// The same tag appears several times in a directory.
+    DuplicateTag { directory: usize, tag: Tag },
+    /// The entries of a directory are not sorted in ascending tag order.
+    UnsortedEntries { directory: usize },
+    /// The `StripByteCounts` of an uncompressed strip differs from the size
+    /// of its rows. Extra bytes are ignored and missing ones read as zeros.
+    StripSizeMismatch {
+        directory: usize,
+        strip: usize,
+        expected: u64,
+        declared: u64,
+    },
+}
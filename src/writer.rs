@@ -0,0 +1,1374 @@
+//! This module handles writing TIFF files.
+use cancel::{self, CancellationToken};
+#[cfg(feature = "checksum")]
+use checksum::{ChecksumAlgorithm, ChecksumBuilder, ChecksumWriter, CHECKSUM_TAG};
+use endian::{Endian, EndianReader, EndianWriter};
+use error::{Result, TiffError};
+use options::StringEncoding;
+use reader::TIFFReader;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use tag::{Field, FieldType, Tag};
+use thumbnail;
+use value::TIFFValue;
+
+/// The fields of a directory to write, kept sorted by tag as the
+/// specification requires.
+#[derive(Debug, Clone, Default)]
+pub struct Directory {
+    fields: BTreeMap<u16, TIFFValue>,
+    raw: BTreeMap<u16, RawEntry>,
+    children: BTreeMap<u16, Directory>,
+}
+
+/// A field value already serialized, written as is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawEntry {
+    /// The type code of the entry, which may be outside of the specification.
+    pub value_type: u16,
+    /// The number of elements of the value.
+    pub count: u32,
+    /// The value, in the byte order of the file being written.
+    pub bytes: Vec<u8>,
+}
+
+impl RawEntry {
+    /// Checks the length of `bytes` against `count`, for the types of the
+    /// specification.
+    fn check(&self) -> io::Result<()> {
+        match FieldType::from_id(self.value_type).map(FieldType::size) {
+            Some(size) if u64::from(self.count) * size != self.bytes.len() as u64 => {
+                Err(invalid_input("Raw entry length does not match its count"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Directory {
+    /// Creates an empty directory.
+    pub fn new() -> Directory {
+        Directory::default()
+    }
+
+    /// The value of `tag`, if present.
+    pub fn get(&self, tag: Tag) -> Option<&TIFFValue> {
+        self.fields.get(&u16::from(tag))
+    }
+
+    /// Decodes the field `T`, if present and valid.
+    pub fn get_field<T: Field>(&self) -> Option<T> {
+        self.get(T::tag()).and_then(T::decode_from_value)
+    }
+
+    /// Sets the value of `tag`, replacing any previous one.
+    pub fn set(&mut self, tag: Tag, value: TIFFValue) {
+        self.children.remove(&u16::from(tag));
+        self.raw.remove(&u16::from(tag));
+        self.fields.insert(u16::from(tag), value);
+    }
+
+    /// Sets the field `T`, replacing any previous value.
+    pub fn set_field<T: Field>(&mut self, field: &T) {
+        if let Some(value) = field.encode_to_value() {
+            self.set(T::tag(), value);
+        }
+    }
+
+    /// Removes `tag`, returning its value.
+    pub fn remove(&mut self, tag: Tag) -> Option<TIFFValue> {
+        self.children.remove(&u16::from(tag));
+        self.raw.remove(&u16::from(tag));
+        self.fields.remove(&u16::from(tag))
+    }
+
+    /// Removes the field `T`, returning its decoded value.
+    pub fn remove_field<T: Field>(&mut self) -> Option<T> {
+        self.remove(T::tag()).as_ref().and_then(T::decode_from_value)
+    }
+
+    /// Whether `tag` will be written, either as a value, a raw entry or a
+    /// pointer to a child directory.
+    pub fn has_field(&self, tag: Tag) -> bool {
+        let tag = u16::from(tag);
+        self.fields.contains_key(&tag)
+            || self.raw.contains_key(&tag)
+            || self.children.contains_key(&tag)
+    }
+
+    /// Sets `tag` to an already serialized value, replacing any previous
+    /// one. This allows types the writer does not know about.
+    pub fn set_raw(&mut self, tag: Tag, entry: RawEntry) {
+        self.remove(tag);
+        self.raw.insert(u16::from(tag), entry);
+    }
+
+    /// The raw entry of `tag`, if set with `set_raw`.
+    pub fn raw(&self, tag: Tag) -> Option<&RawEntry> {
+        self.raw.get(&u16::from(tag))
+    }
+
+    /// The tags of the directory, in ascending order, including raw entries
+    /// and the pointers to child directories.
+    pub fn tags(&self) -> impl Iterator<Item = Tag> + '_ {
+        let mut tags: Vec<u16> = self
+            .fields
+            .keys()
+            .chain(self.raw.keys())
+            .chain(self.children.keys())
+            .cloned()
+            .collect();
+        tags.sort_unstable();
+        tags.into_iter().map(Tag::from)
+    }
+
+    /// The fields of the directory and their values, in ascending tag order.
+    /// Raw entries and child directories are not included.
+    pub fn entries(&self) -> impl Iterator<Item = (Tag, &TIFFValue)> + '_ {
+        self.fields.iter().map(|(tag, value)| (Tag::from(*tag), value))
+    }
+
+    /// Sets a child directory, written along with this one and referenced by
+    /// the pointer field `tag`.
+    pub fn set_sub_directory(&mut self, tag: Tag, directory: Directory) {
+        self.fields.remove(&u16::from(tag));
+        self.raw.remove(&u16::from(tag));
+        self.children.insert(u16::from(tag), directory);
+    }
+
+    /// The child directory referenced by `tag`, if set.
+    pub fn sub_directory(&self, tag: Tag) -> Option<&Directory> {
+        self.children.get(&u16::from(tag))
+    }
+
+    /// Sets the EXIF directory, holding the fields of the EXIF specification.
+    pub fn set_exif(&mut self, exif: Directory) {
+        self.set_sub_directory(Tag::ExifIFD, exif);
+    }
+}
+
+impl TIFFValue {
+    /// The number of elements of the value, as stored in its directory entry.
+    pub(crate) fn count(&self) -> usize {
+        match self {
+            TIFFValue::Byte(el) | TIFFValue::Undefined(el) => el.len(),
+            TIFFValue::Ascii(el) => el.iter().map(|s| s.len() + 1).sum(),
+            TIFFValue::Short(el) => el.len(),
+            TIFFValue::Long(el) => el.len(),
+            TIFFValue::Rational(el) => el.len(),
+            TIFFValue::SByte(el) => el.len(),
+            TIFFValue::SShort(el) => el.len(),
+            TIFFValue::SLong(el) => el.len(),
+            TIFFValue::SRational(el) => el.len(),
+            TIFFValue::Float(el) => el.len(),
+            TIFFValue::Double(el) => el.len(),
+        }
+    }
+
+    /// Serializes the elements of the value.
+    pub(crate) fn write<W: Write>(&self, writer: &mut EndianWriter<W>) -> io::Result<()> {
+        match self {
+            TIFFValue::Byte(el) | TIFFValue::Undefined(el) => {
+                el.iter().try_for_each(|e| writer.write_byte(*e))
+            }
+            TIFFValue::Ascii(el) => el.iter().try_for_each(|s| {
+                s.bytes().try_for_each(|e| writer.write_byte(e))?;
+                writer.write_byte(0)
+            }),
+            TIFFValue::Short(el) => el.iter().try_for_each(|e| writer.write_short(*e)),
+            TIFFValue::Long(el) => el.iter().try_for_each(|e| writer.write_long(*e)),
+            TIFFValue::Rational(el) => el
+                .iter()
+                .try_for_each(|e| writer.write_rational(e.num, e.denom)),
+            TIFFValue::SByte(el) => el.iter().try_for_each(|e| writer.write_byte(*e as u8)),
+            TIFFValue::SShort(el) => el.iter().try_for_each(|e| writer.write_short(*e)),
+            TIFFValue::SLong(el) => el.iter().try_for_each(|e| writer.write_long(*e)),
+            TIFFValue::SRational(el) => el
+                .iter()
+                .try_for_each(|e| writer.write_rational(e.num, e.denom)),
+            TIFFValue::Float(el) => el.iter().try_for_each(|e| writer.write_float(*e)),
+            TIFFValue::Double(el) => el.iter().try_for_each(|e| writer.write_double(*e)),
+        }
+    }
+}
+
+/// Produces the strips or tiles of an image while it is being written, so
+/// that they do not all have to be held in memory.
+pub trait SegmentSource {
+    /// Returns the bytes of segment `index`, as they are stored in the file.
+    fn segment(&mut self, index: usize) -> Result<Vec<u8>>;
+
+    /// Writes segment `index` to `output` and returns its length in bytes.
+    ///
+    /// Sources able to stream their data, such as another file, override
+    /// this to avoid holding a whole segment in memory.
+    fn write_segment(&mut self, index: usize, output: &mut dyn Write) -> Result<u64> {
+        let bytes = self.segment(index)?;
+        output.write_all(&bytes)?;
+        Ok(bytes.len() as u64)
+    }
+}
+
+impl<F: FnMut(usize) -> Result<Vec<u8>>> SegmentSource for F {
+    fn segment(&mut self, index: usize) -> Result<Vec<u8>> {
+        self(index)
+    }
+}
+
+pub(crate) fn invalid_input(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// The offset and byte count tags of the image data of a directory.
+fn segment_tags(directory: &Directory) -> (Tag, Tag) {
+    if directory.get(Tag::TileWidth).is_some() {
+        (Tag::TileOffsets, Tag::TileByteCounts)
+    } else {
+        (Tag::StripOffsets, Tag::StripByteCounts)
+    }
+}
+
+/// Converts a position in the file to a 32-bit offset.
+fn to_offset(position: u64) -> Result<u32> {
+    if position > u64::from(u32::MAX) {
+        return Err(TiffError::Unsupported("Files larger than 4 GiB"));
+    }
+    Ok(position as u32)
+}
+
+/// Regions of the file left unused by edits, sorted and never adjacent.
+#[derive(Debug, Clone, Default)]
+struct FreeSpace {
+    regions: Vec<(u64, u64)>,
+}
+
+impl FreeSpace {
+    /// Adds the `length` bytes at `offset`, merging them with their
+    /// neighbours.
+    fn release(&mut self, offset: u64, length: u64) {
+        if length == 0 {
+            return;
+        }
+        self.regions.push((offset, length));
+        self.regions.sort_unstable();
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(self.regions.len());
+        for (offset, length) in self.regions.drain(..) {
+            match merged.last_mut() {
+                Some(last) if last.0 + last.1 >= offset => {
+                    last.1 = last.1.max(offset + length - last.0);
+                }
+                _ => merged.push((offset, length)),
+            }
+        }
+        self.regions = merged;
+    }
+
+    /// Takes `size` bytes starting on a word boundary from the first region
+    /// large enough, and returns their offset.
+    fn allocate(&mut self, size: u64) -> Option<u64> {
+        let index = self
+            .regions
+            .iter()
+            .position(|(offset, length)| offset % 2 + size <= *length)?;
+        let (offset, length) = self.regions.remove(index);
+        let start = offset + offset % 2;
+        if start > offset {
+            self.regions.insert(index, (offset, 1));
+        }
+        let end = start + size;
+        if end < offset + length {
+            self.release(end, offset + length - end);
+        }
+        Some(start)
+    }
+}
+
+/// A writer producing classic TIFF files.
+///
+/// Image data is written first with `write_data`, then the directory
+/// referencing it with `write_directory`. Directories are chained in the order
+/// they are written.
+///
+/// Regions orphaned by edits can be given back with `release`, to be reused
+/// by the data and directories written next.
+pub struct TIFFWriter<W> {
+    inner: W,
+    endian: Endian,
+    next_link: u64,
+    /// Offsets of the directories of the main chain, in order.
+    directories: Vec<u64>,
+    free: FreeSpace,
+    /// Free regions listed by a directory already written, which are not
+    /// reused so that the list stays valid.
+    recorded: FreeSpace,
+    /// Directories of the file recording free regions, by position, with
+    /// their other entries, to be written without the record once a free
+    /// region is reused.
+    records: Vec<(u64, Vec<[u8; 12]>)>,
+    record_free_space: bool,
+    thumbnail_size: Option<u32>,
+    #[cfg(feature = "checksum")]
+    checksums: Option<ChecksumAlgorithm>,
+    cancellation: Option<CancellationToken>,
+    strings: StringEncoding,
+}
+
+impl<W: Write + Seek> TIFFWriter<W> {
+    /// Creates a writer, emitting the header in the `endian` byte order at the
+    /// start of `inner`.
+    pub fn new(mut inner: W, endian: Endian) -> Result<TIFFWriter<W>> {
+        inner.seek(SeekFrom::Start(0))?;
+        {
+            let mut writer = EndianWriter::new(&mut inner, endian);
+            match endian {
+                Endian::Big => writer.write_short(0x4d4du16)?,
+                Endian::Little => writer.write_short(0x4949u16)?,
+            }
+            writer.write_short(42u16)?;
+            writer.write_long(0u32)?;
+        }
+
+        Ok(TIFFWriter {
+            inner,
+            endian,
+            next_link: 4,
+            directories: Vec::new(),
+            free: FreeSpace::default(),
+            recorded: FreeSpace::default(),
+            records: Vec::new(),
+            record_free_space: false,
+            thumbnail_size: None,
+            #[cfg(feature = "checksum")]
+            checksums: None,
+            cancellation: None,
+            strings: StringEncoding::default(),
+        })
+    }
+
+    /// The byte order of the file.
+    pub fn endianness(&self) -> Endian {
+        self.endian
+    }
+
+    /// Moves to the end of the file, padded to a word boundary.
+    fn seek_to_end(&mut self) -> Result<u64> {
+        let end = self.inner.seek(SeekFrom::End(0))?;
+        if end % 2 == 1 {
+            self.inner.write_all(&[0])?;
+            return Ok(end + 1);
+        }
+        Ok(end)
+    }
+
+    /// Moves to `size` bytes of free space, reused from the released regions
+    /// if one is large enough, or at the end of the file.
+    fn seek_to_free(&mut self, size: u64) -> Result<u64> {
+        match self.free.allocate(size) {
+            Some(offset) => {
+                self.drop_records()?;
+                Ok(self.inner.seek(SeekFrom::Start(offset))?)
+            }
+            None => self.seek_to_end(),
+        }
+    }
+
+    /// Rewrites the directories of the file recording free regions without
+    /// `FreeOffsets` and `FreeByteCounts`, as their records no longer hold
+    /// once a region is reused.
+    fn drop_records(&mut self) -> Result<()> {
+        for (position, entries) in ::std::mem::take(&mut self.records) {
+            // Replaced since, with its record
+            let index = match self.directories.iter().position(|p| *p == position) {
+                Some(index) => index,
+                None => continue,
+            };
+            let next = match self.directories.get(index + 1) {
+                Some(next) => to_offset(*next)?,
+                None => 0,
+            };
+            // Past the two entries of the record
+            let link = position + 2 + 12 * entries.len() as u64;
+            let old_link = link + 24;
+
+            self.inner.seek(SeekFrom::Start(position))?;
+            let mut writer = EndianWriter::new(&mut self.inner, self.endian);
+            writer.write_short(entries.len() as u16)?;
+            for entry in &entries {
+                writer.write_bytes(entry)?;
+            }
+            writer.write_long(next)?;
+            if self.next_link == old_link {
+                self.next_link = link;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes `bytes`, such as a compressed strip, on a word boundary and
+    /// returns their offset.
+    pub fn write_data(&mut self, bytes: &[u8]) -> Result<u32> {
+        let offset = to_offset(self.seek_to_free(bytes.len() as u64)?)?;
+        to_offset(u64::from(offset) + bytes.len() as u64)?;
+        self.inner.write_all(bytes)?;
+        Ok(offset)
+    }
+
+    /// Marks the `length` bytes at `offset`, such as the values of a replaced
+    /// directory, as unused.
+    ///
+    /// Released regions are reused by `write_data` and by directories, but
+    /// not by `write_image`, which does not know the size of its segments in
+    /// advance.
+    pub fn release(&mut self, offset: u64, length: u64) {
+        self.free.release(offset, length);
+    }
+
+    /// The regions released and not reused yet, as offsets and lengths.
+    /// Regions recorded by a directory are left out, as they are no longer
+    /// reused.
+    pub fn free_regions(&self) -> &[(u64, u64)] {
+        &self.free.regions
+    }
+
+    /// Records the free regions in the `FreeOffsets` and `FreeByteCounts` of
+    /// the directories written from now on, so that later edits can reuse
+    /// them. Such directories are written at the end of the file, and the
+    /// recorded regions are no longer reused by this writer.
+    pub fn set_record_free_space(&mut self, record: bool) {
+        self.record_free_space = record;
+    }
+
+    /// Writes `directory` with its values, links it after the previously
+    /// written directory and returns its offset.
+    ///
+    /// Strip or tile offsets and byte counts set by hand must describe data
+    /// already written, without overlaps.
+    pub fn write_directory(&mut self, directory: &Directory) -> Result<u32> {
+        let (position, count) = self.write_main_directory(directory)?;
+
+        // Link the directory from the header or the previous directory
+        let offset = to_offset(position)?;
+        self.write_long_at(self.next_link, offset)?;
+        self.next_link = position + 2 + 12 * count as u64;
+        self.directories.push(position);
+
+        Ok(offset)
+    }
+
+    /// Writes `directory` with its children and, if requested, the free
+    /// regions, and returns its position and its number of entries.
+    fn write_main_directory(&mut self, directory: &Directory) -> Result<(u64, usize)> {
+        self.check_segments(directory)?;
+        let mut fields = self.write_children(directory)?;
+        if self.record_free_space {
+            for (offset, length) in self.free.regions.drain(..) {
+                self.recorded.release(offset, length);
+            }
+        }
+        if self.record_free_space && !self.recorded.regions.is_empty() {
+            let mut offsets = Vec::with_capacity(self.recorded.regions.len());
+            let mut counts = Vec::with_capacity(self.recorded.regions.len());
+            for (offset, length) in &self.recorded.regions {
+                offsets.push(to_offset(*offset)?);
+                counts.push(to_offset(*length)?);
+            }
+            let offsets = self.serialize(&TIFFValue::Long(offsets))?;
+            let counts = self.serialize(&TIFFValue::Long(counts))?;
+            fields.insert(u16::from(Tag::FreeOffsets), offsets);
+            fields.insert(u16::from(Tag::FreeByteCounts), counts);
+        }
+        let position = self.write_fields(&fields, !self.record_free_space)?;
+        Ok((position, fields.len()))
+    }
+
+    /// Overwrites the 32-bit value at `position`, such as a directory link.
+    fn write_long_at(&mut self, position: u64, value: u32) -> Result<()> {
+        self.inner.seek(SeekFrom::Start(position))?;
+        EndianWriter::new(&mut self.inner, self.endian).write_long(value)?;
+        Ok(())
+    }
+
+    /// Checks the strip or tile offsets and byte counts of `directory`
+    /// against each other and against the data written so far.
+    fn check_segments(&mut self, directory: &Directory) -> Result<()> {
+        let (offsets_tag, counts_tag) = segment_tags(directory);
+        let offsets = directory.get(offsets_tag).map(TIFFValue::as_unsigned);
+        let counts = directory.get(counts_tag).map(TIFFValue::as_unsigned);
+        let (offsets, counts) = match (offsets, counts) {
+            (None, None) => return Ok(()),
+            (Some(Some(offsets)), Some(Some(counts))) => (offsets, counts),
+            _ => return Err(invalid_input("Offsets and byte counts must both be set").into()),
+        };
+        if offsets.len() != counts.len() {
+            return Err(invalid_input("Offsets and byte counts differ in number").into());
+        }
+
+        let end = self.inner.seek(SeekFrom::End(0))?;
+        let mut ranges: Vec<(u64, u64)> = offsets
+            .iter()
+            .zip(&counts)
+            .filter(|(_, count)| **count > 0)
+            .map(|(offset, count)| (*offset, offset + count))
+            .collect();
+        ranges.sort_unstable();
+        if ranges.iter().any(|(start, stop)| *start < 8 || *stop > end) {
+            return Err(invalid_input("Image data lies outside the written data").into());
+        }
+        if ranges.windows(2).any(|pair| pair[0].1 > pair[1].0) {
+            return Err(invalid_input("Image data segments overlap").into());
+        }
+        Ok(())
+    }
+
+    /// Writes `directory` as a child directory, outside of the main chain,
+    /// and returns its offset, to be stored in a pointer field.
+    ///
+    /// Directories set with `Directory::set_sub_directory` are written this
+    /// way by `write_directory`.
+    pub fn write_sub_directory(&mut self, directory: &Directory) -> Result<u32> {
+        let fields = self.write_children(directory)?;
+        to_offset(self.write_fields(&fields, true)?)
+    }
+
+    /// Writes the child directories of `directory` and returns its fields
+    /// serialized, completed with the pointers to them.
+    fn write_children(&mut self, directory: &Directory) -> Result<BTreeMap<u16, RawEntry>> {
+        let mut fields = BTreeMap::new();
+        for (tag, value) in &directory.fields {
+            fields.insert(*tag, self.serialize(value)?);
+        }
+        for (tag, entry) in &directory.raw {
+            entry.check()?;
+            fields.insert(*tag, entry.clone());
+        }
+        for (tag, child) in &directory.children {
+            let offset = self.write_sub_directory(child)?;
+            fields.insert(*tag, self.serialize(&TIFFValue::Long(vec![offset]))?);
+        }
+        Ok(fields)
+    }
+
+    /// Serializes `value` in the byte order of the file.
+    fn serialize(&self, value: &TIFFValue) -> Result<RawEntry> {
+        let value = self.encode_strings(value)?;
+        let mut bytes = Vec::new();
+        value.write(&mut EndianWriter::new(&mut bytes, self.endian))?;
+        Ok(RawEntry {
+            value_type: value.value_type_id(),
+            count: value.count() as u32,
+            bytes,
+        })
+    }
+
+    /// Writes a directory made of `fields`, without a next directory, followed
+    /// by its values, and returns its position. With `reuse`, it may be
+    /// written in a released region.
+    fn write_fields(&mut self, fields: &BTreeMap<u16, RawEntry>, reuse: bool) -> Result<u64> {
+        let count = fields.len();
+        let values: u64 = fields
+            .values()
+            .map(|e| e.bytes.len() as u64)
+            .filter(|size| *size > 4)
+            .map(|size| size + size % 2)
+            .sum();
+        let size = 2 + 12 * count as u64 + 4 + values;
+        let position = if reuse {
+            self.seek_to_free(size)?
+        } else {
+            self.seek_to_end()?
+        };
+        let mut value_position = position + 2 + 12 * count as u64 + 4;
+
+        let mut writer = EndianWriter::new(&mut self.inner, self.endian);
+        writer.write_short(count as u16)?;
+        for (tag, entry) in fields {
+            let bytes = &entry.bytes;
+            writer.write_short(*tag)?;
+            writer.write_short(entry.value_type)?;
+            writer.write_long(entry.count)?;
+            if bytes.len() <= 4 {
+                let mut inline = [0; 4];
+                inline[..bytes.len()].copy_from_slice(bytes);
+                writer.write_bytes(&inline)?;
+            } else {
+                writer.write_long(to_offset(value_position)?)?;
+                value_position += bytes.len() as u64 + bytes.len() as u64 % 2;
+            }
+        }
+        writer.write_long(0u32)?;
+
+        for bytes in fields.values().map(|e| &e.bytes).filter(|bytes| bytes.len() > 4) {
+            writer.write_bytes(bytes)?;
+            if bytes.len() % 2 == 1 {
+                writer.write_byte(0)?;
+            }
+        }
+        to_offset(value_position)?;
+
+        Ok(position)
+    }
+
+    /// Checks the strings of ASCII values against the string encoding.
+    fn encode_strings<'v>(&self, value: &'v TIFFValue) -> Result<Cow<'v, TIFFValue>> {
+        match value {
+            TIFFValue::Ascii(strings) => {
+                let mut encoded = Vec::with_capacity(strings.len());
+                for string in strings {
+                    encoded.push(self.strings.encode(string)?.into_owned());
+                }
+                Ok(Cow::Owned(TIFFValue::Ascii(encoded)))
+            }
+            value => Ok(Cow::Borrowed(value)),
+        }
+    }
+
+    /// Writes an image of `count` strips, or tiles when `directory` has a
+    /// `TileWidth`, pulling each one from `source` in order, then writes
+    /// `directory` with the matching offsets and byte counts.
+    pub fn write_image<S: SegmentSource>(
+        &mut self,
+        directory: &Directory,
+        count: usize,
+        mut source: S,
+    ) -> Result<u32> {
+        let mut offsets = Vec::with_capacity(count);
+        let mut counts = Vec::with_capacity(count);
+        #[cfg(feature = "checksum")]
+        let mut builder = self.checksums.map(ChecksumBuilder::new);
+        for index in 0..count {
+            cancel::check(&self.cancellation)?;
+            let offset = to_offset(self.seek_to_end()?)?;
+            #[cfg(feature = "checksum")]
+            let length = match builder.as_mut() {
+                Some(builder) => {
+                    let mut output = ChecksumWriter {
+                        inner: &mut self.inner,
+                        builder,
+                    };
+                    let length = source.write_segment(index, &mut output)?;
+                    output.builder.end_segment();
+                    length
+                }
+                None => source.write_segment(index, &mut self.inner)?,
+            };
+            #[cfg(not(feature = "checksum"))]
+            let length = source.write_segment(index, &mut self.inner)?;
+            to_offset(u64::from(offset) + length)?;
+            offsets.push(offset);
+            counts.push(length as u32);
+        }
+
+        let (offsets_tag, counts_tag) = segment_tags(directory);
+        let mut directory = directory.clone();
+        directory.set(offsets_tag, TIFFValue::Long(offsets));
+        directory.set(counts_tag, TIFFValue::Long(counts));
+        #[cfg(feature = "checksum")]
+        {
+            if let Some(builder) = builder {
+                directory.set(Tag::Unknown(CHECKSUM_TAG), builder.finish().to_value());
+            }
+        }
+        self.write_directory(&directory)
+    }
+
+    /// Writes the image data `data`, split into strips or tiles by the
+    /// `StripByteCounts` or `TileByteCounts` of `directory`, then writes
+    /// `directory` with the matching offsets.
+    ///
+    /// The byte counts must add up to the length of `data`.
+    pub fn write_image_data(&mut self, directory: &Directory, data: &[u8]) -> Result<u32> {
+        let (_, counts_tag) = segment_tags(directory);
+        let counts = directory
+            .get(counts_tag)
+            .and_then(TIFFValue::as_unsigned)
+            .ok_or_else(|| invalid_input("Missing byte counts"))?;
+        let total: u64 = counts.iter().sum();
+        if total != data.len() as u64 {
+            return Err(invalid_input("Byte counts do not add up to the data length").into());
+        }
+
+        let mut starts = Vec::with_capacity(counts.len());
+        let mut start = 0;
+        for count in &counts {
+            starts.push(start);
+            start += *count as usize;
+        }
+        let source = |index: usize| {
+            let start = starts[index];
+            Ok(data[start..start + counts[index] as usize].to_vec())
+        };
+        let offset = self.write_image(directory, counts.len(), source)?;
+
+        let thumbnail = self
+            .thumbnail_size
+            .and_then(|size| thumbnail::reduce(directory, data, size));
+        if let Some((directory, data)) = thumbnail {
+            self.write_image_data(&directory, &data)?;
+        }
+        Ok(offset)
+    }
+
+    /// Embeds checksums of the strips or tiles of the images written from now
+    /// on, computed with `algorithm`, or stops embedding them with `None`.
+    #[cfg(feature = "checksum")]
+    pub fn set_checksums(&mut self, algorithm: Option<ChecksumAlgorithm>) {
+        self.checksums = algorithm;
+    }
+
+    /// Follows each image written with `write_image_data` by a thumbnail
+    /// fitting in `max_size` × `max_size` pixels, in a reduced-resolution
+    /// directory, or stops writing them with `None`.
+    ///
+    /// Only images of uncompressed chunky 8-bit samples in strips get a
+    /// thumbnail, the others being written alone.
+    pub fn set_thumbnail_size(&mut self, max_size: Option<u32>) {
+        self.thumbnail_size = max_size;
+    }
+
+    /// Sets how the strings of ASCII values are checked, UTF-8 by default.
+    pub fn set_string_encoding(&mut self, strings: StringEncoding) {
+        self.strings = strings;
+    }
+
+    /// Stops `write_image` between strips or tiles once `token` is cancelled.
+    pub fn set_cancellation_token(&mut self, token: Option<CancellationToken>) {
+        self.cancellation = token;
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Read + Write + Seek> TIFFWriter<W> {
+    /// Opens the existing file `inner` to edit it in place. Directories
+    /// written from now on are chained after its last one.
+    ///
+    /// The free regions recorded by the most recently written directory
+    /// having `FreeOffsets` and `FreeByteCounts`, the one furthest in the
+    /// file, are reused. Once one is, the records are dropped from the
+    /// directories, so that later edits do not reuse the same bytes again.
+    pub fn append(mut inner: W) -> Result<TIFFWriter<W>> {
+        inner.seek(SeekFrom::Start(0))?;
+        let (endian, directory_count, records) = {
+            let mut reader = TIFFReader::new(&mut inner)?;
+            let mut records = Vec::with_capacity(reader.ifds().len());
+            for index in 0..reader.ifds().len() {
+                reader.select_directory(index)?;
+                let offsets = reader.read_tag(Tag::FreeOffsets)?;
+                let counts = reader.read_tag(Tag::FreeByteCounts)?;
+                let offsets = offsets.and_then(|value| value.as_unsigned());
+                let counts = counts.and_then(|value| value.as_unsigned());
+                records.push(match (offsets, counts) {
+                    (Some(offsets), Some(counts)) => Some(offsets.into_iter().zip(counts)),
+                    _ => None,
+                });
+            }
+            (reader.endianness(), reader.ifds().len(), records)
+        };
+
+        let mut writer = TIFFWriter {
+            inner,
+            endian,
+            next_link: 4,
+            directories: Vec::with_capacity(directory_count),
+            free: FreeSpace::default(),
+            recorded: FreeSpace::default(),
+            records: Vec::new(),
+            record_free_space: false,
+            thumbnail_size: None,
+            #[cfg(feature = "checksum")]
+            checksums: None,
+            cancellation: None,
+            strings: StringEncoding::default(),
+        };
+        // The reader checked the chain, which has no cycles
+        for _ in 0..directory_count {
+            let position = u64::from(writer.read_long_at(writer.next_link)?);
+            let count: u16 = writer.reader_at(position)?.read_short()?;
+            writer.directories.push(position);
+            writer.next_link = position + 2 + 12 * u64::from(count);
+        }
+        let records: Vec<_> = writer
+            .directories
+            .clone()
+            .into_iter()
+            .zip(records)
+            .filter_map(|(position, record)| Some((position, record?)))
+            .collect();
+        let newest = records.iter().map(|(position, _)| *position).max();
+        for (position, record) in records {
+            if Some(position) == newest {
+                for (offset, length) in record {
+                    writer.free.release(offset, length);
+                }
+            }
+            let entries = writer.entries_without_record(position)?;
+            writer.records.push((position, entries));
+        }
+        Ok(writer)
+    }
+
+    /// Replaces directory `index` of the main chain with `directory`, keeping
+    /// its place in the chain, and returns the offset of the new directory.
+    ///
+    /// The old directory and its out of line values are released first, so
+    /// the new one may take their place. Its image data and child
+    /// directories, which may still be referenced, are left in place.
+    pub fn replace_directory(&mut self, index: usize, directory: &Directory) -> Result<u32> {
+        let old = *self
+            .directories
+            .get(index)
+            .ok_or(TiffError::DirectoryIndexOutOfBounds)?;
+        let link = match index {
+            0 => 4,
+            _ => self.link_position(self.directories[index - 1])?,
+        };
+        let old_link = self.link_position(old)?;
+        let next = self.read_long_at(old_link)?;
+        for (offset, length) in self.directory_blocks(old)? {
+            self.free.release(offset, length);
+        }
+
+        let (position, count) = self.write_main_directory(directory)?;
+        let offset = to_offset(position)?;
+        let new_link = position + 2 + 12 * count as u64;
+        self.write_long_at(new_link, next)?;
+        self.write_long_at(link, offset)?;
+        if self.next_link == old_link {
+            self.next_link = new_link;
+        }
+        self.directories[index] = position;
+        Ok(offset)
+    }
+
+    fn reader_at(&mut self, position: u64) -> Result<EndianReader<'_, W>> {
+        self.inner.seek(SeekFrom::Start(position))?;
+        Ok(EndianReader::new(&mut self.inner, self.endian))
+    }
+
+    fn read_long_at(&mut self, position: u64) -> Result<u32> {
+        Ok(self.reader_at(position)?.read_long()?)
+    }
+
+    /// Position of the next directory link of the directory at `position`.
+    fn link_position(&mut self, position: u64) -> Result<u64> {
+        let count: u16 = self.reader_at(position)?.read_short()?;
+        Ok(position + 2 + 12 * u64::from(count))
+    }
+
+    /// The entries of the directory at `position`, as stored, but for its
+    /// `FreeOffsets` and `FreeByteCounts`.
+    fn entries_without_record(&mut self, position: u64) -> Result<Vec<[u8; 12]>> {
+        let count: u16 = self.reader_at(position)?.read_short()?;
+        let mut entries = Vec::with_capacity(usize::from(count));
+        for _ in 0..count {
+            let mut entry = [0; 12];
+            self.inner.read_exact(&mut entry)?;
+            let tag = Tag::from(self.endian.short_from_bytes::<u16>([entry[0], entry[1]]));
+            if tag != Tag::FreeOffsets && tag != Tag::FreeByteCounts {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// The regions holding the directory at `position` and its out of line
+    /// values, as offsets and lengths.
+    fn directory_blocks(&mut self, position: u64) -> Result<Vec<(u64, u64)>> {
+        let mut reader = self.reader_at(position)?;
+        let count: u16 = reader.read_short()?;
+        let mut blocks = vec![(position, 2 + 12 * u64::from(count) + 4)];
+        for _ in 0..count {
+            let _tag: u16 = reader.read_short()?;
+            let value_type: u16 = reader.read_short()?;
+            let count: u32 = reader.read_long()?;
+            let offset: u32 = reader.read_long()?;
+            let size = FieldType::from_id(value_type).map_or(1, FieldType::size);
+            let size = u64::from(count) * size;
+            if size > 4 {
+                blocks.push((u64::from(offset), size));
+            }
+        }
+        Ok(blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::TIFFReader;
+    use std::io::Cursor;
+    use tag::*;
+    use value::Rational;
+
+    #[test]
+    fn test_write_directories() {
+        for &endian in &[Endian::Big, Endian::Little] {
+            let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), endian).unwrap();
+            let offset = writer.write_data(&[1, 2, 3]).unwrap();
+            assert_eq!(offset, 8);
+
+            let mut directory = Directory::new();
+            directory.set_field(&ImageWidth(3));
+            directory.set_field(&ImageLength(1));
+            directory.set_field(&Software("tiff".to_string()));
+            directory.set(Tag::StripOffsets, TIFFValue::Long(vec![offset]));
+            directory.set(Tag::StripByteCounts, TIFFValue::Long(vec![3]));
+            let resolution = Rational { num: 72, denom: 1 };
+            directory.set(Tag::XResolution, TIFFValue::Rational(vec![resolution]));
+            writer.write_directory(&directory).unwrap();
+
+            directory.set_field(&ImageDescription("second".to_string()));
+            writer.write_directory(&directory).unwrap();
+
+            let bytes = writer.into_inner().into_inner();
+            let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+            assert_eq!(read.endianness(), endian);
+            assert_eq!(read.ifds().len(), 2);
+            assert_eq!(read.get_field::<ImageWidth>().unwrap().0, 3);
+            assert_eq!(read.get_field::<Software>().unwrap().0, "tiff");
+            assert_eq!(read.read_strip(0).unwrap(), [1, 2, 3]);
+
+            read.select_directory(1).unwrap();
+            assert_eq!(read.get_field::<ImageDescription>().unwrap().0, "second");
+        }
+    }
+
+    #[test]
+    fn test_string_encoding() {
+        let mut directory = Directory::new();
+        directory.set_field(&Software("Zoë".to_string()));
+        let write = |strings| {
+            let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+            writer.set_string_encoding(strings);
+            writer.write_directory(&directory).map(|_| writer.into_inner().into_inner())
+        };
+
+        let bytes = write(StringEncoding::Utf8).unwrap();
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.get_field::<Software>().unwrap().0, "Zoë");
+        let bytes = write(StringEncoding::Lossy).unwrap();
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.get_field::<Software>().unwrap().0, "Zo?");
+        assert!(write(StringEncoding::Ascii).is_err());
+    }
+
+    /// The offsets of the directories chained from the header of `bytes`.
+    fn directory_chain(bytes: &[u8], endian: Endian) -> Vec<u32> {
+        let read_u16 = |at: usize| {
+            let pair = [bytes[at], bytes[at + 1]];
+            match endian {
+                Endian::Big => u16::from_be_bytes(pair),
+                Endian::Little => u16::from_le_bytes(pair),
+            }
+        };
+        let read_u32 = |at: usize| {
+            let quad = [bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]];
+            match endian {
+                Endian::Big => u32::from_be_bytes(quad),
+                Endian::Little => u32::from_le_bytes(quad),
+            }
+        };
+
+        let mut chain = Vec::new();
+        let mut next = read_u32(4);
+        while next != 0 {
+            chain.push(next);
+            let count = usize::from(read_u16(next as usize));
+            next = read_u32(next as usize + 2 + 12 * count);
+        }
+        chain
+    }
+
+    #[test]
+    fn test_directory_chain() {
+        let samples: [&[u8]; 2] = [
+            include_bytes!("../samples/arbitro_be.tiff"),
+            include_bytes!("../samples/picoawards_le.tiff"),
+        ];
+        for &endian in &[Endian::Big, Endian::Little] {
+            let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), endian).unwrap();
+            let mut written = Vec::new();
+            let mut strips = Vec::new();
+            for (page, sample) in samples.iter().cycle().take(5).enumerate() {
+                // Copy the image, with values and strips of odd lengths
+                let mut source = TIFFReader::new(Cursor::new(*sample)).unwrap();
+                let tags: Vec<Tag> = source.ifds()[0].all_tags().collect();
+                let mut directory = Directory::new();
+                for tag in tags {
+                    if tag != Tag::StripOffsets && tag != Tag::StripByteCounts {
+                        directory.set(tag, source.read_tag(tag).unwrap().unwrap());
+                    }
+                }
+                directory.set_field(&Software("p".repeat(page * 3 + 4)));
+                if page % 2 == 1 {
+                    let mut exif = Directory::new();
+                    exif.set(Tag::ExifVersion, TIFFValue::Undefined(b"0232".to_vec()));
+                    directory.set_exif(exif);
+                }
+
+                let count = source.strip_count().unwrap();
+                let data: Vec<_> = (0..count).map(|i| source.read_strip(i).unwrap()).collect();
+                let offset = writer.write_image(&directory, count, |i: usize| Ok(data[i].clone()));
+                written.push(offset.unwrap());
+                strips.push(data);
+            }
+
+            let bytes = writer.into_inner().into_inner();
+            let chain = directory_chain(&bytes, endian);
+            assert_eq!(chain, written);
+            assert!(chain.iter().all(|offset| offset % 2 == 0));
+
+            let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+            assert_eq!(read.ifds().len(), 5);
+            for (page, data) in strips.iter().enumerate() {
+                read.select_directory(page).unwrap();
+                assert_eq!(read.get_field::<Software>().unwrap().0.len(), page * 3 + 4);
+                assert_eq!(read.read_tag(Tag::ExifIFD).unwrap().is_some(), page % 2 == 1);
+                for (index, strip) in data.iter().enumerate() {
+                    assert_eq!(&read.read_strip(index).unwrap(), strip);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_image() {
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(2));
+        directory.set_field(&ImageLength(3));
+        directory.set_field(&RowsPerStrip(1));
+
+        let mut requested = Vec::new();
+        let source = |index: usize| {
+            requested.push(index);
+            Ok(vec![index as u8; 2])
+        };
+        writer.write_image(&directory, 3, source).unwrap();
+        assert_eq!(requested, [0, 1, 2]);
+
+        let bytes = writer.into_inner().into_inner();
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.strip_count().unwrap(), 3);
+        assert_eq!(read.read_strip(2).unwrap(), [2, 2]);
+    }
+
+    #[test]
+    fn test_check_segments() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(2));
+        directory.set_field(&ImageLength(3));
+        directory.set_field(&RowsPerStrip(1));
+        directory.set(Tag::StripByteCounts, TIFFValue::Long(vec![2, 1, 3]));
+
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        assert!(writer.write_image_data(&directory, &[0, 0, 1, 2]).is_err());
+        writer.write_image_data(&directory, &[0, 0, 1, 2, 2, 2]).unwrap();
+        let bytes = writer.into_inner().into_inner();
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.read_strip(1).unwrap(), [1]);
+        assert_eq!(read.read_strip(2).unwrap(), [2, 2, 2]);
+
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        let offset = writer.write_data(&[0; 6]).unwrap();
+        directory.set(Tag::StripOffsets, TIFFValue::Long(vec![offset, offset + 2]));
+        assert!(writer.write_directory(&directory).is_err());
+        let overlapping = vec![offset, offset + 1, offset + 3];
+        directory.set(Tag::StripOffsets, TIFFValue::Long(overlapping));
+        assert!(writer.write_directory(&directory).is_err());
+        let beyond = vec![offset, offset + 2, offset + 4];
+        directory.set(Tag::StripOffsets, TIFFValue::Long(beyond));
+        assert!(writer.write_directory(&directory).is_err());
+        directory.set(Tag::StripOffsets, TIFFValue::Long(vec![offset, offset + 2, offset + 3]));
+        writer.write_directory(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_thumbnail() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(8));
+        directory.set_field(&ImageLength(4));
+        directory.set_field(&BitsPerSample(vec![8]));
+        directory.set(Tag::StripByteCounts, TIFFValue::Long(vec![32]));
+        let data: Vec<u8> = (0..32).map(|i| if i % 8 < 4 { 10 } else { i * 2 }).collect();
+
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        writer.set_thumbnail_size(Some(2));
+        writer.write_image_data(&directory, &data).unwrap();
+        directory.set_field(&NewSubfileType(1));
+        writer.write_image_data(&directory, &data).unwrap();
+        let bytes = writer.into_inner().into_inner();
+
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.ifds().len(), 3);
+        read.select_directory(1).unwrap();
+        assert!(read.get_field::<NewSubfileType>().unwrap().is_reduced_image());
+        assert_eq!(read.get_field::<ImageWidth>().unwrap().0, 2);
+        assert_eq!(read.get_field::<ImageLength>().unwrap().0, 1);
+        assert_eq!(read.read_strip(0).unwrap(), [10, 35]);
+        read.select_directory(2).unwrap();
+        assert_eq!(read.get_field::<ImageWidth>().unwrap().0, 8);
+    }
+
+    #[test]
+    #[cfg(feature = "checksum")]
+    fn test_embedded_checksums() {
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Big).unwrap();
+        writer.set_checksums(Some(ChecksumAlgorithm::XxHash64));
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(4));
+        directory.set_field(&ImageLength(2));
+        directory.set_field(&RowsPerStrip(1));
+        let source = |index: usize| Ok(vec![index as u8; 4]);
+        writer.write_image(&directory, 2, source).unwrap();
+
+        let mut bytes = writer.into_inner().into_inner();
+        let mut read = TIFFReader::new(Cursor::new(bytes.clone())).unwrap();
+        let embedded = read.embedded_checksums().unwrap().unwrap();
+        assert_eq!(embedded.segments.len(), 2);
+        assert_eq!(read.verify_checksums().unwrap(), Some(true));
+
+        // Flip a bit of the second strip
+        bytes[12] ^= 1;
+        let mut read = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(read.verify_checksums().unwrap(), Some(false));
+    }
+
+    #[test]
+    fn test_write_exif() {
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        let mut exif = Directory::new();
+        let exposure = Rational { num: 1, denom: 60 };
+        exif.set(Tag::ExposureTime, TIFFValue::Rational(vec![exposure]));
+        exif.set(Tag::ExifVersion, TIFFValue::Undefined(b"0232".to_vec()));
+
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(1));
+        directory.set_exif(exif);
+        writer.write_directory(&directory).unwrap();
+
+        let mut bytes = writer.into_inner().into_inner();
+        let mut read = TIFFReader::new(Cursor::new(bytes.clone())).unwrap();
+        assert_eq!(read.ifds().len(), 1);
+        let pointer = read.read_array_element(Tag::ExifIFD, 0).unwrap();
+
+        // Read the EXIF directory as the first one
+        bytes[4..8].copy_from_slice(&(pointer as u32).to_le_bytes());
+        let mut exif = TIFFReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(exif.ifds()[0].all_tags().count(), 2);
+        match exif.read_tag(Tag::ExposureTime).unwrap() {
+            Some(TIFFValue::Rational(values)) => assert_eq!(values, [exposure]),
+            value => panic!("Unexpected value {:?}", value),
+        }
+    }
+
+    #[test]
+    fn test_free_space() {
+        let mut free = FreeSpace::default();
+        free.release(20, 10);
+        free.release(9, 5);
+        free.release(14, 2);
+        free.release(30, 0);
+        free.release(25, 10);
+        assert_eq!(free.regions, [(9, 7), (20, 15)]);
+
+        // Starts are word aligned
+        assert_eq!(free.allocate(6), Some(10));
+        assert_eq!(free.regions, [(9, 1), (20, 15)]);
+        assert_eq!(free.allocate(16), None);
+        assert_eq!(free.allocate(15), Some(20));
+        assert_eq!(free.regions, [(9, 1)]);
+    }
+
+    #[test]
+    fn test_replace_directory() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(4));
+        directory.set_field(&ImageLength(2));
+        directory.set_field(&ImageDescription("A first description".to_string()));
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Big).unwrap();
+        let first = writer.write_image(&directory, 1, |_| Ok(vec![1; 8])).unwrap();
+        let second = writer.write_image(&directory, 1, |_| Ok(vec![2; 8])).unwrap();
+
+        let bytes = writer.into_inner().into_inner();
+        let mut read = TIFFReader::new(Cursor::new(&bytes[..])).unwrap();
+        let mut replacement = read.read_directory().unwrap();
+        let strip = read.read_array_element(Tag::StripOffsets, 0).unwrap();
+        replacement.set(Tag::StripOffsets, TIFFValue::Long(vec![strip as u32]));
+        replacement.set(Tag::StripByteCounts, TIFFValue::Long(vec![8]));
+        replacement.set_field(&ImageDescription("Another one".to_string()));
+
+        // The shorter description leaves room at the end of the old place
+        let length = bytes.len() as u64;
+        let mut writer = TIFFWriter::append(Cursor::new(bytes)).unwrap();
+        assert_eq!(writer.replace_directory(0, &replacement).unwrap(), first);
+        assert_eq!(writer.free_regions().len(), 1);
+        let (free_offset, _) = writer.free_regions()[0];
+        assert!(free_offset < u64::from(second));
+        assert_eq!(u64::from(writer.write_data(&[3; 6]).unwrap()), free_offset);
+
+        // Recording directories go at the end
+        writer.set_record_free_space(true);
+        assert!(u64::from(writer.replace_directory(1, &replacement).unwrap()) >= length);
+        assert!(writer.free_regions().is_empty());
+
+        let bytes = writer.into_inner().into_inner();
+        let mut read = TIFFReader::new(Cursor::new(&bytes[..])).unwrap();
+        assert_eq!(read.ifds().len(), 2);
+        assert_eq!(read.get_field::<ImageDescription>().unwrap().0, "Another one");
+        assert_eq!(read.read_strip(0).unwrap(), [1; 8]);
+        read.select_directory(1).unwrap();
+        assert_eq!(read.read_strip(0).unwrap(), [1; 8]);
+        let offsets = read.read_unsigned(Tag::FreeOffsets).unwrap();
+        let counts = read.read_unsigned(Tag::FreeByteCounts).unwrap();
+        assert!(offsets.contains(&u64::from(second)));
+
+        let writer = TIFFWriter::append(Cursor::new(bytes)).unwrap();
+        let free: Vec<(u64, u64)> = offsets.into_iter().zip(counts).collect();
+        assert_eq!(writer.free_regions(), &free[..]);
+        assert_eq!(writer.directories.len(), 2);
+    }
+
+    #[test]
+    fn test_recorded_free_space() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(4));
+        directory.set_field(&ImageLength(2));
+        directory.set_field(&ImageDescription("A description long enough".to_string()));
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Little).unwrap();
+        writer.write_image(&directory, 1, |_| Ok(vec![1; 8])).unwrap();
+        writer.write_image(&directory, 1, |_| Ok(vec![2; 8])).unwrap();
+        let bytes = writer.into_inner().into_inner();
+        let mut read = TIFFReader::new(Cursor::new(&bytes[..])).unwrap();
+        let mut first = read.read_directory().unwrap();
+        first.set(Tag::StripByteCounts, TIFFValue::Long(vec![8]));
+        read.select_directory(1).unwrap();
+        let mut second = read.read_directory().unwrap();
+        let strip = read.read_array_element(Tag::StripOffsets, 0).unwrap();
+        second.set(Tag::StripOffsets, TIFFValue::Long(vec![strip as u32]));
+        second.set(Tag::StripByteCounts, TIFFValue::Long(vec![8]));
+
+        // Data written after recording the place of the second directory,
+        // and a newer record in the first one
+        let mut writer = TIFFWriter::append(Cursor::new(bytes)).unwrap();
+        writer.set_record_free_space(true);
+        writer.replace_directory(1, &second).unwrap();
+        let strip = writer.write_data(&[3; 8]).unwrap();
+        first.set(Tag::StripOffsets, TIFFValue::Long(vec![strip]));
+        writer.replace_directory(0, &first).unwrap();
+
+        // A later edit reusing every recorded region, without recording
+        let bytes = writer.into_inner().into_inner();
+        let mut writer = TIFFWriter::append(Cursor::new(bytes)).unwrap();
+        let recorded = writer.free_regions().to_vec();
+        let strip = u64::from(writer.write_data(&[9; 8]).unwrap());
+        let inside = |(offset, length): &(u64, u64)| (*offset..offset + length).contains(&strip);
+        assert!(recorded.iter().any(inside));
+        for (offset, length) in writer.free_regions().to_vec() {
+            let size = (length - offset % 2) as usize;
+            assert_eq!(u64::from(writer.write_data(&vec![4; size]).unwrap()), offset + offset % 2);
+        }
+        first.set(Tag::StripOffsets, TIFFValue::Long(vec![strip as u32]));
+        writer.replace_directory(0, &first).unwrap();
+
+        // The records were dropped, so a third edit leaves that data alone
+        let bytes = writer.into_inner().into_inner();
+        let mut writer = TIFFWriter::append(Cursor::new(bytes)).unwrap();
+        assert!(writer.free_regions().is_empty());
+        writer.write_data(&[7; 8]).unwrap();
+
+        let bytes = writer.into_inner().into_inner();
+        let mut read = TIFFReader::new(Cursor::new(&bytes[..])).unwrap();
+        assert_eq!(read.read_strip(0).unwrap(), [9; 8]);
+        assert!(read.read_tag(Tag::FreeOffsets).unwrap().is_none());
+        read.select_directory(1).unwrap();
+        assert_eq!(read.read_strip(0).unwrap(), [2; 8]);
+        assert!(read.read_tag(Tag::FreeOffsets).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_raw_entries() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageWidth(10));
+        let short = RawEntry {
+            value_type: 3,
+            count: 2,
+            bytes: vec![0, 1, 0, 2],
+        };
+        directory.set_raw(Tag::Unknown(0xc000), short.clone());
+        // A type outside of the specification, with a value out of the entry
+        let exotic = RawEntry {
+            value_type: 0x99,
+            count: 1,
+            bytes: vec![1, 2, 3, 4, 5],
+        };
+        directory.set_raw(Tag::Unknown(0xc001), exotic);
+        assert!(directory.has_field(Tag::Unknown(0xc000)));
+        assert_eq!(directory.raw(Tag::Unknown(0xc000)), Some(&short));
+
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Big).unwrap();
+        writer.write_directory(&directory).unwrap();
+        let bytes = writer.into_inner().into_inner();
+        let mut read = TIFFReader::new(Cursor::new(&bytes[..])).unwrap();
+        assert_eq!(read.read_unsigned(Tag::Unknown(0xc000)).unwrap(), [1, 2]);
+        let entry = *read.ifds()[0].get_entry_from_tag(Tag::Unknown(0xc001)).unwrap();
+        assert_eq!((entry.value_type, entry.count), (0x99, 1));
+        let offset = entry.value_offset as usize;
+        assert_eq!(&bytes[offset..offset + 5], [1, 2, 3, 4, 5]);
+
+        // Setting a value replaces the raw entry
+        directory.set(Tag::Unknown(0xc000), TIFFValue::Short(vec![3]));
+        assert!(directory.raw(Tag::Unknown(0xc000)).is_none());
+
+        let mut directory = Directory::new();
+        directory.set_raw(Tag::Unknown(0xc000), RawEntry { count: 3, ..short });
+        let mut writer = TIFFWriter::new(Cursor::new(Vec::new()), Endian::Big).unwrap();
+        assert!(writer.write_directory(&directory).is_err());
+    }
+
+    #[test]
+    fn test_directory_fields() {
+        let mut directory = Directory::new();
+        directory.set_field(&ImageLength(20));
+        directory.set_field(&ImageWidth(10));
+        assert_eq!(directory.tags().collect::<Vec<_>>(), [Tag::ImageWidth, Tag::ImageLength]);
+        assert_eq!(directory.get_field::<ImageLength>().unwrap().0, 20);
+        assert!(directory.remove(Tag::ImageLength).is_some());
+        assert!(directory.get(Tag::ImageLength).is_none());
+
+        directory.set_field(&Software("tiff".to_string()));
+        directory.set_exif(Directory::new());
+        assert!(directory.has_field(Tag::ExifIFD));
+        assert!(directory.has_field(Tag::Software));
+        let entries: Vec<_> = directory.entries().map(|(tag, _)| tag).collect();
+        assert_eq!(entries, [Tag::ImageWidth, Tag::Software]);
+        directory.set_raw(
+            Tag::Unknown(0xc000),
+            RawEntry {
+                value_type: 3,
+                count: 1,
+                bytes: vec![0, 1],
+            },
+        );
+        // SubIFDs
+        directory.set_sub_directory(Tag::Unknown(0x14a), Directory::new());
+        let tags: Vec<_> = directory.tags().collect();
+        let expected = [
+            Tag::ImageWidth,
+            Tag::Software,
+            Tag::Unknown(0x14a),
+            Tag::ExifIFD,
+            Tag::Unknown(0xc000),
+        ];
+        assert_eq!(tags, expected);
+        assert_eq!(directory.remove_field::<Software>().unwrap().0, "tiff");
+        assert!(directory.remove_field::<Software>().is_none());
+        assert!(!directory.has_field(Tag::Software));
+        assert!(directory.remove(Tag::ExifIFD).is_none());
+        assert!(!directory.has_field(Tag::ExifIFD));
+    }
+}